@@ -0,0 +1,53 @@
+#![no_main]
+//! Grows arbitrary shapes block by block and checks that their canonical form does not depend on how
+//! they are oriented or where they are placed, and that canonicalizing a canonical shape keeps it as is.
+//! Run with `cargo fuzz run canonicalize` in the root of the repository.
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use cube_combinations::block_arrangement::BlockArrangement;
+use cube_combinations::orientation::OrientationIterator;
+use cube_combinations::point::Point3D;
+use cube_combinations::polycube::Polycube;
+
+/// Larger shapes only slow the fuzzer down.
+const MAX_BLOCKS: usize = 32;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Every step adds a block next to the block with the first index, in the direction of the second.
+    steps: Vec<(u8, u8)>,
+    orientation: u16,
+}
+
+fuzz_target!(|input: Input| {
+    let mut points = vec![Point3D::new(0, 0, 0)];
+    for (block, direction) in input.steps.into_iter().take(MAX_BLOCKS - 1) {
+        let offsets = BlockArrangement::NEIGHBOR_OFFSETS;
+        let p = points[block as usize % points.len()] + offsets[direction as usize % offsets.len()];
+        if !points.contains(&p) {
+            points.push(p);
+        }
+    }
+    let ba = BlockArrangement::from_points(&points).expect("Grown shapes are connected.");
+    let polycube = Polycube::new(&ba).expect("Grown shapes are connected.");
+
+    let again = Polycube::new(polycube.arrangement()).expect("Canonical shapes are connected.");
+    assert_eq!(polycube.block_iter().collect::<Vec<_>>(), again.block_iter().collect::<Vec<_>>());
+    assert_eq!(polycube.code(), again.code());
+
+    let orientations: Vec<_> = OrientationIterator::default().collect();
+    let mut oriented = ba.clone();
+    oriented.set_orientation(orientations[input.orientation as usize % orientations.len()]);
+    let oriented = Polycube::new(&oriented).expect("Orienting keeps the shape connected.");
+    assert_eq!(polycube.code(), oriented.code());
+    assert_eq!(polycube, oriented);
+    assert_eq!(polycube.is_chiral(), oriented.is_chiral());
+
+    // The arrangement is placed with its first point at the origin, so the reversed points move it.
+    points.reverse();
+    let moved = Polycube::new(&BlockArrangement::from_points(&points).expect("Reordering keeps the shape connected."))
+        .expect("Reordering keeps the shape connected.");
+    assert_eq!(polycube.code(), moved.code());
+    assert_eq!(Ok(&polycube), Polycube::from_code(&polycube.code()).as_ref());
+});
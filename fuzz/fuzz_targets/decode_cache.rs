@@ -0,0 +1,29 @@
+#![no_main]
+//! Feeds arbitrary bytes to every reader of cache and tree files, which may fail but must never panic.
+//! Run with `cargo fuzz run decode_cache` in the root of the repository.
+
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+use cube_combinations::cache::verify::verify_cache;
+use cube_combinations::cache::{read_level, read_shapes, CacheReader};
+use cube_combinations::poly_tree::PolyTree;
+
+fuzz_target!(|data: &[u8]| {
+    let data = data.to_vec();
+    if let Ok(reader) = CacheReader::new(Cursor::new(data.clone())) {
+        for record in reader {
+            if let Ok((_, ba)) = record {
+                let _ = ba.is_connected();
+            }
+        }
+    }
+    let _ = read_level(Cursor::new(data.clone()));
+    if let Ok(shapes) = read_shapes(Cursor::new(data.clone())) {
+        for view in shapes.iter().flatten() {
+            let _ = view.surface_area();
+            let _ = view.to_polycube();
+        }
+    }
+    let _ = verify_cache(Cursor::new(data.clone()));
+    let _ = PolyTree::read_from(&mut data.as_slice());
+});
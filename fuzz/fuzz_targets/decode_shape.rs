@@ -0,0 +1,35 @@
+#![no_main]
+//! Feeds arbitrary bytes to the decoders of single shapes: codes, encoded occupancies, arrangements and
+//! polycubes, and their JSON. Decoding may fail but must never panic, and decoded shapes must be usable.
+//! Run with `cargo fuzz run decode_shape` in the root of the repository.
+
+use libfuzzer_sys::fuzz_target;
+use cube_combinations::block_arrangement::BlockArrangement;
+use cube_combinations::block_hash::BlockHash;
+use cube_combinations::polycube::{Occupancy, Polycube};
+use cube_combinations::shape_ref::ShapeRef;
+
+fuzz_target!(|data: &[u8]| {
+    let config = bincode::config::standard();
+    if let Ok(code) = std::str::from_utf8(data) {
+        if let Ok(polycube) = Polycube::from_code(code) {
+            assert_eq!(Ok(&polycube), Polycube::from_code(&polycube.code()).as_ref());
+        }
+    }
+    if let Ok((occupancy, _)) = bincode::serde::decode_from_slice::<Occupancy, _>(data, config) {
+        if let Ok(view) = ShapeRef::try_from(&occupancy) {
+            let _ = view.surface_area();
+        }
+        let _ = Polycube::from_occupancy(&occupancy);
+    }
+    if let Ok((ba, _)) = bincode::serde::decode_from_slice::<BlockArrangement, _>(data, config) {
+        let _ = BlockHash::from(&ba);
+        let _ = Polycube::new(&ba);
+    }
+    if let Ok((polycube, _)) = bincode::serde::decode_from_slice::<Polycube, _>(data, config) {
+        let _ = polycube.code();
+    }
+    if let Ok(ba) = serde_json::from_slice::<BlockArrangement>(data) {
+        let _ = Polycube::new(&ba);
+    }
+});
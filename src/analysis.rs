@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::cache::index::ShapeMetadata;
+use crate::lattice::{fixed_count, Cubic};
+use crate::polycube::Polycube;
+use crate::symmetry::Symmetry;
+
+/// The number of rotations and reflections of the cube, which free shapes are distinct under.
+const CUBE_SYMMETRIES: u64 = 48;
+
+/// The axis lengths of the tight bounding box of a shape sorted in ascending order,
+/// which is the same for every orientation of the shape.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BoundingBoxClass([u32; 3]);
+
+impl BoundingBoxClass {
+    pub fn from_axis_lengths(mut lengths: [u32; 3]) -> Self {
+        lengths.sort();
+        Self(lengths)
+    }
+
+    pub fn of(polycube: &Polycube) -> Self {
+        let (width, depth, height) = polycube.bounding_box().all_axis_len();
+        // Every axis of the bounding box is at most as long as the number of blocks.
+        Self::from_axis_lengths([width, depth, height].map(|len| len as u32))
+    }
+
+    pub fn axis_lengths(&self) -> [u32; 3] {
+        self.0
+    }
+
+    /// The length of the shortest axis, i.e. the number of layers the shape fits into.
+    pub fn thickness(&self) -> u32 {
+        self.0[0]
+    }
+}
+
+impl From<&ShapeMetadata> for BoundingBoxClass {
+    fn from(metadata: &ShapeMetadata) -> Self {
+        Self::from_axis_lengths(metadata.bounding_box)
+    }
+}
+
+impl Display for BoundingBoxClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c] = self.0;
+        write!(f, "{a}x{b}x{c}")
+    }
+}
+
+/// Counts the shapes per bounding box class.
+pub fn count_by_bounding_box(classes: impl IntoIterator<Item = BoundingBoxClass>) -> BTreeMap<BoundingBoxClass, usize> {
+    let mut counts = BTreeMap::new();
+    for class in classes {
+        *counts.entry(class).or_default() += 1;
+    }
+    counts
+}
+
+/// Derives the number of fixed shapes, which are only distinct up to translation, from the symmetries of
+/// the free shapes of one size. By the orbit-stabilizer theorem a free shape has
+/// `48 / |symmetry group|` distinct orientations.
+pub fn fixed_count_from_free<'a>(symmetries: impl IntoIterator<Item = &'a Symmetry>) -> u64 {
+    symmetries.into_iter()
+        .map(|symmetry| CUBE_SYMMETRIES / symmetry.order() as u64)
+        .sum()
+}
+
+/// Counts the fixed shapes with the given number of blocks by growing every shape in every position,
+/// which is only feasible for small sizes but does not rely on any symmetry handling.
+pub fn fixed_count_direct(block_count: usize) -> u64 {
+    fixed_count(&Cubic, block_count)
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use crate::block_count::BlockCount;
+    use crate::import::{import, ImportFormat};
+    use crate::poly_tree::PolyTree;
+    use super::*;
+
+    fn polycube(xyz: &str) -> Polycube {
+        let shapes = import(xyz.as_bytes(), ImportFormat::Xyz).expect("Valid xyz");
+        Polycube::new(&shapes[0]).expect("Connected shape")
+    }
+
+    #[test]
+    fn test_bounding_box_classes() {
+        let l_tetracube = polycube("0 0 0\n0 1 0\n0 2 0\n1 2 0");
+        assert_eq!(BoundingBoxClass::from_axis_lengths([1, 2, 3]), BoundingBoxClass::of(&l_tetracube));
+        assert_eq!("1x2x3", BoundingBoxClass::of(&l_tetracube).to_string());
+        assert_eq!(1, BoundingBoxClass::of(&l_tetracube).thickness());
+
+        let tetracubes = [
+            "0 0 0\n1 0 0\n2 0 0\n3 0 0",
+            "0 0 0\n1 0 0\n0 1 0\n1 1 0",
+            "0 0 0\n1 0 0\n2 0 0\n1 1 0",
+            "0 0 0\n0 1 0\n0 2 0\n1 2 0",
+            "0 0 0\n1 0 0\n1 1 0\n2 1 0",
+            "0 0 0\n1 0 0\n0 1 0\n0 0 1",
+            "0 0 0\n1 0 0\n1 1 0\n1 1 1",
+            "0 0 0\n1 0 0\n1 1 0\n0 0 1",
+        ];
+        let counts = count_by_bounding_box(tetracubes.map(|xyz| BoundingBoxClass::of(&polycube(xyz))));
+        let counts: Vec<_> = counts.iter().map(|(class, count)| (class.to_string(), *count)).collect();
+        let expected = [("1x1x4", 1), ("1x2x2", 1), ("1x2x3", 3), ("2x2x2", 3)];
+        assert_eq!(expected.map(|(class, count)| (class.to_string(), count)).to_vec(), counts);
+    }
+
+    #[test]
+    fn test_fixed_counts() {
+        let direct: Vec<_> = (1..=6).map(fixed_count_direct).collect();
+        assert_eq!(vec![1, 3, 15, 86, 534, 3481], direct);
+
+        let mut tree = PolyTree::new();
+        for n in BlockCount::ONE.up_to(BlockCount::new(5)) {
+            tree.generate_next_level();
+            let symmetries: Vec<_> = tree.iter_level(n)
+                .map(|ba| Symmetry::of(&Polycube::new(&ba).unwrap()))
+                .collect();
+            assert_eq!(direct[n.as_usize() - 1], fixed_count_from_free(&symmetries), "Fixed count of {n} blocks");
+        }
+    }
+}
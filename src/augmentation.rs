@@ -0,0 +1,114 @@
+//! Counts the shapes without storing them by canonical augmentation, as described by McKay.
+//!
+//! Every shape with more than one block has a single [canonical parent](canonical_parent): the shape left
+//! after removing the last removable block of its canonical form. A child grown from a shape by adding a
+//! block is only accepted if the shape is its canonical parent, so the accepted children of all shapes
+//! form a tree in which every shape appears exactly once. Walking that tree depth first only holds the
+//! children of the shapes on the current path, so the memory does not grow with the number of shapes.
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::polycube::Polycube;
+
+/// The number of subtrees per thread the walk is split into, so threads that finish early take over more.
+const SUBTREES_PER_THREAD: usize = 16;
+
+/// The number of unique shapes with n blocks, counted on every available thread.
+pub fn count_unique_arrangements(n: BlockCount) -> u64 {
+    let threads = thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+    count_levels(n, threads).last().copied().unwrap_or(0)
+}
+
+/// The numbers of unique shapes with one to n blocks, counted with the given number of threads.
+/// The counts are the same for any number of threads.
+pub fn count_levels(n: BlockCount, threads: usize) -> Vec<u64> {
+    let mut counts = vec![0; n.as_usize()];
+    if counts.is_empty() {
+        return counts;
+    }
+    // The first levels are expanded as a whole until there are enough subtrees to share between the threads.
+    let mut frontier = vec![Polycube::new(&BlockArrangement::new()).expect("A single block is connected.")];
+    let mut depth = 1;
+    while depth < counts.len() && frontier.len() < threads * SUBTREES_PER_THREAD {
+        counts[depth - 1] = frontier.len() as u64;
+        frontier = frontier.iter().flat_map(canonical_children).collect();
+        depth += 1;
+    }
+    let next = AtomicUsize::new(0);
+    let totals = Mutex::new(counts);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut counts = vec![0; n.as_usize()];
+                while let Some(shape) = frontier.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    count_descendants(shape, n, &mut counts);
+                }
+                let mut totals = totals.lock().expect("No thread panicked while holding the lock.");
+                totals.iter_mut().zip(counts).for_each(|(total, count)| *total += count);
+            });
+        }
+    });
+    totals.into_inner().expect("No thread panicked while holding the lock.")
+}
+
+/// Adds the shape and its descendants with at most n blocks to the counts of their sizes.
+fn count_descendants(shape: &Polycube, n: BlockCount, counts: &mut [u64]) {
+    counts[shape.num_blocks().as_usize() - 1] += 1;
+    if shape.num_blocks() < n {
+        canonical_children(shape).iter().for_each(|child| count_descendants(child, n, counts));
+    }
+}
+
+/// The distinct children of the shape whose [canonical parent](canonical_parent) it is.
+pub fn canonical_children(parent: &Polycube) -> Vec<Polycube> {
+    let mut children = HashSet::new();
+    for block in parent.free_neighbors() {
+        let mut child = parent.arrangement().clone();
+        child.add_block_at(&block).expect("Free neighbors are adjacent to a block.");
+        let child = Polycube::new(&child).expect("Adding a neighbor keeps the shape connected.");
+        if !children.contains(&child) && canonical_parent(&child).as_ref() == Some(parent) {
+            children.insert(child);
+        }
+    }
+    children.into_iter().collect()
+}
+
+/// The shape left after removing the last block of the canonical form, in the order of
+/// [BlockArrangement::block_iter_sorted], that keeps the other blocks connected. It only depends on
+/// the shape, so every shape has exactly one. `None` for a single block.
+pub fn canonical_parent(shape: &Polycube) -> Option<Polycube> {
+    let blocks: Vec<_> = shape.block_iter_sorted().collect();
+    blocks.iter().rev().find_map(|block| {
+        let mut parent = shape.arrangement().clone();
+        parent.remove_block_at(block).ok()?;
+        Some(Polycube::new(&parent).expect("Removable blocks keep the shape connected."))
+    })
+}
+
+#[cfg(test)]
+mod augmentation_tests {
+    use crate::polycubes::Polycubes;
+    use super::*;
+
+    #[test]
+    fn test_count_levels() {
+        let expected = vec![1, 1, 2, 7, 23, 112];
+        assert_eq!(expected, count_levels(BlockCount::new(6), 1));
+        assert_eq!(expected, count_levels(BlockCount::new(6), 3));
+        assert_eq!(23, count_unique_arrangements(BlockCount::new(5)));
+
+        // Every pentacube is the accepted child of exactly one tetracube.
+        let tetracubes = Polycubes::builtin(BlockCount::new(4)).unwrap();
+        let children: Vec<_> = tetracubes.iter().flat_map(canonical_children).collect();
+        let pentacubes: HashSet<_> = Polycubes::builtin(BlockCount::new(5)).unwrap().iter().cloned().collect();
+        assert_eq!(pentacubes.len(), children.len());
+        assert!(children.iter().all(|child| pentacubes.contains(child)));
+        assert!(children.iter().all(|child| tetracubes.contains(&canonical_parent(child).unwrap())));
+        assert_eq!(None, canonical_parent(&Polycube::new(&BlockArrangement::new()).unwrap()));
+    }
+}
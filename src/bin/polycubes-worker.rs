@@ -0,0 +1,115 @@
+use std::{env, io, process};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use cube_combinations::block_arrangement::block_variation::generate_variants_from;
+use cube_combinations::block_arrangement::BlockArrangement;
+use cube_combinations::cache::{save_partial_shard, CacheReader};
+use cube_combinations::distributed::{run_worker, HEARTBEAT_INTERVAL};
+use cube_combinations::store::open_file_store;
+
+/// A slim worker for cluster deployments that only expands ranges of a parent cache.
+///
+/// Tasks are read line by line from stdin or from the file given as the only argument.
+/// Each task has the form `<parent cache> <start> <end> [<max layers>]` and expands the parents in `start..end`,
+/// optionally discarding children with more than `max layers` layers.
+/// The children are saved as a partial shard next to the parent cache, where a generation run
+/// for the next level picks them up. The parent cache may also be an `s3://` url, see
+/// [cube_combinations::store::open_store], which puts the shard into the same bucket. For every task
+/// one line is written to stdout, either `done <start> <end> <shape count>` or `error <start> <end> <reason>`.
+///
+/// With `--connect <address>` the tasks are received from a coordinator over the network instead,
+/// see [cube_combinations::distributed]. The worker reconnects for the following levels until
+/// the coordinator is gone for [RECONNECT_TIMEOUT].
+fn main() {
+    let mut args = env::args().skip(1);
+    let first = args.next();
+    if first.as_deref() == Some("--connect") {
+        let Some(address) = args.next() else {
+            eprintln!("Expected an address after --connect");
+            process::exit(1);
+        };
+        connect(&address);
+        return;
+    }
+    let input: Box<dyn BufRead> = match first {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("Failed to open task file {path}: {e}");
+                process::exit(1);
+            }
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut stdout = io::stdout().lock();
+    for line in input.lines() {
+        let line = line.expect("Readable task input");
+        let task: Vec<&str> = line.split_whitespace().collect();
+        let response = match task[..] {
+            [] => continue,
+            [cache, start, end, ref max_layers @ ..] if max_layers.len() <= 1 => {
+                let max_layers = max_layers.first().map(|layers| layers.parse::<u32>()).transpose();
+                match (start.parse(), end.parse(), max_layers) {
+                    (Ok(start), Ok(end), Ok(max_layers)) => match expand(cache, start, end, max_layers) {
+                        Ok(count) => format!("done {start} {end} {count}"),
+                        Err(e) => format!("error {start} {end} {e}"),
+                    },
+                    _ => format!("error {start} {end} The range and layers have to be numbers"),
+                }
+            }
+            _ => format!("error - - Malformed task {line}"),
+        };
+        writeln!(stdout, "{response}").expect("Writable stdout");
+        stdout.flush().expect("Writable stdout");
+    }
+}
+
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Works for the coordinator at the address until it does not accept connections anymore.
+fn connect(address: &str) {
+    let mut last_connected = Instant::now();
+    while last_connected.elapsed() < RECONNECT_TIMEOUT {
+        match run_worker(address, HEARTBEAT_INTERVAL) {
+            Ok(completed) => {
+                if completed > 0 {
+                    println!("Completed {completed} tasks");
+                }
+                last_connected = Instant::now();
+            }
+            // The coordinator is between two levels.
+            Err(e) if matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof) => {
+                thread::sleep(Duration::from_millis(200))
+            }
+            Err(e) => {
+                eprintln!("Lost coordinator: {e}");
+                last_connected = Instant::now();
+            }
+        }
+    }
+}
+
+/// Expands the parents in `start..end` of the cache and saves their children as a partial shard.
+/// Returns the number of unique children.
+fn expand(cache: &str, start: usize, end: usize, max_layers: Option<u32>) -> Result<usize, String> {
+    let (store, name) = open_file_store(cache).map_err(|e| format!("Failed to open store: {e}"))?;
+    let reader = store.open(&name)
+        .and_then(CacheReader::new)
+        .map_err(|e| format!("Failed to open cache: {e}"))?;
+    let parent_count = reader.remaining() as usize;
+    if start >= end || end > parent_count {
+        return Err(format!("The range is not within the {parent_count} parents"));
+    }
+    let parents: Vec<BlockArrangement> = reader.skip(start)
+        .take(end - start)
+        .map(|record| record.map(|(_, ba)| ba))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read cache: {e}"))?;
+    let block_count = parents[0].num_blocks().next().ok_or("The parents have the largest possible number of blocks")?;
+    let (children, _) = generate_variants_from(parents.iter(), parents.len() * 8, max_layers);
+    save_partial_shard(&children, store.as_ref(), block_count, &(start..end), parent_count)
+        .map_err(|e| format!("Failed to save shard: {e}"))?;
+    Ok(children.len())
+}
@@ -1,203 +1,710 @@
 pub mod block_variation;
+pub mod row_masks;
 
+use std::collections::{HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use fixedbitset::FixedBitSet;
 use getset::CopyGetters;
-use rust_decimal::{Decimal, RoundingStrategy};
-use rust_decimal::prelude::ToPrimitive;
+#[cfg(feature = "decimal-metrics")]
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use crate::block_hash::BlockHash;
+use crate::block_count::BlockCount;
 use strum::IntoEnumIterator;
+use crate::lattice::{Connectivity, Cubic};
 use crate::mapper::{Mapper};
-use crate::orientation::{Orientation, OrientationIterator};
-use crate::point::{Axis3D, Finite3DDimension, Point3D};
+use crate::orientation::{Orientation, Orientation48, OrientationRanking};
+use crate::polycube::{CanonicalForms, Chirality};
+use crate::block_arrangement::row_masks::RowMasks;
+use crate::metrics::enclosed_cells;
+use crate::symmetry::SymmetryGroup;
+use crate::point::{Axis3D, Boundary, DimensionError, Finite3DDimension, Point3D};
 
 
 /// Describes an arrangement of blocks joined at their faces in a rotation and directionless manner.
 #[derive(Debug, Clone)]
 #[derive(CopyGetters)]
 #[derive(Serialize, Deserialize)]
+#[serde(try_from = "StoredArrangement")]
 pub struct BlockArrangement {
     /// Represents the block_arrangement placement
     bitset: FixedBitSet,
     /// The number of blocks in this arrangement.
     /// Is always > 0
     #[get_copy = "pub"]
-    num_blocks: u8,
+    num_blocks: BlockCount,
     /// Offset from origin
     center_off_mass: Point3D<i32>,
     mapper: Mapper,
+    /// Not serialized so stored arrangements keep their format, which always uses face connectivity.
+    #[serde(skip)]
+    connectivity: Connectivity,
+    /// The color of every position, or `None` while every block has the default color zero so
+    /// uncolored arrangements do not allocate it.
+    /// Not serialized so stored arrangements keep their format.
+    #[serde(skip)]
+    colors: Option<Box<[u8]>>,
 }
 
 impl Default for BlockArrangement {
     /// Creates a block_arrangement arrangement with one block_arrangement at the origin.
     fn default() -> Self {
-        Self::with_capacity(Finite3DDimension::default())
+        Self::with_capacity(Finite3DDimension::default()).expect("A single position is addressable.")
     }
 }
 
 impl Hash for BlockArrangement {
+    /// Hashes the [canonical form](BlockArrangement::canonical), so congruent arrangements, which are
+    /// equal, hash alike in every orientation and position. Colors are only hashed if a block has one,
+    /// like [Polycube](crate::polycube::Polycube)s do.
     fn hash<H: Hasher>(&self, state: &mut H) {
-        BlockHash::from(self).hash(state)
+        let forms = CanonicalForms::of(self);
+        forms.points(Chirality::Free).hash(state);
+        let colors = forms.colors(Chirality::Free);
+        if colors.iter().any(|color| *color != 0) {
+            colors.hash(state);
+        }
     }
 }
 
 impl PartialEq for BlockArrangement {
+    /// Whether both are the same shape, compared as chosen by [congruence](crate::congruence).
     fn eq(&self, other: &Self) -> bool {
+        crate::congruence::congruent(self, other)
+    }
+}
+
+impl Eq for BlockArrangement {}
+
+impl BlockArrangement {
+    /// Tries the orientations in the order of the global [OrientationRanking] until this arrangement
+    /// matches the other one relative to their centers of mass, see
+    /// [EqualityStrategy::OrientationLoop](crate::congruence::EqualityStrategy::OrientationLoop).
+    pub(crate) fn matches_in_some_orientation(&self, other: &Self) -> bool {
+        if self.num_blocks != other.num_blocks {
+            return false;
+        }
         let mut mapper = self.mapper.clone();
-        OrientationIterator::default().any(|orientation| {
-            mapper.set_orientation(orientation);
+        // The center of mass is stored in the current orientation, which the loop replaces.
+        let unoriented_center_of_mass = self.mapper.orientation().inverse().apply(self.center_off_mass);
+        OrientationRanking::global().find_map(|orientation| {
+            mapper.set_orientation(orientation.into());
 
             let oriented_center_of_mass = {
-                let mut p = self.center_off_mass;
+                let mut p = unoriented_center_of_mass;
                 p.apply_orientation(&orientation);
                 p
             };
 
-            self.num_blocks == other.num_blocks
-                && self
-                .bitset.ones()
-                .map(|index| mapper.resolve(index)
-                    .expect("Expect save conversion since mapper dimension is equal."))
-                .map(|p| p - oriented_center_of_mass)
-                .all(|p| other.is_set_relative_to_center_of_mass(&p))
-        })
+            let colored = self.is_colored() || other.is_colored();
+            self.bitset.ones()
+                .map(|index| (mapper.resolve(index)
+                    .expect("Expect save conversion since mapper dimension is equal."), index))
+                .map(|(p, index)| (p - oriented_center_of_mass, index))
+                .all(|(p, index)| if colored {
+                    other.color(&(p + other.center_off_mass)) == Some(self.color_at(index))
+                } else {
+                    other.is_set_relative_to_center_of_mass(&p)
+                })
+                .then_some(())
+        }).is_some()
     }
 }
 
-impl Eq for BlockArrangement {}
-
 #[derive(Debug, Eq, PartialEq)]
 pub enum PlacementError {
-    NotAdjacentToBlock
+    NotAdjacentToBlock,
+    NoBlocks,
+    Occupied,
+    /// There is no block at the position.
+    Unoccupied,
+    /// The arrangement would not fit into an addressable dimension.
+    TooLarge,
+    /// Removing the block would split the arrangement.
+    Disconnects,
+}
+
+impl From<DimensionError> for PlacementError {
+    fn from(value: DimensionError) -> Self {
+        match value {
+            DimensionError::TooLarge => PlacementError::TooLarge,
+        }
+    }
+}
+
+/// Why deserialized fields do not describe an arrangement.
+#[derive(Debug, Eq, PartialEq)]
+pub enum StoredArrangementError {
+    /// The dimension can not be addressed or the bits do not cover exactly its positions.
+    Dimension,
+    /// There are no blocks or their number does not match the set bits.
+    BlockCount,
+    /// The box reaches too far from the origin to compare and grow its blocks without overflowing.
+    Offset,
+}
+
+impl std::fmt::Display for StoredArrangementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoredArrangementError::Dimension => f.write_str("The dimension does not match the stored blocks"),
+            StoredArrangementError::BlockCount => f.write_str("The number of blocks does not match the stored blocks"),
+            StoredArrangementError::Offset => f.write_str("The stored blocks lie too far from the origin"),
+        }
+    }
+}
+
+impl std::error::Error for StoredArrangementError {}
+
+/// The serialized fields of a [BlockArrangement], which are checked before they are trusted so that
+/// corrupted data fails to deserialize instead of panicking later.
+#[derive(Deserialize)]
+struct StoredArrangement {
+    bitset: FixedBitSet,
+    num_blocks: BlockCount,
+    center_off_mass: Point3D<i32>,
+    mapper: Mapper,
+}
+
+impl TryFrom<StoredArrangement> for BlockArrangement {
+    type Error = StoredArrangementError;
+
+    /// The center of mass is recalculated instead of trusting the stored one.
+    fn try_from(stored: StoredArrangement) -> Result<Self, Self::Error> {
+        let StoredArrangement { bitset, num_blocks, center_off_mass, mapper } = stored;
+        let dimension = mapper.dimension();
+        // The bitset does not check its own fields, and counting trusts them.
+        let blocks_fit = bitset.as_slice().len() == bitset.len().div_ceil(32) && bitset.ones().all(|index| index < bitset.len());
+        if !blocks_fit || dimension.size().ok() != Some(bitset.len()) {
+            return Err(StoredArrangementError::Dimension);
+        }
+        if num_blocks == BlockCount::default() || bitset.count_ones(..) != num_blocks.as_usize() {
+            return Err(StoredArrangementError::BlockCount);
+        }
+        // Differences of coordinates and their neighbors have to fit into an i32 as well.
+        let limit = i64::from(i32::MAX / 2);
+        let fits = Axis3D::iter().all(|axis| {
+            let min = i64::from(mapper.offset()[axis]);
+            let max = min + dimension.axis_len(axis) as i64 - 1;
+            -limit <= min && max <= limit
+        });
+        if !fits {
+            return Err(StoredArrangementError::Offset);
+        }
+        let mut ba = Self { bitset, num_blocks, center_off_mass, mapper, connectivity: Connectivity::default(), colors: None };
+        ba.update_center_of_mass();
+        Ok(ba)
+    }
+}
+
+/// An arrangement in the layout of [CacheFormat::V1](crate::cache::CacheFormat::V1) files, which store
+/// the box of the blocks as it was stored before the [Mapper] kept its smallest corner apart from its
+/// lengths: as the lengths along the positive and the negative direction of every axis away from the
+/// origin, so the box always contains the origin.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct V1Arrangement {
+    bitset: FixedBitSet,
+    num_blocks: BlockCount,
+    center_off_mass: Point3D<i32>,
+    mapper: V1Mapper,
+}
+
+#[derive(Serialize, Deserialize)]
+struct V1Mapper {
+    /// The lengths along the positive and the negative direction of the x, y and z axis, in this order.
+    dimension: [u32; 6],
+    orientation: Orientation48,
+}
+
+#[cfg(feature = "decimal-metrics")]
+impl TryFrom<&BlockArrangement> for V1Arrangement {
+    type Error = DimensionError;
+
+    /// Blocks whose box does not contain the origin are moved into one that does first.
+    fn try_from(ba: &BlockArrangement) -> Result<Self, Self::Error> {
+        let mut ba = std::borrow::Cow::Borrowed(ba);
+        let (offset, dim) = (ba.mapper.offset(), ba.mapper.dimension());
+        let contains_origin = Axis3D::iter().all(|axis| offset[axis] <= 0 && i64::from(offset[axis]) + dim.axis_len(axis) as i64 > 0);
+        if !contains_origin {
+            let (corner, dim) = ba.stored_box();
+            let (offset, dim) = dim.expand_to_include(corner, &Point3D::default())?;
+            ba.to_mut().resize(offset, dim)?;
+        }
+        let mut dimension = [0; 6];
+        for (i, axis) in Axis3D::iter().enumerate() {
+            let neg = ba.mapper.offset()[axis].unsigned_abs();
+            let len = u32::try_from(ba.mapper.dimension().axis_len(axis)).map_err(|_| DimensionError::TooLarge)?;
+            dimension[2 * i..2 * i + 2].copy_from_slice(&[len - 1 - neg, neg]);
+        }
+        let mapper = V1Mapper { dimension, orientation: ba.mapper.orientation() };
+        Ok(Self { bitset: ba.bitset.clone(), num_blocks: ba.num_blocks, center_off_mass: ba.center_off_mass, mapper })
+    }
+}
+
+impl TryFrom<V1Arrangement> for BlockArrangement {
+    type Error = StoredArrangementError;
+
+    fn try_from(stored: V1Arrangement) -> Result<Self, Self::Error> {
+        let V1Arrangement { bitset, num_blocks, center_off_mass, mapper: V1Mapper { dimension, orientation } } = stored;
+        let mut lens = [0; 3];
+        let mut offset = Point3D::default();
+        for (i, axis) in Axis3D::iter().enumerate() {
+            let [pos, neg] = [dimension[2 * i], dimension[2 * i + 1]];
+            lens[i] = pos.checked_add(neg).and_then(|len| len.checked_add(1)).ok_or(StoredArrangementError::Dimension)?;
+            offset[axis] = i32::try_from(neg).map(|neg| -neg).map_err(|_| StoredArrangementError::Offset)?;
+        }
+        let mut mapper = Mapper::new(Finite3DDimension::new(lens[0], lens[1], lens[2]))
+            .map_err(|_| StoredArrangementError::Dimension)?
+            .with_offset(offset);
+        mapper.set_orientation(orientation);
+        BlockArrangement::try_from(StoredArrangement { bitset, num_blocks, center_off_mass, mapper })
+    }
 }
 
 impl BlockArrangement {
 
-    pub const NEIGHBOR_OFFSETS: [Point3D<i32>; 6] = [
-        Point3D::new(0, 0, -1),
-        Point3D::new(0, 0, 1),
-        Point3D::new(0, -1, 0),
-        Point3D::new(0, 1, 0),
-        Point3D::new(-1, 0, 0),
-        Point3D::new(1, 0, 0),
-    ];
+    /// The offsets of the positions sharing a face with a block, as defined by the [Cubic] lattice.
+    pub const NEIGHBOR_OFFSETS: [Point3D<i32>; 6] = Cubic::FACE_OFFSETS;
 
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn with_capacity(dim: Finite3DDimension) -> Self {
-        let mut arr = Self {
-            bitset: FixedBitSet::with_capacity(dim.size() as usize),
-            num_blocks: 0,
-            center_off_mass: Point3D::default(),
-            mapper: Mapper::new(dim),
-        };
+    /// Fails if the dimension contains more positions than can be addressed.
+    pub fn with_capacity(dim: Finite3DDimension) -> Result<Self, DimensionError> {
+        Self::with_boundary(dim, Boundary::Bounded)
+    }
+
+    /// Creates an arrangement with one block at the origin of a periodic unit cell.
+    /// Blocks placed beyond the cell wrap around to its opposite side instead of growing it,
+    /// so neighbors are found across the faces of the cell.
+    /// Fails if the cell contains more positions than can be addressed.
+    pub fn periodic(cell: Finite3DDimension) -> Result<Self, DimensionError> {
+        Self::with_boundary(cell, Boundary::Periodic)
+    }
+
+    fn with_boundary(dim: Finite3DDimension, boundary: Boundary) -> Result<Self, DimensionError> {
+        let mut arr = Self::empty(Mapper::with_boundary(dim, boundary)?);
         arr.set_origin_block();
-        arr
+        Ok(arr)
+    }
+
+    /// An arrangement without blocks, which has to get at least one before it is used.
+    fn empty(mapper: Mapper) -> Self {
+        Self {
+            bitset: FixedBitSet::with_capacity(mapper.dimension().size().expect("The mapper checked the size.")),
+            num_blocks: BlockCount::default(),
+            center_off_mass: Point3D::default(),
+            mapper,
+            connectivity: Connectivity::default(),
+            colors: None,
+        }
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.mapper.boundary()
+    }
+
+    /// Sets which positions count as joined with a block when placing blocks and checking connectivity.
+    pub fn with_connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    pub fn connectivity(&self) -> Connectivity {
+        self.connectivity
+    }
+
+    /// Creates an arrangement containing exactly the given points inside a dimension that tightly
+    /// fits them. The arrangement is translated so that the first point lies at the origin.
+    /// Fails if there are no points or if they are not connected at their faces.
+    pub fn from_points(points: &[Point3D<i32>]) -> Result<Self, PlacementError> {
+        Self::from_points_joined(points, Connectivity::Face)
+    }
+
+    /// Like [BlockArrangement::from_points], but the points only have to be joined under the given
+    /// connectivity, which the arrangement keeps.
+    fn from_points_joined(points: &[Point3D<i32>], connectivity: Connectivity) -> Result<Self, PlacementError> {
+        let anchor = *points.first().ok_or(PlacementError::NoBlocks)?;
+        let points: HashSet<Point3D<i32>> = points.iter()
+            .map(|p| *p - anchor)
+            .collect();
+        if !Self::points_connected(&points, connectivity) {
+            return Err(PlacementError::NotAdjacentToBlock);
+        }
+        let (offset, dim) = points.iter()
+            .try_fold((Point3D::default(), Finite3DDimension::default()), |(corner, dim), p| dim.expand_to_include(corner, p))?;
+        let mut arr = Self::empty(Mapper::new(dim)?.with_offset(offset));
+        for p in &points {
+            let index = arr.mapper.unresolve(*p)
+                .expect("Save conversion since the dimension fits all points.");
+            arr.bitset.set(index, true);
+        }
+        arr.num_blocks = BlockCount::try_from(points.len()).map_err(|_| PlacementError::TooLarge)?;
+        arr.connectivity = connectivity;
+        arr.update_center_of_mass();
+        Ok(arr)
+    }
+
+    /// The same shape in its canonical form, which is the form [Polycube](crate::polycube::Polycube)s
+    /// keep: of the sorted block coordinates in every orientation, including reflections, the
+    /// lexicographically smallest, translated so the smallest block lies at the origin and stored in a
+    /// tightly fitting dimension, see [CanonicalForms]. Colors break ties and are kept, like the connectivity.
+    ///
+    /// Congruent arrangements have the same canonical form block for block, so two canonical forms are
+    /// compared without trying orientations by [EqualityStrategy::CanonicalForm](crate::congruence::EqualityStrategy::CanonicalForm),
+    /// and the bits of their [Occupancy](crate::polycube::Occupancy) are an exact key of the shape.
+    /// Searching the form takes all orientations, so keep it where shapes are compared repeatedly.
+    pub fn canonical(&self) -> Self {
+        self.canonical_one_sided().0
+    }
+
+    /// The [canonical form](BlockArrangement::canonical) together with the number of one sided shapes the
+    /// free shape stands for, which are found in the same search of the orientations.
+    pub fn canonical_one_sided(&self) -> (Self, usize) {
+        let forms = CanonicalForms::of(self);
+        let points = forms.points(Chirality::Free);
+        let mut canonical = Self::from_points_joined(points, self.connectivity)
+            .expect("Reorienting an arrangement keeps its blocks joined.");
+        for (p, color) in points.iter().zip(forms.colors(Chirality::Free)) {
+            canonical.set_color(p, *color).expect("Every canonical point is a block.");
+        }
+        (canonical, forms.one_sided_count())
+    }
+
+    /// Replaces the arrangement by its [canonical form](BlockArrangement::canonical).
+    pub fn canonicalize(&mut self) {
+        *self = self.canonical();
     }
 
     pub fn add_block_at(&mut self, point: &Point3D<i32>) -> Result<(), PlacementError> {
         if !self.has_neighbors(point) {
             return Err(PlacementError::NotAdjacentToBlock);
         }
-        for axis in Axis3D::iter() {
-            if !self.mapper.dimension().dim_in_bounds(point, axis) {
-                let positive_enlargement = *match axis {
-                    Axis3D::X => {point.x()}
-                    Axis3D::Y => {point.y()}
-                    Axis3D::Z => {point.z()}
-                } > 0;
-                self.grow(axis, positive_enlargement)
-            }
+        // Periodic arrangements wrap the point into their cell instead.
+        if self.boundary() == Boundary::Bounded && self.mapper.unresolve(*point).is_none() {
+            self.grow_to_include(point)?;
         }
         let index = self.mapper.unresolve(*point)
             .unwrap_or_else(|| panic!("Expected a save resolve from point {point} but was unsafe."));
         if !self.bitset[index] {
-            self.num_blocks += 1;
+            self.num_blocks = self.num_blocks.next().ok_or(PlacementError::TooLarge)?;
         }
         self.bitset.set(index, true);
         self.update_center_of_mass();
         Ok(())
     }
 
-    fn grow(&mut self, axis: Axis3D, positive: bool) {
-        use Axis3D::*;
-        let mut dim_clone = self.mapper.dimension();
-        match (axis, positive) {
-            (X, true) => {
-                dim_clone.set_x_pos((dim_clone.x_pos() + 1) * 2)
-            }
-            (X, false) => {
-                dim_clone.set_x_neg((dim_clone.x_neg() + 1) * 2)
-            }
-            (Y, true) => {
-                dim_clone.set_y_pos((dim_clone.y_pos() + 1) * 2)
+    /// Adds all points at once, which grows the dimension at most once to fit all of them and updates
+    /// the center of mass once. Every point has to be joined with a block of the arrangement, either
+    /// directly or through other points of the batch. Fails without changing the arrangement otherwise.
+    pub fn add_blocks(&mut self, points: &[Point3D<i32>]) -> Result<(), PlacementError> {
+        let mut pending: HashSet<_> = points.iter()
+            .map(|p| self.wrap(*p))
+            .filter(|p| !self.is_set(p))
+            .collect();
+        let mut joined: Vec<_> = pending.iter().copied().filter(|p| self.has_neighbors(p)).collect();
+        for p in &joined {
+            pending.remove(p);
+        }
+        let mut added = joined.clone();
+        while let Some(p) = joined.pop() {
+            for offset in self.connectivity.offsets() {
+                let neighbor = self.wrap(*offset + p);
+                if pending.remove(&neighbor) {
+                    joined.push(neighbor);
+                    added.push(neighbor);
+                }
             }
-            (Y, false) => {
-                dim_clone.set_y_neg((dim_clone.y_neg() + 1) * 2)
+        }
+        if !pending.is_empty() {
+            return Err(PlacementError::NotAdjacentToBlock);
+        }
+        let num_blocks = u32::try_from(added.len()).ok()
+            .and_then(|count| self.num_blocks.get().checked_add(count))
+            .map(BlockCount::new)
+            .ok_or(PlacementError::TooLarge)?;
+        if self.boundary() == Boundary::Bounded && added.iter().any(|p| self.mapper.unresolve(*p).is_none()) {
+            let (offset, dim) = added.iter()
+                .try_fold(self.stored_box(), |(corner, dim), p| dim.expand_to_include(corner, p))?;
+            self.resize(offset, dim)?;
+        }
+        for p in &added {
+            let index = self.mapper.unresolve(*p).expect("The dimension fits every added point.");
+            self.bitset.set(index, true);
+        }
+        self.num_blocks = num_blocks;
+        self.update_center_of_mass();
+        Ok(())
+    }
+
+    /// The point itself, or in a periodic arrangement the point wrapped into the unit cell.
+    fn wrap(&self, point: Point3D<i32>) -> Point3D<i32> {
+        match self.boundary() {
+            Boundary::Bounded => point,
+            Boundary::Periodic => self.mapper.unresolve(point)
+                .and_then(|index| self.mapper.resolve(index))
+                .expect("Every point wraps into a periodic dimension."),
+        }
+    }
+
+    /// Removes the block at the point. Fails if there is no block, if it is the last one or if the
+    /// other blocks would not be connected under the [Connectivity] of the arrangement anymore.
+    /// The dimension is kept, so the remaining blocks keep their coordinates.
+    pub fn remove_block_at(&mut self, point: &Point3D<i32>) -> Result<(), PlacementError> {
+        let index = self.mapper.unresolve(*point)
+            .filter(|index| self.bitset[*index])
+            .ok_or(PlacementError::Unoccupied)?;
+        if self.num_blocks == BlockCount::ONE {
+            return Err(PlacementError::NoBlocks);
+        }
+        let rest = self.bitset.ones()
+            .filter(|other| *other != index)
+            .map(|other| self.mapper.resolve(other).expect("Save conversion"))
+            .collect();
+        if !Self::points_connected(&rest, self.connectivity) {
+            return Err(PlacementError::Disconnects);
+        }
+        self.bitset.set(index, false);
+        self.num_blocks = self.num_blocks.previous().expect("There is more than one block.");
+        if let Some(colors) = self.colors.as_mut() {
+            colors[index] = 0;
+        }
+        self.update_center_of_mass();
+        Ok(())
+    }
+
+    /// The blocks together with their images under every transformation of the group around the center
+    /// of their bounding box, see [SymmetryGroup::orbit], which makes the group a subgroup of the
+    /// symmetries of the result. `None` if the union is not connected. Colors are not kept.
+    pub fn symmetrize(&self, group: &SymmetryGroup) -> Option<Self> {
+        Self::from_points(&group.orbit(&self.block_iter().collect::<Vec<_>>())).ok()
+    }
+
+    /// A copy with a block in every empty cell that is enclosed by the blocks, see [enclosed_cells].
+    /// The added blocks have color zero.
+    pub fn fill_holes(&self) -> Self {
+        let mut filled = self.clone();
+        filled.add_blocks(&enclosed_cells(self))
+            .expect("Every enclosed cell is next to a block or to another enclosed cell.");
+        filled
+    }
+
+    /// A copy keeping only the blocks with at least one face that is not shared with another block,
+    /// which includes the walls of enclosed cells, so filling the holes first keeps only the outer skin.
+    /// The blocks keep their coordinates and colors. `None` if the kept blocks are not connected.
+    pub fn shell(&self) -> Option<Self> {
+        let mut shell = self.clone();
+        let interior: Vec<_> = self.block_iter()
+            .filter(|p| Self::NEIGHBOR_OFFSETS.iter().all(|o| self.is_set(&(*o + *p))))
+            .collect();
+        for p in &interior {
+            let index = shell.mapper.unresolve(*p).expect("Every block is stored.");
+            shell.bitset.set(index, false);
+            if let Some(colors) = shell.colors.as_mut() {
+                colors[index] = 0;
             }
-            (Z, true) => {
-                dim_clone.set_z_pos((dim_clone.z_pos() + 1) * 2)
+        }
+        shell.num_blocks = BlockCount::try_from(self.num_blocks.as_usize() - interior.len())
+            .expect("Every shape has a block on its surface.");
+        shell.update_center_of_mass();
+        shell.is_connected().then_some(shell)
+    }
+
+    /// Returns every block that can be removed without splitting the arrangement, see [BlockArrangement::remove_block_at].
+    pub fn removable_blocks(&self) -> Vec<Point3D<i32>> {
+        if self.num_blocks == BlockCount::ONE {
+            return vec![];
+        }
+        self.block_iter()
+            .filter(|p| {
+                let rest = self.block_iter().filter(|other| other != p).collect();
+                Self::points_connected(&rest, self.connectivity)
+            })
+            .collect()
+    }
+
+    /// The smallest corner and the dimension of the box the blocks are stored in, in their current orientation.
+    fn stored_box(&self) -> (Point3D<i32>, Finite3DDimension) {
+        let last = self.mapper.dimension().size().expect("The mapper checked the size.") - 1;
+        let corners = [0, last].map(|index| self.mapper.resolve(index).expect("Save conversion"));
+        let min = Self::min_corner(corners.into_iter());
+        Finite3DDimension::default()
+            .expand_to_include(min, &Self::max_corner(corners.into_iter()))
+            .expect("The stored box has addressable lengths.")
+    }
+
+    /// Enlarges the stored box to contain the point, which lies next to it. The box grows by its whole
+    /// length along every axis the point is outside of, so repeated growth stays cheap.
+    fn grow_to_include(&mut self, point: &Point3D<i32>) -> Result<(), DimensionError> {
+        let (corner, dim) = self.stored_box();
+        let mut far = *point;
+        for axis in Axis3D::iter() {
+            let len = i32::try_from(dim.axis_len(axis)).map_err(|_| DimensionError::TooLarge)?;
+            if point[axis] < corner[axis] {
+                far[axis] = point[axis].checked_sub(len).ok_or(DimensionError::TooLarge)?;
+            } else if i64::from(point[axis]) >= i64::from(corner[axis]) + i64::from(len) {
+                far[axis] = point[axis].checked_add(len).ok_or(DimensionError::TooLarge)?;
             }
-            (Z, false) => {
-                dim_clone.set_z_neg((dim_clone.z_neg() + 1) * 2)
+        }
+        let (offset, dim) = dim.expand_to_include(corner, &far)?;
+        self.resize(offset, dim)
+    }
+
+    /// Moves the blocks into a larger box with the given smallest corner, keeping their coordinates.
+    /// The blocks are stored unoriented afterwards.
+    fn resize(&mut self, offset: Point3D<i32>, dim: Finite3DDimension) -> Result<(), DimensionError> {
+        let mut new_block = BlockArrangement::empty(Mapper::new(dim)?.with_offset(offset));
+        let mut colors = self.colors.as_ref().map(|_| vec![0; new_block.bitset.len()].into_boxed_slice());
+        for index in self.bitset.ones() {
+            let coordinate = self.mapper.resolve(index).expect("Save mappings expected");
+            let new_index = new_block.mapper.unresolve(coordinate).expect("Save mapping expected since it of larger capacity");
+            new_block.bitset.set(new_index, true);
+            if let Some(colors) = colors.as_mut() {
+                colors[new_index] = self.color_at(index);
             }
-        };
-        let mut new_block = BlockArrangement::with_capacity(dim_clone);
-        self.bitset.ones()
-            .map(|index| self.mapper.resolve(index).expect("Save mappings expected"))
-            .map(|coordinate| new_block.mapper.unresolve(coordinate).expect("Save mapping expected since it of larger capacity"))
-            .for_each(|index| new_block.bitset.set(index, true));
+        }
         new_block.num_blocks = self.num_blocks;
+        new_block.connectivity = self.connectivity;
+        new_block.colors = colors;
         *self = new_block;
+        Ok(())
     }
-    /// Returns true if the point has any neighbor blocks.
+    /// Returns true if the point has any neighbor blocks under the [Connectivity] of the arrangement.
     pub fn has_neighbors(&self, point: &Point3D<i32>) -> bool {
-        Self::NEIGHBOR_OFFSETS.iter().cloned()
+        self.connectivity.offsets().iter().cloned()
             .map(|offset| offset + *point)
             // Resolves the point to the corresponding index and filters only in bound indices.
             .filter_map(|coordinate| self.mapper.unresolve(coordinate))
             .any(|i| self.bitset[i])
     }
 
+    /// Returns every unset position joined with a block under the [Connectivity] of the arrangement,
+    /// each exactly once. In a periodic arrangement the positions are wrapped into the unit cell.
+    pub fn free_neighbors(&self) -> Vec<Point3D<i32>> {
+        let mut neighbors: Vec<_> = self.block_iter()
+            .flat_map(|p| self.connectivity.offsets().iter().map(move |o| *o + p))
+            .map(|p| match self.boundary() {
+                Boundary::Bounded => p,
+                Boundary::Periodic => self.mapper.unresolve(p)
+                    .and_then(|index| self.mapper.resolve(index))
+                    .expect("Every point wraps into a periodic dimension."),
+            })
+            .filter(|p| !self.is_set(p))
+            .collect();
+        neighbors.sort();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// Returns the number of block faces that are not shared with another block.
+    pub fn surface_area(&self) -> usize {
+        self.block_iter()
+            .flat_map(|p| Self::NEIGHBOR_OFFSETS.map(|o| o + p))
+            .filter(|p| !self.is_set(p))
+            .count()
+    }
+
     /// Updates the center off mass.
     fn update_center_of_mass(&mut self) {
         self.center_off_mass = self.center_of_mass();
     }
 
-    pub fn set_orientation(&mut self, orientation: Orientation) {
-        self.mapper.set_orientation(orientation);
+    pub fn set_orientation(&mut self, orientation: impl Into<Orientation48>) {
+        self.mapper.set_orientation(orientation.into());
         self.update_center_of_mass();
     }
 
+    /// Changes the [Orientation] moving the blocks like the current one, which is the first of
+    /// [rotations_and_reflections](crate::orientation::rotations_and_reflections) that does.
     pub fn orientation_mut<F: FnOnce(&mut Orientation)>(&mut self, f: F) {
-        f(self.mapper.orientation_mut());
+        let mut orientation = Orientation::from(self.mapper.orientation());
+        f(&mut orientation);
+        self.mapper.set_orientation(orientation.into());
         self.update_center_of_mass();
     }
 
-    /// Calculates the center of mass of the collection of blocks.
+    /// Calculates the center of mass of the collection of blocks, rounded to the nearest block with
+    /// halves rounded away from zero.
     /// If there are no blocks no center can be found.
     pub fn center_of_mass(&self) -> Point3D<i32> {
+        let count = i64::from(self.num_blocks.get());
+        let sum = self.block_sum();
+        let rounded = |v: i64| ((2 * v + v.signum() * count) / (2 * count)) as i32;
+        Point3D::new(rounded(sum[0]), rounded(sum[1]), rounded(sum[2]))
+    }
+
+    /// The sums of the coordinates of the blocks along the x, y and z axis.
+    fn block_sum(&self) -> [i64; 3] {
         self.block_iter()
-            .map(|p| p.map_all(Decimal::from))
-            .map(|p| (p, Decimal::ONE))
-            .reduce(|a, b| {
-            (a.0 + b.0, a.1 + b.1)
-        }).map(|(sum_p, count)| sum_p.map_all(|v| v / count))
-            .map(|dec_p| dec_p.map_all(|v| {
-                let rounded = v.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero);
-                rounded.to_i32().expect("No div by zero or out of bounds expected.")
-            }))
-            .expect("Save call since there is always at least one block_arrangement.")
+            .fold([0; 3], |[x, y, z], p| [x + i64::from(*p.x()), y + i64::from(*p.y()), z + i64::from(*p.z())])
     }
 
+    /// Iterates over the blocks in the order of their indices, which depends on the dimension and the
+    /// orientation of the arrangement, so the same blocks may be visited in another order after growing
+    /// or reorienting it. Use [BlockArrangement::block_iter_sorted] where the order is visible.
     pub fn block_iter(&self) -> impl Iterator<Item = Point3D<i32>> + '_ {
         self.bitset.ones()
             .map(move |index| self.mapper.resolve(index).expect("Expected save conversion"))
     }
 
+    /// The [fingerprint128](crate::block_hash::fingerprint128) of the blocks relative to the minimal
+    /// corner of their bounding box, equal for arrangements that only differ by a translation. The
+    /// fingerprint of a [Polycube](crate::polycube::Polycube) is the one of its canonical arrangement,
+    /// so it identifies the free shape.
+    pub fn fingerprint128(&self) -> u128 {
+        let blocks: Vec<_> = self.block_iter().collect();
+        let mut min = blocks[0];
+        for p in &blocks {
+            for axis in Axis3D::iter() {
+                min[axis] = min[axis].min(p[axis]);
+            }
+        }
+        crate::block_hash::fingerprint128(blocks.into_iter().map(|p| p - min))
+    }
+
+    /// Iterates over the blocks ordered by their x, then y, then z coordinate. The order only depends on
+    /// where the blocks are, so equal sets of blocks are always visited in the same order.
+    pub fn block_iter_sorted(&self) -> impl Iterator<Item = Point3D<i32>> {
+        let mut blocks: Vec<_> = self.block_iter().collect();
+        blocks.sort_unstable();
+        blocks.into_iter()
+    }
+
+    /// Returns the colors of the blocks in the order of [BlockArrangement::block_iter].
+    pub fn color_iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.bitset.ones().map(move |index| self.color_at(index))
+    }
+
+    /// The color of the block at the point, or `None` if there is no block.
+    /// Blocks are colored zero unless colored otherwise.
+    pub fn color(&self, point: &Point3D<i32>) -> Option<u8> {
+        self.mapper.unresolve(*point)
+            .filter(|index| self.bitset[*index])
+            .map(|index| self.color_at(index))
+    }
+
+    fn color_at(&self, index: usize) -> u8 {
+        self.colors.as_ref().map_or(0, |colors| colors[index])
+    }
+
+    /// Colors the block at the point, which makes equality compare colors as well.
+    /// Fails if there is no block at the point.
+    pub fn set_color(&mut self, point: &Point3D<i32>, color: u8) -> Result<(), PlacementError> {
+        let index = self.mapper.unresolve(*point)
+            .filter(|index| self.bitset[*index])
+            .ok_or(PlacementError::Unoccupied)?;
+        if color == 0 && self.colors.is_none() {
+            return Ok(());
+        }
+        let len = self.bitset.len();
+        self.colors.get_or_insert_with(|| vec![0; len].into_boxed_slice())[index] = color;
+        Ok(())
+    }
+
+    /// Checks if any block was colored with a color other than zero.
+    pub fn is_colored(&self) -> bool {
+        self.colors.is_some()
+    }
+
     /// Returns an iterator over the coordinates of the blocks. The coordinates are offset
     /// by the center of mass.
     pub fn center_mass_iter(&self) -> impl Iterator<Item = Point3D<i32>> + '_ {
@@ -207,16 +714,18 @@ impl BlockArrangement {
 
     /// Calculates the density of the blocks.
     /// It is the average distance to the center of mass.
+    #[cfg(feature = "decimal-metrics")]
     pub fn density(&self) -> Decimal {
         let sum: Decimal = self.center_mass_iter()
             .map(|p| p.distance_to_origin())
             .sum();
-        sum / Decimal::from(self.num_blocks)
+        sum / Decimal::from(self.num_blocks.get())
     }
 
     /// Calculates the alignment along the different axis.
     /// Returns an array of the alignment values with 0 being a straight line along the axis.
     /// The order is X Y Z.
+    #[cfg(feature = "decimal-metrics")]
     pub fn axis_alignments(&self) -> [Decimal; 3] {
         [
             self.axis_alignment(Axis3D::X),
@@ -228,30 +737,44 @@ impl BlockArrangement {
     /// Calculates the average distance of the block_arrangement to the specified axis.
     /// The lower the value the stronger the alignment.
     /// The Origin is set to the center of mass.
+    #[cfg(feature = "decimal-metrics")]
     fn axis_alignment(&self, axis: Axis3D) -> Decimal {
         let sum: Decimal = self.center_mass_iter()
-            .map(|point| {
-                let distance = match axis {
-                    Axis3D::X => {point.x()}
-                    Axis3D::Y => {point.y()}
-                    Axis3D::Z => {point.z()}
-                }.abs();
-                Decimal::from(distance)
-            })
+            .map(|point| Decimal::from(point[axis].abs()))
             .sum();
-        sum / Decimal::from(self.num_blocks)
+        sum / Decimal::from(self.num_blocks.get())
     }
 
-    fn set_origin_block(&mut self) {
-        self.bitset.set(self.mapper.unresolve(Point3D::default()).expect("Save conversion"), true);
-        self.num_blocks += 1;
+    /// The integer counterpart of the density: the sum of the squared distances of the blocks to their
+    /// exact center of mass, multiplied by the number of blocks. Unlike the density it is the same for
+    /// every orientation and position without rounding.
+    pub fn exact_density(&self) -> u64 {
+        let count = i64::from(self.num_blocks.get());
+        let squares: i64 = self.block_iter()
+            .map(|p| [*p.x(), *p.y(), *p.z()].map(i64::from).iter().map(|v| v * v).sum::<i64>())
+            .sum();
+        let sum = self.block_sum();
+        (count * squares - sum.iter().map(|v| v * v).sum::<i64>()) as u64
+    }
+
+    /// The integer counterpart of the axis alignments: the sum of the distances of the blocks to the plane
+    /// through the exact center of mass orthogonal to the axis, multiplied by the number of blocks.
+    /// The order is X Y Z.
+    pub fn exact_axis_alignments(&self) -> [u64; 3] {
+        let count = i64::from(self.num_blocks.get());
+        let sum = self.block_sum();
+        let mut alignments = [0; 3];
+        for p in self.block_iter() {
+            for (alignment, (v, sum)) in alignments.iter_mut().zip([*p.x(), *p.y(), *p.z()].into_iter().zip(sum)) {
+                *alignment += (count * i64::from(v) - sum).unsigned_abs();
+            }
+        }
+        alignments
     }
 
-    /// Returns the offset center off mass with the current mapper [Orientation] applied.
-    fn oriented_offset_center_of_mass(&self) -> Point3D<i32> {
-        let mut oriented_center = self.center_off_mass;
-        oriented_center.apply_orientation(&self.mapper.orientation());
-        oriented_center
+    fn set_origin_block(&mut self) {
+        self.bitset.set(self.mapper.unresolve(Point3D::default()).expect("Save conversion"), true);
+        self.num_blocks = BlockCount::ONE;
     }
 
     /// Checks if a block_arrangement at the point is set.
@@ -265,13 +788,102 @@ impl BlockArrangement {
     pub fn is_set_relative_to_center_of_mass(&self, point: &Point3D<i32>) -> bool {
         self.is_set(&(*point + self.center_off_mass))
     }
+
+    /// The dimension the blocks are currently stored in.
+    pub fn dimension(&self) -> Finite3DDimension {
+        self.mapper.dimension()
+    }
+
+    /// Returns the smallest dimension containing every block in the current orientation.
+    pub fn bounding_box(&self) -> Finite3DDimension {
+        Finite3DDimension::fitting(&self.block_iter().collect::<Vec<_>>())
+            .map(|(_, dim)| dim)
+            .expect("There is always at least one block.")
+    }
+
+    /// Finds the orientation and translation mapping this arrangement onto the other one, if they are
+    /// congruent including reflections: every block of this arrangement oriented by the orientation and
+    /// moved by the translation is a block of the other one, both in the coordinates of [BlockArrangement::block_iter].
+    /// Colors have to match as well if either arrangement is colored.
+    ///
+    /// The oriented blocks are slid over the other arrangement a row at a time with [RowMasks], and only
+    /// arrangements wider than [RowMasks::MAX_WIDTH] are compared block by block at their smallest corners.
+    pub fn congruence(&self, other: &Self) -> Option<(Orientation, Point3D<i32>)> {
+        if self.num_blocks != other.num_blocks {
+            return None;
+        }
+        let other_masks = RowMasks::new(other.block_iter());
+        let colored = self.is_colored() || other.is_colored();
+        OrientationRanking::global().find_map(|orientation| {
+            let oriented: Vec<_> = self.block_iter()
+                .map(|mut p| {
+                    p.apply_orientation(&orientation);
+                    p
+                })
+                .collect();
+            let translation = match (&other_masks, RowMasks::new(oriented.iter().copied())) {
+                (Some(other_masks), Some(masks)) => {
+                    // With the same number of blocks, covering the other blocks means matching them.
+                    let translation = masks.translations_into(other_masks).first().copied()?;
+                    if !colored {
+                        return Some((orientation, translation));
+                    }
+                    translation
+                }
+                _ => Self::min_corner(other.block_iter()) - Self::min_corner(oriented.iter().copied()),
+            };
+            oriented.iter().zip(self.color_iter())
+                .all(|(p, color)| match colored {
+                    true => other.color(&(*p + translation)) == Some(color),
+                    false => other.is_set(&(*p + translation)),
+                })
+                .then_some((orientation, translation))
+        })
+    }
+
+    /// The smallest coordinate on every axis.
+    pub(crate) fn min_corner(points: impl Iterator<Item = Point3D<i32>>) -> Point3D<i32> {
+        points.reduce(|a, b| Point3D::new(*a.x().min(b.x()), *a.y().min(b.y()), *a.z().min(b.z())))
+            .expect("There is always at least one block.")
+    }
+
+    /// The largest coordinate on every axis.
+    pub(crate) fn max_corner(points: impl Iterator<Item = Point3D<i32>>) -> Point3D<i32> {
+        points.reduce(|a, b| Point3D::new(*a.x().max(b.x()), *a.y().max(b.y()), *a.z().max(b.z())))
+            .expect("There is always at least one block.")
+    }
+
+    /// Checks if every block can be reached from every other block over joined positions
+    /// under the [Connectivity] of the arrangement.
+    pub fn is_connected(&self) -> bool {
+        Self::points_connected(&self.block_iter().collect(), self.connectivity)
+    }
+
+    fn points_connected(points: &HashSet<Point3D<i32>>, connectivity: Connectivity) -> bool {
+        let Some(start) = points.iter().next() else {
+            return true;
+        };
+        let mut visited = HashSet::from([*start]);
+        let mut queue = VecDeque::from([*start]);
+        while let Some(p) = queue.pop_front() {
+            for neighbor in connectivity.offsets().iter().map(|o| *o + p) {
+                if points.contains(&neighbor) && visited.insert(neighbor) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        visited.len() == points.len()
+    }
 }
 
 #[cfg(test)]
 mod block_arrangement_tests {
     use std::collections::HashSet;
+    use crate::congruence::EqualityStrategy;
     use crate::orientation::Orientation;
+    use crate::orientation::OrientationIterator;
     use crate::orientation::RotationAmount;
+    use crate::polycube::Polycube;
     use super::*;
 
     #[test]
@@ -279,19 +891,54 @@ mod block_arrangement_tests {
         let _block = BlockArrangement::new();
     }
 
+    #[test]
+    fn test_transformed_equality() {
+        use std::hash::{DefaultHasher, Hasher};
+        crate::transforms::assert_invariant("arrangement", BlockArrangement::clone);
+        crate::transforms::assert_invariant("hash", |ba| {
+            let mut hasher = DefaultHasher::new();
+            ba.hash(&mut hasher);
+            hasher.finish()
+        });
+    }
+
+    #[test]
+    fn test_hash_set() {
+        // The children of the tetracubes in all their orientations and positions are the 23 pentacubes.
+        let tetracubes = crate::polycubes::Polycubes::builtin(BlockCount::new(4)).unwrap();
+        let children: Vec<_> = tetracubes.iter()
+            .flat_map(|shape| block_variation::VariationGenerator::new(shape).collect::<Vec<_>>())
+            .collect();
+        let orientations: Vec<_> = OrientationIterator::default().collect();
+        let mut oriented = children.clone();
+        for (i, child) in children.iter().enumerate() {
+            let mut moved = BlockArrangement::from_points(&child.block_iter().map(|p| p + Point3D::new(i as i32, -3, 7)).collect::<Vec<_>>()).unwrap();
+            moved.set_orientation(orientations[i % orientations.len()]);
+            oriented.push(moved);
+        }
+        let unique: HashSet<_> = oriented.into_iter().collect();
+        assert_eq!(23, unique.len());
+
+        let mut colored = BlockArrangement::new();
+        colored.add_block_at(&Point3D::new(1, 0, 0)).unwrap();
+        let plain: HashSet<_> = [colored.clone()].into_iter().collect();
+        colored.set_color(&Point3D::new(1, 0, 0), 2).unwrap();
+        assert!(!plain.contains(&colored));
+    }
+
     #[test]
     fn test_num_blocks() {
         let mut blocks = BlockArrangement::new();
-        assert_eq!(1, blocks.num_blocks());
+        assert_eq!(BlockCount::new(1), blocks.num_blocks());
         blocks.add_block_at(&Point3D::new(1,0,0)).expect("Checked coordinates.");
         dbg!(blocks.block_iter().collect::<Vec<_>>());
-        assert_eq!(2, blocks.num_blocks());
+        assert_eq!(BlockCount::new(2), blocks.num_blocks());
         blocks.add_block_at(&Point3D::new(2,0,0)).expect("Checked coordinates.");
-        assert_eq!(3, blocks.num_blocks());
+        assert_eq!(BlockCount::new(3), blocks.num_blocks());
         dbg!(blocks.block_iter().collect::<Vec<_>>());
         assert!(blocks.has_neighbors(&Point3D::new(2,0,0)));
         blocks.add_block_at(&Point3D::new(2,0,0)).expect("Checked coordinates.");
-        assert_eq!(3, blocks.num_blocks());
+        assert_eq!(BlockCount::new(3), blocks.num_blocks());
     }
 
     #[test]
@@ -591,6 +1238,219 @@ mod block_arrangement_tests {
 
     }
 
+    #[test]
+    fn test_from_points() {
+        let points = [Point3D::new(2, 2, 2), Point3D::new(3, 2, 2), Point3D::new(3, 3, 2)];
+        let blocks = BlockArrangement::from_points(&points).expect("Connected points");
+        assert_eq!(BlockCount::new(3), blocks.num_blocks());
+        assert!(blocks.is_set(&Point3D::new(0, 0, 0)));
+        assert!(blocks.is_set(&Point3D::new(1, 0, 0)));
+        assert!(blocks.is_set(&Point3D::new(1, 1, 0)));
+        assert_eq!(Finite3DDimension::new(2, 2, 1), blocks.dimension());
+        assert!(blocks.is_connected());
+
+        let disconnected = [Point3D::new(0, 0, 0), Point3D::new(2, 0, 0)];
+        assert_eq!(Some(PlacementError::NotAdjacentToBlock), BlockArrangement::from_points(&disconnected).err());
+        assert_eq!(Some(PlacementError::NoBlocks), BlockArrangement::from_points(&[]).err());
+    }
+
+    #[test]
+    fn test_add_blocks() {
+        // Listed out of order, so the later points only join through the earlier ones.
+        let points = [Point3D::new(-1, 3, 0), Point3D::new(0, 3, 0), Point3D::new(0, 2, 0), Point3D::new(0, 1, 0), Point3D::new(0, 0, 0), Point3D::new(0, 0, -1)];
+        let mut batch = BlockArrangement::new();
+        batch.add_blocks(&points).expect("Connected points");
+        let mut single = BlockArrangement::new();
+        for p in points.iter().rev() {
+            single.add_block_at(p).expect("Connected points");
+        }
+        assert_eq!(BlockCount::new(6), batch.num_blocks());
+        assert_eq!(single.block_iter().collect::<HashSet<_>>(), batch.block_iter().collect::<HashSet<_>>());
+        assert_eq!(single.center_of_mass(), batch.center_of_mass());
+        assert_eq!(Finite3DDimension::new(2, 4, 2), batch.dimension());
+        batch.add_blocks(&[]).expect("Nothing to add");
+
+        let unchanged = batch.clone();
+        let detached = [Point3D::new(1, 0, 0), Point3D::new(3, 0, 0)];
+        assert_eq!(Some(PlacementError::NotAdjacentToBlock), batch.add_blocks(&detached).err());
+        assert_eq!(unchanged.block_iter().collect::<Vec<_>>(), batch.block_iter().collect::<Vec<_>>());
+        assert_eq!(unchanged.dimension(), batch.dimension());
+
+        let mut periodic = BlockArrangement::periodic(Finite3DDimension::new(4, 1, 1)).expect("Small cell");
+        periodic.add_blocks(&[Point3D::new(-1, 0, 0), Point3D::new(-2, 0, 0)]).expect("Joined across the cell");
+        assert_eq!(BlockCount::new(3), periodic.num_blocks());
+    }
+
+    #[test]
+    fn test_block_iter_sorted() {
+        let points = [Point3D::new(0, 0, 0), Point3D::new(0, 1, 0), Point3D::new(-1, 1, 0), Point3D::new(-1, 1, 1), Point3D::new(0, 0, -1)];
+        let mut grown = BlockArrangement::new();
+        for p in &points[1..] {
+            grown.add_block_at(p).expect("Connected points");
+        }
+        let fitted = BlockArrangement::from_points(&points).expect("Connected points");
+        let mut expected = points.to_vec();
+        expected.sort();
+        // The indices order the blocks by z first.
+        assert_ne!(expected, grown.block_iter().collect::<Vec<_>>());
+        assert_eq!(expected, grown.block_iter_sorted().collect::<Vec<_>>());
+        assert_eq!(expected, fitted.block_iter_sorted().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_free_neighbors() {
+        let mut blocks = BlockArrangement::new();
+        assert_eq!(6, blocks.free_neighbors().len());
+        blocks.add_block_at(&Point3D::new(1,0,0)).expect("Checked coordinates.");
+        assert_eq!(10, blocks.free_neighbors().len());
+        blocks.add_block_at(&Point3D::new(1,1,0)).expect("Checked coordinates.");
+        let neighbors = blocks.free_neighbors();
+        assert_eq!(13, neighbors.len());
+        assert!(neighbors.iter().all(|p| !blocks.is_set(p) && blocks.has_neighbors(p)));
+    }
+
+    #[test]
+    fn test_surface_area() {
+        let mut blocks = BlockArrangement::new();
+        assert_eq!(6, blocks.surface_area());
+        blocks.add_block_at(&Point3D::new(1,0,0)).expect("Checked coordinates.");
+        blocks.add_block_at(&Point3D::new(1,1,0)).expect("Checked coordinates.");
+        blocks.add_block_at(&Point3D::new(0,1,0)).expect("Checked coordinates.");
+        assert_eq!(16, blocks.surface_area());
+    }
+
+    #[test]
+    fn test_periodic() {
+        // A ring of three positions along the x axis.
+        let cell = Finite3DDimension::new(3, 1, 1);
+        let mut periodic = BlockArrangement::periodic(cell).expect("Addressable cell");
+        let bounded = BlockArrangement::with_capacity(cell).expect("Addressable dimension");
+        assert_eq!(Boundary::Periodic, periodic.boundary());
+        assert!(periodic.has_neighbors(&Point3D::new(2, 0, 0)));
+        assert!(!bounded.has_neighbors(&Point3D::new(2, 0, 0)));
+
+        periodic.add_block_at(&Point3D::new(-1, 0, 0)).expect("Adjacent across the cell face");
+        assert_eq!(cell, periodic.dimension());
+        assert_eq!(BlockCount::new(2), periodic.num_blocks());
+        assert!(periodic.is_set(&Point3D::new(2, 0, 0)));
+        assert_eq!(vec![Point3D::new(1, 0, 0)], periodic.free_neighbors());
+
+        periodic.add_block_at(&Point3D::new(4, 0, 0)).expect("Adjacent across the cell face");
+        assert_eq!(BlockCount::new(3), periodic.num_blocks());
+        // Every face of the filled cell touches a block of the neighboring cell.
+        assert_eq!(0, periodic.surface_area());
+        assert!(periodic.free_neighbors().is_empty());
+    }
+
+    #[test]
+    fn test_connectivity() {
+        let face = BlockArrangement::new();
+        let edge = BlockArrangement::new().with_connectivity(Connectivity::Edge);
+        let corner = BlockArrangement::new().with_connectivity(Connectivity::Corner);
+        assert_eq!([6, 18, 26], [&face, &edge, &corner].map(|ba| ba.free_neighbors().len()));
+        assert!(!face.has_neighbors(&Point3D::new(1, 1, 0)));
+        assert!(edge.has_neighbors(&Point3D::new(1, 1, 0)));
+        assert!(!edge.has_neighbors(&Point3D::new(1, 1, 1)));
+
+        let mut diagonal = corner.clone();
+        diagonal.add_block_at(&Point3D::new(-1, 1, -1)).expect("Joined at a corner");
+        assert_eq!(Connectivity::Corner, diagonal.connectivity());
+        assert!(diagonal.is_connected());
+        assert_eq!(Err(PlacementError::NotAdjacentToBlock), face.clone().add_block_at(&Point3D::new(-1, 1, -1)));
+        assert!(!diagonal.with_connectivity(Connectivity::Face).is_connected());
+    }
+
+    #[test]
+    fn test_colors() {
+        let mut blocks = BlockArrangement::new();
+        blocks.add_block_at(&Point3D::new(1, 0, 0)).expect("Save placement");
+        let uncolored = blocks.clone();
+        blocks.set_color(&Point3D::new(0, 0, 0), 0).expect("Block at the origin");
+        assert!(!blocks.is_colored());
+        blocks.set_color(&Point3D::new(1, 0, 0), 3).expect("Placed block");
+        assert_eq!(Err(PlacementError::Unoccupied), blocks.set_color(&Point3D::new(2, 0, 0), 1));
+        assert_eq!([Some(0), Some(3), None], [0, 1, 2].map(|x| blocks.color(&Point3D::new(x, 0, 0))));
+        assert_ne!(uncolored, blocks);
+
+        // The colors move with their blocks when the arrangement grows.
+        blocks.add_block_at(&Point3D::new(-1, 0, 0)).expect("Save placement");
+        assert_eq!(vec![0, 0, 3], blocks.color_iter().collect::<Vec<_>>());
+
+        let mut mirrored = BlockArrangement::new();
+        mirrored.add_block_at(&Point3D::new(1, 0, 0)).expect("Save placement");
+        mirrored.add_block_at(&Point3D::new(2, 0, 0)).expect("Save placement");
+        mirrored.set_color(&Point3D::new(0, 0, 0), 3).expect("Block at the origin");
+        assert_eq!(blocks, mirrored);
+        mirrored.set_color(&Point3D::new(1, 0, 0), 3).expect("Placed block");
+        assert_ne!(blocks, mirrored);
+    }
+
+    #[test]
+    fn test_remove_block_at() {
+        let points = [(0, 0, 0), (1, 0, 0), (2, 0, 0), (1, 1, 0)].map(|(x, y, z)| Point3D::new(x, y, z));
+        let mut t = BlockArrangement::from_points(&points).unwrap();
+        let center = t.block_iter().find(|p| t.block_iter().filter(|o| (*o - *p).manhattan_norm() == 1).count() == 3).unwrap();
+        assert_eq!(3, t.removable_blocks().len());
+        assert!(!t.removable_blocks().contains(&center));
+        assert_eq!(Err(PlacementError::Disconnects), t.remove_block_at(&center));
+        assert_eq!(Err(PlacementError::Unoccupied), t.remove_block_at(&(center + Point3D::new(0, 0, 1))));
+        let end = t.removable_blocks()[0];
+        t.remove_block_at(&end).unwrap();
+        assert_eq!(BlockCount::new(3), t.num_blocks());
+        assert!(!t.is_set(&end));
+        assert!(t.is_connected());
+
+        let mut single = BlockArrangement::new();
+        assert!(single.removable_blocks().is_empty());
+        assert_eq!(Err(PlacementError::NoBlocks), single.remove_block_at(&Point3D::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_congruence() {
+        let points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (1, 1, 1), (2, 1, 1)].map(|(x, y, z)| Point3D::new(x, y, z));
+        let ba = BlockArrangement::from_points(&points).unwrap();
+        let mut orientation = Orientation::default();
+        orientation.rotate(Axis3D::Z, RotationAmount::Ninety);
+        orientation.mirror(Axis3D::X);
+        let offset = Point3D::new(3, -2, 5);
+        let moved: Vec<_> = ba.block_iter()
+            .map(|mut p| {
+                p.apply_orientation(&orientation);
+                p + offset
+            })
+            .collect();
+        let other = BlockArrangement::from_points(&moved).unwrap();
+        let (found, translation) = ba.congruence(&other).expect("Congruent arrangements");
+        for mut p in ba.block_iter() {
+            p.apply_orientation(&found);
+            assert!(other.is_set(&(p + translation)));
+        }
+        assert!(found.is_reflection());
+
+        let mut grown = ba.clone();
+        grown.add_block_at(&Point3D::new(0, 1, 0)).unwrap();
+        assert_eq!(None, ba.congruence(&grown));
+        let line = BlockArrangement::from_points(&points.map(|p| Point3D::new(*p.x() + *p.y() + *p.z(), 0, 0))).unwrap();
+        assert_eq!(None, ba.congruence(&line));
+
+        let mut first = ba.block_iter().next().unwrap();
+        let mut colored = ba.clone();
+        colored.set_color(&first, 1).unwrap();
+        first.apply_orientation(&found);
+        let mut other_colored = other.clone();
+        other_colored.set_color(&(first + translation), 1).unwrap();
+        assert!(colored.congruence(&other_colored).is_some());
+        assert_eq!(None, colored.congruence(&other));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let mut blocks = BlockArrangement::new();
+        blocks.add_block_at(&Point3D::new(1,0,0)).expect("Checked coordinates.");
+        blocks.add_block_at(&Point3D::new(1,0,-1)).expect("Checked coordinates.");
+        assert_eq!(Finite3DDimension::new(2, 1, 2), blocks.bounding_box());
+    }
+
     #[test]
     fn test_serde() {
         let block = BlockArrangement::new();
@@ -604,6 +1464,106 @@ mod block_arrangement_tests {
             config
         ).expect("Expecting successful deserialization.");
         assert_eq!(block, new_block);
+
+        // The smallest corner of the stored box is kept apart from its lengths.
+        let moved = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(-1, 0, 0), Point3D::new(-1, -1, 0)]).unwrap();
+        let ser = bincode::serde::encode_to_vec(&moved, config).expect("Expecting successful serialization");
+        let (decoded, _): (BlockArrangement, _) = bincode::serde::decode_from_slice(&ser[..], config).expect("Expecting successful deserialization.");
+        assert_eq!(moved.block_iter().collect::<Vec<_>>(), decoded.block_iter().collect::<Vec<_>>());
+
+        // Inconsistent fields are rejected instead of panicking when the blocks are used.
+        let json = serde_json::to_value(&moved).unwrap();
+        let tampered = |pointer: &str, value: serde_json::Value| {
+            let mut json = json.clone();
+            *json.pointer_mut(pointer).unwrap() = value;
+            serde_json::from_value::<BlockArrangement>(json).map_err(|e| e.to_string())
+        };
+        assert_eq!(Err(StoredArrangementError::BlockCount.to_string()), tampered("/num_blocks", 4.into()));
+        assert_eq!(Err(StoredArrangementError::Dimension.to_string()), tampered("/mapper/dimension/width", 3.into()));
+        assert_eq!(Err(StoredArrangementError::Dimension.to_string()), tampered("/bitset/length", 1000.into()));
+        assert_eq!(Err(StoredArrangementError::Offset.to_string()), tampered("/mapper/offset/x", i32::MIN.into()));
+        // Comparing relies on the center of mass, which is recalculated.
+        assert_eq!(moved, tampered("/center_off_mass/x", 7.into()).unwrap());
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let config = bincode::config::standard();
+        // An L tromino as version 1 caches stored it, reaching one position into the negative x direction.
+        let mut bitset = FixedBitSet::with_capacity(4);
+        bitset.insert_range(1..4);
+        let old = |center: Point3D<i32>| (bitset.clone(), BlockCount::new(3), center, ([0u32, 1, 1, 0, 0, 0], Orientation::default()));
+        let bytes = bincode::serde::encode_to_vec(old(Point3D::default()), config).unwrap();
+        let (stored, _): (V1Arrangement, _) = bincode::serde::decode_from_slice(&bytes, config).unwrap();
+        let ba = BlockArrangement::try_from(stored).unwrap();
+        let expected = [Point3D::new(-1, 1, 0), Point3D::new(0, 0, 0), Point3D::new(0, 1, 0)];
+        assert_eq!(expected.to_vec(), ba.block_iter_sorted().collect::<Vec<_>>());
+
+        #[cfg(feature = "decimal-metrics")]
+        {
+            let bytes = bincode::serde::encode_to_vec(old(ba.center_off_mass), config).unwrap();
+            assert_eq!(bytes, bincode::serde::encode_to_vec(V1Arrangement::try_from(&ba).unwrap(), config).unwrap());
+            // A box away from the origin is enlarged to contain it.
+            let mut json = serde_json::to_value(&ba).unwrap();
+            json["mapper"]["offset"]["y"] = 3.into();
+            let away: BlockArrangement = serde_json::from_value(json).unwrap();
+            assert_eq!(Some(&Point3D::new(-1, 4, 0)), away.block_iter_sorted().collect::<Vec<_>>().first());
+            let bytes = bincode::serde::encode_to_vec(V1Arrangement::try_from(&away).unwrap(), config).unwrap();
+            let (stored, _): (V1Arrangement, _) = bincode::serde::decode_from_slice(&bytes, config).unwrap();
+            let decoded = BlockArrangement::try_from(stored).unwrap();
+            assert_eq!(away.block_iter_sorted().collect::<Vec<_>>(), decoded.block_iter_sorted().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        crate::transforms::assert_invariant("canonical form", |ba| ba.canonical().block_iter_sorted().collect::<Vec<_>>());
+        let mut l_tromino = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(1, 1, 0)]).unwrap();
+        l_tromino.set_color(&Point3D::new(1, 1, 0), 2).unwrap();
+        let canonical = l_tromino.canonical();
+        assert_eq!(Polycube::new(&l_tromino).unwrap().arrangement().block_iter_sorted().collect::<Vec<_>>(), canonical.block_iter_sorted().collect::<Vec<_>>());
+        assert_eq!(Point3D::new(0, 0, 0), canonical.block_iter_sorted().next().unwrap());
+        assert!(EqualityStrategy::CanonicalForm.congruent(&canonical, &canonical.canonical()));
+        let mut colors: Vec<_> = canonical.color_iter().collect();
+        colors.sort();
+        assert_eq!(vec![0, 0, 2], colors);
+        let mut copy = l_tromino.clone();
+        copy.set_orientation(OrientationIterator::default().nth(100).unwrap());
+        copy.canonicalize();
+        assert!(EqualityStrategy::CanonicalForm.congruent(&canonical, &copy));
+
+        // Diagonal neighbors stay joined.
+        let mut diagonal = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0)]).unwrap()
+            .with_connectivity(Connectivity::Edge);
+        diagonal.add_block_at(&Point3D::new(2, 1, 0)).unwrap();
+        assert_eq!(Connectivity::Edge, diagonal.canonical().connectivity());
+        assert_eq!(3, diagonal.canonical().num_blocks().get());
+    }
+
+    #[test]
+    fn test_fill_holes_and_shell() {
+        let cube = |len: u32| crate::placement::Region::cuboid([len; 3]).cells().collect::<Vec<_>>();
+        let mut solid = BlockArrangement::from_points(&cube(3)).unwrap();
+        solid.set_color(&Point3D::new(2, 2, 2), 3).unwrap();
+        let hollow = solid.shell().expect("Connected skin");
+        assert_eq!(26, hollow.num_blocks().get());
+        assert!(!hollow.is_set(&Point3D::new(1, 1, 1)));
+        assert_eq!(Some(3), hollow.color(&Point3D::new(2, 2, 2)));
+        assert_eq!(vec![Point3D::new(1, 1, 1)], enclosed_cells(&hollow));
+        let filled = hollow.fill_holes();
+        assert_eq!(27, filled.num_blocks().get());
+        assert_eq!(Some(0), filled.color(&Point3D::new(1, 1, 1)));
+        assert!(enclosed_cells(&filled).is_empty());
+        assert_eq!(hollow.block_iter().collect::<Vec<_>>(), hollow.shell().unwrap().block_iter().collect::<Vec<_>>());
+
+        // The walls of the cavity are not joined with the outer skin.
+        let mut with_cavity = BlockArrangement::from_points(&cube(7)).unwrap();
+        with_cavity.remove_block_at(&Point3D::new(3, 3, 3)).unwrap();
+        assert!(with_cavity.shell().is_none());
+        assert_eq!(7 * 7 * 7 - 5 * 5 * 5, with_cavity.fill_holes().shell().unwrap().num_blocks().get());
+        let line = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0)]).unwrap();
+        assert_eq!(line.block_iter().collect::<Vec<_>>(), line.shell().unwrap().block_iter().collect::<Vec<_>>());
+        assert_eq!(2, line.fill_holes().num_blocks().get());
     }
 
     #[test]
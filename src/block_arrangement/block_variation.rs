@@ -1,26 +1,166 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicBool, Ordering};
+use getset::CopyGetters;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
 use crate::block_arrangement::BlockArrangement;
-use crate::point::Point3D;
+use crate::block_hash::BlockHash;
+use crate::bloom::BloomFilter;
+use crate::level::Level;
+use crate::point::{Finite3DDimension, Point3D};
 
-/// Creates different variations of a [BlockArrangement] that has one more block.
+/// Counts the work done by one or more [VariationGenerator]s.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[derive(CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct VariationStats {
+    /// The free neighbor positions that were considered for the new block.
+    candidates: u64,
+    /// The candidates that were skipped because the position was already used by another variation.
+    skipped: u64,
+    /// The variations that were returned.
+    emitted: u64,
+    /// The candidates that were rejected because the variation would exceed the maximum number of layers.
+    pruned: u64,
+    /// The variations checked against a [BloomFilter], see [generate_variants_filtered].
+    filtered: u64,
+    /// The filtered variations the filter reported as probable duplicates, which were looked up.
+    probable_duplicates: u64,
+    /// The probable duplicates that turned out to be new shapes.
+    false_positives: u64,
+}
+
+impl VariationStats {
+    /// The share of the new shapes the [BloomFilter] reported as probable duplicates, `None` without a filter.
+    pub fn false_positive_rate(&self) -> Option<f64> {
+        let new = self.filtered - self.probable_duplicates + self.false_positives;
+        (self.filtered > 0).then(|| self.false_positives as f64 / new.max(1) as f64)
+    }
+}
+
+impl AddAssign for VariationStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.candidates += rhs.candidates;
+        self.skipped += rhs.skipped;
+        self.emitted += rhs.emitted;
+        self.pruned += rhs.pruned;
+        self.filtered += rhs.filtered;
+        self.probable_duplicates += rhs.probable_duplicates;
+        self.false_positives += rhs.false_positives;
+    }
+}
+
+/// The order a [VariationGenerator] visits the free neighbors of the blocks in. Both create the same
+/// variations; on release builds creating all variations of the 9-block shapes took the same time within
+/// a few percent with either order, so [NeighborOrder::Offsets] stays the default.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(EnumString, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum NeighborOrder {
+    /// The blocks in the order of their indices, and the neighbors of every block in the order of its
+    /// [Connectivity](crate::lattice::Connectivity) offsets. A position next to several blocks is visited
+    /// once per block.
+    #[default]
+    Offsets,
+    /// Every position once, in the order of its index in the stored box so the bitset is read in
+    /// ascending order. Positions outside of the box follow ordered by their coordinates.
+    Index,
+}
+
+/// Set by [NeighborOrder::set_global], `true` for [NeighborOrder::Index].
+static INDEX_ORDER: AtomicBool = AtomicBool::new(false);
+
+impl NeighborOrder {
+    /// The order of the generators that do not choose one with [VariationGenerator::with_neighbor_order].
+    pub fn global() -> Self {
+        match INDEX_ORDER.load(Ordering::Relaxed) {
+            true => NeighborOrder::Index,
+            false => NeighborOrder::Offsets,
+        }
+    }
+
+    pub fn set_global(order: NeighborOrder) {
+        INDEX_ORDER.store(order == NeighborOrder::Index, Ordering::Relaxed);
+    }
+
+    /// The free neighbors of the blocks in this order.
+    fn candidates(self, ba: &BlockArrangement) -> Box<dyn Iterator<Item = Point3D<i32>> + '_> {
+        let offsets = ba.connectivity().offsets();
+        let neighbors = ba.block_iter()
+            .flat_map(move |block_p| offsets.iter()
+                .map(move |o| *o + block_p))
+            .filter(|p| !ba.is_set(p));
+        match self {
+            NeighborOrder::Offsets => Box::new(neighbors),
+            NeighborOrder::Index => {
+                let mut neighbors: Vec<_> = neighbors
+                    .map(|p| (ba.mapper.unresolve(p).map_or((1, 0), |index| (0, index)), p))
+                    .collect();
+                neighbors.sort_unstable();
+                neighbors.dedup();
+                Box::new(neighbors.into_iter().map(|(_, p)| p))
+            }
+        }
+    }
+}
+
+/// Creates different variations of a [BlockArrangement] that has one more block joined under the
+/// [Connectivity](crate::lattice::Connectivity) of the arrangement.
 /// Generated variations are guaranteed to be unique against each other.
 pub struct VariationGenerator<'a> {
     original: &'a BlockArrangement,
     memory_block: BlockArrangement,
     new_block_pos_iter: Box<dyn Iterator<Item = Point3D<i32>> + 'a>,
+    stats: VariationStats,
+    /// The smallest and the largest corner of the blocks of the original.
+    bounds: (Point3D<i32>, Point3D<i32>),
+    max_layers: Option<u64>,
 }
 
 impl<'a> VariationGenerator<'a> {
+    /// Visits the free neighbors in the [global](NeighborOrder::global) order.
     pub fn new(ba: &'a BlockArrangement) -> Self {
-        let p_set = ba.block_iter()
-            .flat_map(|block_p| BlockArrangement::NEIGHBOR_OFFSETS
-                .map(|o| o + block_p))
-            .filter(|p| !ba.is_set(p));
         Self {
             original: ba,
             memory_block: ba.clone(),
-            new_block_pos_iter: Box::new(p_set),
+            new_block_pos_iter: NeighborOrder::global().candidates(ba),
+            stats: VariationStats::default(),
+            bounds: (BlockArrangement::min_corner(ba.block_iter()), BlockArrangement::max_corner(ba.block_iter())),
+            max_layers: None,
         }
     }
+
+    /// Visits the free neighbors in the given order instead. Call it before the first variation is created.
+    pub fn with_neighbor_order(mut self, order: NeighborOrder) -> Self {
+        self.new_block_pos_iter = order.candidates(self.original);
+        self
+    }
+
+    /// Only creates variations whose bounding box is at most `max_layers` long along its shortest axis.
+    /// Since adding blocks never shrinks a bounding box, no descendant of a rejected variation could
+    /// satisfy the limit either.
+    pub fn with_max_layers(mut self, max_layers: Option<u32>) -> Self {
+        self.max_layers = max_layers.map(u64::from);
+        self
+    }
+
+    fn exceeds_max_layers((min, max): &(Point3D<i32>, Point3D<i32>), max_layers: Option<u64>, p: &Point3D<i32>) -> bool {
+        let Some(max_layers) = max_layers else {
+            return false;
+        };
+        let (_, bounding_box) = Finite3DDimension::fitting(&[*min, *max, *p]).expect("There are points.");
+        let (x, y, z) = bounding_box.all_axis_len();
+        x.min(y).min(z) > max_layers
+    }
+
+    /// The work done so far.
+    pub fn stats(&self) -> VariationStats {
+        self.stats
+    }
 }
 
 
@@ -29,12 +169,20 @@ impl<'a> Iterator for VariationGenerator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         for p in self.new_block_pos_iter.by_ref() {
-            if !self.memory_block.is_set(&p) {
+            self.stats.candidates += 1;
+            if self.memory_block.is_set(&p) {
+                self.stats.skipped += 1;
+            } else {
                 self.memory_block.add_block_at(&p)
                     .unwrap_or_else(|_e| panic!("Expected save block placement at point {p} but wasn't"));
+                if Self::exceeds_max_layers(&self.bounds, self.max_layers, &p) {
+                    self.stats.pruned += 1;
+                    continue;
+                }
                 let mut new_block = self.original.clone();
                 new_block.add_block_at(&p)
                     .unwrap_or_else(|_e| panic!("Expected save block placement at point {p} but wasn't"));
+                self.stats.emitted += 1;
                 return Some(new_block);
             }
         }
@@ -42,10 +190,109 @@ impl<'a> Iterator for VariationGenerator<'a> {
     }
 }
 
+/// Deduplicates arrangements by their [BlockHash] like a map, but keeps distinct shapes whose hashes
+/// collide. The first shape of every hash is kept in one map and the others in lists of a second map,
+/// so shapes without collisions do not pay for a list.
+struct DedupMap {
+    shapes: HashMap<BlockHash, BlockArrangement>,
+    collided: HashMap<BlockHash, Vec<BlockArrangement>>,
+}
+
+impl DedupMap {
+    fn with_capacity(capacity: usize) -> Self {
+        Self { shapes: HashMap::with_capacity(capacity), collided: HashMap::new() }
+    }
+
+    /// Replaces a congruent arrangement with the same hash, or adds the arrangement.
+    fn insert(&mut self, hash: BlockHash, ba: BlockArrangement) {
+        match self.shapes.entry(hash) {
+            Entry::Vacant(entry) => {
+                entry.insert(ba);
+            }
+            Entry::Occupied(mut entry) if *entry.get() == ba => {
+                entry.insert(ba);
+            }
+            Entry::Occupied(_) => {
+                let others = self.collided.entry(hash).or_default();
+                match others.iter_mut().find(|other| **other == ba) {
+                    Some(same) => *same = ba,
+                    None => others.push(ba),
+                }
+            }
+        }
+    }
+
+    fn into_records(self) -> impl Iterator<Item = (BlockHash, BlockArrangement)> {
+        let collided = self.collided.into_iter()
+            .flat_map(|(hash, others)| others.into_iter().map(move |ba| (hash, ba)));
+        self.shapes.into_iter().chain(collided)
+    }
+}
+
+/// Generates variants of blocks from the given iterator and returns a set of those blocks.
+/// The parents may be borrowed or, like those read from a cache one at a time, owned.
+/// The deduplication map is pre-sized to the given capacity to avoid rehashing while it fills up.
+/// Also returns the combined [VariationStats] of the generators.
+/// See [VariationGenerator::with_max_layers] for the meaning of `max_layers`.
+pub fn generate_variants_from(
+    iter: impl Iterator<Item = impl Borrow<BlockArrangement>>,
+    capacity: usize,
+    max_layers: Option<u32>,
+) -> (Level, VariationStats) {
+    let mut dedup_map = DedupMap::with_capacity(capacity);
+    let mut stats = VariationStats::default();
+    for parent in iter {
+        let mut generator = VariationGenerator::new(parent.borrow()).with_max_layers(max_layers);
+        generator.by_ref().for_each(|ba| {
+            dedup_map.insert(BlockHash::from(&ba), ba);
+        });
+        stats += generator.stats();
+    }
+    // The entries are sorted once after the level is complete.
+    (Level::from_unsorted(dedup_map.into_records().collect()), stats)
+}
+
+/// Like [generate_variants_from], but consults a [BloomFilter] of the given number of bytes before the
+/// deduplication map. Variations the filter has not seen are new and are collected without a lookup, only the
+/// probable duplicates are deduplicated in a map and checked against the new ones once all are generated.
+/// Trades the memory of the filter for fewer lookups in a large map, and counts the probable duplicates
+/// that were new in the [VariationStats]. Returns the same level as [generate_variants_from].
+pub fn generate_variants_filtered(
+    iter: impl Iterator<Item = impl Borrow<BlockArrangement>>,
+    capacity: usize,
+    max_layers: Option<u32>,
+    filter_bytes: u64,
+) -> (Level, VariationStats) {
+    let mut filter = BloomFilter::new(filter_bytes, capacity);
+    let mut new = Vec::with_capacity(capacity);
+    let mut probable_duplicates = DedupMap::with_capacity(0);
+    let mut stats = VariationStats::default();
+    for parent in iter {
+        let mut generator = VariationGenerator::new(parent.borrow()).with_max_layers(max_layers);
+        generator.by_ref().for_each(|ba| {
+            let hash = BlockHash::from(&ba);
+            if filter.insert(&hash) {
+                probable_duplicates.insert(hash, ba);
+            } else {
+                new.push((hash, ba));
+            }
+        });
+        stats += generator.stats();
+    }
+    let new = Level::from_unsorted(new);
+    stats.filtered = stats.emitted;
+    stats.probable_duplicates = stats.emitted - new.len() as u64;
+    stats.false_positives = probable_duplicates.shapes.keys().filter(|hash| !new.contains_key(hash)).count() as u64;
+    // The probable duplicates come last, so the last variation of a shape is kept like in a map.
+    (Level::merge([new, probable_duplicates.into_records().collect()]), stats)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
+    use std::str::FromStr;
     use crate::block_hash::BlockHash;
+    use crate::lattice::Connectivity;
     use super::*;
 
     #[test]
@@ -91,4 +338,92 @@ mod tests {
         let expected_len = 13;
         assert_eq!(expected_len, variations.len());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stats() {
+        let mut block = BlockArrangement::new();
+        block.add_block_at(&Point3D::new(1,0,0)).expect("Save placement");
+        let mut generator = VariationGenerator::new(&block);
+        generator.by_ref().for_each(drop);
+        let stats = generator.stats();
+        // Each of the two blocks has five free neighbors and none are shared.
+        assert_eq!(10, stats.candidates());
+        assert_eq!(0, stats.skipped());
+        assert_eq!(10, stats.emitted());
+
+        let mut l_block = BlockArrangement::new();
+        l_block.add_block_at(&Point3D::new(1,0,0)).expect("Save placement");
+        l_block.add_block_at(&Point3D::new(0,1,0)).expect("Save placement");
+        let mut generator = VariationGenerator::new(&l_block);
+        generator.by_ref().for_each(drop);
+        let mut total = stats;
+        total += generator.stats();
+        // The corner between both arms is a neighbor of both arms.
+        assert_eq!(1, generator.stats().skipped());
+        assert_eq!(24, total.candidates());
+        assert_eq!(23, total.emitted());
+    }
+
+    #[test]
+    fn test_connectivity_variations() {
+        for (connectivity, positions, shapes) in [(Connectivity::Edge, 18, 2), (Connectivity::Corner, 26, 3)] {
+            let block = BlockArrangement::new().with_connectivity(connectivity);
+            let variations = VariationGenerator::new(&block).collect::<Vec<_>>();
+            assert_eq!(positions, variations.len());
+            assert!(variations.iter().all(|ba| ba.connectivity() == connectivity && ba.is_connected()));
+            assert_eq!(shapes, variations.into_iter().collect::<HashSet<_>>().len(), "Dicubes joined at a {connectivity}");
+        }
+    }
+
+    #[test]
+    fn test_max_layers() {
+        let mut l_block = BlockArrangement::new();
+        l_block.add_block_at(&Point3D::new(1,0,0)).expect("Save placement");
+        l_block.add_block_at(&Point3D::new(0,1,0)).expect("Save placement");
+        let mut generator = VariationGenerator::new(&l_block).with_max_layers(Some(1));
+        let variations: Vec<_> = generator.by_ref().collect();
+        // The six positions above and below the L leave the plane.
+        assert_eq!(6, generator.stats().pruned());
+        assert_eq!(7, variations.len());
+        assert!(variations.iter().all(|ba| ba.block_iter().all(|p| *p.z() == 0)));
+        assert_eq!(13, VariationGenerator::new(&l_block).with_max_layers(Some(2)).count());
+    }
+
+    #[test]
+    fn test_filtered_variants() {
+        let (dicube, _) = generate_variants_from([BlockArrangement::new()].iter(), 1, None);
+        let (tricubes, _) = generate_variants_from(dicube.values(), 2, None);
+        let (tetracubes, _) = generate_variants_from(tricubes.values(), 8, None);
+        let (expected, stats) = generate_variants_from(tetracubes.values(), 29, None);
+        assert_eq!(None, stats.false_positive_rate());
+        for bytes in [1, 1 << 16] {
+            let (level, stats) = generate_variants_filtered(tetracubes.values(), 29, None, bytes);
+            assert!(level.keys().eq(expected.keys()), "Filter of {bytes} bytes");
+            assert_eq!(stats.emitted(), stats.filtered());
+            assert!(stats.probable_duplicates() >= stats.emitted() - level.len() as u64);
+            let rate = stats.false_positive_rate().unwrap();
+            if bytes == 1 {
+                assert!(stats.false_positives() > 0 && rate > 0.0);
+            } else {
+                assert_eq!((0, 0.0), (stats.false_positives(), rate));
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbor_order() {
+        assert_eq!(NeighborOrder::Index, NeighborOrder::from_str("index").unwrap());
+        let mut l_block = BlockArrangement::new();
+        l_block.add_block_at(&Point3D::new(1,0,0)).expect("Save placement");
+        l_block.add_block_at(&Point3D::new(0,1,0)).expect("Save placement");
+        let mut generator = VariationGenerator::new(&l_block).with_neighbor_order(NeighborOrder::Index);
+        let variations: HashSet<_> = generator.by_ref().map(|ba| BlockHash::from(&ba)).collect();
+        // Every position is visited once, so the corner shared by both arms is not skipped.
+        assert_eq!(0, generator.stats().skipped());
+        assert_eq!(13, generator.stats().emitted());
+        let expected: HashSet<_> = VariationGenerator::new(&l_block).with_neighbor_order(NeighborOrder::Offsets)
+            .map(|ba| BlockHash::from(&ba))
+            .collect();
+        assert_eq!(expected, variations);
+    }
+}
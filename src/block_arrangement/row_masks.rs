@@ -0,0 +1,135 @@
+use crate::point::Point3D;
+
+/// A set of cells stored as one bit mask per row along the x axis, so two sets can be compared and
+/// slid against each other a whole row at a time. Bit `i` of the row at `y` and `z` is the cell at
+/// `x = i` relative to the smallest coordinates of the set.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RowMasks {
+    min: Point3D<i32>,
+    size: [usize; 3],
+    /// The rows ordered by z and then by y.
+    rows: Vec<u64>,
+}
+
+impl RowMasks {
+    /// The widest set along the x axis that fits into the masks.
+    pub const MAX_WIDTH: usize = u64::BITS as usize;
+
+    /// Returns `None` for an empty set or one that is wider than [RowMasks::MAX_WIDTH].
+    pub fn new(cells: impl IntoIterator<Item = Point3D<i32>>) -> Option<Self> {
+        let cells: Vec<_> = cells.into_iter().collect();
+        let first = *cells.first()?;
+        let (min, max) = cells.iter().fold((first, first), |(min, max), p| (
+            Point3D::new(*min.x().min(p.x()), *min.y().min(p.y()), *min.z().min(p.z())),
+            Point3D::new(*max.x().max(p.x()), *max.y().max(p.y()), *max.z().max(p.z())),
+        ));
+        let size = [max.x() - min.x(), max.y() - min.y(), max.z() - min.z()].map(|length| length as usize + 1);
+        if size[0] > Self::MAX_WIDTH {
+            return None;
+        }
+        let mut rows = vec![0; size[1] * size[2]];
+        for p in cells {
+            let offset = p - min;
+            rows[*offset.z() as usize * size[1] + *offset.y() as usize] |= 1 << offset.x();
+        }
+        Some(Self { min, size, rows })
+    }
+
+    /// The smallest coordinate of the set on every axis.
+    pub fn min_corner(&self) -> Point3D<i32> {
+        self.min
+    }
+
+    /// The width, depth and height of the bounding box.
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    /// The mask of the row at the given y and z relative to [RowMasks::min_corner].
+    pub fn row(&self, y: usize, z: usize) -> u64 {
+        self.rows[z * self.size[1] + y]
+    }
+
+    /// Every translation moving all cells of this set onto cells of the other one, ordered by z, y and then x.
+    ///
+    /// Rows are compared bit-parallel: for each shift along y and z, the possible shifts along x start as
+    /// one bit each, and every cell of a row keeps only the shifts for which the other row, shifted back
+    /// by the position of the cell, has a cell as well.
+    pub fn translations_into(&self, other: &Self) -> Vec<Point3D<i32>> {
+        let [width, depth, height] = self.size;
+        let [other_width, other_depth, other_height] = other.size;
+        if width > other_width || depth > other_depth || height > other_height {
+            return vec![];
+        }
+        let all_shifts = u64::MAX >> (Self::MAX_WIDTH - 1 - (other_width - width));
+        let mut translations = vec![];
+        for dz in 0..=other_height - height {
+            for dy in 0..=other_depth - depth {
+                let mut shifts = all_shifts;
+                'rows: for z in 0..height {
+                    for y in 0..depth {
+                        let target = other.row(y + dy, z + dz);
+                        let mut row = self.row(y, z);
+                        while row != 0 {
+                            shifts &= target >> row.trailing_zeros();
+                            row &= row - 1;
+                        }
+                        if shifts == 0 {
+                            break 'rows;
+                        }
+                    }
+                }
+                while shifts != 0 {
+                    let dx = shifts.trailing_zeros() as i32;
+                    translations.push(other.min - self.min + Point3D::new(dx, dy as i32, dz as i32));
+                    shifts &= shifts - 1;
+                }
+            }
+        }
+        translations
+    }
+
+    /// Checks if both sets have the same cells after moving their smallest coordinates onto each other.
+    pub fn same_shape(&self, other: &Self) -> bool {
+        self.size == other.size && self.rows == other.rows
+    }
+}
+
+#[cfg(test)]
+mod row_masks_tests {
+    use super::*;
+
+    fn masks(points: &[(i32, i32, i32)]) -> RowMasks {
+        RowMasks::new(points.iter().map(|(x, y, z)| Point3D::new(*x, *y, *z))).unwrap()
+    }
+
+    #[test]
+    fn test_translations_into() {
+        let l_tromino = masks(&[(5, 5, 5), (6, 5, 5), (5, 6, 5)]);
+        assert_eq!([2, 2, 1], l_tromino.size());
+        assert_eq!(0b11, l_tromino.row(0, 0));
+        let mut cells = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                cells.push((x, y, 0));
+            }
+        }
+        let square = masks(&cells);
+        let translations = l_tromino.translations_into(&square);
+        assert_eq!(4, translations.len());
+        assert_eq!(Point3D::new(-5, -5, -5), translations[0]);
+        assert_eq!(Point3D::new(-4, -4, -5), translations[3]);
+
+        let ring = masks(&[(0, 0, 0), (1, 0, 0), (2, 0, 0), (0, 1, 0), (2, 1, 0)]);
+        assert_eq!(vec![Point3D::new(-5, -5, -5)], l_tromino.translations_into(&ring));
+        assert!(square.translations_into(&l_tromino).is_empty());
+
+        let wide: Vec<_> = (0..64).map(|x| (x, 0, 0)).collect();
+        assert_eq!(vec![Point3D::new(0, 0, 0)], masks(&wide).translations_into(&masks(&wide)));
+        assert_eq!(64, masks(&[(0, 0, 0)]).translations_into(&masks(&wide)).len());
+        assert_eq!(None, RowMasks::new((0..65).map(|x| Point3D::new(x, 0, 0))));
+        assert_eq!(None, RowMasks::new([]));
+        assert!(l_tromino.same_shape(&masks(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)])));
+        assert!(!l_tromino.same_shape(&masks(&[(0, 0, 0), (1, 0, 0), (1, 1, 0)])));
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::{Display, Formatter};
+use std::num::{ParseIntError, TryFromIntError};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// The number of blocks of a shape, which also names the level and the cache the shape belongs to.
+///
+/// Kept apart from the `usize` positions and lengths of collections of shapes so neither can be passed
+/// where the other is expected. Serialized as the bare number, which stored caches written with a `u8`
+/// count decode to as well.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockCount(u32);
+
+impl BlockCount {
+    pub const ONE: Self = Self(1);
+    /// The largest number of blocks a shape can have.
+    pub const MAX: Self = Self(u32::MAX);
+
+    pub const fn new(count: u32) -> Self {
+        Self(count)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The count as a length, which is lossless on every platform with at least 32 bit pointers.
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The count of a shape with one more block, `None` beyond [BlockCount::MAX].
+    pub fn next(self) -> Option<Self> {
+        self.0.checked_add(1).map(Self)
+    }
+
+    /// The count of a shape with one block less, `None` below zero.
+    pub fn previous(self) -> Option<Self> {
+        self.0.checked_sub(1).map(Self)
+    }
+
+    /// Every count from this one up to and including the last one.
+    pub fn up_to(self, last: Self) -> impl DoubleEndedIterator<Item = Self> {
+        (self.0..=last.0).map(Self)
+    }
+}
+
+impl Display for BlockCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for BlockCount {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl From<u8> for BlockCount {
+    fn from(count: u8) -> Self {
+        Self(count.into())
+    }
+}
+
+impl From<u16> for BlockCount {
+    fn from(count: u16) -> Self {
+        Self(count.into())
+    }
+}
+
+impl From<u32> for BlockCount {
+    fn from(count: u32) -> Self {
+        Self(count)
+    }
+}
+
+/// Fails for more than [BlockCount::MAX] blocks.
+impl TryFrom<usize> for BlockCount {
+    type Error = TryFromIntError;
+
+    fn try_from(count: usize) -> Result<Self, Self::Error> {
+        u32::try_from(count).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod block_count_tests {
+    use super::*;
+
+    #[test]
+    fn test_block_count() {
+        let count = BlockCount::from(7u8);
+        assert_eq!(Some(BlockCount::new(8)), count.next());
+        assert_eq!(None, BlockCount::MAX.next());
+        assert_eq!(None, BlockCount::default().previous());
+        assert_eq!(vec![5, 6, 7], BlockCount::new(5).up_to(count).map(BlockCount::as_usize).collect::<Vec<_>>());
+        assert!(BlockCount::try_from(usize::MAX).is_err());
+        assert_eq!(Ok(BlockCount::new(70_000)), "70000".parse());
+
+        // A count stored as u8 by earlier versions decodes to the same count.
+        let config = bincode::config::standard();
+        let stored = bincode::serde::encode_to_vec(7u8, config).unwrap();
+        assert_eq!(stored, bincode::serde::encode_to_vec(count, config).unwrap());
+        let (decoded, _): (BlockCount, _) = bincode::serde::decode_from_slice(&stored, config).unwrap();
+        assert_eq!(count, decoded);
+    }
+}
@@ -1,27 +1,61 @@
+use std::ops::RangeInclusive;
 use getset::CopyGetters;
+#[cfg(feature = "decimal-metrics")]
 use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_128;
 use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::point::Point3D;
+
+/// The type of the density and the axis alignments of a [BlockHash]: the averages rounded to five decimal
+/// places with the `decimal-metrics` feature, the exact integers of [BlockArrangement::exact_density] and
+/// [BlockArrangement::exact_axis_alignments] without it. Either identifies the same shapes, but hashes
+/// computed with and without the feature differ.
+#[cfg(feature = "decimal-metrics")]
+pub type Metric = Decimal;
+#[cfg(not(feature = "decimal-metrics"))]
+pub type Metric = u64;
+
+#[cfg(feature = "decimal-metrics")]
+const METRIC_BOUNDS: (Metric, Metric) = (Decimal::MIN, Decimal::MAX);
+#[cfg(not(feature = "decimal-metrics"))]
+const METRIC_BOUNDS: (Metric, Metric) = (u64::MIN, u64::MAX);
 
 /// A hash like value for a [BlockArrangement].
 /// The values aim to uniquely identify a Block arrangement independent of any mirroring or
 /// rotational symmetry.
+///
+/// Hashes are ordered by the number of blocks first, then by the volume of the bounding box and then
+/// by the remaining invariants. So the hashes of all shapes of one size are contiguous in a sorted
+/// store like a [Level](crate::level::Level) or a `BTreeMap`, with the most compact shapes first, and
+/// a single range query of [BlockHash::all_with] finds them.
 #[derive(Eq, PartialEq, Default, Hash, Copy, Clone, Ord, PartialOrd, Debug)]
 #[derive(CopyGetters)]
 #[derive(Serialize, Deserialize)]
 pub struct BlockHash {
     #[get_copy = "pub"]
-    num_blocks: u8,
+    num_blocks: BlockCount,
+    /// The number of cells of the bounding box, which does not depend on the orientation.
+    #[get_copy = "pub"]
+    volume: u64,
     /// A measure for how close blocks are to the center of mass.
     #[get_copy = "pub"]
-    #[serde(with = "rust_decimal::serde::str")]
-    density: Decimal,
+    #[cfg_attr(feature = "decimal-metrics", serde(with = "rust_decimal::serde::str"))]
+    density: Metric,
     /// Sorted by size for consistency.
     #[get_copy = "pub"]
-    axis_alignments: [Decimal; 3]
+    axis_alignments: [Metric; 3]
 }
 
 impl BlockHash {
+    /// The range of every possible hash of a shape with the given number of blocks.
+    pub fn all_with(num_blocks: BlockCount) -> RangeInclusive<Self> {
+        let bound = |volume, metric| Self { num_blocks, volume, density: metric, axis_alignments: [metric; 3] };
+        bound(0, METRIC_BOUNDS.0)..=bound(u64::MAX, METRIC_BOUNDS.1)
+    }
+
+    #[cfg(feature = "decimal-metrics")]
     fn round(&mut self) {
         let default_round = |dec: &mut Decimal| {
             *dec = dec.round_dp_with_strategy(5, RoundingStrategy::MidpointAwayFromZero)
@@ -33,23 +67,83 @@ impl BlockHash {
 }
 
 impl From<&BlockArrangement> for BlockHash {
+    /// The decimal metrics are measured from the center of mass rounded to a block, which depends on
+    /// the orientation, so they are computed for the [canonical](BlockArrangement::canonical) form.
+    #[cfg(feature = "decimal-metrics")]
     fn from(ba: &BlockArrangement) -> Self {
+        let ba = &ba.canonical();
         let mut alignment = ba.axis_alignments();
         alignment.sort();
+        let (width, depth, height) = ba.bounding_box().all_axis_len();
         let mut hash = Self {
             num_blocks: ba.num_blocks(),
+            volume: width * depth * height,
             density: ba.density(),
             axis_alignments: alignment,
         };
         hash.round();
         hash
     }
+
+    #[cfg(not(feature = "decimal-metrics"))]
+    fn from(ba: &BlockArrangement) -> Self {
+        let mut alignment = ba.exact_axis_alignments();
+        alignment.sort();
+        let (width, depth, height) = ba.bounding_box().all_axis_len();
+        Self {
+            num_blocks: ba.num_blocks(),
+            volume: width * depth * height,
+            density: ba.exact_density(),
+            axis_alignments: alignment,
+        }
+    }
+}
+
+/// The key in front of every arrangement of a [CacheFormat::V1](crate::cache::CacheFormat::V1) file,
+/// which is a [BlockHash] as it was stored before it contained the volume. Without the `decimal-metrics`
+/// feature the decimals are only read as text, so such keys can neither be written nor checked.
+#[derive(Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct V1BlockHash {
+    num_blocks: BlockCount,
+    #[cfg(feature = "decimal-metrics")]
+    #[serde(with = "rust_decimal::serde::str")]
+    density: Decimal,
+    #[cfg(feature = "decimal-metrics")]
+    axis_alignments: [Decimal; 3],
+    #[cfg(not(feature = "decimal-metrics"))]
+    density: String,
+    #[cfg(not(feature = "decimal-metrics"))]
+    axis_alignments: [String; 3],
+}
+
+#[cfg(feature = "decimal-metrics")]
+impl From<&BlockHash> for V1BlockHash {
+    fn from(hash: &BlockHash) -> Self {
+        Self { num_blocks: hash.num_blocks, density: hash.density, axis_alignments: hash.axis_alignments }
+    }
+}
+
+/// A 128 bit xxh3 hash of the sorted points, so it is equal for the same points in any order. Unlike a
+/// [BlockHash] it does not identify a shape up to its symmetries or translations, but two different sets
+/// of points practically never share it, which makes it a compact key where the points do not have to be
+/// recovered. Normalize the points first to compare shapes at different positions.
+pub fn fingerprint128(points: impl IntoIterator<Item = Point3D<i32>>) -> u128 {
+    let mut points: Vec<_> = points.into_iter().collect();
+    points.sort_unstable();
+    let bytes: Vec<u8> = points.iter()
+        .flat_map(|p| [*p.x(), *p.y(), *p.z()])
+        .flat_map(i32::to_le_bytes)
+        .collect();
+    xxh3_128(&bytes)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+    use crate::block_arrangement::block_variation::VariationGenerator;
     use crate::orientation::OrientationIterator;
-    use crate::point::Point3D;
+    use crate::polycube::Polycube;
     use super::*;
 
     #[test]
@@ -66,6 +160,30 @@ mod tests {
             })
     }
 
+    #[test]
+    fn test_transformed_hashing() {
+        crate::transforms::assert_invariant("block hash", |ba| BlockHash::from(ba));
+        crate::transforms::assert_invariant("fingerprint", |ba| Polycube::new(ba).unwrap().fingerprint128());
+    }
+
+    #[test]
+    fn test_exact_metrics() {
+        crate::transforms::assert_invariant("exact density", |ba| ba.exact_density());
+        crate::transforms::assert_invariant("exact axis alignments", |ba| {
+            let mut alignments = ba.exact_axis_alignments();
+            alignments.sort();
+            alignments
+        });
+        let line = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(2, 0, 0)]).unwrap();
+        // Three times the squared distances 1, 0 and 1 to the center, and three times the distances to its plane.
+        assert_eq!(6, line.exact_density());
+        assert_eq!([6, 0, 0], line.exact_axis_alignments());
+        assert_eq!(Point3D::new(1, 0, 0), line.center_of_mass());
+        // Halves are rounded away from zero.
+        let domino = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0)]).unwrap();
+        assert_eq!(Point3D::new(1, 0, 0), domino.center_of_mass());
+    }
+
     #[test]
     fn test_serde() {
         let mut block = BlockArrangement::new();
@@ -81,4 +199,39 @@ mod tests {
             .expect("Expecting save decoding.");
         assert_eq!(hash, deser_hash);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ordering() {
+        let mut map = BTreeMap::new();
+        let mut level = vec![BlockArrangement::new()];
+        for _ in 0..4 {
+            level.iter().for_each(|ba| {
+                map.insert(BlockHash::from(ba), ba.clone());
+            });
+            level = level.iter().flat_map(VariationGenerator::new).collect();
+        }
+        let trominoes: Vec<_> = map.range(BlockHash::all_with(BlockCount::new(3))).map(|(hash, _)| *hash).collect();
+        // The line fits into a box of three cells, the L needs four.
+        assert_eq!(vec![3, 4], trominoes.iter().map(BlockHash::volume).collect::<Vec<_>>());
+        assert!(trominoes.iter().all(|hash| hash.num_blocks() == BlockCount::new(3)));
+        assert_eq!(1, map.range(BlockHash::all_with(BlockCount::new(1))).count());
+        assert!(map.keys().zip(map.keys().skip(1)).all(|(a, b)| (a.num_blocks(), a.volume()) <= (b.num_blocks(), b.volume())));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let points = [Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(1, 1, 0)];
+        let shifted = points.map(|p| p + Point3D::new(-3, 5, 2));
+        let a = BlockArrangement::from_points(&points).unwrap();
+        let b = BlockArrangement::from_points(&shifted).unwrap();
+        assert_eq!(a.fingerprint128(), b.fingerprint128());
+        assert_eq!(fingerprint128(points), fingerprint128(points.into_iter().rev()));
+        assert_ne!(fingerprint128(points), fingerprint128(shifted));
+
+        let line = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(2, 0, 0)]).unwrap();
+        assert_ne!(a.fingerprint128(), line.fingerprint128());
+        let mut rotated = a.clone();
+        rotated.set_orientation(OrientationIterator::default().nth(5).unwrap());
+        assert_eq!(Polycube::new(&a).unwrap().fingerprint128(), Polycube::new(&rotated).unwrap().fingerprint128());
+    }
+}
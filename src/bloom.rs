@@ -0,0 +1,83 @@
+//! A bloom filter over [BlockHash]es, which lets the deduplication of a level tell most new shapes apart
+//! from duplicates without looking them up, see
+//! [generate_variants_filtered](crate::block_arrangement::block_variation::generate_variants_filtered).
+
+use std::hash::Hash;
+use xxhash_rust::xxh3::Xxh3;
+use crate::block_hash::BlockHash;
+
+/// The most hash functions a filter uses, which a very large filter for few items would otherwise exceed.
+const MAX_HASHES: u32 = 16;
+
+/// Remembers which hashes were inserted in a fixed number of bits. A hash that was inserted is always
+/// reported as such, while a new hash is reported as inserted with a probability that grows with the
+/// number of inserted hashes per bit.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// A filter of the given number of bytes, at least eight, using the number of hash functions that
+    /// reports the fewest new hashes as inserted once the expected number of hashes is inserted.
+    pub fn new(bytes: u64, expected_items: usize) -> Self {
+        let words = bytes.div_ceil(8).max(1) as usize;
+        let bits_per_item = (words * 64) as f64 / expected_items.max(1) as f64;
+        let hashes = (bits_per_item * std::f64::consts::LN_2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+        Self { bits: vec![0; words], hashes }
+    }
+
+    /// The number of bytes of the filter.
+    pub fn bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// The number of bits set per hash.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Inserts the hash and returns whether it may have been inserted before. `false` means it is new.
+    pub fn insert(&mut self, hash: &BlockHash) -> bool {
+        let mut hasher = Xxh3::new();
+        hash.hash(&mut hasher);
+        let digest = hasher.digest128();
+        // Double hashing derives every bit from two halves of one digest.
+        let (first, second) = (digest as u64, (digest >> 64) as u64 | 1);
+        let len = self.bits.len() as u64 * 64;
+        let mut present = true;
+        for i in 0..u64::from(self.hashes) {
+            let bit = first.wrapping_add(i.wrapping_mul(second)) % len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            present &= self.bits[word] & mask != 0;
+            self.bits[word] |= mask;
+        }
+        present
+    }
+}
+
+#[cfg(test)]
+mod bloom_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::point::Point3D;
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let hashes: Vec<_> = (1..=200)
+            .map(|len| BlockHash::from(&BlockArrangement::from_points(&(0..len).map(|x| Point3D::new(x, 0, 0)).collect::<Vec<_>>()).unwrap()))
+            .collect();
+        let mut filter = BloomFilter::new(1024, hashes.len());
+        assert_eq!(1024, filter.bytes());
+        assert_eq!(16, filter.hashes());
+        assert_eq!(0, hashes.iter().filter(|hash| filter.insert(hash)).count());
+        assert!(hashes.iter().all(|hash| filter.insert(hash)));
+
+        // A single word is soon full, so every hash is reported as inserted.
+        let mut tiny = BloomFilter::new(1, hashes.len());
+        assert_eq!((8, 1), (tiny.bytes(), tiny.hashes()));
+        let reported = hashes.iter().filter(|hash| tiny.insert(hash)).count();
+        assert!(reported > 100, "{reported}");
+    }
+}
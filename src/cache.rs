@@ -0,0 +1,964 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::thread;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+use crate::block_arrangement::{BlockArrangement, V1Arrangement};
+use crate::block_count::BlockCount;
+use crate::block_hash::{BlockHash, V1BlockHash};
+use crate::cache::ids::ShapeId;
+use crate::cache::manifest::{CacheManifest, ManifestEntry};
+use crate::level::Level;
+use crate::poly_tree::PolyTree;
+use crate::polycube::{Occupancy, Polycube};
+use crate::shape_ref::ShapeRefs;
+use crate::store::{replace_file, CacheStore};
+
+pub mod checkpoint;
+pub mod combined;
+pub mod ids;
+pub mod index;
+pub mod manifest;
+pub mod sharded;
+pub mod verify;
+
+/// The layouts a cache file can have.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum CacheFormat {
+    /// The whole map of hashes and arrangements encoded at once. The arrangements keep the layout they had
+    /// when the format was introduced, whose box always contains the origin, so old files stay readable.
+    /// The stored hashes lack the volume of the current [BlockHash], so they are recalculated on load as well.
+    V1,
+    /// A header followed by the [Occupancy] of every canonical shape. The hashes are recalculated on load.
+    /// Files of version 3 additionally contain an [index] section between the header and the records.
+    /// Files of version 4, which are the ones written now, optionally contain the index and split the
+    /// records into chunks that are encoded, compressed and decoded on several threads, see [read_level].
+    V2,
+}
+
+/// The compression applied to the records of a [CacheFormat::V2] cache.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+/// How the caches of the sizes of a run are kept in a [CacheStore]. Caches are read from any layout.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(EnumString, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheLayout {
+    /// A separate file per size, see [cache_file_name].
+    #[default]
+    PerSize,
+    /// A single file with a section per size, see [combined].
+    Combined,
+    /// Several files per size, each holding the shapes of one hash shard, see [sharded].
+    Sharded,
+}
+
+impl CacheLayout {
+    /// Saves the arrangements with the given number of blocks in this layout and lists them in the
+    /// [manifest] together with the size of the level they were generated from. The sharded layout splits
+    /// the level into the given number of hash shards.
+    /// The separate file or hash shards of the size that would hide the new cache or be hidden by it are removed.
+    pub fn save(&self, set: &Level, store: &dyn CacheStore, block_count: BlockCount, indexed: bool, shards: u32, parent_shapes: Option<u64>) -> Result<(), Error> {
+        let encoded = || -> Result<(Vec<u8>, String), Error> {
+            let mut data = vec![];
+            encode_cache(&mut data, set.iter(), set.len(), CacheFormat::V2, Compression::None, indexed)?;
+            let checksum = manifest::checksum(data.as_slice())?;
+            Ok((data, checksum))
+        };
+        let checksum = match self {
+            CacheLayout::PerSize => {
+                let (data, checksum) = encoded()?;
+                store.put(&cache_file_name(block_count), &data)?;
+                sharded::remove_hash_shards(store, block_count)?;
+                checksum
+            }
+            CacheLayout::Combined => {
+                let (data, checksum) = encoded()?;
+                combined::put_section(store, block_count, data)?;
+                remove_cache_file(store, block_count)?;
+                sharded::remove_hash_shards(store, block_count)?;
+                checksum
+            }
+            CacheLayout::Sharded => {
+                let checksum = sharded::put_hash_shards(set, store, block_count, shards, indexed)?;
+                remove_cache_file(store, block_count)?;
+                checksum
+            }
+        };
+        let entry = ManifestEntry { blocks: block_count, shapes: set.len() as u64, parent_shapes, checksum };
+        let mut manifest = CacheManifest::load(store).unwrap_or_else(|e| {
+            eprintln!("Replacing the unreadable cache manifest: {e}");
+            CacheManifest::default()
+        });
+        manifest.insert(entry);
+        manifest.save(store)
+    }
+}
+
+fn remove_cache_file(store: &dyn CacheStore, block_count: BlockCount) -> Result<(), Error> {
+    match store.remove(&cache_file_name(block_count)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Marks a [CacheFormat::V2] file. Starts with a byte that never starts a [CacheFormat::V1] file.
+const V2_MAGIC: [u8; 4] = [0xFF, b'P', b'C', b'C'];
+
+/// The version of the [CacheFormat::V2] files that are written, whose records are split into chunks.
+const CHUNKED_VERSION: u8 = 4;
+
+/// The number of records per chunk of a chunked cache. Chunks are the unit of work of the threads
+/// encoding and decoding a cache, so smaller levels are written in fewer chunks than there are threads.
+/// Tests use tiny chunks so their small caches consist of several chunks.
+const CHUNK_RECORDS: usize = if cfg!(test) { 4 } else { 1 << 16 };
+
+/// The entry of a chunk in the chunk table of a chunked cache, which is followed by the chunks.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+struct Chunk {
+    records: u64,
+    /// The length of the chunk after compression.
+    bytes: u64,
+}
+
+/// Loads the cache of arrangements with the given number of blocks from the store.
+pub fn load_cache(store: &dyn CacheStore, block_count: BlockCount) -> Result<Level, Error> {
+    read_level(open_cache(store, block_count)?)
+}
+
+/// Opens the cache of arrangements with the given number of blocks, which is either its own file or,
+/// if there is none, its [hash shards](sharded) or a section of the [combined] cache.
+pub fn open_cache(store: &dyn CacheStore, block_count: BlockCount) -> Result<Box<dyn Read + Send>, Error> {
+    let not_found = |result: &Result<Box<dyn Read + Send>, Error>| matches!(result, Err(e) if e.kind() == ErrorKind::NotFound);
+    let file = store.open(&cache_file_name(block_count));
+    if !not_found(&file) {
+        return file;
+    }
+    let shards = sharded::open_hash_shards(store, block_count);
+    if !not_found(&shards) {
+        return shards;
+    }
+    match combined::open_section(store, block_count) {
+        section if not_found(&section) => file,
+        section => section,
+    }
+}
+
+/// The records of a cache read one at a time, see [open_records].
+pub type Records = Box<dyn Iterator<Item = Result<(BlockHash, BlockArrangement), Error>>>;
+
+/// Reads the records of the cache of arrangements with the given number of blocks one at a time in the order
+/// of the level, which for [hash shards](sharded) differs from the order of [open_cache]. Returns the number
+/// of records together with them.
+pub fn open_records(store: &dyn CacheStore, block_count: BlockCount) -> Result<(u64, Records), Error> {
+    let has_file = !matches!(store.open(&cache_file_name(block_count)), Err(e) if e.kind() == ErrorKind::NotFound);
+    if !has_file && sharded::hash_shard_count(store, block_count)?.is_some() {
+        let reader = sharded::HashShardReader::open(store, block_count)?;
+        return Ok((reader.remaining(), Box::new(reader)));
+    }
+    let reader = CacheReader::new(open_cache(store, block_count)?)?;
+    Ok((reader.remaining(), Box::new(reader)))
+}
+
+/// Where the cache of arrangements with the given number of blocks is stored, for messages to the user.
+pub fn cache_location(store: &dyn CacheStore, block_count: BlockCount) -> String {
+    let name = cache_file_name(block_count);
+    if !matches!(store.open(&name), Err(e) if e.kind() == ErrorKind::NotFound) {
+        return store.location(&name);
+    }
+    if sharded::hash_shard_count(store, block_count).is_ok_and(|shards| shards.is_some()) {
+        return sharded::hash_shards_location(store, block_count);
+    }
+    let is_combined = combined::combined_sizes(store).is_ok_and(|sizes| sizes.contains(&block_count));
+    store.location(if is_combined { combined::COMBINED_CACHE_FILE_NAME } else { &name })
+}
+
+/// Loads a whole cache file of any format into memory.
+pub fn load_cache_from(path: impl AsRef<Path>) -> Result<Level, Error> {
+    read_level(File::open(path)?)
+}
+
+/// Reads a whole cache of any format. The chunks of a chunked cache are read into memory first and then
+/// decoded on as many threads as there are cores, caches of other versions are decoded record by record.
+pub fn read_level(reader: impl Read + 'static) -> Result<Level, Error> {
+    let mut reader = BufReader::new(reader);
+    let header = read_header(&mut reader)?;
+    let Header::V2 { compression, indexed, chunked: true } = header else {
+        return CacheReader::after_header(reader, header)?.collect();
+    };
+    skip_index(&mut reader, indexed)?;
+    let chunks: Vec<Chunk> = decode_next(&mut reader)?;
+    let mut bodies = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        let mut body = vec![];
+        (&mut reader).take(chunk.bytes).read_to_end(&mut body)?;
+        if body.len() as u64 != chunk.bytes {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "The cache is truncated"));
+        }
+        bodies.push((chunk.records, body));
+    }
+    let records = in_parallel(&bodies, |(records, body)| decode_chunk(body, *records, compression))?;
+    Ok(Level::from_unsorted(records.into_iter().flatten().collect()))
+}
+
+/// The encoded records of a [CacheFormat::V2] cache held in memory, which are viewed as [ShapeRef](crate::shape_ref::ShapeRef)s
+/// instead of being decoded into arrangements.
+pub struct CachedShapes {
+    records: RecordBytes,
+    len: u64,
+}
+
+/// The bytes of the records of [CachedShapes], either a mapped cache file or a buffer.
+enum RecordBytes {
+    Buffered(Vec<u8>),
+    /// The records start at the given position of the mapped file.
+    Mapped(Mmap, usize),
+}
+
+impl RecordBytes {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            RecordBytes::Buffered(records) => records,
+            RecordBytes::Mapped(map, start) => &map[*start..],
+        }
+    }
+}
+
+impl CachedShapes {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the shapes are viewed in a mapped cache file instead of a copy in memory.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.records, RecordBytes::Mapped(..))
+    }
+
+    /// Views the shapes in the order of the cache.
+    pub fn iter(&self) -> ShapeRefs<'_> {
+        ShapeRefs::new(self.records.as_slice(), self.len)
+    }
+}
+
+/// Loads the records of the cache of arrangements with the given number of blocks from the store
+/// without decoding them. A separate cache file on the local disk is mapped, see [map_shapes], any other
+/// cache is read into memory, see [read_shapes].
+pub fn load_shapes(store: &dyn CacheStore, block_count: BlockCount) -> Result<CachedShapes, Error> {
+    if let Some(path) = store.local_path(&cache_file_name(block_count)).filter(|path| path.is_file()) {
+        return map_shapes(path);
+    }
+    read_shapes(open_cache(store, block_count)?)
+}
+
+/// Maps a [CacheFormat::V2] cache file into memory, so its uncompressed records are viewed right in the
+/// file without copying them. Compressed records are decompressed into memory like by [read_shapes].
+pub fn map_shapes(path: impl AsRef<Path>) -> Result<CachedShapes, Error> {
+    let file = File::open(path)?;
+    // SAFETY: Caches are never modified in place, a store replaces them by renaming a new file over them,
+    // which leaves the mapped file unchanged.
+    let map = unsafe { Mmap::map(&file)? };
+    let mut reader = &map[..];
+    let (compression, len) = read_records_header(&mut reader)?;
+    let Some(len) = len.filter(|_| compression == 0) else {
+        return read_shapes(std::io::Cursor::new(map));
+    };
+    let start = map.len() - reader.len();
+    Ok(CachedShapes { records: RecordBytes::Mapped(map, start), len })
+}
+
+/// Reads the records of a [CacheFormat::V2] cache into memory. Compressed records are decompressed once,
+/// so the shapes are viewed without allocating anything per shape. [CacheFormat::V1] caches store
+/// arrangements instead of occupancies and fail with [ErrorKind::Unsupported].
+pub fn read_shapes(reader: impl Read + 'static) -> Result<CachedShapes, Error> {
+    let mut reader = BufReader::new(reader);
+    let (compression, len) = read_records_header(&mut reader)?;
+    let mut records = vec![];
+    if len == Some(0) {
+        // An empty level has no chunks and so not even an empty compressed frame.
+        return Ok(CachedShapes { records: RecordBytes::Buffered(records), len: 0 });
+    }
+    match compression {
+        0 => reader.read_to_end(&mut records)?,
+        1 => zstd::Decoder::with_buffer(reader)?.read_to_end(&mut records)?,
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown compression {compression}"))),
+    };
+    if let Some(len) = len {
+        return Ok(CachedShapes { records: RecordBytes::Buffered(records), len });
+    }
+    let mut count = records.as_slice();
+    let len = decode_next(&mut count)?;
+    let header_len = records.len() - count.len();
+    records.drain(..header_len);
+    Ok(CachedShapes { records: RecordBytes::Buffered(records), len })
+}
+
+/// Reads a [CacheFormat::V2] cache up to its records and returns their compression and number. The number
+/// of records of a compressed cache that is not chunked is compressed together with them and `None`.
+fn read_records_header(reader: &mut impl BufRead) -> Result<(u8, Option<u64>), Error> {
+    let (compression, indexed, chunked) = match read_header(reader)? {
+        Header::V1 { .. } => return Err(Error::new(ErrorKind::Unsupported, "Version 1 caches can not be viewed, convert them first")),
+        Header::V2 { compression, indexed, chunked } => (compression, indexed, chunked),
+    };
+    skip_index(reader, indexed)?;
+    let len = match chunked {
+        true => Some(decode_next::<Vec<Chunk>>(reader)?.iter().map(|chunk| chunk.records).sum()),
+        false if compression == 0 => Some(decode_next(reader)?),
+        false => None,
+    };
+    Ok((compression, len))
+}
+
+/// Applies the function to every item on as many threads as there are cores and returns the results
+/// in the order of the items.
+fn in_parallel<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> Result<R, Error> + Sync) -> Result<Vec<R>, Error> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).clamp(1, items.len().max(1));
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(items.len().div_ceil(threads).max(1))
+            .map(|group| scope.spawn(move || group.iter().map(f).collect::<Result<Vec<_>, _>>()))
+            .collect();
+        let mut results = Vec::with_capacity(items.len());
+        for handle in handles {
+            results.extend(handle.join().expect("Encoding and decoding never panics.")?);
+        }
+        Ok(results)
+    })
+}
+
+fn decode_chunk(body: &[u8], records: u64, compression: u8) -> Result<Vec<(BlockHash, BlockArrangement)>, Error> {
+    let mut reader: Box<dyn Read + '_> = match compression {
+        0 => Box::new(body),
+        1 => Box::new(zstd::Decoder::new(body)?),
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown compression {compression}"))),
+    };
+    (0..records).map(|_| read_v2_record(&mut reader)).collect()
+}
+
+fn read_v2_record(reader: &mut impl Read) -> Result<(BlockHash, BlockArrangement), Error> {
+    let occupancy: Occupancy = decode_next(reader)?;
+    let ba = Polycube::from_occupancy(&occupancy)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+        .into_inner();
+    Ok((BlockHash::from(&ba), ba))
+}
+
+fn skip_index(reader: &mut impl Read, indexed: bool) -> Result<(), Error> {
+    if indexed {
+        let len: u64 = decode_next(reader)?;
+        std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    }
+    Ok(())
+}
+
+/// Reads only the record at the given position of a cache file. The chunks before the one holding the
+/// record are skipped by their lengths, and the offsets of the [index] section point at the record in its
+/// chunk, so an uncompressed record is read right away. In caches without offsets the records before it
+/// in its chunk are decoded and discarded, and unchunked caches are decoded one record at a time up to it.
+/// Seeking by these offsets takes the place of a memory-mapped index, only the index section and the chunk
+/// table are read. Returns `None` if the cache has fewer records.
+pub fn load_record(path: impl AsRef<Path>, id: ShapeId) -> Result<Option<(BlockHash, BlockArrangement)>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = read_header(&mut reader)?;
+    let Header::V2 { compression, indexed, chunked: true } = header else {
+        return CacheReader::after_header(reader, header)?.nth(id.as_usize()).transpose();
+    };
+    let offsets = match indexed {
+        true => index::read_index_section(&mut reader)?.1,
+        false => None,
+    };
+    let chunks: Vec<Chunk> = decode_next(&mut reader)?;
+    let (mut first, mut skipped, mut found) = (0, 0, None);
+    for chunk in &chunks {
+        if id.get() < first + chunk.records {
+            found = Some(chunk);
+            break;
+        }
+        first += chunk.records;
+        skipped += chunk.bytes;
+    }
+    let Some(chunk) = found else {
+        return Ok(None);
+    };
+    let offset = offsets.and_then(|offsets| offsets.get(id.as_usize()).copied());
+    let to_i64 = |bytes: u64| i64::try_from(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+    if let (0, Some(offset)) = (compression, offset) {
+        reader.seek_relative(to_i64(skipped + offset)?)?;
+        return read_v2_record(&mut reader).map(Some);
+    }
+    reader.seek_relative(to_i64(skipped)?)?;
+    let body = reader.take(chunk.bytes);
+    let mut body: Box<dyn Read> = match compression {
+        0 => Box::new(body),
+        1 => Box::new(zstd::Decoder::new(body)?),
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown compression {compression}"))),
+    };
+    match offset {
+        Some(offset) => {
+            std::io::copy(&mut (&mut body).take(offset), &mut std::io::sink())?;
+        }
+        None => {
+            for _ in first..id.get() {
+                decode_next::<Occupancy>(&mut body)?;
+            }
+        }
+    }
+    read_v2_record(&mut body).map(Some)
+}
+
+/// Reads the records of a cache file of any format one at a time.
+pub struct CacheReader {
+    reader: Box<dyn Read>,
+    format: CacheFormat,
+    remaining: u64,
+}
+
+impl CacheReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(File::open(path)?)
+    }
+
+    /// Reads a cache from any source, e.g. a file of a [CacheStore].
+    /// The chunks of a chunked cache are decoded one after the other, see [read_level] to decode them in parallel.
+    pub fn new(reader: impl Read + 'static) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+        let header = read_header(&mut reader)?;
+        Self::after_header(reader, header)
+    }
+
+    fn after_header(mut reader: BufReader<impl Read + 'static>, header: Header) -> Result<Self, Error> {
+        let (compression, chunks) = match header {
+            Header::V1 { remaining } => return Ok(Self { reader: Box::new(reader), format: CacheFormat::V1, remaining }),
+            Header::V2 { compression, indexed, chunked } => {
+                skip_index(&mut reader, indexed)?;
+                let chunks: Option<Vec<Chunk>> = match chunked {
+                    true => Some(decode_next(&mut reader)?),
+                    false => None,
+                };
+                (compression, chunks)
+            }
+        };
+        // The compressed chunks are consecutive zstd frames, which are decoded like a single stream.
+        let mut reader: Box<dyn Read> = match compression {
+            0 => Box::new(reader),
+            1 => Box::new(zstd::Decoder::with_buffer(reader)?),
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown compression {compression}"))),
+        };
+        let remaining = match chunks {
+            Some(chunks) => chunks.iter().map(|chunk| chunk.records).sum(),
+            None => decode_next(&mut reader)?,
+        };
+        Ok(Self { reader, format: CacheFormat::V2, remaining })
+    }
+
+    pub fn format(&self) -> CacheFormat {
+        self.format
+    }
+
+    /// The number of records that have not been read yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    fn read_record(&mut self) -> Result<(BlockHash, BlockArrangement), Error> {
+        match self.format {
+            CacheFormat::V1 => decode_next::<(V1BlockHash, V1Arrangement)>(&mut self.reader)
+                .and_then(|(_, stored)| BlockArrangement::try_from(stored).map_err(|e| Error::new(ErrorKind::InvalidData, e)))
+                .map(|ba| (BlockHash::from(&ba), ba)),
+            CacheFormat::V2 => read_v2_record(&mut self.reader),
+        }
+    }
+}
+
+impl Iterator for CacheReader {
+    type Item = Result<(BlockHash, BlockArrangement), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let record = self.read_record();
+        if record.is_err() {
+            self.remaining = 0;
+        }
+        Some(record)
+    }
+}
+
+/// The information in front of the records of a cache.
+enum Header {
+    V1 { remaining: u64 },
+    /// The reader is positioned at the index section if the cache is indexed.
+    /// The records of a chunked cache are preceded by the chunk table instead of their number.
+    V2 { compression: u8, indexed: bool, chunked: bool },
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<Header, Error> {
+    if !reader.fill_buf()?.starts_with(&V2_MAGIC) {
+        // Maps are encoded as their length followed by their entries, which allows reading them piecewise.
+        return Ok(Header::V1 { remaining: decode_next(reader)? });
+    }
+    reader.consume(V2_MAGIC.len());
+    let version: u8 = decode_next(reader)?;
+    if !matches!(version, 2..=CHUNKED_VERSION) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported cache version {version}")));
+    }
+    let compression = decode_next(reader)?;
+    if version == CHUNKED_VERSION {
+        let indexed: bool = decode_next(reader)?;
+        return Ok(Header::V2 { compression, indexed, chunked: true });
+    }
+    Ok(Header::V2 { compression, indexed: version == 3, chunked: false })
+}
+
+/// Decodes the next value of a cache file.
+fn decode_next<T: serde::de::DeserializeOwned>(reader: &mut impl Read) -> Result<T, Error> {
+    let config = bincode::config::standard();
+    bincode::serde::decode_from_std_read(reader, config)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn encode_next<T: serde::Serialize>(value: T, writer: &mut impl Write) -> Result<(), Error> {
+    let config = bincode::config::standard();
+    bincode::serde::encode_into_std_write(value, writer, config)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(())
+}
+
+/// Saves the arrangements with the given number of blocks in the store using the current format,
+/// optionally with an [index] section.
+pub fn save_cache(set: &Level, store: &dyn CacheStore, block_count: BlockCount, indexed: bool) -> Result<(), Error> {
+    put_cache(set, store, &cache_file_name(block_count), indexed)
+}
+
+fn put_cache(set: &Level, store: &dyn CacheStore, name: &str, indexed: bool) -> Result<(), Error> {
+    let mut data = vec![];
+    encode_cache(&mut data, set.iter(), set.len(), CacheFormat::V2, Compression::None, indexed)?;
+    store.put(name, &data)
+}
+
+/// Writes the records to a new cache file in the given format.
+/// [CacheFormat::V1] files are never compressed or indexed, and need the `decimal-metrics` feature.
+pub fn write_cache<'a>(
+    path: impl AsRef<Path>,
+    records: impl Iterator<Item = (&'a BlockHash, &'a BlockArrangement)> + Clone,
+    len: usize,
+    format: CacheFormat,
+    compression: Compression,
+    indexed: bool,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_cache(&mut writer, records, len, format, compression, indexed)?;
+    writer.flush()
+}
+
+fn encode_cache<'a>(
+    mut writer: &mut impl Write,
+    records: impl Iterator<Item = (&'a BlockHash, &'a BlockArrangement)> + Clone,
+    len: usize,
+    format: CacheFormat,
+    compression: Compression,
+    indexed: bool,
+) -> Result<(), Error> {
+    match format {
+        #[cfg(feature = "decimal-metrics")]
+        CacheFormat::V1 => {
+            // Encoded exactly like a map to stay readable by older versions.
+            encode_next(len as u64, &mut writer)?;
+            for (hash, ba) in records {
+                let stored = V1Arrangement::try_from(ba).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+                encode_next((V1BlockHash::from(hash), stored), &mut writer)?;
+            }
+        }
+        #[cfg(not(feature = "decimal-metrics"))]
+        CacheFormat::V1 => {
+            let _ = (records, len);
+            return Err(Error::new(ErrorKind::Unsupported, "Version 1 caches are only written with the decimal-metrics feature"));
+        }
+        CacheFormat::V2 => {
+            writer.write_all(&V2_MAGIC)?;
+            encode_next(CHUNKED_VERSION, &mut writer)?;
+            let compression_id = match compression {
+                Compression::None => 0u8,
+                Compression::Zstd => 1u8,
+            };
+            encode_next(compression_id, &mut writer)?;
+            encode_next(indexed, &mut writer)?;
+            let arrangements: Vec<_> = records.clone().map(|(_, ba)| ba).collect();
+            let groups: Vec<_> = arrangements.chunks(CHUNK_RECORDS).collect();
+            let encoded = in_parallel(&groups, |group| encode_chunk(group, compression))?;
+            if indexed {
+                let offsets: Vec<_> = encoded.iter().flat_map(|(_, offsets)| offsets.iter().copied()).collect();
+                writer.write_all(&index::encode_index(records, &offsets)?)?;
+            }
+            let chunks: Vec<_> = groups.iter().zip(&encoded)
+                .map(|(group, (body, _))| Chunk { records: group.len() as u64, bytes: body.len() as u64 })
+                .collect();
+            encode_next(&chunks, &mut writer)?;
+            for (body, _) in encoded {
+                writer.write_all(&body)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the records as a chunk and returns it together with the offset of every record in the chunk
+/// before compression.
+fn encode_chunk(records: &[&BlockArrangement], compression: Compression) -> Result<(Vec<u8>, Vec<u64>), Error> {
+    let mut body = vec![];
+    let offsets = match compression {
+        Compression::None => write_occupancies(records.iter().copied(), &mut body)?,
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(&mut body, 0)?;
+            let offsets = write_occupancies(records.iter().copied(), &mut encoder)?;
+            encoder.finish()?;
+            offsets
+        }
+    };
+    Ok((body, offsets))
+}
+
+/// Writes the occupancies of the records and returns the offset every one of them was written at.
+fn write_occupancies<'a>(
+    records: impl Iterator<Item = &'a BlockArrangement>,
+    writer: &mut impl Write,
+) -> Result<Vec<u64>, Error> {
+    let mut offsets = vec![];
+    let mut record = vec![];
+    let mut offset = 0;
+    for ba in records {
+        let polycube = Polycube::new(ba).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        record.clear();
+        encode_next(polycube.occupancy(), &mut record)?;
+        writer.write_all(&record)?;
+        offsets.push(offset);
+        offset += record.len() as u64;
+    }
+    Ok(offsets)
+}
+
+/// Rewrites a cache file of any format in the given format.
+/// The output is written to a temporary file first, so the output may be the input itself.
+pub fn convert_cache(input: impl AsRef<Path>, output: impl AsRef<Path>, format: CacheFormat, compression: Compression, indexed: bool) -> Result<usize, Error> {
+    let records = load_cache_from(input)?;
+    let output = output.as_ref();
+    let mut temp_name = output.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    write_cache(&temp_name, records.iter(), records.len(), format, compression, indexed)?;
+    replace_file(&temp_name, output)?;
+    Ok(records.len())
+}
+
+/// The name of the cache of arrangements with the given number of blocks inside a [CacheStore].
+pub fn cache_file_name(block_count: BlockCount) -> String {
+    format!("shape_cache_{block_count}.cac")
+}
+
+/// The output of a completed part of a level whose generation was interrupted.
+/// Holds the arrangements generated from the parents in `range` of the sorted parent level.
+pub struct PartialShard {
+    pub range: Range<usize>,
+    pub records: Level,
+}
+
+fn partial_shard_prefix(block_count: BlockCount) -> String {
+    format!("shape_cache_{block_count}.part_")
+}
+
+/// The file name of a partial shard also encodes the size of the parent level it was generated from,
+/// so shards of a level generated from different parents are never mixed.
+pub fn partial_shard_file_name(block_count: BlockCount, range: &Range<usize>, parent_count: usize) -> String {
+    let prefix = partial_shard_prefix(block_count);
+    format!("{prefix}{}_{}_of_{parent_count}.cac", range.start, range.end)
+}
+
+/// Saves the output of a part of a level. Stores never expose partially written files,
+/// so an interrupted write never leaves a shard behind that looks complete.
+pub fn save_partial_shard(
+    set: &Level,
+    store: &dyn CacheStore,
+    block_count: BlockCount,
+    range: &Range<usize>,
+    parent_count: usize,
+) -> Result<(), Error> {
+    put_cache(set, store, &partial_shard_file_name(block_count, range, parent_count), false)
+}
+
+/// Finds the partial shards of the level with the given block count that were generated from a parent
+/// level of the given size. Shards that can not be read, contain shapes of the wrong size or overlap an
+/// already found shard are skipped with a warning.
+pub fn load_partial_shards(store: &dyn CacheStore, block_count: BlockCount, parent_count: usize) -> Result<Vec<PartialShard>, Error> {
+    let prefix = partial_shard_prefix(block_count);
+    let mut candidates = vec![];
+    for name in store.list()? {
+        let Some(range) = name.strip_prefix(&prefix)
+            .and_then(|name| name.strip_suffix(".cac"))
+            .and_then(|name| parse_partial_shard_range(name, parent_count)) else {
+            continue;
+        };
+        candidates.push((range, name));
+    }
+    candidates.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut shards: Vec<PartialShard> = vec![];
+    for (range, name) in candidates {
+        if shards.last().is_some_and(|shard| shard.range.end > range.start) {
+            eprintln!("Skipping partial shard {} as it overlaps another one", store.location(&name));
+            continue;
+        }
+        let records = store.open(&name).and_then(CacheReader::new).and_then(Iterator::collect).and_then(|records: Level| {
+            if records.values().all(|ba| ba.num_blocks() == block_count) {
+                Ok(records)
+            } else {
+                Err(Error::new(ErrorKind::InvalidData, "Contains shapes of the wrong size"))
+            }
+        });
+        match records {
+            Ok(records) => shards.push(PartialShard { range, records }),
+            Err(e) => eprintln!("Skipping invalid partial shard {}: {e}", store.location(&name)),
+        }
+    }
+    Ok(shards)
+}
+
+/// Parses `<start>_<end>_of_<parent_count>`, returning `None` for shards of another parent level.
+fn parse_partial_shard_range(name: &str, parent_count: usize) -> Option<Range<usize>> {
+    let (range, count) = name.split_once("_of_")?;
+    let (start, end) = range.split_once('_')?;
+    let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+    (count.parse::<usize>().ok()? == parent_count && start < end && end <= parent_count).then_some(start..end)
+}
+
+/// Removes all partial shards of the level with the given block count.
+pub fn remove_partial_shards(store: &dyn CacheStore, block_count: BlockCount) -> Result<(), Error> {
+    let prefix = partial_shard_prefix(block_count);
+    for name in store.list()? {
+        if name.starts_with(&prefix) {
+            store.remove(&name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits the parents of a level that are not covered by a partial shard into ranges of at most
+/// `shard_size` parents. The covered ranges have to be sorted and must not overlap.
+pub fn uncovered_ranges(parent_count: usize, covered: &[Range<usize>], shard_size: usize) -> Vec<Range<usize>> {
+    let shard_size = shard_size.max(1);
+    let mut ranges = vec![];
+    let mut start = 0;
+    let bounds = covered.iter()
+        .map(|range| (range.start, range.end))
+        .chain(std::iter::once((parent_count, parent_count)));
+    for (gap_end, next_start) in bounds {
+        while start < gap_end {
+            let end = gap_end.min(start + shard_size);
+            ranges.push(start..end);
+            start = end;
+        }
+        start = start.max(next_start);
+    }
+    ranges
+}
+
+/// Loads the [PolyTree] from the store.
+pub fn load_poly_tree(store: &dyn CacheStore) -> Result<PolyTree, Error> {
+    PolyTree::read_from(&mut store.open(POLY_TREE_FILE_NAME)?)
+}
+
+/// Stores the [PolyTree], replacing a previously stored one.
+pub fn save_poly_tree(tree: &PolyTree, store: &dyn CacheStore) -> Result<(), Error> {
+    let mut data = vec![];
+    tree.write_to(&mut data)?;
+    store.put(POLY_TREE_FILE_NAME, &data)
+}
+
+const POLY_TREE_FILE_NAME: &str = "shape_tree.ptr";
+
+#[cfg(test)]
+mod cache_tests {
+    #[cfg(feature = "decimal-metrics")]
+    use std::collections::{BTreeMap, HashSet};
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_load_chunked_record() {
+        let path = std::env::temp_dir().join("cache_tests_load_chunked_record.cac");
+        let mut level = Level::from_iter([(BlockHash::from(&BlockArrangement::new()), BlockArrangement::new())]);
+        for _ in 0..4 {
+            level = crate::block_arrangement::block_variation::generate_variants_from(level.values(), 0, None).0;
+        }
+        for (compression, indexed) in [(Compression::None, true), (Compression::Zstd, true), (Compression::None, false), (Compression::Zstd, false)] {
+            write_cache(&path, level.iter(), level.len(), CacheFormat::V2, compression, indexed).expect("Writable temp dir");
+            let mut reader = BufReader::new(File::open(&path).unwrap());
+            read_header(&mut reader).unwrap();
+            // Indexed caches store the offset of every record in its chunk.
+            if indexed {
+                let offsets = index::read_index_section(&mut reader).unwrap().1.unwrap();
+                assert_eq!((0, 0), (offsets[CHUNK_RECORDS], offsets[2 * CHUNK_RECORDS]));
+                assert!(offsets[1] > 0);
+            }
+            let records: Vec<_> = CacheReader::open(&path).unwrap().map(Result::unwrap).collect();
+            for (position, record) in records.iter().enumerate() {
+                assert_eq!(Some(record), load_record(&path, ShapeId::from(position)).unwrap().as_ref());
+            }
+            assert!(load_record(&path, ShapeId::from(records.len())).unwrap().is_none());
+        }
+        std::fs::remove_file(path).expect("Removable temp file");
+    }
+
+    #[test]
+    #[cfg(feature = "decimal-metrics")]
+    fn test_load_record() {
+        let path = std::env::temp_dir().join("cache_tests_load_record.cac");
+        let mut map = BTreeMap::new();
+        let ba = BlockArrangement::new();
+        VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .for_each(|ba| {
+                map.insert(BlockHash::from(&ba), ba);
+            });
+        // Encoded like a map of the keys stored by earlier versions.
+        let stored: Vec<_> = map.iter().map(|(hash, ba)| (V1BlockHash::from(hash), V1Arrangement::try_from(ba).unwrap())).collect();
+        let file = File::create(&path).expect("Writable temp dir");
+        bincode::serde::encode_into_std_write(&stored, &mut BufWriter::new(file), bincode::config::standard())
+            .expect("Successful serialization");
+
+        for (index, (hash, ba)) in map.iter().enumerate() {
+            let (loaded_hash, loaded_ba) = load_record(&path, ShapeId::from(index))
+                .expect("Readable cache")
+                .expect("Record in range");
+            assert_eq!(*hash, loaded_hash);
+            assert_eq!(*ba, loaded_ba);
+        }
+        assert!(load_record(&path, ShapeId::from(map.len())).expect("Readable cache").is_none());
+        std::fs::remove_file(path).expect("Removable temp file");
+    }
+
+    #[test]
+    #[cfg(feature = "decimal-metrics")]
+    fn test_convert() {
+        let v1_path = std::env::temp_dir().join("cache_tests_convert_v1.cac");
+        let v2_path = std::env::temp_dir().join("cache_tests_convert_v2.cac");
+        let mut map = BTreeMap::new();
+        let ba = BlockArrangement::new();
+        VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .for_each(|ba| {
+                map.insert(BlockHash::from(&ba), ba);
+            });
+        write_cache(&v1_path, map.iter(), map.len(), CacheFormat::V1, Compression::None, false)
+            .expect("Writable temp dir");
+        assert_eq!(CacheFormat::V1, CacheReader::open(&v1_path).expect("Readable cache").format());
+
+        for compression in [Compression::None, Compression::Zstd] {
+            let converted = convert_cache(&v1_path, &v2_path, CacheFormat::V2, compression, compression == Compression::Zstd)
+                .expect("Convertible cache");
+            assert_eq!(map.len(), converted);
+            let reader = CacheReader::open(&v2_path).expect("Readable cache");
+            assert_eq!(CacheFormat::V2, reader.format());
+            assert_eq!(map.len() as u64, reader.remaining());
+            let loaded = load_cache_from(&v2_path).expect("Readable cache");
+            let expected: HashSet<_> = map.values().map(|ba| Polycube::new(ba).unwrap()).collect();
+            let actual: HashSet<_> = loaded.values().map(|ba| Polycube::new(ba).unwrap()).collect();
+            assert_eq!(expected, actual);
+        }
+
+        convert_cache(&v2_path, &v2_path, CacheFormat::V1, Compression::None, false).expect("Convertible cache");
+        assert_eq!(CacheFormat::V1, CacheReader::open(&v2_path).expect("Readable cache").format());
+        assert_eq!(map.len(), load_cache_from(&v2_path).expect("Readable cache").len());
+        std::fs::remove_file(v1_path).expect("Removable temp file");
+        std::fs::remove_file(v2_path).expect("Removable temp file");
+    }
+
+    #[test]
+    fn test_chunks() {
+        let ba = BlockArrangement::new();
+        let tetrominoes: Level = VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .map(|ba| Polycube::new(&ba).unwrap().into_inner())
+            .map(|ba| (BlockHash::from(&ba), ba))
+            .collect();
+        assert!(tetrominoes.len() > CHUNK_RECORDS);
+        for compression in [Compression::None, Compression::Zstd] {
+            for level in [Level::new(), tetrominoes.clone()] {
+                let mut data = vec![];
+                encode_cache(&mut data, level.iter(), level.len(), CacheFormat::V2, compression, true).unwrap();
+                let sequential: Level = CacheReader::new(std::io::Cursor::new(data.clone())).unwrap().collect::<Result<_, _>>().unwrap();
+                assert_eq!(level, sequential);
+                assert_eq!(level, read_level(std::io::Cursor::new(data.clone())).unwrap());
+                let shapes = read_shapes(std::io::Cursor::new(data.clone())).unwrap();
+                let viewed: Level = shapes.iter()
+                    .map(|view| view.unwrap().to_polycube().unwrap().into_inner())
+                    .map(|ba| (BlockHash::from(&ba), ba))
+                    .collect();
+                assert_eq!(level, viewed);
+                let path = std::env::temp_dir().join(format!("cache_tests_chunks_{compression}_{}.cac", level.len()));
+                std::fs::write(&path, &data).unwrap();
+                let mapped = map_shapes(&path).unwrap();
+                assert_eq!(compression == Compression::None, mapped.is_mapped());
+                assert!(mapped.iter().map(Result::unwrap).eq(shapes.iter().map(Result::unwrap)));
+                drop(mapped);
+                std::fs::remove_file(path).unwrap();
+                assert!(verify::verify_cache(std::io::Cursor::new(data)).unwrap().is_valid());
+            }
+        }
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_uncovered_ranges() {
+        assert_eq!(vec![0..4, 4..8, 8..10], uncovered_ranges(10, &[], 4));
+        assert_eq!(vec![0..2, 5..9, 9..10], uncovered_ranges(10, &[2..5], 4));
+        assert_eq!(vec![3..4], uncovered_ranges(10, &[0..3, 4..10], 4));
+        assert!(uncovered_ranges(10, &[0..10], 4).is_empty());
+        assert!(uncovered_ranges(0, &[], 4).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_partial_shards() {
+        let dir = std::env::temp_dir().join("cache_tests_partial_shards");
+        let store = FsStore::new(&dir);
+        let ba = BlockArrangement::new();
+        let dominoes: Level = VariationGenerator::new(&ba)
+            .map(|ba| (BlockHash::from(&ba), ba))
+            .collect();
+        save_partial_shard(&dominoes, &store, BlockCount::new(2), &(0..1), 3).expect("Writable temp dir");
+        // Overlaps the first shard.
+        save_partial_shard(&dominoes, &store, BlockCount::new(2), &(0..2), 3).expect("Writable temp dir");
+        // Generated from another parent level.
+        save_partial_shard(&dominoes, &store, BlockCount::new(2), &(1..2), 4).expect("Writable temp dir");
+        // Contains shapes of the wrong size.
+        save_partial_shard(&dominoes, &store, BlockCount::new(3), &(1..2), 3).expect("Writable temp dir");
+        store.put(&partial_shard_file_name(BlockCount::new(2), &(2..3), 3), &[1, 2, 3]).expect("Writable temp dir");
+
+        let shards = load_partial_shards(&store, BlockCount::new(2), 3).expect("Readable dir");
+        assert_eq!(vec![0..1], shards.iter().map(|shard| shard.range.clone()).collect::<Vec<_>>());
+        assert_eq!(dominoes, shards[0].records);
+        assert!(load_partial_shards(&store, BlockCount::new(3), 3).expect("Readable dir").is_empty());
+
+        remove_partial_shards(&store, BlockCount::new(2)).expect("Removable shards");
+        assert!(load_partial_shards(&store, BlockCount::new(2), 4).expect("Readable dir").is_empty());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
@@ -0,0 +1,166 @@
+//! Checkpoints of the level a run is generating. The children of every completed range of parents are
+//! saved as a [partial shard](crate::cache::PartialShard) and listed in a manifest next to the shards with
+//! their number of shapes and checksum, so a restarted run continues in the middle of the level and skips
+//! shards that were changed since they were saved. The shards workers save themselves are not listed, a
+//! run finds them by their [file names](partial_shard_file_name) only.
+
+use std::io::{Error, ErrorKind, Read};
+use std::ops::Range;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::block_count::BlockCount;
+use crate::cache::manifest::checksum;
+use crate::cache::{encode_cache, load_partial_shards, partial_shard_file_name, partial_shard_prefix, CacheFormat, Compression, PartialShard};
+use crate::level::Level;
+use crate::store::CacheStore;
+
+/// A partial shard listed in a [CheckpointManifest].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    /// The range of parents the shapes of the shard were generated from.
+    pub start: usize,
+    pub end: usize,
+    pub shapes: u64,
+    /// The hexadecimal xxh3 hash of the bytes of the shard, see [checksum].
+    pub checksum: String,
+}
+
+/// The partial shards a run saved for a level, which is generated from a parent level of `parents` shapes.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointManifest {
+    pub parents: usize,
+    pub shards: Vec<CheckpointEntry>,
+}
+
+/// The name of the manifest of the level with the given number of blocks inside a [CacheStore]. It starts like
+/// the names of the partial shards, so [remove_partial_shards](crate::cache::remove_partial_shards) removes it
+/// together with them once the level is complete.
+pub fn checkpoint_manifest_name(block_count: BlockCount) -> String {
+    format!("{}manifest.toml", partial_shard_prefix(block_count))
+}
+
+impl CheckpointManifest {
+    /// Reads the manifest of the level, an empty one if there is none.
+    pub fn load(store: &dyn CacheStore, block_count: BlockCount) -> Result<Self, Error> {
+        let mut content = String::new();
+        match store.open(&checkpoint_manifest_name(block_count)) {
+            Ok(mut reader) => reader.read_to_string(&mut content)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&content).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, store: &dyn CacheStore, block_count: BlockCount) -> Result<(), Error> {
+        let content = toml::to_string(self).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        store.put(&checkpoint_manifest_name(block_count), content.as_bytes())
+    }
+
+    /// The entry of the shard generated from exactly the given range of parents.
+    pub fn get(&self, range: &Range<usize>) -> Option<&CheckpointEntry> {
+        self.shards.iter().find(|entry| entry.start == range.start && entry.end == range.end)
+    }
+}
+
+/// Saves the partial shards of the level a run is generating and keeps its [CheckpointManifest] up to date.
+/// Shards may be saved from several threads at once, like those receiving the shards of workers.
+pub struct Checkpoints<'a> {
+    store: &'a dyn CacheStore,
+    block_count: BlockCount,
+    parents: usize,
+    manifest: Mutex<CheckpointManifest>,
+}
+
+impl<'a> Checkpoints<'a> {
+    /// Continues the manifest of the level if it lists the shards of a parent level of the same size.
+    pub fn open(store: &'a dyn CacheStore, block_count: BlockCount, parents: usize) -> Self {
+        let manifest = match CheckpointManifest::load(store, block_count) {
+            Ok(manifest) if manifest.parents == parents => manifest,
+            Ok(_) => CheckpointManifest { parents, shards: vec![] },
+            Err(e) => {
+                eprintln!("Replacing the unreadable checkpoint manifest: {e}");
+                CheckpointManifest { parents, shards: vec![] }
+            }
+        };
+        Self { store, block_count, parents, manifest: Mutex::new(manifest) }
+    }
+
+    /// Loads the partial shards of the level like [load_partial_shards]. Listed shards that no longer have the
+    /// listed number of shapes or checksum are skipped with a warning, so their parents are generated again.
+    pub fn load(&self) -> Result<Vec<PartialShard>, Error> {
+        let manifest = self.manifest.lock().expect("No thread panicked while holding the lock.").clone();
+        let mut shards = load_partial_shards(self.store, self.block_count, self.parents)?;
+        shards.retain(|shard| {
+            let Some(entry) = manifest.get(&shard.range) else {
+                return true;
+            };
+            let name = partial_shard_file_name(self.block_count, &shard.range, self.parents);
+            let problem = match self.store.open(&name).and_then(checksum) {
+                Ok(_) if entry.shapes != shard.records.len() as u64 => format!("it contains {} shapes instead of {}", shard.records.len(), entry.shapes),
+                Ok(checksum) if checksum != entry.checksum => "its checksum changed".to_string(),
+                Ok(_) => return true,
+                Err(e) => e.to_string(),
+            };
+            eprintln!("Skipping partial shard {} as {problem}", self.store.location(&name));
+            false
+        });
+        Ok(shards)
+    }
+
+    /// Saves the shapes generated from the range of parents as a partial shard and lists it in the manifest.
+    pub fn save(&self, records: &Level, range: &Range<usize>) -> Result<(), Error> {
+        let mut data = vec![];
+        encode_cache(&mut data, records.iter(), records.len(), CacheFormat::V2, Compression::None, false)?;
+        let entry = CheckpointEntry { start: range.start, end: range.end, shapes: records.len() as u64, checksum: checksum(data.as_slice())? };
+        self.store.put(&partial_shard_file_name(self.block_count, range, self.parents), &data)?;
+        let mut manifest = self.manifest.lock().expect("No thread panicked while holding the lock.");
+        manifest.shards.retain(|listed| listed.start != range.start || listed.end != range.end);
+        manifest.shards.push(entry);
+        manifest.save(self.store, self.block_count)
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::block_hash::BlockHash;
+    use crate::cache::{remove_partial_shards, save_partial_shard};
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let dir = std::env::temp_dir().join("checkpoint_tests_checkpoints");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        let ba = BlockArrangement::new();
+        let dominoes: Level = VariationGenerator::new(&ba).map(|ba| (BlockHash::from(&ba), ba)).collect();
+        let blocks = BlockCount::new(2);
+        let checkpoints = Checkpoints::open(&store, blocks, 4);
+        checkpoints.save(&dominoes, &(0..1)).unwrap();
+        checkpoints.save(&dominoes, &(1..3)).unwrap();
+        // A shard saved by a worker is found without being listed.
+        save_partial_shard(&dominoes, &store, blocks, &(3..4), 4).unwrap();
+        let manifest = CheckpointManifest::load(&store, blocks).unwrap();
+        assert_eq!((4, 2), (manifest.parents, manifest.shards.len()));
+        assert_eq!(Some(dominoes.len() as u64), manifest.get(&(1..3)).map(|entry| entry.shapes));
+
+        let ranges = |shards: Vec<PartialShard>| shards.into_iter().map(|shard| shard.range).collect::<Vec<_>>();
+        assert_eq!(vec![0..1, 1..3, 3..4], ranges(Checkpoints::open(&store, blocks, 4).load().unwrap()));
+        // A listed shard replaced behind the back of the manifest is generated again.
+        save_partial_shard(&Level::new(), &store, blocks, &(1..3), 4).unwrap();
+        assert_eq!(vec![0..1, 3..4], ranges(Checkpoints::open(&store, blocks, 4).load().unwrap()));
+
+        // The manifest of other parents is replaced.
+        Checkpoints::open(&store, blocks, 5).save(&dominoes, &(0..5)).unwrap();
+        let manifest = CheckpointManifest::load(&store, blocks).unwrap();
+        assert_eq!((5, 1), (manifest.parents, manifest.shards.len()));
+        remove_partial_shards(&store, blocks).unwrap();
+        assert_eq!(CheckpointManifest::default(), CheckpointManifest::load(&store, blocks).unwrap());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
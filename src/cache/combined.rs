@@ -0,0 +1,196 @@
+//! A single file holding the caches of many sizes, so a whole run can be copied, reported on and queried
+//! without juggling a file per size. The file starts with an index of the sizes it contains and the
+//! lengths of their sections, followed by the sections ordered by size. Every section is a complete cache
+//! file of any format, so its records are read by a [CacheReader](crate::cache::CacheReader) like those
+//! of a separate file.
+
+use std::io::{BufReader, Error, ErrorKind, Read};
+use crate::block_count::BlockCount;
+use crate::cache::{cache_file_name, decode_next, encode_next};
+use crate::store::CacheStore;
+
+/// The name of the combined cache inside a [CacheStore].
+pub const COMBINED_CACHE_FILE_NAME: &str = "shape_cache_all.cac";
+
+/// Marks a combined cache. Differs from the magic of a single cache in its last byte.
+const COMBINED_MAGIC: [u8; 4] = [0xFF, b'P', b'C', b'A'];
+
+const COMBINED_VERSION: u8 = 1;
+
+/// The sizes in a combined cache together with the lengths of their sections in bytes, ordered by size.
+pub type SizeIndex = Vec<(BlockCount, u64)>;
+
+/// Reads the header of a combined cache, leaving the reader at the first section.
+pub fn read_size_index(reader: &mut impl Read) -> Result<SizeIndex, Error> {
+    let mut magic = [0; COMBINED_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != COMBINED_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a combined cache"));
+    }
+    let version: u8 = decode_next(reader)?;
+    if version != COMBINED_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported combined cache version {version}")));
+    }
+    decode_next(reader)
+}
+
+/// The sizes the combined cache of the store contains, none if there is no combined cache.
+pub fn combined_sizes(store: &dyn CacheStore) -> Result<Vec<BlockCount>, Error> {
+    match store.open(COMBINED_CACHE_FILE_NAME) {
+        Ok(mut reader) => Ok(read_size_index(&mut reader)?.into_iter().map(|(size, _)| size).collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Opens the section of the arrangements with the given number of blocks. Only the index and the
+/// sections in front of it are read. Fails with [ErrorKind::NotFound] if there is no such section.
+pub fn open_section(store: &dyn CacheStore, block_count: BlockCount) -> Result<Box<dyn Read + Send>, Error> {
+    let mut reader = BufReader::new(store.open(COMBINED_CACHE_FILE_NAME)?);
+    let mut offset = 0;
+    for (size, len) in read_size_index(&mut reader)? {
+        if size == block_count {
+            if std::io::copy(&mut (&mut reader).take(offset), &mut std::io::sink())? < offset {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "The combined cache is truncated"));
+            }
+            return Ok(Box::new(reader.take(len)));
+        }
+        offset += len;
+    }
+    let location = store.location(COMBINED_CACHE_FILE_NAME);
+    Err(Error::new(ErrorKind::NotFound, format!("{location} contains no shapes with {block_count} blocks")))
+}
+
+/// Stores an encoded cache as the section of the given number of blocks of the combined cache, replacing
+/// a previous section of the same size. The other sections are kept, so the whole file is rewritten.
+pub fn put_section(store: &dyn CacheStore, block_count: BlockCount, section: Vec<u8>) -> Result<(), Error> {
+    let mut sections = read_sections(store)?;
+    match sections.binary_search_by_key(&block_count, |(size, _)| *size) {
+        Ok(position) => sections[position].1 = section,
+        Err(position) => sections.insert(position, (block_count, section)),
+    }
+    put_sections(store, &sections)
+}
+
+/// Moves the separate cache files of the store into the combined cache and returns the moved sizes.
+/// Files of sizes that are already combined replace their sections.
+pub fn combine_caches(store: &dyn CacheStore) -> Result<Vec<BlockCount>, Error> {
+    let mut sections = read_sections(store)?;
+    let mut moved = vec![];
+    for name in store.list()? {
+        let Some(size) = name.strip_prefix("shape_cache_")
+            .and_then(|name| name.strip_suffix(".cac"))
+            .and_then(|size| size.parse::<BlockCount>().ok()) else {
+            continue;
+        };
+        let mut data = vec![];
+        store.open(&name)?.read_to_end(&mut data)?;
+        match sections.binary_search_by_key(&size, |(size, _)| *size) {
+            Ok(position) => sections[position].1 = data,
+            Err(position) => sections.insert(position, (size, data)),
+        }
+        moved.push(size);
+    }
+    put_sections(store, &sections)?;
+    for &size in &moved {
+        store.remove(&cache_file_name(size))?;
+    }
+    moved.sort();
+    Ok(moved)
+}
+
+/// Moves every section of the combined cache into a separate cache file and returns their sizes.
+pub fn split_caches(store: &dyn CacheStore) -> Result<Vec<BlockCount>, Error> {
+    let sections = read_sections(store)?;
+    for (size, data) in &sections {
+        store.put(&cache_file_name(*size), data)?;
+    }
+    if !sections.is_empty() {
+        store.remove(COMBINED_CACHE_FILE_NAME)?;
+    }
+    Ok(sections.into_iter().map(|(size, _)| size).collect())
+}
+
+/// Reads every section of the combined cache, none if there is no combined cache.
+fn read_sections(store: &dyn CacheStore) -> Result<Vec<(BlockCount, Vec<u8>)>, Error> {
+    let mut reader = match store.open(COMBINED_CACHE_FILE_NAME) {
+        Ok(reader) => BufReader::new(reader),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    read_size_index(&mut reader)?.into_iter()
+        .map(|(size, len)| {
+            let mut data = vec![];
+            (&mut reader).take(len).read_to_end(&mut data)?;
+            match data.len() as u64 == len {
+                true => Ok((size, data)),
+                false => Err(Error::new(ErrorKind::UnexpectedEof, "The combined cache is truncated")),
+            }
+        })
+        .collect()
+}
+
+fn put_sections(store: &dyn CacheStore, sections: &[(BlockCount, Vec<u8>)]) -> Result<(), Error> {
+    let mut data = COMBINED_MAGIC.to_vec();
+    encode_next(COMBINED_VERSION, &mut data)?;
+    let index: SizeIndex = sections.iter().map(|(size, section)| (*size, section.len() as u64)).collect();
+    encode_next(&index, &mut data)?;
+    for (_, section) in sections {
+        data.extend_from_slice(section);
+    }
+    store.put(COMBINED_CACHE_FILE_NAME, &data)
+}
+
+#[cfg(test)]
+mod combined_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::block_hash::BlockHash;
+    use crate::cache::{load_cache, save_cache, CacheLayout};
+    use crate::cache::index::load_index;
+    use crate::cache::sharded::DEFAULT_HASH_SHARDS;
+    use crate::level::Level;
+    use crate::polycube::Polycube;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_combined() {
+        let dir = std::env::temp_dir().join("combined_tests_combined");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        let mut levels = vec![Level::from_iter([(BlockHash::from(&BlockArrangement::new()), BlockArrangement::new())])];
+        for _ in 0..2 {
+            let next: Level = levels.last().unwrap().values()
+                .flat_map(|ba| VariationGenerator::new(ba).collect::<Vec<_>>())
+                .map(|ba| Polycube::new(&ba).unwrap().into_inner())
+                .map(|ba| (BlockHash::from(&ba), ba))
+                .collect();
+            levels.push(next);
+        }
+        // Saved out of order and one size twice.
+        for size in [3, 1, 2, 3] {
+            let level = &levels[size as usize - 1];
+            CacheLayout::Combined.save(level, &store, BlockCount::new(size), size == 3, DEFAULT_HASH_SHARDS, None).expect("Writable temp dir");
+        }
+        assert!(store.list().unwrap().contains(&COMBINED_CACHE_FILE_NAME.to_string()));
+        assert!(!store.list().unwrap().contains(&cache_file_name(BlockCount::new(3))));
+        assert_eq!(BlockCount::ONE.up_to(BlockCount::new(3)).collect::<Vec<_>>(), combined_sizes(&store).unwrap());
+        for (level, size) in levels.iter().zip(BlockCount::ONE.up_to(BlockCount::new(3))) {
+            assert_eq!(*level, load_cache(&store, size).expect("Readable section"));
+        }
+        assert_eq!(levels[2].len(), load_index(&store, BlockCount::new(3)).unwrap().expect("Indexed section").len());
+        assert_eq!(ErrorKind::NotFound, load_cache(&store, BlockCount::new(4)).unwrap_err().kind());
+
+        // A separate file takes precedence over a section until it is combined.
+        save_cache(&levels[1], &store, BlockCount::new(3), false).expect("Writable temp dir");
+        assert_eq!(levels[1], load_cache(&store, BlockCount::new(3)).unwrap());
+        assert_eq!(vec![BlockCount::new(3)], combine_caches(&store).unwrap());
+        assert_eq!(levels[1], load_cache(&store, BlockCount::new(3)).unwrap());
+
+        assert_eq!(3, split_caches(&store).unwrap().len());
+        assert!(combined_sizes(&store).unwrap().is_empty());
+        assert_eq!(levels[0], load_cache(&store, BlockCount::ONE).unwrap());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
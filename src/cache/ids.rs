@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::level::Level;
+use crate::polycube::Polycube;
+use crate::store::CacheStore;
+
+/// The position of a shape in a list of shapes of the same size, like the records of a cache or the
+/// stable ids of [ShapeIds]. Serialized as the bare number.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ShapeId(u64);
+
+impl ShapeId {
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// The id as a position in a slice, which is lossless on every platform a cache of the shapes fits onto.
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Display for ShapeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ShapeId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl From<usize> for ShapeId {
+    fn from(position: usize) -> Self {
+        Self(position as u64)
+    }
+}
+
+/// Stable integer ids of the shapes of a cache. The id of a shape is its rank in the [Ord] of
+/// [Polycube], so it only depends on the set of shapes and not on the order the records were
+/// generated or stored in, and is the same on every machine running the same version of the crate.
+///
+/// The ids file lists the code of every shape in the order of its id, one per line.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct ShapeIds {
+    codes: Vec<String>,
+    ids: HashMap<String, ShapeId>,
+}
+
+impl ShapeIds {
+    pub fn new(shapes: impl IntoIterator<Item = Polycube>) -> Self {
+        let mut shapes: Vec<_> = shapes.into_iter().collect();
+        shapes.sort();
+        shapes.dedup();
+        Self::from_codes(shapes.iter().map(Polycube::code).collect())
+    }
+
+    /// The ids of the shapes of the records of a cache.
+    pub fn of_records<'a>(records: impl Iterator<Item = &'a BlockArrangement>) -> Result<Self, Error> {
+        let shapes = records
+            .map(Polycube::new)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self::new(shapes))
+    }
+
+    fn from_codes(codes: Vec<String>) -> Self {
+        let ids = codes.iter().enumerate().map(|(id, code)| (code.clone(), ShapeId::from(id))).collect();
+        Self { codes, ids }
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    pub fn id(&self, polycube: &Polycube) -> Option<ShapeId> {
+        self.ids.get(&polycube.code()).copied()
+    }
+
+    /// The code of the shape with the id.
+    pub fn code(&self, id: ShapeId) -> Option<&str> {
+        self.codes.get(id.as_usize()).map(String::as_str)
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        for code in &self.codes {
+            writeln!(writer, "{code}")?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(reader: impl Read) -> Result<Self, Error> {
+        let codes = BufReader::new(reader).lines()
+            .filter(|line| !line.as_ref().is_ok_and(|line| line.is_empty()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ids = Self::from_codes(codes);
+        if ids.ids.len() != ids.codes.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "The ids file lists a code more than once"));
+        }
+        Ok(ids)
+    }
+}
+
+/// The name of the ids file of the cache of arrangements with the given number of blocks inside a [CacheStore].
+pub fn ids_file_name(block_count: BlockCount) -> String {
+    format!("shape_cache_{block_count}.ids.idx")
+}
+
+/// The path of the ids file belonging to the cache file at the path, which replaces its `.cac` extension.
+pub fn ids_path(cache_path: impl AsRef<Path>) -> PathBuf {
+    cache_path.as_ref().with_extension("ids.idx")
+}
+
+/// Saves the ids of the arrangements with the given number of blocks next to their cache in the store.
+pub fn save_ids(set: &Level, store: &dyn CacheStore, block_count: BlockCount) -> Result<(), Error> {
+    let mut data = vec![];
+    ShapeIds::of_records(set.values())?.write_to(&mut data)?;
+    store.put(&ids_file_name(block_count), &data)
+}
+
+/// Reads the ids of the shapes with the given number of blocks from the store.
+pub fn load_ids(store: &dyn CacheStore, block_count: BlockCount) -> Result<ShapeIds, Error> {
+    ShapeIds::read_from(store.open(&ids_file_name(block_count))?)
+}
+
+#[cfg(test)]
+mod ids_tests {
+    use crate::block_hash::BlockHash;
+    use crate::poly_tree::PolyTree;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_ids() {
+        let mut tree = PolyTree::new();
+        for _ in 0..4 {
+            tree.generate_next_level();
+        }
+        let shapes: Vec<_> = tree.iter_level(BlockCount::new(5)).collect();
+        let mut reversed = shapes.clone();
+        reversed.reverse();
+        let ids = ShapeIds::of_records(shapes.iter()).expect("Valid shapes");
+        assert_eq!(ids, ShapeIds::of_records(reversed.iter()).unwrap());
+        assert_eq!(23, ids.len());
+        let mut sorted: Vec<_> = shapes.iter().map(|ba| Polycube::new(ba).unwrap()).collect();
+        sorted.sort();
+        for (id, polycube) in sorted.iter().enumerate() {
+            assert_eq!(Some(ShapeId::from(id)), ids.id(polycube));
+            assert_eq!(Some(polycube.code().as_str()), ids.code(ShapeId::from(id)));
+        }
+        assert_eq!(None, ids.code(ShapeId::new(23)));
+
+        let dir = std::env::temp_dir().join("ids_tests_ids");
+        let store = FsStore::new(&dir);
+        let set: Level = shapes.iter().map(|ba| (BlockHash::from(ba), ba.clone())).collect();
+        save_ids(&set, &store, BlockCount::new(5)).expect("Writable temp dir");
+        assert_eq!(ShapeIds::of_records(set.values()).unwrap(), load_ids(&store, BlockCount::new(5)).expect("Readable ids"));
+        assert!(ShapeIds::read_from("1x1x1:01\n1x1x1:01\n".as_bytes()).is_err());
+        assert_eq!(PathBuf::from("dir/shape_cache_5.ids.idx"), ids_path("dir/shape_cache_5.cac"));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
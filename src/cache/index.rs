@@ -0,0 +1,120 @@
+use std::io::{Error, ErrorKind, Read};
+use serde::{Deserialize, Serialize};
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::block_hash::BlockHash;
+use crate::cache::{decode_next, encode_next, open_cache, read_header, Header};
+use crate::polycube::Polycube;
+use crate::store::CacheStore;
+use crate::symmetry::Symmetry;
+
+/// A summary of a shape that is small enough to be stored for every record in the index section
+/// of a cache, so common queries never decode the records.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ShapeMetadata {
+    /// The axis lengths of the bounding box in the canonical orientation.
+    pub bounding_box: [u32; 3],
+    /// The number of block faces that are not shared with another block.
+    pub surface_area: u32,
+    pub symmetry: Symmetry,
+}
+
+impl ShapeMetadata {
+    pub fn of(polycube: &Polycube) -> Self {
+        Self {
+            bounding_box: polycube.occupancy().size,
+            surface_area: polycube.surface_area() as u32,
+            symmetry: Symmetry::of(polycube),
+        }
+    }
+
+    pub fn is_chiral(&self) -> bool {
+        !self.symmetry.is_achiral()
+    }
+}
+
+/// Reads the index section of a cache without touching its records.
+/// Returns `None` if the cache was written without an index.
+pub fn read_index(reader: impl Read) -> Result<Option<Vec<ShapeMetadata>>, Error> {
+    let mut reader = std::io::BufReader::new(reader);
+    match read_header(&mut reader)? {
+        Header::V2 { indexed: true, .. } => read_index_section(&mut reader).map(|(metadata, _)| Some(metadata)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the index section the reader is positioned at, see [encode_index]. The offsets are `None` for
+/// caches written before the index contained them.
+pub(super) fn read_index_section(reader: &mut impl Read) -> Result<(Vec<ShapeMetadata>, Option<Vec<u64>>), Error> {
+    let len: u64 = decode_next(reader)?;
+    let mut section = reader.take(len);
+    let metadata: Vec<ShapeMetadata> = decode_next(&mut section)?;
+    let offsets: Option<Vec<u64>> = match section.limit() {
+        0 => None,
+        _ => Some(decode_next(&mut section)?),
+    };
+    if offsets.as_ref().is_some_and(|offsets| offsets.len() != metadata.len()) {
+        return Err(Error::new(ErrorKind::InvalidData, "The index has another number of offsets than of shapes"));
+    }
+    std::io::copy(&mut section, &mut std::io::sink())?;
+    Ok((metadata, offsets))
+}
+
+/// Reads the index of the cache of arrangements with the given number of blocks from the store.
+pub fn load_index(store: &dyn CacheStore, block_count: BlockCount) -> Result<Option<Vec<ShapeMetadata>>, Error> {
+    read_index(open_cache(store, block_count)?)
+}
+
+/// Encodes the index section for the records, which is its length in bytes followed by the metadata and
+/// the offset of every record in the bytes of its chunk before compression, see [load_record](crate::cache::load_record).
+/// Readers of the metadata ignore the offsets, so older versions still read the index.
+pub(super) fn encode_index<'a>(records: impl Iterator<Item = (&'a BlockHash, &'a BlockArrangement)>, offsets: &[u64]) -> Result<Vec<u8>, Error> {
+    let metadata = records
+        .map(|(_, ba)| Polycube::new(ba).map(|polycube| ShapeMetadata::of(&polycube)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut section = vec![];
+    encode_next(&metadata, &mut section)?;
+    encode_next(offsets, &mut section)?;
+    let mut data = vec![];
+    encode_next(section.len() as u64, &mut data)?;
+    data.extend(section);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod index_tests {
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::cache::{load_cache, save_cache, CacheReader};
+    use crate::level::Level;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let dir = std::env::temp_dir().join("index_tests_index");
+        let store = FsStore::new(&dir);
+        let ba = BlockArrangement::new();
+        let trominoes: Level = VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .map(|ba| (BlockHash::from(&ba), ba))
+            .collect();
+
+        save_cache(&trominoes, &store, BlockCount::new(3), false).expect("Writable temp dir");
+        assert_eq!(None, load_index(&store, BlockCount::new(3)).expect("Readable cache"));
+
+        save_cache(&trominoes, &store, BlockCount::new(3), true).expect("Writable temp dir");
+        let index = load_index(&store, BlockCount::new(3)).expect("Readable cache").expect("Indexed cache");
+        assert_eq!(trominoes.len(), index.len());
+        for (metadata, ba) in index.iter().zip(trominoes.values()) {
+            assert_eq!(ShapeMetadata::of(&Polycube::new(ba).unwrap()), *metadata);
+            assert_eq!(14, metadata.surface_area);
+            assert!(!metadata.is_chiral());
+        }
+        // The records behind the index are still readable.
+        assert_eq!(trominoes.len() as u64, CacheReader::new(store.open("shape_cache_3.cac").unwrap()).unwrap().remaining());
+        assert_eq!(trominoes.len(), load_cache(&store, BlockCount::new(3)).expect("Readable cache").len());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
@@ -0,0 +1,208 @@
+//! A list of the cached levels of a store with their numbers of shapes and checksums. Runs find the
+//! largest cached level in it without opening every cache, and a cache that no longer matches the
+//! manifest is noticed from its header before its records are decoded.
+
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, Read};
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::Xxh3;
+use crate::block_count::BlockCount;
+use crate::cache::{open_cache, read_level, CacheReader};
+use crate::level::Level;
+use crate::store::CacheStore;
+
+/// The file name of the manifest inside a [CacheStore].
+pub const MANIFEST_FILE_NAME: &str = "shape_caches.toml";
+
+/// A cached level listed in a [CacheManifest].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub blocks: BlockCount,
+    pub shapes: u64,
+    /// The number of shapes of the level the shapes were generated from, if they were generated by a run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_shapes: Option<u64>,
+    /// The hexadecimal xxh3 hash of the bytes of the cache, see [checksum].
+    pub checksum: String,
+}
+
+impl ManifestEntry {
+    /// Opens the cache of the entry and checks that its header announces the listed number of shapes.
+    pub fn open(&self, store: &dyn CacheStore) -> Result<CacheReader, Error> {
+        let reader = CacheReader::new(open_cache(store, self.blocks)?)?;
+        match reader.remaining() == self.shapes {
+            true => Ok(reader),
+            false => Err(Error::new(ErrorKind::InvalidData, format!(
+                "The cache contains {} shapes but the manifest lists {}", reader.remaining(), self.shapes,
+            ))),
+        }
+    }
+
+    /// Loads the cache of the entry after checking its header like [ManifestEntry::open].
+    pub fn load(&self, store: &dyn CacheStore) -> Result<Level, Error> {
+        self.open(store)?;
+        read_level(open_cache(store, self.blocks)?)
+    }
+
+    /// Whether the bytes of the cache still have the listed checksum. Reads the whole cache.
+    pub fn check(&self, store: &dyn CacheStore) -> Result<bool, Error> {
+        Ok(checksum(open_cache(store, self.blocks)?)? == self.checksum)
+    }
+}
+
+/// The levels cached in a store, ordered by their number of blocks. Kept up to date by
+/// [CacheLayout::save](crate::cache::CacheLayout::save).
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheManifest {
+    levels: Vec<ManifestEntry>,
+}
+
+impl CacheManifest {
+    /// Reads the manifest of the store, an empty one if there is none.
+    pub fn load(store: &dyn CacheStore) -> Result<Self, Error> {
+        let mut content = String::new();
+        match store.open(MANIFEST_FILE_NAME) {
+            Ok(mut reader) => reader.read_to_string(&mut content)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut manifest: Self = toml::from_str(&content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        manifest.levels.sort_by_key(|entry| entry.blocks);
+        Ok(manifest)
+    }
+
+    pub fn save(&self, store: &dyn CacheStore) -> Result<(), Error> {
+        let content = toml::to_string(self).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        store.put(MANIFEST_FILE_NAME, content.as_bytes())
+    }
+
+    pub fn levels(&self) -> &[ManifestEntry] {
+        &self.levels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn get(&self, blocks: BlockCount) -> Option<&ManifestEntry> {
+        self.levels.iter().find(|entry| entry.blocks == blocks)
+    }
+
+    /// Lists the level, replacing a previous entry with the same number of blocks.
+    pub fn insert(&mut self, entry: ManifestEntry) {
+        match self.levels.binary_search_by_key(&entry.blocks, |entry| entry.blocks) {
+            Ok(position) => self.levels[position] = entry,
+            Err(position) => self.levels.insert(position, entry),
+        }
+    }
+
+    /// The listed levels with fewer blocks than the given number, largest first.
+    pub fn below(&self, blocks: BlockCount) -> impl Iterator<Item = &ManifestEntry> {
+        self.levels.iter().rev().filter(move |entry| entry.blocks < blocks)
+    }
+}
+
+/// Why saving a generated level would replace a cache that was generated differently.
+#[derive(Debug)]
+pub enum LineageError {
+    /// The manifest lists the cached level as generated from a parent level of another size.
+    Parents { listed: u64, actual: u64 },
+    /// The cached level has another number of shapes.
+    Shapes { cached: u64, generated: u64 },
+    /// The manifest or the cached level can not be read.
+    Unreadable(Error),
+}
+
+impl Display for LineageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineageError::Parents { listed, actual } => write!(f, "it was generated from {listed} parents instead of {actual}"),
+            LineageError::Shapes { cached, generated } => write!(f, "it contains {cached} shapes instead of {generated}"),
+            LineageError::Unreadable(e) => write!(f, "it can not be read: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LineageError {}
+
+/// Checks that the cache of a level can be replaced by the given number of shapes generated from the
+/// given number of parents without losing a different result. A listed cache has to be generated from
+/// as many parents, and any cache has to contain as many shapes according to its header. A missing or
+/// corrupted cache can always be replaced.
+pub fn check_lineage(store: &dyn CacheStore, blocks: BlockCount, shapes: u64, parent_shapes: u64) -> Result<(), LineageError> {
+    let manifest = CacheManifest::load(store).map_err(LineageError::Unreadable)?;
+    if let Some(listed) = manifest.get(blocks).and_then(|entry| entry.parent_shapes) {
+        if listed != parent_shapes {
+            return Err(LineageError::Parents { listed, actual: parent_shapes });
+        }
+    }
+    match open_cache(store, blocks).and_then(CacheReader::new) {
+        Ok(reader) if reader.remaining() != shapes => Err(LineageError::Shapes { cached: reader.remaining(), generated: shapes }),
+        Ok(_) => Ok(()),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::InvalidData | ErrorKind::UnexpectedEof) => Ok(()),
+        Err(e) => Err(LineageError::Unreadable(e)),
+    }
+}
+
+/// The hexadecimal xxh3 hash of all bytes of the reader.
+pub fn checksum(mut reader: impl Read) -> Result<String, Error> {
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok(format!("{:016x}", hasher.digest())),
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::block_hash::BlockHash;
+    use crate::cache::{cache_file_name, CacheLayout};
+    use crate::cache::sharded::DEFAULT_HASH_SHARDS;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir().join("manifest_tests_manifest");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        assert!(CacheManifest::load(&store).unwrap().is_empty());
+        let ba = BlockArrangement::new();
+        let dominoes: Level = VariationGenerator::new(&ba).map(|ba| (BlockHash::from(&ba), ba)).collect();
+        CacheLayout::PerSize.save(&dominoes, &store, BlockCount::new(3), false, DEFAULT_HASH_SHARDS, Some(1)).unwrap();
+        CacheLayout::Combined.save(&dominoes, &store, BlockCount::new(2), true, DEFAULT_HASH_SHARDS, None).unwrap();
+
+        let manifest = CacheManifest::load(&store).unwrap();
+        let blocks: Vec<_> = manifest.levels().iter().map(|entry| entry.blocks).collect();
+        assert_eq!(vec![BlockCount::new(2), BlockCount::new(3)], blocks);
+        assert_eq!(dominoes.len() as u64, manifest.get(BlockCount::new(2)).unwrap().shapes);
+        let shapes = dominoes.len() as u64;
+        assert!(check_lineage(&store, BlockCount::new(3), shapes, 1).is_ok());
+        assert!(matches!(check_lineage(&store, BlockCount::new(3), shapes, 2), Err(LineageError::Parents { listed: 1, actual: 2 })));
+        assert!(matches!(check_lineage(&store, BlockCount::new(2), shapes + 1, 2), Err(LineageError::Shapes { .. })));
+        assert!(check_lineage(&store, BlockCount::new(4), 100, 2).is_ok());
+        assert_eq!(Some(BlockCount::new(2)), manifest.below(BlockCount::new(3)).next().map(|entry| entry.blocks));
+        for entry in manifest.levels() {
+            assert!(entry.check(&store).unwrap());
+            assert_eq!(dominoes.len(), entry.open(&store).unwrap().count());
+            assert_eq!(dominoes.len(), entry.load(&store).unwrap().len());
+        }
+
+        // A cache replaced behind the back of the manifest.
+        crate::cache::save_cache(&Level::new(), &store, BlockCount::new(3), false).unwrap();
+        let entry = manifest.get(BlockCount::new(3)).unwrap();
+        assert!(!entry.check(&store).unwrap());
+        assert_eq!(Some(ErrorKind::InvalidData), entry.open(&store).err().map(|e| e.kind()));
+        store.remove(&cache_file_name(BlockCount::new(3))).unwrap();
+        assert_eq!(Some(ErrorKind::NotFound), entry.open(&store).err().map(|e| e.kind()));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
@@ -0,0 +1,397 @@
+//! A level kept in several cache files, its hash shards. A shape belongs to the shard picked by the
+//! leading 32 bits of the xxh3 hash of its encoded [BlockHash], see [hash_shard]. These bits are spread
+//! evenly whatever the shapes look like, so the shards of a level hold about the same number of shapes.
+//! The number of shards is chosen when the level is saved and is part of the file names, so shards of
+//! different counts are never mixed up. The shards are encoded and written in parallel, and no single file
+//! holds the whole level.
+//!
+//! Sharding is only a storage layout. A level is generated and deduplicated as a whole and split into its
+//! shards once it is saved, and an interrupted run continues from its partial shards, not from hash shards.
+//!
+//! Every shard is a complete chunked cache holding its shapes in the order of the level. [open_hash_shards]
+//! joins their chunk tables into the header of a single chunked cache whose chunks are read from the shards
+//! one after the other, so every reader of caches reads a sharded level like a separate file, with the
+//! shapes of one shard after those of the previous one. Positions in a cache, like those of
+//! [load_record](crate::cache::load_record) and of the index, count in this order. [HashShardReader] merges
+//! the shards back into the order of the level instead, which is the order parents are generated in.
+
+use std::collections::VecDeque;
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read};
+use xxhash_rust::xxh3::xxh3_64;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::block_hash::BlockHash;
+use crate::cache::index::{load_index, read_index_section};
+use crate::cache::manifest::{checksum, CacheManifest, ManifestEntry};
+use crate::cache::{cache_file_name, decode_next, encode_cache, encode_next, in_parallel, read_header, read_level, Chunk, CacheFormat, CacheReader, Compression, Header, CHUNKED_VERSION, V2_MAGIC};
+use crate::level::Level;
+use crate::store::CacheStore;
+
+/// The number of hash shards a level is split into unless another number is configured.
+pub const DEFAULT_HASH_SHARDS: u32 = 16;
+
+/// The shard of the shapes with the hash among the given number of shards. The leading 32 bits of the
+/// xxh3 hash of the encoded hash are scaled to the number of shards, so every shard gets an equal range of
+/// them. Equal hashes share a shard, so the shapes whose hashes collide stay together.
+pub fn hash_shard(hash: &BlockHash, shards: u32) -> u32 {
+    let encoded = bincode::serde::encode_to_vec(hash, bincode::config::standard()).expect("Hashes can be encoded.");
+    let prefix = xxh3_64(&encoded) >> 32;
+    ((prefix * u64::from(shards)) >> 32) as u32
+}
+
+fn hash_shard_prefix(block_count: BlockCount) -> String {
+    format!("shape_cache_{block_count}.shard_")
+}
+
+/// The name of the hash shard with the given number among the given number of shards of a level.
+pub fn hash_shard_file_name(block_count: BlockCount, shard: u32, shards: u32) -> String {
+    format!("{}{shard}_of_{shards}.cac", hash_shard_prefix(block_count))
+}
+
+/// Where the hash shards of the given number of blocks are stored, for messages to the user.
+pub fn hash_shards_location(store: &dyn CacheStore, block_count: BlockCount) -> String {
+    store.location(&format!("{}*.cac", hash_shard_prefix(block_count)))
+}
+
+/// Parses `shape_cache_<blocks>.shard_<shard>_of_<shards>.cac`.
+fn parse_hash_shard_name(name: &str) -> Option<(BlockCount, u32, u32)> {
+    let (size, shard) = name.strip_prefix("shape_cache_")?.strip_suffix(".cac")?.split_once(".shard_")?;
+    let (shard, shards) = shard.split_once("_of_")?;
+    Some((size.parse().ok()?, shard.parse().ok()?, shards.parse().ok()?))
+}
+
+/// The numbers of the hash shards of the given number of blocks in the store together with the number of
+/// shards they were saved among, in ascending order.
+fn listed_hash_shards(store: &dyn CacheStore, block_count: BlockCount) -> Result<Vec<(u32, u32)>, Error> {
+    let mut shards: Vec<_> = store.list()?.iter()
+        .filter_map(|name| parse_hash_shard_name(name))
+        .filter(|(size, _, _)| *size == block_count)
+        .map(|(_, shard, shards)| (shard, shards))
+        .collect();
+    shards.sort();
+    Ok(shards)
+}
+
+/// The number of hash shards the level with the given number of blocks is split into, `None` if it has
+/// none. Fails with [ErrorKind::InvalidData] unless the store holds every shard of a single count.
+pub fn hash_shard_count(store: &dyn CacheStore, block_count: BlockCount) -> Result<Option<u32>, Error> {
+    let listed = listed_hash_shards(store, block_count)?;
+    let Some(&(_, shards)) = listed.first() else {
+        return Ok(None);
+    };
+    if !listed.iter().copied().eq((0..shards).map(|shard| (shard, shards))) {
+        let location = hash_shards_location(store, block_count);
+        return Err(Error::new(ErrorKind::InvalidData, format!("The hash shards {location} are incomplete or of different counts")));
+    }
+    Ok(Some(shards))
+}
+
+/// The sizes that are stored in hash shards, in ascending order.
+pub fn sharded_sizes(store: &dyn CacheStore) -> Result<Vec<BlockCount>, Error> {
+    let mut sizes: Vec<_> = store.list()?.iter().filter_map(|name| parse_hash_shard_name(name)).map(|(size, _, _)| size).collect();
+    sizes.sort();
+    sizes.dedup();
+    Ok(sizes)
+}
+
+/// Opens every hash shard of the given number of blocks. Fails with [ErrorKind::NotFound] if there are none.
+fn open_each_hash_shard(store: &dyn CacheStore, block_count: BlockCount) -> Result<Vec<Box<dyn Read + Send>>, Error> {
+    let Some(shards) = hash_shard_count(store, block_count)? else {
+        let location = hash_shards_location(store, block_count);
+        return Err(Error::new(ErrorKind::NotFound, format!("There are no hash shards {location}")));
+    };
+    (0..shards).map(|shard| store.open(&hash_shard_file_name(block_count, shard, shards))).collect()
+}
+
+/// Opens the hash shards of the given number of blocks as a single cache. Fails with [ErrorKind::NotFound]
+/// if there are none.
+pub fn open_hash_shards(store: &dyn CacheStore, block_count: BlockCount) -> Result<Box<dyn Read + Send>, Error> {
+    join(open_each_hash_shard(store, block_count)?)
+}
+
+/// Saves the arrangements with the given number of blocks as the given number of hash shards, optionally
+/// with an index section, and removes the shards of another count. Shards without shapes are saved as empty
+/// caches. Returns the checksum of the level as it is read by [open_hash_shards].
+pub fn put_hash_shards(set: &Level, store: &dyn CacheStore, block_count: BlockCount, shards: u32, indexed: bool) -> Result<String, Error> {
+    if shards == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "A level needs at least one hash shard"));
+    }
+    let mut groups = vec![vec![]; shards as usize];
+    for (hash, ba) in set.iter() {
+        groups[hash_shard(hash, shards) as usize].push((hash, ba));
+    }
+    let encoded = in_parallel(&groups, |group| {
+        let mut data = vec![];
+        encode_cache(&mut data, group.iter().copied(), group.len(), CacheFormat::V2, Compression::None, indexed)?;
+        Ok(data)
+    })?;
+    let checksum = checksum(join(encoded.iter().map(|data| Box::new(data.as_slice()) as Box<dyn Read + Send>).collect())?)?;
+    let numbered: Vec<_> = encoded.iter().enumerate().collect();
+    in_parallel(&numbered, |(shard, data)| store.put(&hash_shard_file_name(block_count, *shard as u32, shards), data))?;
+    for (shard, count) in listed_hash_shards(store, block_count)? {
+        if count != shards {
+            store.remove(&hash_shard_file_name(block_count, shard, count))?;
+        }
+    }
+    Ok(checksum)
+}
+
+/// Removes the hash shards of the given number of blocks, if there are any.
+pub fn remove_hash_shards(store: &dyn CacheStore, block_count: BlockCount) -> Result<(), Error> {
+    for (shard, shards) in listed_hash_shards(store, block_count)? {
+        store.remove(&hash_shard_file_name(block_count, shard, shards))?;
+    }
+    Ok(())
+}
+
+/// Moves the separate cache files of the store into the given number of hash shards, splits the levels that
+/// are sharded among another number again and returns the sizes of both. The shards keep the index of a
+/// level, and the checksums of the moved levels are updated in the manifest.
+pub fn shard_caches(store: &dyn CacheStore, shards: u32) -> Result<Vec<BlockCount>, Error> {
+    let mut manifest = CacheManifest::load(store)?;
+    let mut moved = vec![];
+    for name in store.list()? {
+        let Some(size) = name.strip_prefix("shape_cache_")
+            .and_then(|name| name.strip_suffix(".cac"))
+            .and_then(|size| size.parse::<BlockCount>().ok()) else {
+            continue;
+        };
+        let level = read_level(store.open(&name)?)?;
+        let indexed = load_index(store, size)?.is_some();
+        let checksum = put_hash_shards(&level, store, size, shards, indexed)?;
+        store.remove(&name)?;
+        update_checksum(&mut manifest, size, checksum);
+        moved.push(size);
+    }
+    for size in sharded_sizes(store)? {
+        if moved.contains(&size) || hash_shard_count(store, size)? == Some(shards) {
+            continue;
+        }
+        let level = read_level(open_hash_shards(store, size)?)?;
+        let indexed = load_index(store, size)?.is_some();
+        update_checksum(&mut manifest, size, put_hash_shards(&level, store, size, shards, indexed)?);
+        moved.push(size);
+    }
+    if !moved.is_empty() {
+        manifest.save(store)?;
+    }
+    moved.sort();
+    Ok(moved)
+}
+
+/// Moves the hash shards of every size into a separate cache file in the order of the level and returns
+/// their sizes. The file keeps the index of the shards, and the checksums in the manifest are updated.
+pub fn unshard_caches(store: &dyn CacheStore) -> Result<Vec<BlockCount>, Error> {
+    let mut manifest = CacheManifest::load(store)?;
+    let sizes = sharded_sizes(store)?;
+    for &size in &sizes {
+        let level = read_level(open_hash_shards(store, size)?)?;
+        let indexed = load_index(store, size)?.is_some();
+        let mut data = vec![];
+        encode_cache(&mut data, level.iter(), level.len(), CacheFormat::V2, Compression::None, indexed)?;
+        update_checksum(&mut manifest, size, checksum(data.as_slice())?);
+        store.put(&cache_file_name(size), &data)?;
+        remove_hash_shards(store, size)?;
+    }
+    if !sizes.is_empty() {
+        manifest.save(store)?;
+    }
+    Ok(sizes)
+}
+
+/// Replaces the checksum of the level with the given number of blocks in the manifest, if it is listed.
+fn update_checksum(manifest: &mut CacheManifest, block_count: BlockCount, checksum: String) {
+    if let Some(entry) = manifest.get(block_count) {
+        manifest.insert(ManifestEntry { checksum, ..entry.clone() });
+    }
+}
+
+/// Reads the records of the hash shards of a level in the order of the level, by merging the shards, which
+/// are each in that order, by the hashes of their records.
+pub struct HashShardReader {
+    shards: Vec<CacheReader>,
+    /// The next record of every shard, `None` once it was read completely.
+    heads: Vec<Option<Result<(BlockHash, BlockArrangement), Error>>>,
+    remaining: u64,
+}
+
+impl HashShardReader {
+    /// Opens the hash shards of the given number of blocks. Fails with [ErrorKind::NotFound] if there are none.
+    pub fn open(store: &dyn CacheStore, block_count: BlockCount) -> Result<Self, Error> {
+        let mut shards = open_each_hash_shard(store, block_count)?.into_iter()
+            .map(CacheReader::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let remaining = shards.iter().map(CacheReader::remaining).sum();
+        let heads = shards.iter_mut().map(Iterator::next).collect();
+        Ok(Self { shards, heads, remaining })
+    }
+
+    /// The number of records that have not been read yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl Iterator for HashShardReader {
+    type Item = Result<(BlockHash, BlockArrangement), Error>;
+
+    /// The record with the smallest hash among the next records of the shards, or the first error of a shard.
+    fn next(&mut self) -> Option<Self::Item> {
+        let shard = match self.heads.iter().position(|head| matches!(head, Some(Err(_)))) {
+            Some(shard) => shard,
+            None => self.heads.iter().enumerate()
+                .filter_map(|(shard, head)| match head {
+                    Some(Ok((hash, _))) => Some((hash, shard)),
+                    _ => None,
+                })
+                .min()?.1,
+        };
+        let record = std::mem::replace(&mut self.heads[shard], self.shards[shard].next());
+        self.remaining = self.remaining.saturating_sub(1);
+        record
+    }
+}
+
+/// Reads the header, the index and the chunk table of every shard, and returns a reader of a single chunked
+/// cache with their indices and chunk tables joined in front of the chunks of the shards.
+fn join<'a>(shards: Vec<Box<dyn Read + Send + 'a>>) -> Result<Box<dyn Read + Send + 'a>, Error> {
+    let mut compression = None;
+    let mut metadata = Some(vec![]);
+    let mut offsets = Some(vec![]);
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut bodies: VecDeque<Box<dyn Read + Send + 'a>> = VecDeque::new();
+    for shard in shards {
+        let mut reader = BufReader::new(shard);
+        let Header::V2 { compression: shard_compression, indexed, chunked: true } = read_header(&mut reader)? else {
+            return Err(Error::new(ErrorKind::InvalidData, "A hash shard is not a chunked cache"));
+        };
+        if *compression.get_or_insert(shard_compression) != shard_compression {
+            return Err(Error::new(ErrorKind::InvalidData, "The hash shards are compressed differently"));
+        }
+        match indexed {
+            true => {
+                let (index, shard_offsets) = read_index_section(&mut reader)?;
+                if let Some(metadata) = &mut metadata {
+                    metadata.extend(index);
+                }
+                // The offsets count from the start of the chunk of a record, so they stay valid.
+                match (&mut offsets, shard_offsets) {
+                    (Some(offsets), Some(shard_offsets)) => offsets.extend(shard_offsets),
+                    _ => offsets = None,
+                }
+            }
+            // The joined cache is only indexed if every shard is.
+            false => metadata = None,
+        }
+        chunks.extend(decode_next::<Vec<Chunk>>(&mut reader)?);
+        bodies.push_back(Box::new(reader));
+    }
+    let mut header = V2_MAGIC.to_vec();
+    encode_next(CHUNKED_VERSION, &mut header)?;
+    encode_next(compression.unwrap_or(0), &mut header)?;
+    encode_next(metadata.is_some(), &mut header)?;
+    if let Some(metadata) = metadata {
+        let mut section = vec![];
+        encode_next(&metadata, &mut section)?;
+        if let Some(offsets) = offsets {
+            encode_next(&offsets, &mut section)?;
+        }
+        encode_next(section.len() as u64, &mut header)?;
+        header.extend(section);
+    }
+    encode_next(&chunks, &mut header)?;
+    bodies.push_front(Box::new(Cursor::new(header)));
+    Ok(Box::new(Concatenated(bodies)))
+}
+
+/// Reads the readers one after the other.
+struct Concatenated<'a>(VecDeque<Box<dyn Read + Send + 'a>>);
+
+impl Read for Concatenated<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while let Some(reader) = self.0.front_mut() {
+            match reader.read(buf)? {
+                0 if !buf.is_empty() => {
+                    self.0.pop_front();
+                }
+                read => return Ok(read),
+            }
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod sharded_tests {
+    use std::collections::BTreeSet;
+    use crate::point::Point3D;
+    use crate::block_arrangement::block_variation::generate_variants_from;
+    use crate::cache::{cache_location, load_cache, open_records, CacheLayout};
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_hash_shards() {
+        let dir = std::env::temp_dir().join("sharded_tests_hash_shards");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        let mut level = Level::from_iter([(BlockHash::from(&BlockArrangement::new()), BlockArrangement::new())]);
+        for _ in 0..5 {
+            level = generate_variants_from(level.values(), 0, None).0;
+        }
+        let blocks = BlockCount::new(6);
+        CacheLayout::Sharded.save(&level, &store, blocks, true, 4, Some(2)).unwrap();
+        assert_eq!(Some(4), hash_shard_count(&store, blocks).unwrap());
+        assert!(cache_location(&store, blocks).ends_with("shape_cache_6.shard_*.cac"));
+        let shapes: u64 = (0..4).map(|shard| CacheReader::new(store.open(&hash_shard_file_name(blocks, shard, 4)).unwrap()).unwrap().remaining()).sum();
+        assert_eq!(level.len() as u64, shapes);
+        // The joined shards hold the level with the index of every shard, and are merged back into its order.
+        assert_eq!(level, load_cache(&store, blocks).unwrap());
+        assert_eq!(Some(level.len()), load_index(&store, blocks).unwrap().map(|index| index.len()));
+        let (len, records) = open_records(&store, blocks).unwrap();
+        assert_eq!(level.len() as u64, len);
+        assert!(records.map(Result::unwrap).eq(level.iter().map(|(hash, ba)| (*hash, ba.clone()))));
+        assert!(CacheManifest::load(&store).unwrap().get(blocks).unwrap().check(&store).unwrap());
+
+        // Resharding and moving the shards into a file keep the manifest valid.
+        assert_eq!(vec![blocks], shard_caches(&store, 3).unwrap());
+        assert_eq!(Some(3), hash_shard_count(&store, blocks).unwrap());
+        assert!(CacheManifest::load(&store).unwrap().get(blocks).unwrap().check(&store).unwrap());
+        assert_eq!(vec![blocks], unshard_caches(&store).unwrap());
+        assert_eq!(None, hash_shard_count(&store, blocks).unwrap());
+        assert_eq!(level, load_cache(&store, blocks).unwrap());
+        assert!(CacheManifest::load(&store).unwrap().get(blocks).unwrap().check(&store).unwrap());
+        assert_eq!(vec![blocks], shard_caches(&store, 3).unwrap());
+        assert!(!store.list().unwrap().contains(&cache_file_name(blocks)));
+        assert!(CacheManifest::load(&store).unwrap().get(blocks).unwrap().check(&store).unwrap());
+
+        // A missing shard is reported instead of reading an incomplete level.
+        store.remove(&hash_shard_file_name(blocks, 1, 3)).unwrap();
+        assert_eq!(ErrorKind::InvalidData, load_cache(&store, blocks).unwrap_err().kind());
+
+        // Saving an empty level leaves empty shards, and another layout removes the shards.
+        put_hash_shards(&Level::default(), &store, blocks, 2, false).unwrap();
+        assert_eq!(Some(2), hash_shard_count(&store, blocks).unwrap());
+        assert!(load_cache(&store, blocks).unwrap().is_empty());
+        CacheLayout::PerSize.save(&level, &store, blocks, false, 2, Some(2)).unwrap();
+        assert!(sharded_sizes(&store).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_even_shards() {
+        // L shapes with arms of every pair of lengths, whose hashes differ in few of their parts.
+        let hashes: BTreeSet<_> = (1..36).flat_map(|x| (1..36).map(move |y| (x, y)))
+            .map(|(x, y)| {
+                let points: Vec<_> = (0..x).map(|i| Point3D::new(i, 0, 0)).chain((1..y).map(|j| Point3D::new(0, j, 0))).collect();
+                BlockHash::from(&BlockArrangement::from_points(&points).unwrap())
+            })
+            .collect();
+        for shards in [1, 4, 7] {
+            let mut sizes = vec![0; shards as usize];
+            hashes.iter().for_each(|hash| sizes[hash_shard(hash, shards) as usize] += 1);
+            let mean = hashes.len() / shards as usize;
+            assert!(sizes.iter().all(|&size| mean * 3 / 4 <= size && size <= mean * 5 / 4), "Uneven shards {sizes:?} of {} hashes", hashes.len());
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, Read};
+use crate::block_arrangement::{BlockArrangement, V1Arrangement};
+use crate::block_count::BlockCount;
+#[cfg(feature = "decimal-metrics")]
+use crate::block_hash::BlockHash;
+use crate::block_hash::V1BlockHash;
+use crate::cache::{decode_next, CacheFormat, CacheReader};
+use crate::polycube::{Occupancy, Polycube, PolycubeError};
+
+/// Something wrong with a record of a cache found by [verify_cache].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RecordProblem {
+    /// The record could not be decoded, so none of the following records were checked.
+    Unreadable(String),
+    /// The stored blocks do not describe a shape.
+    Malformed,
+    /// Not every block is reachable from every other block over shared faces.
+    Disconnected,
+    /// The stored hash of a [CacheFormat::V1] record differs from the one computed from its arrangement.
+    KeyMismatch,
+    /// The stored occupancy of a [CacheFormat::V2] record is not the canonical orientation of its shape.
+    NotCanonical,
+    /// The shape has another number of blocks than the first record.
+    WrongBlockCount { expected: BlockCount, found: BlockCount },
+    /// The same shape was already stored in an earlier record.
+    Duplicate { first: usize },
+}
+
+impl Display for RecordProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordProblem::Unreadable(e) => write!(f, "unreadable: {e}"),
+            RecordProblem::Malformed => f.write_str("the stored blocks do not describe a shape"),
+            RecordProblem::Disconnected => f.write_str("the blocks are not connected"),
+            RecordProblem::KeyMismatch => f.write_str("the stored key differs from the recomputed one"),
+            RecordProblem::NotCanonical => f.write_str("the stored orientation is not the canonical one"),
+            RecordProblem::WrongBlockCount { expected, found } => write!(f, "{found} blocks instead of {expected}"),
+            RecordProblem::Duplicate { first } => write!(f, "the shape is already stored in record {first}"),
+        }
+    }
+}
+
+/// The outcome of [verify_cache].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VerifyReport {
+    pub format: CacheFormat,
+    /// The number of records the cache claims to contain.
+    pub records: u64,
+    /// The position of every record with a problem, in ascending order.
+    pub problems: Vec<(usize, RecordProblem)>,
+}
+
+impl VerifyReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Reads every record of a cache exactly as it is stored, recomputes its canonical form and key and
+/// reports every record that disagrees with them, is disconnected or repeats an earlier shape.
+/// Only a cache whose header can not be read is an error.
+pub fn verify_cache(reader: impl Read + 'static) -> Result<VerifyReport, Error> {
+    let mut reader = CacheReader::new(reader)?;
+    let mut report = VerifyReport { format: reader.format, records: reader.remaining, problems: vec![] };
+    let mut first_records = HashMap::new();
+    let mut block_count = None;
+    for position in 0..report.records as usize {
+        let checked = match reader.format {
+            CacheFormat::V1 => decode_next::<(V1BlockHash, V1Arrangement)>(&mut reader.reader)
+                .map(|(hash, stored)| BlockArrangement::try_from(stored)
+                    .map_err(|_| RecordProblem::Malformed)
+                    .and_then(|ba| check_v1_record(&hash, &ba))),
+            CacheFormat::V2 => decode_next::<Occupancy>(&mut reader.reader)
+                .map(|occupancy| check_v2_record(&occupancy)),
+        };
+        let polycube = match checked {
+            Ok(Ok(polycube)) => polycube,
+            Ok(Err(problem)) => {
+                report.problems.push((position, problem));
+                continue;
+            }
+            Err(e) => {
+                report.problems.push((position, RecordProblem::Unreadable(e.to_string())));
+                break;
+            }
+        };
+        let found = polycube.num_blocks();
+        let expected = *block_count.get_or_insert(found);
+        if found != expected {
+            report.problems.push((position, RecordProblem::WrongBlockCount { expected, found }));
+        }
+        if let Some(first) = first_records.insert(polycube.code(), position) {
+            report.problems.push((position, RecordProblem::Duplicate { first }));
+            first_records.insert(polycube.code(), first);
+        }
+    }
+    Ok(report)
+}
+
+fn check_v1_record(hash: &V1BlockHash, ba: &BlockArrangement) -> Result<Polycube, RecordProblem> {
+    if !ba.is_connected() {
+        return Err(RecordProblem::Disconnected);
+    }
+    // The stored decimals can only be recomputed with the feature.
+    #[cfg(feature = "decimal-metrics")]
+    if V1BlockHash::from(&BlockHash::from(ba)) != *hash {
+        return Err(RecordProblem::KeyMismatch);
+    }
+    #[cfg(not(feature = "decimal-metrics"))]
+    let _ = hash;
+    Polycube::new(ba).map_err(|_| RecordProblem::Disconnected)
+}
+
+fn check_v2_record(occupancy: &Occupancy) -> Result<Polycube, RecordProblem> {
+    let polycube = Polycube::from_occupancy(occupancy).map_err(|e| match e {
+        PolycubeError::Disconnected => RecordProblem::Disconnected,
+        PolycubeError::InvalidCode => RecordProblem::Malformed,
+    })?;
+    match polycube.occupancy() == *occupancy {
+        true => Ok(polycube),
+        false => Err(RecordProblem::NotCanonical),
+    }
+}
+
+#[cfg(test)]
+mod verify_tests {
+    #[cfg(feature = "decimal-metrics")]
+    use crate::cache::{encode_cache, Compression};
+    use crate::cache::{encode_next, V2_MAGIC};
+    use crate::point::Point3D;
+    use super::*;
+
+    fn shape(points: &[(i32, i32, i32)]) -> BlockArrangement {
+        let points: Vec<_> = points.iter().map(|(x, y, z)| Point3D::new(*x, *y, *z)).collect();
+        BlockArrangement::from_points(&points).unwrap()
+    }
+
+    #[test]
+    fn test_verify_v2() {
+        let line = Polycube::new(&shape(&[(0, 0, 0), (1, 0, 0), (2, 0, 0)])).unwrap().occupancy();
+        let l_tromino = Polycube::new(&shape(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)])).unwrap().occupancy();
+        let [a, b, c] = line.size;
+        let standing_line = Occupancy { size: [c, b, a], bits: line.bits.clone() };
+        let gap = Occupancy { size: [3, 1, 1], bits: vec![0b101] };
+        let domino = Polycube::new(&shape(&[(0, 0, 0), (1, 0, 0)])).unwrap().occupancy();
+        let records = [line.clone(), l_tromino.clone(), standing_line, gap, domino, l_tromino];
+
+        let mut data = V2_MAGIC.to_vec();
+        for value in [2u8, 0u8] {
+            encode_next(value, &mut data).unwrap();
+        }
+        encode_next(records.len() as u64 + 1, &mut data).unwrap();
+        for occupancy in &records {
+            encode_next(occupancy, &mut data).unwrap();
+        }
+        let report = verify_cache(std::io::Cursor::new(data)).expect("Readable header");
+        assert_eq!(CacheFormat::V2, report.format);
+        assert_eq!(7, report.records);
+        assert_eq!(vec![2, 3, 4, 5, 6], report.problems.iter().map(|(position, _)| *position).collect::<Vec<_>>());
+        let [not_canonical, gap, domino, duplicate, truncated]: [RecordProblem; 5] = report.problems.into_iter()
+            .map(|(_, problem)| problem)
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Five problems");
+        assert_eq!(RecordProblem::NotCanonical, not_canonical);
+        assert_eq!(RecordProblem::Disconnected, gap);
+        assert_eq!(RecordProblem::WrongBlockCount { expected: BlockCount::new(3), found: BlockCount::new(2) }, domino);
+        assert_eq!(RecordProblem::Duplicate { first: 1 }, duplicate);
+        assert!(matches!(truncated, RecordProblem::Unreadable(_)));
+    }
+
+    #[test]
+    #[cfg(feature = "decimal-metrics")]
+    fn test_verify_v1() {
+        let line = shape(&[(0, 0, 0), (1, 0, 0), (2, 0, 0)]);
+        let l_tromino = shape(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)]);
+        let records = [(BlockHash::from(&line), line.clone()), (BlockHash::from(&line), l_tromino)];
+        let mut data = vec![];
+        encode_cache(&mut data, records.iter().map(|(hash, ba)| (hash, ba)), 2, CacheFormat::V1, Compression::None, false).unwrap();
+        let report = verify_cache(std::io::Cursor::new(data)).expect("Readable header");
+        assert_eq!(vec![(1, RecordProblem::KeyMismatch)], report.problems);
+
+        let mut data = vec![];
+        encode_cache(&mut data, records[..1].iter().map(|(hash, ba)| (hash, ba)), 1, CacheFormat::V1, Compression::None, false).unwrap();
+        assert!(verify_cache(std::io::Cursor::new(data)).unwrap().is_valid());
+    }
+}
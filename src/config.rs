@@ -0,0 +1,360 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::thread;
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+use crate::block_count::BlockCount;
+use crate::cache::CacheLayout;
+use crate::cache::sharded::DEFAULT_HASH_SHARDS;
+use crate::export::ExportFormat;
+use crate::block_arrangement::block_variation::NeighborOrder;
+use crate::orientation::OrientationOrder;
+use crate::polycube::Polycube;
+use crate::store::{open_store, CacheStore};
+use crate::symmetry::is_asymmetric;
+
+/// The file name the effective configuration of a run is stored under inside the cache directory.
+pub const RUN_CONFIG_FILE_NAME: &str = "run_config.toml";
+
+/// The factor by which the number of unique arrangements is expected to grow from one block count
+/// to the next. Used to pre-size the deduplication map of a new level.
+pub const DEFAULT_GROWTH_FACTOR: f64 = 8.0;
+
+/// The default number of parents per partial shard of a level.
+pub const DEFAULT_SHARD_SIZE: usize = 100_000;
+
+/// How the shapes of a run are enumerated, see [strategy](crate::strategy) for the implementations.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(EnumString, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// Deduplicates every level in a hash map and stores it as a cache file.
+    #[default]
+    HashMap,
+    /// Grows a [PolyTree](crate::poly_tree::PolyTree).
+    PolyTree,
+    /// Adds two blocks at a time without keeping the levels in between.
+    DoubleExtension,
+    /// Removes blocks from the boxes the shapes fit into.
+    Subtractive,
+}
+
+/// Restricts which shapes are written to the output files of a run.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFilter {
+    /// Only shapes that differ from their mirror image.
+    Chiral,
+    /// Only shapes that are identical to their mirror image.
+    Achiral,
+    /// Only shapes whose only symmetry is the identity, see [is_asymmetric].
+    Asymmetric,
+    /// Only shapes with a symmetry other than the identity.
+    Symmetric,
+}
+
+impl OutputFilter {
+    pub fn accepts(&self, polycube: &Polycube) -> bool {
+        match self {
+            OutputFilter::Chiral => polycube.is_chiral(),
+            OutputFilter::Achiral => !polycube.is_chiral(),
+            OutputFilter::Asymmetric => is_asymmetric(polycube),
+            OutputFilter::Symmetric => !is_asymmetric(polycube),
+        }
+    }
+}
+
+/// Everything that determines the outcome of a run.
+/// Can be read from a toml file and overridden from the command line.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    /// The number of blocks of the shapes to count.
+    pub n: BlockCount,
+    pub algorithm: Algorithm,
+    /// The number of threads used by [Algorithm::PolyTree] and by [RunConfig::count_only] runs.
+    pub threads: usize,
+    /// The maximum number of bytes pre-allocated for the deduplication map of a level.
+    pub memory_budget: Option<u64>,
+    pub growth_factor: f64,
+    /// When set, [Algorithm::HashMap] checks the children of every shard against a bloom filter of this many
+    /// bytes before deduplicating them, see
+    /// [generate_variants_filtered](crate::block_arrangement::block_variation::generate_variants_filtered).
+    /// A larger filter takes more memory and reports fewer new shapes as probable duplicates that are looked up.
+    pub bloom_filter_bytes: Option<u64>,
+    /// The number of parents whose children are saved together as a partial shard of a level,
+    /// so an interrupted run only redoes the unfinished shards.
+    pub shard_size: usize,
+    /// When set, [Algorithm::HashMap] ends a shard early once it was generated for this many seconds and
+    /// saves it as a checkpoint, see [checkpoint](crate::cache::checkpoint), so a run that dies loses at most
+    /// this much of the work on a level. Zero saves the children of every parent on their own.
+    pub checkpoint_seconds: Option<u64>,
+    /// Whether [Algorithm::HashMap] reads the parents of every level from its cache one at a time instead
+    /// of holding the whole level in memory, so only the children of the level being generated are kept.
+    pub stream_parents: bool,
+    /// A directory or, with the `s3` feature, an `s3://<bucket>/<prefix>` url, see [open_store].
+    pub cache_dir: PathBuf,
+    /// Whether caches are written with an [index](crate::cache::index) section.
+    pub cache_index: bool,
+    /// Whether the caches of the sizes are kept in separate files or a single combined one.
+    pub cache_layout: CacheLayout,
+    /// The number of hash shards every level is split into with [CacheLayout::Sharded].
+    pub cache_shards: u32,
+    /// Whether caches may be replaced by levels that were generated from other parents or contain another
+    /// number of shapes, see [check_lineage](crate::cache::manifest::check_lineage).
+    pub force: bool,
+    /// When set, only shapes whose bounding box is at most this long along its shortest axis are enumerated.
+    /// Their caches are kept in the `layers_<k>` subdirectory of the cache directory.
+    pub max_layers: Option<u32>,
+    /// When set, [Algorithm::HashMap] levels are expanded by workers connecting to this address.
+    pub listen: Option<String>,
+    /// When set, the progress of the run is served as JSON over HTTP on this port or address,
+    /// see [dashboard](crate::dashboard).
+    pub serve: Option<String>,
+    /// When set, the insertions of every [Algorithm::PolyTree] level are recorded to this file,
+    /// see [Trace](crate::poly_tree::trace::Trace).
+    pub trace: Option<PathBuf>,
+    /// The formats the shapes with n blocks are exported to, one file per format in the cache directory.
+    pub output_formats: Vec<ExportFormat>,
+    /// Filters every exported shape has to pass.
+    pub filters: Vec<OutputFilter>,
+    /// The codes of shapes with one block less than n. When given, only their children are exported,
+    /// as recorded by the parents of the [PolyTree](crate::poly_tree::PolyTree), so a pipeline following
+    /// some families of shapes only processes the new members of these families.
+    pub descendants_of: Vec<String>,
+    /// The order arrangements are compared in, see [OrientationRanking](crate::orientation::OrientationRanking).
+    pub orientation_order: OrientationOrder,
+    /// The order children are generated in, see [NeighborOrder].
+    pub neighbor_order: NeighborOrder,
+    /// When set, the run stops once it generated this many shapes, counting the shapes of every level.
+    /// See [StopPoint](crate::run::StopPoint) for how a stopped run is continued.
+    pub stop_after_shapes: Option<u64>,
+    /// When set, the run stops once it ran for this many seconds, see [RunConfig::stop_after_shapes].
+    pub stop_after_seconds: Option<u64>,
+    /// When set, the shapes with n blocks are only counted by [canonical augmentation](crate::augmentation)
+    /// instead of being enumerated by the algorithm, so no level is ever held as a whole, cached or exported.
+    pub count_only: bool,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            n: BlockCount::default(),
+            algorithm: Algorithm::default(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            memory_budget: None,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            bloom_filter_bytes: None,
+            shard_size: DEFAULT_SHARD_SIZE,
+            checkpoint_seconds: None,
+            stream_parents: false,
+            cache_dir: PathBuf::from("."),
+            cache_index: false,
+            cache_layout: CacheLayout::default(),
+            cache_shards: DEFAULT_HASH_SHARDS,
+            force: false,
+            max_layers: None,
+            listen: None,
+            serve: None,
+            trace: None,
+            output_formats: vec![],
+            filters: vec![],
+            descendants_of: vec![],
+            orientation_order: OrientationOrder::default(),
+            neighbor_order: NeighborOrder::default(),
+            stop_after_shapes: None,
+            stop_after_seconds: None,
+            count_only: false,
+        }
+    }
+}
+
+impl RunConfig {
+    /// Reads a configuration from a toml file. Missing values keep their defaults.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Opens the store the caches and outputs of the run are kept in.
+    pub fn store(&self) -> Result<Box<dyn CacheStore>, Error> {
+        open_store(self.store_dir())
+    }
+
+    fn store_dir(&self) -> PathBuf {
+        match self.max_layers {
+            Some(layers) => self.cache_dir.join(format!("layers_{layers}")),
+            None => self.cache_dir.clone(),
+        }
+    }
+
+    /// Opens the store of a run whose levels are pruned by the [ShapeFilter](crate::shape_filter::ShapeFilter)s
+    /// with the given names, a `filtered_<names>` subdirectory of the directory of [RunConfig::store].
+    pub fn filtered_store(&self, names: &[String]) -> Result<Box<dyn CacheStore>, Error> {
+        open_store(self.store_dir().join(format!("filtered_{}", names.join("_"))))
+    }
+
+    /// Stores the configuration as [RUN_CONFIG_FILE_NAME] in the store.
+    pub fn save(&self, store: &dyn CacheStore) -> Result<(), Error> {
+        store.put(RUN_CONFIG_FILE_NAME, self.to_string().as_bytes())
+    }
+
+    /// The address the dashboard listens on. A bare port listens on every interface.
+    pub fn serve_address(&self) -> Option<String> {
+        self.serve.as_ref().map(|serve| match serve.parse::<u16>() {
+            Ok(port) => format!("0.0.0.0:{port}"),
+            Err(_) => serve.clone(),
+        })
+    }
+
+    /// Whether a run of the config checks its stop conditions and an interrupt, which is only done
+    /// where it saves the partial shards to continue from.
+    pub fn can_stop_early(&self) -> bool {
+        self.algorithm == Algorithm::HashMap && self.listen.is_none() && !self.count_only
+    }
+
+    /// Checks the combinations of values that can not be run.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.n == BlockCount::default() {
+            return Err("Expected the number of blocks as an argument or in the config");
+        }
+        match self.max_layers {
+            Some(0) => return Err("Shapes need at least one layer"),
+            Some(_) if self.algorithm != Algorithm::HashMap => return Err("Only the hash map supports --max-layers"),
+            _ => {}
+        }
+        if self.cache_shards == 0 {
+            return Err("A level needs at least one hash shard");
+        }
+        match self.bloom_filter_bytes {
+            Some(0) => return Err("A bloom filter needs at least one byte"),
+            Some(_) if self.algorithm != Algorithm::HashMap => return Err("Only the hash map supports --bloom-filter-bytes"),
+            _ => {}
+        }
+        if self.checkpoint_seconds.is_some() && self.algorithm != Algorithm::HashMap {
+            return Err("Only the hash map supports --checkpoint-seconds");
+        }
+        if self.trace.is_some() && self.algorithm != Algorithm::PolyTree {
+            return Err("Only the poly tree supports --trace");
+        }
+        if !self.descendants_of.is_empty() && self.algorithm != Algorithm::PolyTree {
+            return Err("Only the poly tree records the parents --descendants-of needs");
+        }
+        if self.stream_parents {
+            if self.algorithm != Algorithm::HashMap {
+                return Err("Only the hash map supports --stream-parents");
+            }
+            if self.listen.is_some() {
+                return Err("Workers are sent the parents from memory, so they can not be streamed");
+            }
+        }
+        if self.stop_after_shapes.is_some() || self.stop_after_seconds.is_some() {
+            if self.algorithm != Algorithm::HashMap {
+                return Err("Only the hash map saves the partial shards a stopped run continues from");
+            }
+            if self.listen.is_some() {
+                return Err("A run distributed to workers can not be stopped early");
+            }
+        }
+        if self.count_only {
+            if !self.output_formats.is_empty() || !self.filters.is_empty() || !self.descendants_of.is_empty() {
+                return Err("A run with --count-only keeps no shapes to export or filter");
+            }
+            if self.max_layers.is_some() || self.listen.is_some() || self.stop_after_shapes.is_some() || self.stop_after_seconds.is_some() {
+                return Err("A run with --count-only can not be limited, distributed or stopped early");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the shape passes all filters of the run.
+    pub fn accepts(&self, polycube: &Polycube) -> bool {
+        self.filters.iter().all(|filter| filter.accepts(polycube))
+    }
+}
+
+impl Display for RunConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let toml = toml::to_string(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&toml)
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::str::FromStr;
+    use crate::block_arrangement::BlockArrangement;
+    use crate::point::Point3D;
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: RunConfig = toml::from_str(r#"
+            n = 6
+            algorithm = "poly-tree"
+            memory_budget = 1048576
+            cache_dir = "caches"
+            output_formats = ["json", "code"]
+            filters = ["chiral"]
+        "#).expect("Valid config");
+        assert_eq!(BlockCount::new(6), config.n);
+        assert_eq!(Algorithm::PolyTree, config.algorithm);
+        assert_eq!(Some(1048576), config.memory_budget);
+        assert_eq!(PathBuf::from("caches"), config.cache_dir);
+        assert_eq!(vec![ExportFormat::Json, ExportFormat::Code], config.output_formats);
+        assert_eq!(vec![OutputFilter::Chiral], config.filters);
+        assert_eq!(DEFAULT_GROWTH_FACTOR, config.growth_factor);
+        assert!(toml::from_str::<RunConfig>("blocks = 3").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = RunConfig {
+            n: BlockCount::new(4),
+            memory_budget: Some(10),
+            output_formats: vec![ExportFormat::Obj],
+            filters: vec![OutputFilter::Achiral],
+            orientation_order: OrientationOrder::HitRate,
+            cache_layout: CacheLayout::Combined,
+            cache_shards: 64,
+            stop_after_seconds: Some(3600),
+            bloom_filter_bytes: Some(1 << 20),
+            ..RunConfig::default()
+        };
+        let parsed: RunConfig = toml::from_str(&config.to_string()).expect("Valid config");
+        assert_eq!(config, parsed);
+    }
+
+    #[test]
+    fn test_serve_address() {
+        assert_eq!(None, RunConfig::default().serve_address());
+        let config = RunConfig { serve: Some("8080".to_string()), ..RunConfig::default() };
+        assert_eq!(Some("0.0.0.0:8080".to_string()), config.serve_address());
+        let config = RunConfig { serve: Some("127.0.0.1:9000".to_string()), ..RunConfig::default() };
+        assert_eq!(Some("127.0.0.1:9000".to_string()), config.serve_address());
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut twisted = BlockArrangement::new();
+        for p in [Point3D::new(1, 0, 0), Point3D::new(1, 1, 0), Point3D::new(1, 1, 1)] {
+            twisted.add_block_at(&p).expect("Checked coordinates.");
+        }
+        let twisted = Polycube::new(&twisted).expect("Connected shape");
+        let line = Polycube::new(&BlockArrangement::new()).expect("Connected shape");
+        let config = RunConfig { filters: vec![OutputFilter::Chiral], ..RunConfig::default() };
+        assert!(config.accepts(&twisted));
+        assert!(!config.accepts(&line));
+        assert!(RunConfig::default().accepts(&line));
+
+        // The twisted shape is chiral but still has a half turn symmetry.
+        let config = RunConfig { filters: vec![OutputFilter::Chiral, OutputFilter::Symmetric], ..RunConfig::default() };
+        assert!(config.accepts(&twisted));
+        assert!(!RunConfig { filters: vec![OutputFilter::from_str("asymmetric").unwrap()], ..RunConfig::default() }.accepts(&twisted));
+    }
+}
@@ -0,0 +1,136 @@
+//! Decides whether two arrangements are the same shape, i.e. whether a rotation or reflection followed by
+//! a translation moves the blocks of one onto the blocks of the other. Every comparison of shapes goes
+//! through [congruent] or an [EqualityStrategy] chosen here, so the choice is made in one place.
+//!
+//! The orientation loop often finds a matching orientation after a few tries, but has to try all 48
+//! [rotations and reflections](rotations_and_reflections) before it rejects a shape. The bit-parallel
+//! comparison costs more per orientation, but rejects shapes with other bounding boxes at once, and most
+//! shapes that are compared differ. Comparing canonical forms is cheapest of all, but computing them with
+//! [BlockArrangement::canonical] only pays off if they are kept, as [Polycube](crate::polycube::Polycube)s do.
+
+use strum::{Display, EnumIter, EnumString};
+use crate::block_arrangement::BlockArrangement;
+use crate::block_arrangement::row_masks::RowMasks;
+use crate::block_count::BlockCount;
+use crate::orientation::rotations_and_reflections;
+use crate::point::Point3D;
+
+/// Shapes up to this number of blocks are compared by the [EqualityStrategy::OrientationLoop]. This is a
+/// heuristic rather than a measured crossover: the blocks of such small shapes are compared in hardly any
+/// time in every orientation, so building the row masks of the bit-parallel comparison is not worth it.
+const ORIENTATION_LOOP_MAX_BLOCKS: BlockCount = BlockCount::new(2);
+
+/// The ways two arrangements can be compared, see the module documentation for how they differ.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display, EnumIter)]
+#[strum(serialize_all = "kebab-case")]
+pub enum EqualityStrategy {
+    /// Tries the orientations in the order of the [OrientationRanking](crate::orientation::OrientationRanking)
+    /// and compares the blocks relative to their centers of mass. Compares colors as well.
+    OrientationLoop,
+    /// Compares the blocks as they are, which is only correct for arrangements in their canonical form,
+    /// like those of [Polycube](crate::polycube::Polycube)s. Compares colors as well.
+    CanonicalForm,
+    /// Compares the row masks of the 48 distinct orientations of one arrangement with those of the other
+    /// after checking their bounding boxes. Ignores colors.
+    BitParallel,
+}
+
+impl EqualityStrategy {
+    /// The fastest strategy for arrangements with the given number of blocks. `canonical` tells that
+    /// both arrangements are in their canonical form already, and colored arrangements are never compared
+    /// by a strategy that ignores colors.
+    pub fn choose(blocks: BlockCount, canonical: bool, colored: bool) -> Self {
+        match (canonical, colored) {
+            (true, _) => EqualityStrategy::CanonicalForm,
+            (false, true) => EqualityStrategy::OrientationLoop,
+            (false, false) if blocks <= ORIENTATION_LOOP_MAX_BLOCKS => EqualityStrategy::OrientationLoop,
+            (false, false) => EqualityStrategy::BitParallel,
+        }
+    }
+
+    pub fn congruent(&self, a: &BlockArrangement, b: &BlockArrangement) -> bool {
+        if a.num_blocks() != b.num_blocks() {
+            return false;
+        }
+        match self {
+            EqualityStrategy::OrientationLoop => a.matches_in_some_orientation(b),
+            EqualityStrategy::CanonicalForm => a.block_iter().eq(b.block_iter())
+                && (!a.is_colored() && !b.is_colored() || a.color_iter().eq(b.color_iter())),
+            EqualityStrategy::BitParallel => bit_parallel(a, b)
+                .unwrap_or_else(|| a.matches_in_some_orientation(b)),
+        }
+    }
+}
+
+/// Whether the arrangements are the same shape, compared with the strategy [EqualityStrategy::choose]s
+/// for arrangements that are not known to be canonical.
+pub fn congruent(a: &BlockArrangement, b: &BlockArrangement) -> bool {
+    let colored = a.is_colored() || b.is_colored();
+    EqualityStrategy::choose(a.num_blocks(), false, colored).congruent(a, b)
+}
+
+/// Compares the arrangements bit-parallel, `None` if the other arrangement is too wide for [RowMasks].
+fn bit_parallel(a: &BlockArrangement, b: &BlockArrangement) -> Option<bool> {
+    let target = RowMasks::new(b.block_iter())?;
+    let mut target_size = target.size();
+    target_size.sort_unstable();
+    let blocks: Vec<_> = a.block_iter().collect();
+    if sorted_size(&blocks) != target_size {
+        return Some(false);
+    }
+    Some(rotations_and_reflections().any(|orientation| {
+        let oriented = blocks.iter().map(|p| {
+            let mut p = *p;
+            p.apply_orientation(&orientation);
+            p
+        });
+        // An orientation that makes the blocks too wide can not match the narrower other arrangement.
+        RowMasks::new(oriented).is_some_and(|masks| masks.same_shape(&target))
+    }))
+}
+
+/// The axis lengths of the bounding box of the points, shortest first.
+fn sorted_size(points: &[Point3D<i32>]) -> [usize; 3] {
+    let mut size = [0, 1, 2].map(|axis| {
+        let coordinates = points.iter().map(|p| [*p.x(), *p.y(), *p.z()][axis]);
+        let (min, max) = coordinates.fold((i32::MAX, i32::MIN), |(min, max), c| (min.min(c), max.max(c)));
+        (max - min) as usize + 1
+    });
+    size.sort_unstable();
+    size
+}
+
+#[cfg(test)]
+mod congruence_tests {
+    use strum::IntoEnumIterator;
+    use crate::polycube::Polycube;
+    use crate::transforms::{golden_set, transformed_copies};
+    use super::*;
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(EqualityStrategy::CanonicalForm, EqualityStrategy::choose(BlockCount::new(9), true, false));
+        assert_eq!(EqualityStrategy::OrientationLoop, EqualityStrategy::choose(BlockCount::new(2), false, false));
+        assert_eq!(EqualityStrategy::OrientationLoop, EqualityStrategy::choose(BlockCount::new(9), false, true));
+        assert_eq!(EqualityStrategy::BitParallel, EqualityStrategy::choose(BlockCount::new(9), false, false));
+
+        let shapes: Vec<_> = golden_set().into_iter().filter(|polycube| polycube.num_blocks() == BlockCount::new(4)).collect();
+        for (i, a) in shapes.iter().enumerate() {
+            let copy = &transformed_copies(a.arrangement())[17];
+            for (j, b) in shapes.iter().enumerate() {
+                for strategy in EqualityStrategy::iter() {
+                    assert_eq!(i == j, strategy.congruent(a.arrangement(), b.arrangement()), "{strategy} on {} and {}", a.code(), b.code());
+                    if strategy != EqualityStrategy::CanonicalForm {
+                        assert_eq!(i == j, strategy.congruent(copy, b.arrangement()), "{strategy} on a copy of {} and {}", a.code(), b.code());
+                    }
+                }
+                assert_eq!(i == j, congruent(copy, b.arrangement()));
+            }
+        }
+        assert!(EqualityStrategy::CanonicalForm.congruent(
+            Polycube::new(&transformed_copies(shapes[0].arrangement())[5]).unwrap().arrangement(),
+            shapes[0].arrangement(),
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+use std::io::{BufRead, BufReader, Error, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+use serde::Serialize;
+use crate::block_count::BlockCount;
+
+/// The state of a worker connected to a [Coordinator](crate::distributed::Coordinator).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkerState {
+    Idle,
+    Busy,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct WorkerReport {
+    pub address: String,
+    pub state: WorkerState,
+    /// The range of parents the worker is expanding right now.
+    pub range: Option<Range<usize>>,
+    /// The number of ranges the worker completed.
+    pub completed: usize,
+}
+
+/// A level that has been generated completely.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct LevelReport {
+    pub block_count: BlockCount,
+    pub shapes: usize,
+    /// The number of one sided shapes, which tells mirror images apart, `None` if they were not counted.
+    pub one_sided: Option<usize>,
+    pub seconds: f64,
+}
+
+/// The level that is being generated.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct CurrentLevel {
+    pub block_count: BlockCount,
+    pub parents: usize,
+    pub parents_done: usize,
+    pub seconds: f64,
+    /// The remaining seconds of the level, extrapolated from the parents expanded so far.
+    pub eta_seconds: Option<f64>,
+}
+
+/// Everything the dashboard reports, serialized as the JSON body of every response.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct Snapshot {
+    /// The number of blocks of the shapes the run counts.
+    pub n: BlockCount,
+    pub seconds: f64,
+    pub level: Option<CurrentLevel>,
+    pub levels: Vec<LevelReport>,
+    pub workers: Vec<WorkerReport>,
+}
+
+struct ProgressState {
+    level: Option<(BlockCount, usize, usize, Instant)>,
+    levels: Vec<LevelReport>,
+    workers: Vec<WorkerReport>,
+}
+
+/// The progress of a run, updated by the generation and read by the dashboard.
+pub struct Progress {
+    n: BlockCount,
+    started: Instant,
+    state: Mutex<ProgressState>,
+}
+
+impl Progress {
+    pub fn new(n: BlockCount) -> Self {
+        Self {
+            n,
+            started: Instant::now(),
+            state: Mutex::new(ProgressState { level: None, levels: vec![], workers: vec![] }),
+        }
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut ProgressState) -> T) -> T {
+        f(&mut self.state.lock().expect("No thread panicked while holding the lock."))
+    }
+
+    /// Starts the level with the given block count, whose shapes are grown from the given number of parents.
+    pub fn start_level(&self, block_count: BlockCount, parents: usize) {
+        self.update(|state| state.level = Some((block_count, parents, 0, Instant::now())));
+    }
+
+    /// Records that the children of the given number of parents of the current level are known.
+    pub fn complete_parents(&self, count: usize) {
+        self.update(|state| if let Some((_, _, done, _)) = &mut state.level {
+            *done += count;
+        });
+    }
+
+    /// Finishes the current level which resulted in the given number of free and one sided shapes.
+    pub fn finish_level(&self, shapes: usize, one_sided: Option<usize>) {
+        self.update(|state| if let Some((block_count, _, _, started)) = state.level.take() {
+            state.levels.push(LevelReport { block_count, shapes, one_sided, seconds: started.elapsed().as_secs_f64() });
+        });
+    }
+
+    /// Registers a newly connected worker and returns its index.
+    pub fn add_worker(&self, address: String) -> usize {
+        self.update(|state| {
+            state.workers.push(WorkerReport { address, state: WorkerState::Idle, range: None, completed: 0 });
+            state.workers.len() - 1
+        })
+    }
+
+    pub fn assign_worker(&self, worker: usize, range: Range<usize>) {
+        self.update(|state| {
+            state.workers[worker].state = WorkerState::Busy;
+            state.workers[worker].range = Some(range);
+        });
+    }
+
+    pub fn complete_worker(&self, worker: usize) {
+        self.update(|state| {
+            let report = &mut state.workers[worker];
+            report.state = WorkerState::Idle;
+            report.range = None;
+            report.completed += 1;
+        });
+    }
+
+    pub fn disconnect_worker(&self, worker: usize) {
+        self.update(|state| {
+            state.workers[worker].state = WorkerState::Disconnected;
+            state.workers[worker].range = None;
+        });
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.update(|state| Snapshot {
+            n: self.n,
+            seconds: self.started.elapsed().as_secs_f64(),
+            level: state.level.map(|(block_count, parents, parents_done, started)| {
+                let seconds = started.elapsed().as_secs_f64();
+                CurrentLevel {
+                    block_count,
+                    parents,
+                    parents_done,
+                    seconds,
+                    eta_seconds: (parents_done > 0)
+                        .then(|| seconds * parents.saturating_sub(parents_done) as f64 / parents_done as f64),
+                }
+            }),
+            levels: state.levels.clone(),
+            workers: state.workers.clone(),
+        })
+    }
+}
+
+/// Answers every HTTP request to the listener with the current [Snapshot] of the progress as JSON
+/// until the process exits.
+pub fn serve(listener: TcpListener, progress: Arc<Progress>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| respond(stream, &progress));
+            if let Err(e) = result {
+                eprintln!("Failed to answer a dashboard request: {e}");
+            }
+        }
+    })
+}
+
+fn respond(stream: TcpStream, progress: &Progress) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed but have to be read before answering.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/") | Some("/status") => ("200 OK", serde_json::to_string(&progress.snapshot())?),
+        _ => ("404 Not Found", "{\"error\":\"Not found\"}".to_string()),
+    };
+    let mut writer = stream;
+    write!(
+        writer,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    )?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod dashboard_tests {
+    use std::io::Read;
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new(BlockCount::new(5));
+        progress.start_level(BlockCount::new(4), 3);
+        assert_eq!(None, progress.snapshot().level.unwrap().eta_seconds);
+        progress.complete_parents(2);
+        let level = progress.snapshot().level.unwrap();
+        assert_eq!((4, 3, 2), (level.block_count.get(), level.parents, level.parents_done));
+        assert!(level.eta_seconds.unwrap() <= level.seconds);
+        progress.finish_level(7, Some(8));
+        let snapshot = progress.snapshot();
+        assert_eq!(None, snapshot.level);
+        assert_eq!(vec![(4, 7, Some(8))], snapshot.levels.iter().map(|level| (level.block_count.get(), level.shapes, level.one_sided)).collect::<Vec<_>>());
+
+        let worker = progress.add_worker("10.0.0.2:5000".to_string());
+        progress.assign_worker(worker, 0..2);
+        assert_eq!(WorkerState::Busy, progress.snapshot().workers[worker].state);
+        progress.complete_worker(worker);
+        progress.disconnect_worker(worker);
+        let report = &progress.snapshot().workers[worker];
+        assert_eq!((WorkerState::Disconnected, None, 1), (report.state, report.range.clone(), report.completed));
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Free local port");
+        let address = listener.local_addr().unwrap();
+        let progress = Arc::new(Progress::new(BlockCount::new(6)));
+        progress.start_level(BlockCount::new(2), 1);
+        serve(listener, progress);
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).expect("Running dashboard");
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let json: serde_json::Value = serde_json::from_str(body).expect("Valid json");
+        assert_eq!(6, json["n"]);
+        assert_eq!(2, json["level"]["block_count"]);
+        assert!(json["workers"].as_array().unwrap().is_empty());
+        assert!(get("/missing").starts_with("HTTP/1.1 404"));
+    }
+}
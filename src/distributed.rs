@@ -0,0 +1,380 @@
+use std::collections::VecDeque;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::block_arrangement::block_variation::generate_variants_from;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_hash::BlockHash;
+use crate::cache::PartialShard;
+use crate::dashboard::Progress;
+use crate::level::Level;
+use crate::polycube::{Occupancy, Polycube};
+
+/// Changes whenever the messages change so mismatched coordinators and workers refuse each other.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// How often a worker reports that it is still working on a task.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the coordinator waits for a message from a busy worker before it assigns the task to another one.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Protects against allocating huge buffers for corrupted length prefixes.
+const MAX_MESSAGE_LEN: u32 = 1 << 30;
+
+/// The messages exchanged between a coordinator and its workers.
+/// Every message is sent as its length as a little endian u32 followed by its bincode encoding.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    /// Sent by a worker right after connecting.
+    Hello { version: u32 },
+    /// Asks the worker to expand the parents, which are the given range of the sorted parent level.
+    /// Children with more than `max_layers` layers are discarded, see
+    /// [VariationGenerator::with_max_layers](crate::block_arrangement::block_variation::VariationGenerator::with_max_layers).
+    Assign { range: Range<usize>, parents: Vec<Occupancy>, max_layers: Option<u32> },
+    /// Sent by a busy worker every [HEARTBEAT_INTERVAL].
+    Heartbeat,
+    /// The unique children of an assigned range. The digest covers the encoded children.
+    Completed { range: Range<usize>, count: u64, digest: u64, children: Vec<Occupancy> },
+    /// Tells the worker that there is no work left.
+    Shutdown,
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Message) -> Result<(), Error> {
+    let payload = bincode::serde::encode_to_vec(message, bincode::config::standard())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let len = u32::try_from(payload.len()).ok()
+        .filter(|len| *len <= MAX_MESSAGE_LEN)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Message too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+pub fn read_message(reader: &mut impl Read) -> Result<Message, Error> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Message of {len} bytes exceeds the limit")));
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    bincode::serde::decode_from_slice(&payload, bincode::config::standard())
+        .map(|(message, _)| message)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// The digest of the children reported in [Message::Completed].
+pub fn digest(children: &[Occupancy]) -> u64 {
+    let encoded = bincode::serde::encode_to_vec(children, bincode::config::standard())
+        .expect("Occupancies can always be encoded.");
+    xxhash_rust::xxh3::xxh3_64(&encoded)
+}
+
+fn to_occupancy(ba: &BlockArrangement) -> Result<Occupancy, Error> {
+    Polycube::new(ba)
+        .map(|polycube| polycube.occupancy())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn from_occupancy(occupancy: &Occupancy) -> Result<BlockArrangement, Error> {
+    Polycube::from_occupancy(occupancy)
+        .map(Polycube::into_inner)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+struct CoordinatorState {
+    pending: VecDeque<Range<usize>>,
+    in_flight: usize,
+    shards: Vec<PartialShard>,
+}
+
+impl CoordinatorState {
+    fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight == 0
+    }
+}
+
+/// Hands out ranges of a parent level to workers connecting over TCP and collects their children.
+/// Ranges of workers that disconnect or stop sending heartbeats are assigned to another worker.
+pub struct Coordinator<'a> {
+    parents: &'a [&'a BlockArrangement],
+    heartbeat_timeout: Duration,
+    max_layers: Option<u32>,
+    progress: Option<&'a Progress>,
+}
+
+impl<'a> Coordinator<'a> {
+    pub fn new(parents: &'a [&'a BlockArrangement]) -> Self {
+        Self { parents, heartbeat_timeout: HEARTBEAT_TIMEOUT, max_layers: None, progress: None }
+    }
+
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    pub fn with_max_layers(mut self, max_layers: Option<u32>) -> Self {
+        self.max_layers = max_layers;
+        self
+    }
+
+    /// Reports the state of every worker to the progress.
+    pub fn with_progress(mut self, progress: &'a Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Distributes the ranges until every one of them is completed.
+    /// Every completed shard is passed to `on_shard` as soon as it arrives.
+    pub fn run(
+        &self,
+        listener: &TcpListener,
+        ranges: Vec<Range<usize>>,
+        on_shard: &(dyn Fn(&PartialShard) + Sync),
+    ) -> Result<Vec<PartialShard>, Error> {
+        let state = Mutex::new(CoordinatorState { pending: ranges.into(), in_flight: 0, shards: vec![] });
+        listener.set_nonblocking(true)?;
+        thread::scope(|s| {
+            while !state.lock().expect("No thread panicked while holding the lock.").is_done() {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        stream.set_nonblocking(false)?;
+                        s.spawn(|| {
+                            let worker = self.progress.map(|progress| {
+                                let address = stream.peer_addr().map_or("unknown".to_string(), |address| address.to_string());
+                                (progress, progress.add_worker(address))
+                            });
+                            if let Err(e) = self.serve(stream, &state, on_shard, worker) {
+                                eprintln!("Lost worker: {e}");
+                            }
+                            if let Some((progress, worker)) = worker {
+                                progress.disconnect_worker(worker);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(20)),
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        })?;
+        Ok(state.into_inner().expect("No thread panicked while holding the lock.").shards)
+    }
+
+    fn serve(
+        &self,
+        stream: TcpStream,
+        state: &Mutex<CoordinatorState>,
+        on_shard: &(dyn Fn(&PartialShard) + Sync),
+        worker: Option<(&Progress, usize)>,
+    ) -> Result<(), Error> {
+        stream.set_read_timeout(Some(self.heartbeat_timeout))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        match read_message(&mut reader)? {
+            Message::Hello { version: PROTOCOL_VERSION } => {}
+            message => return Err(Error::new(ErrorKind::InvalidData, format!("Expected hello but got {message:?}"))),
+        }
+        loop {
+            let range = {
+                let mut state = state.lock().expect("No thread panicked while holding the lock.");
+                if state.is_done() {
+                    break;
+                }
+                let range = state.pending.pop_front();
+                state.in_flight += range.is_some() as usize;
+                range
+            };
+            // Other workers might still fail and return their ranges.
+            let Some(range) = range else {
+                thread::sleep(Duration::from_millis(20));
+                continue;
+            };
+            if let Some((progress, worker)) = worker {
+                progress.assign_worker(worker, range.clone());
+            }
+            match self.complete(&range, &mut reader, &mut writer) {
+                Ok(shard) => {
+                    if let Some((progress, worker)) = worker {
+                        progress.complete_worker(worker);
+                    }
+                    on_shard(&shard);
+                    let mut state = state.lock().expect("No thread panicked while holding the lock.");
+                    state.shards.push(shard);
+                    state.in_flight -= 1;
+                }
+                Err(e) => {
+                    let mut state = state.lock().expect("No thread panicked while holding the lock.");
+                    state.pending.push_back(range);
+                    state.in_flight -= 1;
+                    return Err(e);
+                }
+            }
+        }
+        write_message(&mut writer, &Message::Shutdown)
+    }
+
+    /// Assigns the range to the worker and waits for its result.
+    fn complete(&self, range: &Range<usize>, reader: &mut impl Read, writer: &mut impl Write) -> Result<PartialShard, Error> {
+        let parents = self.parents[range.clone()].iter()
+            .map(|ba| to_occupancy(ba))
+            .collect::<Result<_, _>>()?;
+        write_message(writer, &Message::Assign { range: range.clone(), parents, max_layers: self.max_layers })?;
+        loop {
+            match read_message(reader)? {
+                Message::Heartbeat => {}
+                Message::Completed { range: completed, count, digest: expected, children } => {
+                    if completed != *range || count != children.len() as u64 || digest(&children) != expected {
+                        return Err(Error::new(ErrorKind::InvalidData, "Corrupted result"));
+                    }
+                    let records = children.iter()
+                        .map(|occupancy| from_occupancy(occupancy).map(|ba| (BlockHash::from(&ba), ba)))
+                        .collect::<Result<Level, _>>()?;
+                    return Ok(PartialShard { range: completed, records });
+                }
+                message => return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected message {message:?}"))),
+            }
+        }
+    }
+}
+
+/// Connects to a coordinator and expands the assigned ranges until it shuts the worker down.
+/// Returns the number of completed tasks.
+pub fn run_worker(address: impl ToSocketAddrs, heartbeat_interval: Duration) -> Result<usize, Error> {
+    let stream = TcpStream::connect(address)?;
+    let writer = Mutex::new(stream.try_clone()?);
+    let mut reader = BufReader::new(stream);
+    let send = |message: &Message| write_message(&mut *writer.lock().expect("No thread panicked while holding the lock."), message);
+    send(&Message::Hello { version: PROTOCOL_VERSION })?;
+    let mut completed = 0;
+    loop {
+        let (range, parents, max_layers) = match read_message(&mut reader)? {
+            Message::Assign { range, parents, max_layers } => (range, parents, max_layers),
+            Message::Shutdown => return Ok(completed),
+            message => return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected message {message:?}"))),
+        };
+        let parents = parents.iter().map(from_occupancy).collect::<Result<Vec<_>, _>>()?;
+        let finished = AtomicBool::new(false);
+        let children = thread::scope(|s| {
+            s.spawn(|| {
+                let mut last_heartbeat = Instant::now();
+                while !finished.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(10));
+                    if last_heartbeat.elapsed() >= heartbeat_interval {
+                        last_heartbeat = Instant::now();
+                        // A broken connection is noticed when sending the result.
+                        let _ = send(&Message::Heartbeat);
+                    }
+                }
+            });
+            let (children, _) = generate_variants_from(parents.iter(), parents.len() * 8, max_layers);
+            finished.store(true, Ordering::Relaxed);
+            children
+        });
+        let children = children.values().map(to_occupancy).collect::<Result<Vec<_>, _>>()?;
+        send(&Message::Completed {
+            range,
+            count: children.len() as u64,
+            digest: digest(&children),
+            children,
+        })?;
+        completed += 1;
+    }
+}
+
+#[cfg(test)]
+mod distributed_tests {
+    use std::collections::HashSet;
+    use std::io::Cursor;
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::block_count::BlockCount;
+    use super::*;
+
+    fn trominoes() -> Vec<BlockArrangement> {
+        let ba = BlockArrangement::new();
+        let dominoes: Vec<_> = VariationGenerator::new(&ba).take(1).collect();
+        let (trominoes, _) = generate_variants_from(dominoes.iter(), 8, None);
+        trominoes.into_values().collect()
+    }
+
+    fn shapes(shards: &[PartialShard]) -> HashSet<Polycube> {
+        shards.iter()
+            .flat_map(|shard| shard.records.values())
+            .map(|ba| Polycube::new(ba).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = Message::Completed { range: 2..5, count: 1, digest: 7, children: vec![Polycube::new(&BlockArrangement::new()).unwrap().occupancy()] };
+        let mut buffer = vec![];
+        write_message(&mut buffer, &message).expect("Writable buffer");
+        write_message(&mut buffer, &Message::Heartbeat).expect("Writable buffer");
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(message, read_message(&mut reader).expect("Valid message"));
+        assert_eq!(Message::Heartbeat, read_message(&mut reader).expect("Valid message"));
+        assert!(read_message(&mut reader).is_err());
+        let mut oversized = Cursor::new(u32::MAX.to_le_bytes().to_vec());
+        assert_eq!(ErrorKind::InvalidData, read_message(&mut oversized).unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_distribution() {
+        let parents = trominoes();
+        let parent_refs: Vec<_> = parents.iter().collect();
+        let (expected, _) = generate_variants_from(parents.iter(), 64, None);
+        let expected: HashSet<_> = expected.values().map(|ba| Polycube::new(ba).unwrap()).collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Free local port");
+        let address = listener.local_addr().unwrap();
+        let received = Mutex::new(0);
+        let progress = Progress::new(BlockCount::new(4));
+        let shards = thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(move || run_worker(address, Duration::from_millis(20)).expect("Completed worker"));
+            }
+            Coordinator::new(&parent_refs)
+                .with_progress(&progress)
+                .run(&listener, vec![0..1, 1..2], &|_| *received.lock().unwrap() += 1)
+                .expect("Completed distribution")
+        });
+        assert_eq!(2, *received.lock().unwrap());
+        assert_eq!(expected, shapes(&shards));
+        let workers = progress.snapshot().workers;
+        assert_eq!(2, workers.iter().map(|worker| worker.completed).sum::<usize>());
+    }
+
+    #[test]
+    fn test_reassignment() {
+        let parents = trominoes();
+        let parent_refs: Vec<_> = parents.iter().collect();
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Free local port");
+        let address = listener.local_addr().unwrap();
+        let shards = thread::scope(|s| {
+            s.spawn(move || {
+                // Accepts a task and then stops responding.
+                let mut stream = TcpStream::connect(address).unwrap();
+                write_message(&mut stream, &Message::Hello { version: PROTOCOL_VERSION }).unwrap();
+                let assigned = read_message(&mut stream).unwrap();
+                assert!(matches!(assigned, Message::Assign { .. }));
+                thread::sleep(Duration::from_millis(300));
+                run_worker(address, Duration::from_millis(20)).expect("Completed worker")
+            });
+            Coordinator::new(&parent_refs)
+                .with_heartbeat_timeout(Duration::from_millis(100))
+                .run(&listener, vec![0..1, 1..2], &|_| {})
+                .expect("Completed distribution")
+        });
+        let mut ranges: Vec<_> = shards.iter().map(|shard| shard.range.clone()).collect();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(vec![0..1, 1..2], ranges);
+    }
+}
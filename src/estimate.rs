@@ -0,0 +1,181 @@
+//! Monte-Carlo estimates of the number of shapes for sizes that can not be enumerated.
+//!
+//! Redelmeier's algorithm enumerates every fixed shape exactly once as a node of a search tree, so the
+//! number of fixed shapes with n blocks is the number of nodes at depth n. Knuth's estimator walks down
+//! a random path of that tree and multiplies the number of children met on the way, which is an unbiased
+//! estimate of the number of nodes at every depth along the path. The mean over many paths converges to
+//! the exact counts, and its standard error gives the confidence interval.
+
+use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::point::Point3D;
+
+/// The default number of random paths [CountEstimator] walks.
+pub const DEFAULT_SAMPLES: usize = 100_000;
+
+/// The number of rotations and reflections of the cube. Almost every large shape has no symmetry, so
+/// it has this many fixed orientations.
+const CUBE_SYMMETRIES: f64 = 48.0;
+
+/// The z score of a two sided 95% confidence interval of a normal distribution.
+const Z_95: f64 = 1.96;
+
+/// An approximate number of shapes with 95% confidence bounds. Never an exact count.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct CountEstimate {
+    pub mean: f64,
+    pub standard_error: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl CountEstimate {
+    fn from_samples(sum: f64, sum_of_squares: f64, samples: usize) -> Self {
+        let count = samples as f64;
+        let mean = sum / count;
+        let variance = (sum_of_squares / count - mean * mean).max(0.0) * count / (count - 1.0).max(1.0);
+        let standard_error = (variance / count).sqrt();
+        Self {
+            mean,
+            standard_error,
+            low: (mean - Z_95 * standard_error).max(0.0),
+            high: mean + Z_95 * standard_error,
+        }
+    }
+
+    fn scaled(&self, factor: f64) -> Self {
+        Self {
+            mean: self.mean * factor,
+            standard_error: self.standard_error * factor,
+            low: self.low * factor,
+            high: self.high * factor,
+        }
+    }
+
+    /// The half width of the confidence interval relative to the mean.
+    pub fn relative_error(&self) -> f64 {
+        match self.mean > 0.0 {
+            true => (self.high - self.mean) / self.mean,
+            false => f64::INFINITY,
+        }
+    }
+}
+
+/// The estimated numbers of shapes with a number of blocks.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct LevelEstimate {
+    pub blocks: BlockCount,
+    /// The shapes that are distinct up to translation.
+    pub fixed: CountEstimate,
+    /// The shapes that are distinct up to rotations and reflections, approximated as the fixed shapes
+    /// divided by 48. Shapes with a symmetry are rare for large sizes, but make this too low for small ones.
+    pub free: CountEstimate,
+}
+
+/// Estimates the numbers of shapes up to a number of blocks with Knuth's estimator on the search tree of
+/// Redelmeier's algorithm. The same seed always results in the same estimates.
+pub struct CountEstimator {
+    n: BlockCount,
+    samples: usize,
+    seed: u64,
+}
+
+impl CountEstimator {
+    pub fn new(n: BlockCount) -> Self {
+        Self { n, samples: DEFAULT_SAMPLES, seed: 0 }
+    }
+
+    /// Sets the number of random paths, at least one.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// An estimate for every number of blocks from one to n.
+    pub fn estimate(&self) -> Vec<LevelEstimate> {
+        let depth = self.n.as_usize();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut sums = vec![0.0; depth];
+        let mut sums_of_squares = vec![0.0; depth];
+        for _ in 0..self.samples {
+            for (level, weight) in random_path(depth, &mut rng).into_iter().enumerate() {
+                sums[level] += weight;
+                sums_of_squares[level] += weight * weight;
+            }
+        }
+        BlockCount::ONE.up_to(self.n)
+            .zip(sums.into_iter().zip(sums_of_squares))
+            .map(|(blocks, (sum, sum_of_squares))| {
+                let fixed = CountEstimate::from_samples(sum, sum_of_squares, self.samples);
+                let free = fixed.scaled(1.0 / CUBE_SYMMETRIES);
+                LevelEstimate { blocks, fixed, free }
+            })
+            .collect()
+    }
+}
+
+/// Walks down a random path of Redelmeier's search tree and returns the product of the numbers of
+/// children met up to every depth, which is zero below a leaf.
+/// Only cells after the origin in the order of z, y and x are used, so every fixed shape is counted once
+/// with its first cell at the origin.
+fn random_path(depth: usize, rng: &mut impl Rng) -> Vec<f64> {
+    let origin = Point3D::default();
+    let mut weights = vec![0.0; depth];
+    let mut untried = vec![origin];
+    let mut seen = HashSet::from([origin]);
+    let mut weight = 1.0;
+    for level_weight in weights.iter_mut() {
+        if untried.is_empty() {
+            break;
+        }
+        weight *= untried.len() as f64;
+        let index = rng.gen_range(0..untried.len());
+        let cell = untried[index];
+        untried.drain(..=index);
+        for offset in BlockArrangement::NEIGHBOR_OFFSETS {
+            let neighbor = cell + offset;
+            if is_after_origin(&neighbor) && seen.insert(neighbor) {
+                untried.push(neighbor);
+            }
+        }
+        *level_weight = weight;
+    }
+    weights
+}
+
+fn is_after_origin(p: &Point3D<i32>) -> bool {
+    (*p.z(), *p.y(), *p.x()) > (0, 0, 0)
+}
+
+#[cfg(test)]
+mod estimate_tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        // The numbers of fixed shapes with one to six blocks.
+        let exact = [1.0, 3.0, 15.0, 86.0, 534.0, 3481.0];
+        let estimates = CountEstimator::new(BlockCount::new(6)).with_samples(20_000).with_seed(1).estimate();
+        assert_eq!(6, estimates.len());
+        assert_eq!(CountEstimate { mean: 1.0, standard_error: 0.0, low: 1.0, high: 1.0 }, estimates[0].fixed);
+        for (estimate, exact) in estimates.iter().zip(exact) {
+            let fixed = &estimate.fixed;
+            assert!(fixed.low - fixed.standard_error <= exact && exact <= fixed.high + fixed.standard_error,
+                "{exact} fixed shapes with {} blocks, estimated {fixed:?}", estimate.blocks);
+            assert!(fixed.relative_error() < 0.05);
+        }
+        assert_eq!(estimates, CountEstimator::new(BlockCount::new(6)).with_samples(20_000).with_seed(1).estimate());
+        assert_eq!(estimates[5].fixed.mean / 48.0, estimates[5].free.mean);
+    }
+}
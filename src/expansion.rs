@@ -0,0 +1,78 @@
+//! The single step every enumeration of this crate is built from, for drivers that orchestrate the work
+//! themselves, like a volunteer computing client handing out parents to machines that never talk to each other.
+//!
+//! [expand_one] grows a shape by one block in every possible way. [is_canonical_parent] decides which
+//! of the parents of a child is responsible for it without knowing any other shape: every shape with
+//! more than one block has exactly one canonical parent, see [canonical_parent]. So expanding any
+//! partition of the shapes with n blocks with [canonical_children] yields every shape with n + 1 blocks
+//! exactly once over all parts, without a shared set to deduplicate the children in.
+
+use crate::polycube::Polycube;
+
+/// Every distinct shape created by adding one block next to the parent, sorted by the order of [Polycube].
+/// The added block has color 0, the other blocks keep their colors.
+pub fn expand_one(parent: &Polycube) -> Vec<Polycube> {
+    let mut children: Vec<_> = parent.free_neighbors().into_iter()
+        .map(|p| {
+            let mut child = parent.arrangement().clone();
+            child.add_block_at(&p).expect("Free neighbors can always be added.");
+            Polycube::new(&child).expect("Adding an adjacent block keeps the shape connected.")
+        })
+        .collect();
+    children.sort_unstable();
+    children.dedup();
+    children
+}
+
+/// The smallest shape by the order of [Polycube] that the shape can be created from by adding one block,
+/// which only depends on the shape itself. `None` for a single block.
+pub fn canonical_parent(shape: &Polycube) -> Option<Polycube> {
+    shape.removable_blocks().into_iter()
+        .map(|p| {
+            let mut parent = shape.arrangement().clone();
+            parent.remove_block_at(&p).expect("Only removable blocks are removed.");
+            Polycube::new(&parent).expect("Removable blocks keep the shape connected.")
+        })
+        .min()
+}
+
+/// Whether the parent is the [canonical_parent] of the child, so the child is counted when the parent
+/// is expanded. False if the child does not have one block more than the parent.
+pub fn is_canonical_parent(parent: &Polycube, child: &Polycube) -> bool {
+    parent.num_blocks().next() == Some(child.num_blocks()) && canonical_parent(child).as_ref() == Some(parent)
+}
+
+/// The children of [expand_one] whose canonical parent is the parent, see the module documentation.
+pub fn canonical_children(parent: &Polycube) -> Vec<Polycube> {
+    expand_one(parent).into_iter()
+        .filter(|child| is_canonical_parent(parent, child))
+        .collect()
+}
+
+#[cfg(test)]
+mod expansion_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_count::BlockCount;
+    use crate::polycubes::Polycubes;
+    use super::*;
+
+    #[test]
+    fn test_expand_one() {
+        let single = Polycube::new(&BlockArrangement::new()).unwrap();
+        assert_eq!(None, canonical_parent(&single));
+        assert_eq!(1, expand_one(&single).len());
+        for n in BlockCount::new(2).up_to(BlockCount::new(6)) {
+            let parents = Polycubes::builtin(n.previous().unwrap()).unwrap();
+            let shapes = Polycubes::builtin(n).unwrap();
+            let mut children: Vec<_> = parents.iter().flat_map(expand_one).collect();
+            children.sort_unstable();
+            children.dedup();
+            assert_eq!(shapes, children, "Children with {n} blocks");
+
+            let mut canonical: Vec<_> = parents.iter().flat_map(canonical_children).collect();
+            canonical.sort_unstable();
+            assert_eq!(shapes, canonical, "Canonical children with {n} blocks");
+            assert!(!is_canonical_parent(&shapes[0], &parents[0]));
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Write;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+use crate::analysis::BoundingBoxClass;
+use crate::permutation::Permutation;
+use crate::polycube::Polycube;
+use crate::symmetry::Symmetry;
+
+/// The text formats a single [Polycube] can be exported to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A Wavefront OBJ mesh with one unit cube per block.
+    Obj,
+    /// A JSON object listing the block coordinates.
+    Json,
+    /// The code returned by [Polycube::code].
+    Code,
+    /// A drawing of the layers of the bounding box along z side by side, see [to_ascii].
+    Ascii,
+}
+
+/// The orders shapes can be exported in. Shapes with the same key are ordered by their rank,
+/// see the [Ord] implementation of [Polycube], so every order is total.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SortKey {
+    /// Only the rank.
+    #[default]
+    Rank,
+    /// Ascending by [BlockArrangement::surface_area](crate::block_arrangement::BlockArrangement::surface_area).
+    SurfaceArea,
+    /// Ascending by the sorted axis lengths of the bounding box, see [BoundingBoxClass].
+    Bbox,
+    /// Ascending by the number of symmetries, see [Symmetry].
+    Symmetry,
+}
+
+impl SortKey {
+    fn key(&self, polycube: &Polycube) -> [u32; 3] {
+        match self {
+            SortKey::Rank => [0; 3],
+            SortKey::SurfaceArea => [polycube.surface_area() as u32, 0, 0],
+            SortKey::Bbox => BoundingBoxClass::of(polycube).axis_lengths(),
+            SortKey::Symmetry => [Symmetry::of(polycube).order() as u32, 0, 0],
+        }
+    }
+}
+
+/// The shapes at the positions `offset..offset + limit` when ordered by the sort key.
+/// Only the first `offset + limit` shapes are kept in memory while the others are read.
+pub fn page(shapes: impl IntoIterator<Item = Polycube>, sort_by: SortKey, offset: usize, limit: usize) -> Vec<Polycube> {
+    let kept = offset.saturating_add(limit);
+    let mut smallest = BinaryHeap::new();
+    for polycube in shapes {
+        if kept == 0 {
+            break;
+        }
+        smallest.push((sort_by.key(&polycube), polycube));
+        if smallest.len() > kept {
+            smallest.pop();
+        }
+    }
+    smallest.into_sorted_vec().into_iter()
+        .skip(offset)
+        .map(|(_, polycube)| polycube)
+        .collect()
+}
+
+/// The shapes at the positions `offset..offset + limit` of the order the permutation shuffles the
+/// positions of the shapes into. Only the shapes of the page are kept in memory while the others are
+/// read, and reading stops once all of them were found.
+pub fn shuffled_page(shapes: impl IntoIterator<Item = Polycube>, permutation: &Permutation, offset: usize, limit: usize) -> Vec<Polycube> {
+    let end = (offset as u64).saturating_add(limit as u64).min(permutation.len());
+    let wanted: HashMap<u64, usize> = (offset as u64..end)
+        .enumerate()
+        .map(|(i, position)| (permutation.get(position).expect("The position is in range."), i))
+        .collect();
+    let mut page: Vec<Option<Polycube>> = vec![None; wanted.len()];
+    let mut found = 0;
+    for (id, polycube) in shapes.into_iter().enumerate() {
+        if found == wanted.len() {
+            break;
+        }
+        if let Some(i) = wanted.get(&(id as u64)) {
+            page[*i] = Some(polycube);
+            found += 1;
+        }
+    }
+    page.into_iter().flatten().collect()
+}
+
+/// K items chosen uniformly at random from the stream by reservoir sampling, in the order they were read.
+/// Only the chosen items are kept in memory, so the stream may be far longer than fits into it.
+/// The same seed always results in the same sample of the same stream.
+pub fn sample<T>(items: impl IntoIterator<Item = T>, k: usize, seed: u64) -> Vec<T> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reservoir = Vec::with_capacity(k.min(1 << 16));
+    for (position, item) in items.into_iter().enumerate() {
+        if position < k {
+            reservoir.push((position, item));
+            continue;
+        }
+        let replaced = rng.gen_range(0..=position);
+        if replaced < k {
+            reservoir[replaced] = (position, item);
+        }
+    }
+    reservoir.sort_by_key(|(position, _)| *position);
+    reservoir.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Exports the polycube in the given format.
+pub fn export(polycube: &Polycube, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Obj => to_obj(polycube),
+        ExportFormat::Json => to_json(polycube),
+        ExportFormat::Code => polycube.code(),
+        ExportFormat::Ascii => to_ascii(polycube),
+    }
+}
+
+/// Draws the code followed by the layers of the bounding box along z from left to right, separated by a
+/// column of spaces. Every layer has a row per y from top to bottom and a column per x, with `#` for a
+/// block and `.` for an empty cell.
+fn to_ascii(polycube: &Polycube) -> String {
+    let occupancy = polycube.occupancy();
+    let [width, depth, height] = occupancy.size.map(|len| len as usize);
+    let mut ascii = polycube.code();
+    for y in 0..depth {
+        ascii.push('\n');
+        for z in 0..height {
+            if z > 0 {
+                ascii.push(' ');
+            }
+            for x in 0..width {
+                let index = x + width * (y + depth * z);
+                ascii.push(if occupancy.bits[index / 8] & (1 << (index % 8)) != 0 { '#' } else { '.' });
+            }
+        }
+    }
+    ascii
+}
+
+fn to_json(polycube: &Polycube) -> String {
+    let blocks = polycube.block_iter_sorted()
+        .map(|p| format!("[{},{},{}]", p.x(), p.y(), p.z()))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{\"num_blocks\":{},\"code\":\"{}\",\"blocks\":[{blocks}]}}", polycube.num_blocks(), polycube.code())
+}
+
+/// The corners of a unit cube, as offsets from its minimal corner.
+const CUBE_VERTICES: [(i32, i32, i32); 8] = [
+    (0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0),
+    (0, 0, 1), (1, 0, 1), (1, 1, 1), (0, 1, 1),
+];
+
+/// The faces of a unit cube as counter-clockwise indices into [CUBE_VERTICES].
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [2, 3, 7, 6],
+    [0, 4, 7, 3],
+    [1, 2, 6, 5],
+];
+
+fn to_obj(polycube: &Polycube) -> String {
+    let mut obj = format!("# polycube {}\n", polycube.code());
+    for p in polycube.block_iter_sorted() {
+        for (x, y, z) in CUBE_VERTICES {
+            writeln!(obj, "v {} {} {}", p.x() + x, p.y() + y, p.z() + z)
+                .expect("Writing to a string can not fail");
+        }
+    }
+    for cube in 0..polycube.num_blocks().as_usize() {
+        for face in CUBE_FACES {
+            // Obj indices start at 1.
+            let [a, b, c, d] = face.map(|v| cube * CUBE_VERTICES.len() + v + 1);
+            writeln!(obj, "f {a} {b} {c} {d}").expect("Writing to a string can not fail");
+        }
+    }
+    obj
+}
+
+#[cfg(test)]
+mod export_tests {
+    use std::str::FromStr;
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_count::BlockCount;
+    use crate::point::Point3D;
+    use super::*;
+
+    fn domino() -> Polycube {
+        let mut blocks = BlockArrangement::new();
+        blocks.add_block_at(&Point3D::new(1, 0, 0)).expect("Checked coordinates.");
+        Polycube::new(&blocks).expect("Connected shape")
+    }
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!(Ok(ExportFormat::Obj), ExportFormat::from_str("obj"));
+        assert_eq!(Ok(ExportFormat::Json), ExportFormat::from_str("json"));
+        assert_eq!(Ok(ExportFormat::Code), ExportFormat::from_str("code"));
+        assert!(ExportFormat::from_str("png").is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let polycube = domino();
+        let json = export(&polycube, ExportFormat::Json);
+        assert_eq!(format!("{{\"num_blocks\":2,\"code\":\"{}\",\"blocks\":[[0,0,0],[0,0,1]]}}", polycube.code()), json);
+    }
+
+    #[test]
+    fn test_page() {
+        let mut tree = crate::poly_tree::PolyTree::new();
+        (0..5).for_each(|_| { tree.generate_next_level(); });
+        let shapes: Vec<_> = tree.iter_level(BlockCount::new(5)).map(|ba| Polycube::new(&ba).unwrap()).collect();
+        let mut sorted = shapes.clone();
+        sorted.sort();
+        assert_eq!(sorted, page(shapes.iter().rev().cloned(), SortKey::Rank, 0, usize::MAX));
+        assert_eq!(sorted[5..8], page(shapes.iter().cloned(), SortKey::Rank, 5, 3));
+        assert!(page(shapes.iter().cloned(), SortKey::Rank, 30, 3).is_empty());
+
+        let by_area = page(shapes.iter().cloned(), SortKey::from_str("surface-area").unwrap(), 0, 23);
+        assert!(by_area.windows(2).all(|pair| pair[0].surface_area() <= pair[1].surface_area()));
+        let by_box = page(shapes.iter().cloned(), SortKey::Bbox, 0, 1);
+        assert_eq!([1, 1, 5], BoundingBoxClass::of(&by_box[0]).axis_lengths());
+        let by_symmetry = page(shapes.iter().cloned(), SortKey::Symmetry, 20, 3);
+        assert_eq!(16, Symmetry::of(&by_symmetry[2]).order());
+
+        let permutation = Permutation::new(shapes.len() as u64, 3);
+        let shuffled = shuffled_page(shapes.iter().cloned(), &permutation, 0, usize::MAX);
+        let expected: Vec<_> = permutation.iter().map(|id| shapes[id as usize].clone()).collect();
+        assert_eq!(expected, shuffled);
+        assert_eq!(expected[20..], shuffled_page(shapes.iter().cloned(), &permutation, 20, 10));
+    }
+
+    #[test]
+    fn test_sample() {
+        let sampled = sample(0..1000, 10, 5);
+        assert_eq!(10, sampled.len());
+        assert!(sampled.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(sampled, sample(0..1000, 10, 5));
+        assert_ne!(sampled, sample(0..1000, 10, 6));
+        assert_eq!((0..5).collect::<Vec<_>>(), sample(0..5, 10, 5));
+        assert!(sample(0..5, 0, 5).is_empty());
+
+        // Every position is about equally likely to be chosen.
+        let mut hits = [0; 10];
+        for seed in 0..2000 {
+            for position in sample(0..10, 3, seed) {
+                hits[position] += 1;
+            }
+        }
+        assert!(hits.iter().all(|&hit| (500..700).contains(&hit)), "{hits:?}");
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut blocks = BlockArrangement::new();
+        for p in [Point3D::new(1, 0, 0), Point3D::new(1, 1, 0), Point3D::new(1, 1, 1)] {
+            blocks.add_block_at(&p).expect("Checked coordinates.");
+        }
+        let polycube = Polycube::new(&blocks).expect("Connected shape");
+        let ascii = export(&polycube, ExportFormat::from_str("ascii").unwrap());
+        let mut lines = ascii.lines();
+        assert_eq!(Some(polycube.code().as_str()), lines.next());
+        let [width, depth, height] = polycube.occupancy().size;
+        let rows: Vec<_> = lines.collect();
+        assert_eq!(depth as usize, rows.len());
+        assert!(rows.iter().all(|row| row.len() as u32 == width * height + height - 1));
+        assert_eq!(4, rows.iter().map(|row| row.matches('#').count()).sum::<usize>());
+        assert_eq!(export(&domino(), ExportFormat::Ascii), format!("{}\n# #", domino().code()));
+    }
+
+    #[test]
+    fn test_obj() {
+        let obj = export(&domino(), ExportFormat::Obj);
+        assert_eq!(16, obj.lines().filter(|l| l.starts_with("v ")).count());
+        assert_eq!(12, obj.lines().filter(|l| l.starts_with("f ")).count());
+    }
+}
@@ -0,0 +1,87 @@
+//! Regression tests comparing the generated shapes against the canonical codes stored in `golden/`.
+//! Run the tests with `UPDATE_GOLDEN=1` to rewrite the files after an intended change, e.g.
+//! `UPDATE_GOLDEN=1 cargo test --release golden -- --include-ignored`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use crate::block_arrangement::block_variation::generate_variants_from;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::poly_tree::PolyTree;
+use crate::polycube::Polycube;
+
+/// The largest number of blocks with a golden file.
+const MAX_GOLDEN_BLOCKS: usize = 8;
+
+/// The largest number of blocks checked by the tests that are not ignored.
+const QUICK_GOLDEN_BLOCKS: usize = 6;
+
+pub(crate) fn golden_path(block_count: usize) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden").join(format!("shapes_{block_count}.txt"))
+}
+
+/// The codes of the shapes with every number of blocks up to the given one, starting with one block.
+fn poly_tree_codes(max_blocks: usize) -> Vec<BTreeSet<String>> {
+    let max_blocks = BlockCount::try_from(max_blocks).expect("A valid block count");
+    let mut tree = PolyTree::new();
+    while tree.depth() < max_blocks {
+        tree.generate_next_level();
+    }
+    BlockCount::ONE.up_to(max_blocks)
+        .map(|n| tree.iter_level(n).map(|ba| Polycube::new(&ba).unwrap().code()).collect())
+        .collect()
+}
+
+/// Rewrites the golden file of the block count if `UPDATE_GOLDEN` is set, otherwise compares the codes with it.
+/// Only the shapes of the [PolyTree] are used as reference.
+fn check_or_update_golden(block_count: usize, codes: &BTreeSet<String>) {
+    let path = golden_path(block_count);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).expect("Writable golden directory");
+        let content: String = codes.iter().map(|code| format!("{code}\n")).collect();
+        fs::write(&path, content).expect("Writable golden file");
+        return;
+    }
+    check_golden(block_count, codes);
+}
+
+fn check_golden(block_count: usize, codes: &BTreeSet<String>) {
+    let path = golden_path(block_count);
+    let content = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Missing golden file {}, run with UPDATE_GOLDEN=1 to create it: {e}", path.display()));
+    let expected: BTreeSet<String> = content.lines().map(str::to_string).collect();
+    let missing: Vec<_> = expected.difference(codes).take(5).collect();
+    let unexpected: Vec<_> = codes.difference(&expected).take(5).collect();
+    assert!(
+        missing.is_empty() && unexpected.is_empty(),
+        "The {} shapes with {block_count} blocks differ from the {} golden ones, missing {missing:?}, unexpected {unexpected:?}",
+        codes.len(), expected.len(),
+    );
+}
+
+#[test]
+fn test_golden_shapes() {
+    for (i, codes) in poly_tree_codes(QUICK_GOLDEN_BLOCKS).iter().enumerate() {
+        check_or_update_golden(i + 1, codes);
+    }
+}
+
+#[test]
+#[ignore]
+fn test_golden_shapes_large() {
+    for (i, codes) in poly_tree_codes(MAX_GOLDEN_BLOCKS).iter().enumerate().skip(QUICK_GOLDEN_BLOCKS) {
+        check_or_update_golden(i + 1, codes);
+    }
+}
+
+#[test]
+fn test_golden_hash_map() {
+    let mut level = vec![BlockArrangement::new()];
+    for block_count in 2..=QUICK_GOLDEN_BLOCKS {
+        let (children, _) = generate_variants_from(level.iter(), level.len() * 8, None);
+        level = children.into_values().collect();
+        let codes = level.iter().map(|ba| Polycube::new(ba).unwrap().code()).collect();
+        check_golden(block_count, &codes);
+    }
+}
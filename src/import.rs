@@ -0,0 +1,297 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use serde::Deserialize;
+use strum::{Display, EnumString};
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::point::Point3D;
+
+/// The voxel formats shapes can be imported from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ImportFormat {
+    /// A MagicaVoxel model. Every model of the file is imported.
+    Vox,
+    /// A run length encoded binvox voxel grid.
+    Binvox,
+    /// Plain text with the coordinates of one voxel per line. Lines starting with `#` are ignored.
+    Xyz,
+    /// The JSON written by [ExportFormat::Json](crate::export::ExportFormat::Json) or a plain array of coordinates.
+    Json,
+}
+
+impl ImportFormat {
+    /// Determines the format from the extension of the path.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        extension.parse().ok()
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    /// The file extension does not belong to an [ImportFormat].
+    UnknownFormat,
+    /// The content does not follow the format.
+    Malformed(String),
+    /// A connected shape has more blocks than an arrangement can hold.
+    TooManyBlocks(usize),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "Failed to read voxels: {e}"),
+            ImportError::UnknownFormat => f.write_str("The voxel format is not supported"),
+            ImportError::Malformed(reason) => write!(f, "Malformed voxel data: {reason}"),
+            ImportError::TooManyBlocks(count) => write!(f, "A shape has {count} blocks, at most {} are supported", BlockCount::MAX),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(value: std::io::Error) -> Self {
+        ImportError::Io(value)
+    }
+}
+
+/// Imports a voxel file whose format is determined by its extension.
+pub fn import_file(path: impl AsRef<Path>) -> Result<Vec<BlockArrangement>, ImportError> {
+    let format = ImportFormat::from_path(&path).ok_or(ImportError::UnknownFormat)?;
+    import(&std::fs::read(path)?, format)
+}
+
+/// Converts voxel data into arrangements, one per connected component of every model.
+pub fn import(data: &[u8], format: ImportFormat) -> Result<Vec<BlockArrangement>, ImportError> {
+    let models = match format {
+        ImportFormat::Vox => read_vox(data)?,
+        ImportFormat::Binvox => vec![read_binvox(data)?],
+        ImportFormat::Xyz => vec![read_xyz(data)?],
+        ImportFormat::Json => vec![read_json(data)?],
+    };
+    models.iter()
+        .flat_map(|voxels| connected_components(voxels))
+        .map(|component| {
+            if BlockCount::try_from(component.len()).is_err() {
+                return Err(ImportError::TooManyBlocks(component.len()));
+            }
+            Ok(BlockArrangement::from_points(&component).expect("Components are connected and not empty."))
+        })
+        .collect()
+}
+
+/// Splits the voxels into the groups that are connected at their faces.
+/// The components are ordered by their smallest voxel.
+pub fn connected_components(voxels: &[Point3D<i32>]) -> Vec<Vec<Point3D<i32>>> {
+    let mut remaining: BTreeSet<Point3D<i32>> = voxels.iter().copied().collect();
+    let mut components = vec![];
+    while let Some(start) = remaining.pop_first() {
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(p) = queue.pop_front() {
+            for neighbor in BlockArrangement::NEIGHBOR_OFFSETS.map(|o| o + p) {
+                if remaining.remove(&neighbor) {
+                    component.push(neighbor);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        component.sort();
+        components.push(component);
+    }
+    components
+}
+
+fn read_xyz(data: &[u8]) -> Result<Vec<Point3D<i32>>, ImportError> {
+    let text = std::str::from_utf8(data).map_err(|e| ImportError::Malformed(e.to_string()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let coordinates = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<i32>().map_err(|e| ImportError::Malformed(format!("{line}: {e}"))))
+                .collect::<Result<Vec<_>, _>>()?;
+            match coordinates[..] {
+                [x, y, z] => Ok(Point3D::new(x, y, z)),
+                _ => Err(ImportError::Malformed(format!("Expected three coordinates in {line}"))),
+            }
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonShape {
+    Object { blocks: Vec<[i32; 3]> },
+    Blocks(Vec<[i32; 3]>),
+}
+
+fn read_json(data: &[u8]) -> Result<Vec<Point3D<i32>>, ImportError> {
+    let shape: JsonShape = serde_json::from_slice(data).map_err(|e| ImportError::Malformed(e.to_string()))?;
+    let (JsonShape::Object { blocks } | JsonShape::Blocks(blocks)) = shape;
+    Ok(blocks.into_iter().map(|[x, y, z]| Point3D::new(x, y, z)).collect())
+}
+
+fn read_binvox(data: &[u8]) -> Result<Vec<Point3D<i32>>, ImportError> {
+    let malformed = |reason: &str| ImportError::Malformed(reason.to_string());
+    let mut dim = None;
+    let mut rest = data;
+    loop {
+        let line_end = rest.iter().position(|b| *b == b'\n').ok_or_else(|| malformed("Missing data section"))?;
+        let line = std::str::from_utf8(&rest[..line_end]).map_err(|_| malformed("Invalid header"))?.trim();
+        rest = &rest[line_end + 1..];
+        if line == "data" {
+            break;
+        }
+        if let Some(sizes) = line.strip_prefix("dim ") {
+            let sizes = sizes.split_whitespace()
+                .map(|s| s.parse::<usize>().map_err(|_| malformed("Invalid dimension")))
+                .collect::<Result<Vec<_>, _>>()?;
+            match sizes[..] {
+                [depth, height, width] => dim = Some((depth, height, width)),
+                _ => return Err(malformed("Expected three dimensions")),
+            }
+        }
+    }
+    let (depth, height, width) = dim.ok_or_else(|| malformed("Missing dimension"))?;
+    let total = depth.checked_mul(height)
+        .and_then(|area| area.checked_mul(width))
+        .ok_or_else(|| malformed("The dimension holds too many voxels"))?;
+    let mut voxels = vec![];
+    let mut index = 0;
+    for pair in rest.chunks(2) {
+        let [value, count] = pair else {
+            return Err(malformed("Truncated run"));
+        };
+        let end = index + *count as usize;
+        if end > total {
+            return Err(malformed("More voxels than the dimension holds"));
+        }
+        if *value != 0 {
+            // The y coordinate runs fastest, then z, then x.
+            voxels.extend((index..end).map(|i| Point3D::new(
+                (i / (width * height)) as i32,
+                (i % width) as i32,
+                (i / width % height) as i32,
+            )));
+        }
+        index = end;
+    }
+    if index != total {
+        return Err(malformed("Fewer voxels than the dimension holds"));
+    }
+    Ok(voxels)
+}
+
+fn read_vox(data: &[u8]) -> Result<Vec<Vec<Point3D<i32>>>, ImportError> {
+    let malformed = |reason: &str| ImportError::Malformed(reason.to_string());
+    let body = data.strip_prefix(b"VOX ").ok_or_else(|| malformed("Missing VOX header"))?;
+    // Skips the version.
+    let mut chunks = body.get(4..).ok_or_else(|| malformed("Missing version"))?;
+    let mut models = vec![];
+    while !chunks.is_empty() {
+        let header = chunks.get(..12).ok_or_else(|| malformed("Truncated chunk header"))?;
+        let id = &header[..4];
+        let content_size = read_u32(&header[4..8]) as usize;
+        // The children of the MAIN chunk follow directly after its content, so they are read as siblings.
+        let content_end = content_size.checked_add(12).ok_or_else(|| malformed("Truncated chunk"))?;
+        let content = chunks.get(12..content_end).ok_or_else(|| malformed("Truncated chunk"))?;
+        chunks = &chunks[content_end..];
+        if id != b"XYZI" {
+            continue;
+        }
+        let count = read_u32(content.get(..4).ok_or_else(|| malformed("Truncated XYZI chunk"))?) as usize;
+        let voxels = content[4..].chunks_exact(4)
+            .take(count)
+            .map(|v| Point3D::new(v[0] as i32, v[1] as i32, v[2] as i32))
+            .collect::<Vec<_>>();
+        if voxels.len() != count {
+            return Err(malformed("Truncated XYZI chunk"));
+        }
+        models.push(voxels);
+    }
+    Ok(models)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("Called with four bytes."))
+}
+
+#[cfg(test)]
+mod import_tests {
+    use crate::polycube::Polycube;
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((content.len() as u32).to_le_bytes());
+        chunk.extend((children.len() as u32).to_le_bytes());
+        chunk.extend(content);
+        chunk.extend(children);
+        chunk
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Some(ImportFormat::Vox), ImportFormat::from_path("shape.VOX"));
+        assert_eq!(Some(ImportFormat::Binvox), ImportFormat::from_path("dir/shape.binvox"));
+        assert_eq!(Some(ImportFormat::Xyz), ImportFormat::from_path("shape.xyz"));
+        assert_eq!(Some(ImportFormat::Json), ImportFormat::from_path("shape.json"));
+        assert_eq!(None, ImportFormat::from_path("shape.obj"));
+    }
+
+    #[test]
+    fn test_xyz_components() {
+        let data = b"# two dominoes and a single block\n0 0 0\n1,0,0\n\n5 5 5\n5 6 5\n-3 0 0\n";
+        let shapes = import(data, ImportFormat::Xyz).expect("Valid xyz");
+        let sizes: Vec<_> = shapes.iter().map(|ba| ba.num_blocks().get()).collect();
+        assert_eq!(vec![1, 2, 2], sizes);
+        assert_eq!(Polycube::new(&shapes[1]).unwrap(), Polycube::new(&shapes[2]).unwrap());
+        assert!(import(b"1 2\n", ImportFormat::Xyz).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let domino = import(b"[[0,0,0],[0,1,0]]", ImportFormat::Json).expect("Valid json");
+        let polycube = Polycube::new(&domino[0]).unwrap();
+        let exported = crate::export::export(&polycube, crate::export::ExportFormat::Json);
+        let imported = import(exported.as_bytes(), ImportFormat::Json).expect("Valid json");
+        assert_eq!(polycube, Polycube::new(&imported[0]).unwrap());
+        assert!(import(b"{\"blocks\":[[0,0]]}", ImportFormat::Json).is_err());
+    }
+
+    #[test]
+    fn test_binvox() {
+        // An L tromino in a 2x2x2 grid: voxels 0, 1 and 2 are set.
+        let mut data = b"#binvox 1\ndim 2 2 2\ntranslate 0 0 0\nscale 1\ndata\n".to_vec();
+        data.extend([1, 3, 0, 5]);
+        let shapes = import(&data, ImportFormat::Binvox).expect("Valid binvox");
+        assert_eq!(1, shapes.len());
+        assert_eq!(BlockCount::new(3), shapes[0].num_blocks());
+        let l_tromino = import(b"0 0 0\n0 1 0\n0 0 1\n", ImportFormat::Xyz).expect("Valid xyz");
+        assert_eq!(Polycube::new(&l_tromino[0]).unwrap(), Polycube::new(&shapes[0]).unwrap());
+        data.push(0);
+        assert!(import(&data, ImportFormat::Binvox).is_err());
+    }
+
+    #[test]
+    fn test_vox() {
+        let size = chunk(b"SIZE", &[3, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0], &[]);
+        let xyzi = chunk(b"XYZI", &[3, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1], &[]);
+        let children = [size, xyzi].concat();
+        let mut data = b"VOX ".to_vec();
+        data.extend(150u32.to_le_bytes());
+        data.extend(chunk(b"MAIN", &[], &children));
+        let shapes = import(&data, ImportFormat::Vox).expect("Valid vox");
+        assert_eq!(1, shapes.len());
+        assert_eq!(BlockCount::new(3), shapes[0].num_blocks());
+        data.truncate(data.len() - 2);
+        assert!(import(&data, ImportFormat::Vox).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{BTreeSet, HashSet};
+use std::io::{Error, ErrorKind};
+use strum::{Display, EnumString};
+use crate::block_hash::fingerprint128;
+use crate::point::Point3D;
+use crate::store::CacheStore;
+
+/// A system of cells that shapes are built from by joining neighboring cells.
+/// Cells are identified by integer coordinates whose meaning depends on the lattice.
+pub trait Lattice {
+    /// The name identifying the lattice in file names.
+    fn name(&self) -> &'static str;
+
+    /// One cell of every class of cells that translations of the lattice can not map onto each other.
+    fn origins(&self) -> Vec<Point3D<i32>> {
+        vec![Point3D::default()]
+    }
+
+    /// The cells joined with the cell.
+    fn neighbors(&self, cell: Point3D<i32>) -> Vec<Point3D<i32>>;
+
+    /// The order of the symmetry group, under which free shapes are distinct.
+    fn symmetry_count(&self) -> usize;
+
+    /// Applies the symmetry with the given index, where zero is the identity.
+    fn apply_symmetry(&self, symmetry: usize, cell: Point3D<i32>) -> Point3D<i32>;
+
+    /// Moves the cells by a translation of the lattice such that every translated copy of a shape
+    /// results in the same cells, which are sorted afterwards.
+    fn normalize(&self, cells: &mut [Point3D<i32>]) {
+        let min = min_coordinates(cells);
+        cells.iter_mut().for_each(|cell| *cell -= min);
+        cells.sort();
+    }
+}
+
+/// The smallest coordinate on every axis.
+fn min_coordinates(cells: &[Point3D<i32>]) -> Point3D<i32> {
+    cells.iter().fold(cells[0], |min, p| Point3D::new(
+        *min.x().min(p.x()),
+        *min.y().min(p.y()),
+        *min.z().min(p.z()),
+    ))
+}
+
+/// The lattices that can be enumerated by name.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LatticeKind {
+    Cubic,
+    Hexagonal,
+    Triangular,
+}
+
+impl LatticeKind {
+    pub fn lattice(&self) -> &'static dyn Lattice {
+        match self {
+            LatticeKind::Cubic => &Cubic,
+            LatticeKind::Hexagonal => &Hexagonal,
+            LatticeKind::Triangular => &Triangular,
+        }
+    }
+}
+
+/// Cubes joined at their faces, which polycubes are made of.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Cubic;
+
+impl Cubic {
+    pub const FACE_OFFSETS: [Point3D<i32>; 6] = [
+        Point3D::new(0, 0, -1),
+        Point3D::new(0, 0, 1),
+        Point3D::new(0, -1, 0),
+        Point3D::new(0, 1, 0),
+        Point3D::new(-1, 0, 0),
+        Point3D::new(1, 0, 0),
+    ];
+
+    /// The face offsets followed by the offsets of the cubes sharing only an edge.
+    pub const EDGE_OFFSETS: [Point3D<i32>; 18] = [
+        Point3D::new(0, 0, -1),
+        Point3D::new(0, 0, 1),
+        Point3D::new(0, -1, 0),
+        Point3D::new(0, 1, 0),
+        Point3D::new(-1, 0, 0),
+        Point3D::new(1, 0, 0),
+        Point3D::new(0, -1, -1),
+        Point3D::new(0, -1, 1),
+        Point3D::new(0, 1, -1),
+        Point3D::new(0, 1, 1),
+        Point3D::new(-1, 0, -1),
+        Point3D::new(-1, 0, 1),
+        Point3D::new(1, 0, -1),
+        Point3D::new(1, 0, 1),
+        Point3D::new(-1, -1, 0),
+        Point3D::new(-1, 1, 0),
+        Point3D::new(1, -1, 0),
+        Point3D::new(1, 1, 0),
+    ];
+
+    /// The edge offsets followed by the offsets of the cubes sharing only a corner.
+    pub const CORNER_OFFSETS: [Point3D<i32>; 26] = [
+        Point3D::new(0, 0, -1),
+        Point3D::new(0, 0, 1),
+        Point3D::new(0, -1, 0),
+        Point3D::new(0, 1, 0),
+        Point3D::new(-1, 0, 0),
+        Point3D::new(1, 0, 0),
+        Point3D::new(0, -1, -1),
+        Point3D::new(0, -1, 1),
+        Point3D::new(0, 1, -1),
+        Point3D::new(0, 1, 1),
+        Point3D::new(-1, 0, -1),
+        Point3D::new(-1, 0, 1),
+        Point3D::new(1, 0, -1),
+        Point3D::new(1, 0, 1),
+        Point3D::new(-1, -1, 0),
+        Point3D::new(-1, 1, 0),
+        Point3D::new(1, -1, 0),
+        Point3D::new(1, 1, 0),
+        Point3D::new(-1, -1, -1),
+        Point3D::new(-1, -1, 1),
+        Point3D::new(-1, 1, -1),
+        Point3D::new(-1, 1, 1),
+        Point3D::new(1, -1, -1),
+        Point3D::new(1, -1, 1),
+        Point3D::new(1, 1, -1),
+        Point3D::new(1, 1, 1),
+    ];
+
+    const AXIS_PERMUTATIONS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+}
+
+impl Lattice for Cubic {
+    fn name(&self) -> &'static str {
+        "cubic"
+    }
+
+    fn neighbors(&self, cell: Point3D<i32>) -> Vec<Point3D<i32>> {
+        Self::FACE_OFFSETS.map(|o| o + cell).to_vec()
+    }
+
+    fn symmetry_count(&self) -> usize {
+        48
+    }
+
+    /// Every permutation of the axes combined with every choice of mirrored axes.
+    fn apply_symmetry(&self, symmetry: usize, cell: Point3D<i32>) -> Point3D<i32> {
+        let coordinates = [*cell.x(), *cell.y(), *cell.z()];
+        let permutation = Self::AXIS_PERMUTATIONS[symmetry / 8];
+        let [x, y, z] = [0, 1, 2].map(|i| {
+            let sign = if symmetry & (1 << i) == 0 { 1 } else { -1 };
+            sign * coordinates[permutation[i]]
+        });
+        Point3D::new(x, y, z)
+    }
+}
+
+/// Which cubes count as joined, from polycubes whose cubes share faces to the looser variants
+/// where sharing an edge or a corner suffices.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Connectivity {
+    /// 6 neighbors.
+    #[default]
+    Face,
+    /// 18 neighbors.
+    Edge,
+    /// 26 neighbors.
+    Corner,
+}
+
+impl Connectivity {
+    /// The offsets of the positions joined with a cube at the origin.
+    pub fn offsets(&self) -> &'static [Point3D<i32>] {
+        match self {
+            Connectivity::Face => &Cubic::FACE_OFFSETS,
+            Connectivity::Edge => &Cubic::EDGE_OFFSETS,
+            Connectivity::Corner => &Cubic::CORNER_OFFSETS,
+        }
+    }
+}
+
+/// Regular hexagons joined at their edges, which polyhexes are made of.
+/// Cells use axial coordinates in the x and y axis, while z is always zero.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Hexagonal;
+
+impl Hexagonal {
+    pub const EDGE_OFFSETS: [Point3D<i32>; 6] = [
+        Point3D::new(1, 0, 0),
+        Point3D::new(-1, 0, 0),
+        Point3D::new(0, 1, 0),
+        Point3D::new(0, -1, 0),
+        Point3D::new(1, -1, 0),
+        Point3D::new(-1, 1, 0),
+    ];
+}
+
+impl Lattice for Hexagonal {
+    fn name(&self) -> &'static str {
+        "hexagonal"
+    }
+
+    fn neighbors(&self, cell: Point3D<i32>) -> Vec<Point3D<i32>> {
+        Self::EDGE_OFFSETS.map(|o| o + cell).to_vec()
+    }
+
+    fn symmetry_count(&self) -> usize {
+        12
+    }
+
+    /// Rotates by a multiple of 60 degrees, followed by a reflection for the second half of the symmetries.
+    fn apply_symmetry(&self, symmetry: usize, cell: Point3D<i32>) -> Point3D<i32> {
+        let (mut q, mut r) = (*cell.x(), *cell.y());
+        for _ in 0..symmetry % 6 {
+            (q, r) = (-r, q + r);
+        }
+        if symmetry >= 6 {
+            (q, r) = (r, q);
+        }
+        Point3D::new(q, r, 0)
+    }
+}
+
+/// Equilateral triangles joined at their edges, which polyiamonds are made of.
+/// A cell `(a, b, c)` points up if `a + b + c == 2` and down if `a + b + c == 1`,
+/// and shares an edge with the cells differing by one in a single coordinate.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Triangular;
+
+impl Lattice for Triangular {
+    fn name(&self) -> &'static str {
+        "triangular"
+    }
+
+    fn origins(&self) -> Vec<Point3D<i32>> {
+        vec![Point3D::new(0, 0, 1), Point3D::new(1, 0, 1)]
+    }
+
+    fn neighbors(&self, cell: Point3D<i32>) -> Vec<Point3D<i32>> {
+        let step = if cell.x() + cell.y() + cell.z() == 2 { -1 } else { 1 };
+        vec![
+            cell + Point3D::new(step, 0, 0),
+            cell + Point3D::new(0, step, 0),
+            cell + Point3D::new(0, 0, step),
+        ]
+    }
+
+    fn symmetry_count(&self) -> usize {
+        12
+    }
+
+    /// Rotates by a multiple of 120 degrees, optionally followed by a point reflection flipping the
+    /// direction of every triangle and a reflection swapping the first two coordinates.
+    fn apply_symmetry(&self, symmetry: usize, cell: Point3D<i32>) -> Point3D<i32> {
+        let (mut a, mut b, mut c) = (*cell.x(), *cell.y(), *cell.z());
+        for _ in 0..symmetry % 3 {
+            (a, b, c) = (c, a, b);
+        }
+        if (symmetry / 3) % 2 == 1 {
+            (a, b, c) = (1 - a, 1 - b, 1 - c);
+        }
+        if symmetry >= 6 {
+            (a, b) = (b, a);
+        }
+        Point3D::new(a, b, c)
+    }
+
+    /// Translations keep the sum of the coordinates, so the third coordinate compensates for the
+    /// first two.
+    fn normalize(&self, cells: &mut [Point3D<i32>]) {
+        let min = min_coordinates(cells);
+        let offset = Point3D::new(*min.x(), *min.y(), -min.x() - min.y());
+        cells.iter_mut().for_each(|cell| *cell -= offset);
+        cells.sort();
+    }
+}
+
+/// The smallest normalized image of the cells under every symmetry of the lattice,
+/// which is equal for shapes that only differ by a symmetry and a translation.
+pub fn canonical_form(lattice: &dyn Lattice, cells: &[Point3D<i32>]) -> Vec<Point3D<i32>> {
+    (0..lattice.symmetry_count())
+        .map(|symmetry| {
+            let mut image: Vec<_> = cells.iter().map(|cell| lattice.apply_symmetry(symmetry, *cell)).collect();
+            lattice.normalize(&mut image);
+            image
+        })
+        .min()
+        .expect("Every lattice has the identity symmetry.")
+}
+
+/// Every normalized shape with one more cell than the shape.
+fn children<'a>(lattice: &'a dyn Lattice, shape: &'a [Point3D<i32>]) -> impl Iterator<Item = Vec<Point3D<i32>>> + 'a {
+    let free: BTreeSet<_> = shape.iter()
+        .flat_map(|cell| lattice.neighbors(*cell))
+        .filter(|cell| !shape.contains(cell))
+        .collect();
+    free.into_iter().map(move |cell| {
+        let mut child = shape.to_vec();
+        child.push(cell);
+        lattice.normalize(&mut child);
+        child
+    })
+}
+
+/// The shapes with the given number of cells that are distinct up to translation.
+pub fn fixed_shapes(lattice: &dyn Lattice, cell_count: usize) -> HashSet<Vec<Point3D<i32>>> {
+    if cell_count == 0 {
+        return HashSet::new();
+    }
+    let mut level: HashSet<_> = lattice.origins().into_iter().map(|origin| vec![origin]).collect();
+    for _ in 1..cell_count {
+        level = level.iter().flat_map(|shape| children(lattice, shape)).collect();
+    }
+    level
+}
+
+/// The number of shapes with the given number of cells that are distinct up to translation. The last
+/// level is only kept as the [fingerprint128] of its shapes.
+pub fn fixed_count(lattice: &dyn Lattice, cell_count: usize) -> u64 {
+    if cell_count < 2 {
+        return fixed_shapes(lattice, cell_count).len() as u64;
+    }
+    let fingerprints: HashSet<u128> = fixed_shapes(lattice, cell_count - 1).iter()
+        .flat_map(|shape| children(lattice, shape))
+        .map(fingerprint128)
+        .collect();
+    fingerprints.len() as u64
+}
+
+/// The shapes with the given number of cells that are distinct up to the symmetries of the lattice,
+/// each in its [canonical_form].
+pub fn free_shapes(lattice: &dyn Lattice, cell_count: usize) -> BTreeSet<Vec<Point3D<i32>>> {
+    if cell_count == 0 {
+        return BTreeSet::new();
+    }
+    let mut level: BTreeSet<_> = lattice.origins().into_iter()
+        .map(|origin| canonical_form(lattice, &[origin]))
+        .collect();
+    for _ in 1..cell_count {
+        level = level.iter()
+            .flat_map(|shape| children(lattice, shape))
+            .map(|child| canonical_form(lattice, &child))
+            .collect();
+    }
+    level
+}
+
+pub fn shapes_file_name(lattice: &dyn Lattice, cell_count: usize) -> String {
+    format!("{}_shapes_{cell_count}.cac", lattice.name())
+}
+
+/// Saves the shapes of one size of the lattice in the store.
+pub fn save_shapes(shapes: &BTreeSet<Vec<Point3D<i32>>>, store: &dyn CacheStore, lattice: &dyn Lattice, cell_count: usize) -> Result<(), Error> {
+    let data = bincode::serde::encode_to_vec(shapes, bincode::config::standard())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    store.put(&shapes_file_name(lattice, cell_count), &data)
+}
+
+pub fn load_shapes(store: &dyn CacheStore, lattice: &dyn Lattice, cell_count: usize) -> Result<BTreeSet<Vec<Point3D<i32>>>, Error> {
+    let mut reader = store.open(&shapes_file_name(lattice, cell_count))?;
+    bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod lattice_tests {
+    use std::str::FromStr;
+    use crate::store::FsStore;
+    use super::*;
+
+    fn free_counts(lattice: &dyn Lattice, max_cells: usize) -> Vec<usize> {
+        (1..=max_cells).map(|n| free_shapes(lattice, n).len()).collect()
+    }
+
+    #[test]
+    fn test_free_counts() {
+        assert_eq!(vec![1, 1, 2, 7, 23], free_counts(&Cubic, 5));
+        assert_eq!(vec![1, 1, 3, 7, 22, 82], free_counts(&Hexagonal, 6));
+        assert_eq!(vec![1, 1, 1, 3, 4, 12, 24], free_counts(&Triangular, 7));
+    }
+
+    #[test]
+    fn test_fixed_counts() {
+        let fixed = |lattice: &dyn Lattice| (1..=5).map(|n| fixed_shapes(lattice, n).len()).collect::<Vec<_>>();
+        assert_eq!(vec![1, 3, 11, 44, 186], fixed(&Hexagonal));
+        assert_eq!(vec![2, 3, 6, 14, 36], fixed(&Triangular));
+    }
+
+    #[test]
+    fn test_symmetries_keep_neighbors() {
+        for kind in [LatticeKind::Cubic, LatticeKind::Hexagonal, LatticeKind::Triangular] {
+            let lattice = kind.lattice();
+            for origin in lattice.origins() {
+                for symmetry in 0..lattice.symmetry_count() {
+                    let image = lattice.apply_symmetry(symmetry, origin);
+                    let mut expected: Vec<_> = lattice.neighbors(origin).iter().map(|n| lattice.apply_symmetry(symmetry, *n)).collect();
+                    let mut actual = lattice.neighbors(image);
+                    expected.sort();
+                    actual.sort();
+                    assert_eq!(expected, actual, "Symmetry {symmetry} of the {kind} lattice");
+                }
+            }
+        }
+        assert_eq!(LatticeKind::Triangular, LatticeKind::from_str("triangular").unwrap());
+    }
+
+    #[test]
+    fn test_connectivity_offsets() {
+        for connectivity in [Connectivity::Face, Connectivity::Edge, Connectivity::Corner] {
+            let offsets = connectivity.offsets();
+            let distinct: HashSet<_> = offsets.iter().collect();
+            assert_eq!(offsets.len(), distinct.len());
+            assert!(offsets.iter().all(|o| offsets.contains(&-*o)), "Offsets of {connectivity} are symmetric");
+            assert!(!offsets.contains(&Point3D::default()));
+        }
+        assert_eq!([6, 18, 26], [Connectivity::Face, Connectivity::Edge, Connectivity::Corner].map(|c| c.offsets().len()));
+        assert_eq!(Connectivity::Edge, Connectivity::from_str("edge").unwrap());
+    }
+
+    #[test]
+    fn test_storage() {
+        let dir = std::env::temp_dir().join("lattice_tests_storage");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        let polyhexes = free_shapes(&Hexagonal, 4);
+        save_shapes(&polyhexes, &store, &Hexagonal, 4).expect("Writable temp dir");
+        assert_eq!(polyhexes, load_shapes(&store, &Hexagonal, 4).expect("Saved shapes"));
+        assert_eq!(ErrorKind::NotFound, load_shapes(&store, &Triangular, 4).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use rayon::prelude::*;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_hash::BlockHash;
+use crate::polycube::CanonicalForms;
+
+/// The arrangements of a completed level, sorted by their [BlockHash].
+///
+/// A hash does not identify a shape exactly, so distinct shapes with equal hashes are kept side by
+/// side as [collisions](Level::collisions), ordered by their canonical forms. Only congruent records
+/// with equal hashes are duplicates.
+///
+/// Levels are written once and read many times, so instead of a tree they are stored as a single
+/// sorted vector that is built by sorting and deduplicating all records at once. This saves the
+/// per entry overhead of the nodes, and lookups are binary searches over contiguous memory.
+/// Shapes that are still being collected are kept in a `HashMap` or `BTreeMap` until the level is complete.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Level {
+    records: Vec<(BlockHash, BlockArrangement)>,
+}
+
+impl Level {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sorts the records by their hashes. Of several congruent records with the same hash only the last
+    /// one is kept, like inserting them into a map one after another would.
+    pub fn from_unsorted(mut records: Vec<(BlockHash, BlockArrangement)>) -> Self {
+        records.sort_by_key(|(hash, _)| *hash);
+        let mut kept: Vec<(BlockHash, BlockArrangement)> = Vec::with_capacity(records.len());
+        let mut group_start = 0;
+        for record in records {
+            if kept.last().is_none_or(|(hash, _)| *hash != record.0) {
+                Self::sort_collisions(&mut kept[group_start..]);
+                group_start = kept.len();
+            }
+            match kept[group_start..].iter_mut().find(|(_, ba)| *ba == record.1) {
+                Some(same) => *same = record,
+                None => kept.push(record),
+            }
+        }
+        Self::sort_collisions(&mut kept[group_start..]);
+        Self { records: kept }
+    }
+
+    /// Orders distinct shapes with equal hashes by their canonical forms, so they do not depend on the
+    /// order they were found in.
+    fn sort_collisions(group: &mut [(BlockHash, BlockArrangement)]) {
+        if group.len() > 1 {
+            group.sort_by_cached_key(|(_, ba)| ba.canonical().block_iter_sorted().collect::<Vec<_>>());
+        }
+    }
+
+    /// Combines levels generated from different parents into one.
+    pub fn merge(levels: impl IntoIterator<Item = Level>) -> Self {
+        Self::from_unsorted(levels.into_iter().flat_map(|level| level.records).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn position(&self, hash: &BlockHash) -> Result<usize, usize> {
+        self.records.binary_search_by(|(key, _)| key.cmp(hash))
+    }
+
+    /// The first record with the hash, see [Level::get_all] for the others.
+    pub fn get(&self, hash: &BlockHash) -> Option<&BlockArrangement> {
+        self.get_all(hash).first().map(|(_, ba)| ba)
+    }
+
+    /// Every record with the hash, which are several distinct shapes if their hashes collide.
+    pub fn get_all(&self, hash: &BlockHash) -> &[(BlockHash, BlockArrangement)] {
+        self.range(hash..=hash)
+    }
+
+    /// The number of records whose hash equals the hash of the record before them, so every shape
+    /// beyond the first of those sharing a hash is counted once.
+    pub fn collisions(&self) -> usize {
+        self.records.windows(2).filter(|pair| pair[0].0 == pair[1].0).count()
+    }
+
+    pub fn contains_key(&self, hash: &BlockHash) -> bool {
+        self.position(hash).is_ok()
+    }
+
+    /// The records in ascending order of their hashes.
+    pub fn iter(&self) -> impl Iterator<Item = (&BlockHash, &BlockArrangement)> + Clone {
+        self.records.iter().map(|(hash, ba)| (hash, ba))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &BlockHash> + Clone {
+        self.records.iter().map(|(hash, _)| hash)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &BlockArrangement> + Clone {
+        self.records.iter().map(|(_, ba)| ba)
+    }
+
+    /// The number of shapes when mirror images are considered distinct. A level is deduplicated by hashes
+    /// that never tell mirror images apart, so the canonical forms are searched once per shape, on as
+    /// many threads as there are cores.
+    pub fn one_sided_count(&self) -> usize {
+        self.records.par_iter()
+            .map(|(_, ba)| CanonicalForms::of(ba).one_sided_count())
+            .sum()
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = BlockArrangement> {
+        self.records.into_iter().map(|(_, ba)| ba)
+    }
+
+    /// The records whose hashes lie in the range, found by two binary searches.
+    pub fn range(&self, range: impl RangeBounds<BlockHash>) -> &[(BlockHash, BlockArrangement)] {
+        let start = self.records.partition_point(|(hash, _)| match range.start_bound() {
+            Bound::Included(start) => hash < start,
+            Bound::Excluded(start) => hash <= start,
+            Bound::Unbounded => false,
+        });
+        let end = self.records.partition_point(|(hash, _)| match range.end_bound() {
+            Bound::Included(end) => hash <= end,
+            Bound::Excluded(end) => hash < end,
+            Bound::Unbounded => true,
+        });
+        &self.records[start..end.max(start)]
+    }
+
+    /// Keeps only the records the predicate returns true for, in their order.
+    pub fn retain(&mut self, mut f: impl FnMut(&BlockHash, &BlockArrangement) -> bool) {
+        self.records.retain(|(hash, ba)| f(hash, ba));
+    }
+
+    /// The records in ascending order of their hashes.
+    pub fn as_slice(&self) -> &[(BlockHash, BlockArrangement)] {
+        &self.records
+    }
+}
+
+impl FromIterator<(BlockHash, BlockArrangement)> for Level {
+    fn from_iter<T: IntoIterator<Item = (BlockHash, BlockArrangement)>>(iter: T) -> Self {
+        Self::from_unsorted(iter.into_iter().collect())
+    }
+}
+
+impl From<BTreeMap<BlockHash, BlockArrangement>> for Level {
+    fn from(map: BTreeMap<BlockHash, BlockArrangement>) -> Self {
+        // The map is sorted and free of duplicates already.
+        Self { records: map.into_iter().collect() }
+    }
+}
+
+impl IntoIterator for Level {
+    type Item = (BlockHash, BlockArrangement);
+    type IntoIter = std::vec::IntoIter<(BlockHash, BlockArrangement)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod level_tests {
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use crate::orientation::OrientationIterator;
+    use crate::point::Point3D;
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        let ba = BlockArrangement::new();
+        let records: Vec<_> = VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>())
+            .map(|ba| (BlockHash::from(&ba), ba))
+            .collect();
+        let map: BTreeMap<_, _> = records.iter().cloned().collect();
+        let level = Level::from_unsorted(records.clone());
+        assert_eq!(map.len(), level.len());
+        assert!(level.keys().zip(map.keys()).all(|(a, b)| a == b));
+        assert_eq!(Level::from(map.clone()), level);
+        for (hash, ba) in &map {
+            assert_eq!(Some(ba), level.get(hash));
+        }
+
+        let hashes: Vec<_> = level.keys().copied().collect();
+        assert_eq!(hashes.len(), level.range(..).len());
+        assert_eq!(1, level.range(hashes[0]..=hashes[0]).len());
+        assert_eq!(hashes.len() - 1, level.range(hashes[1]..).len());
+        assert!(level.range(hashes[1]..hashes[1]).is_empty());
+
+        let (first, second) = records.split_at(records.len() / 2);
+        let merged = Level::merge([Level::from_unsorted(second.to_vec()), Level::from_unsorted(first.to_vec())]);
+        assert_eq!(level, merged);
+        assert!(!Level::new().contains_key(&hashes[0]));
+        assert_eq!(0, level.collisions());
+    }
+
+    #[test]
+    fn test_collisions() {
+        let line = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(2, 0, 0)]).unwrap();
+        let corner = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(1, 1, 0)]).unwrap();
+        let mut turned = corner.clone();
+        turned.set_orientation(OrientationIterator::default().nth(5).unwrap());
+        // Both shapes are given the same hash, as if it collided.
+        let hash = BlockHash::from(&line);
+        let level = Level::from_unsorted(vec![(hash, corner.clone()), (hash, line.clone()), (hash, turned.clone())]);
+        assert_eq!(2, level.len());
+        assert_eq!(1, level.collisions());
+        assert_eq!(2, level.get_all(&hash).len());
+        assert!(level.values().any(|ba| *ba == line) && level.values().any(|ba| *ba == corner));
+        // The order does not depend on the order the shapes were found in.
+        assert_eq!(level, Level::from_unsorted(vec![(hash, line), (hash, turned)]));
+        assert_eq!(level, Level::merge([level.clone(), level.clone()]));
+    }
+}
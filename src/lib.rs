@@ -0,0 +1,46 @@
+pub mod block_arrangement;
+pub mod mapper;
+pub mod point;
+pub mod block_hash;
+pub mod orientation;
+pub mod polycube;
+pub mod cache;
+pub mod export;
+pub mod poly_tree;
+pub mod config;
+pub mod import;
+pub mod symmetry;
+pub mod distributed;
+pub mod store;
+pub mod analysis;
+pub mod morph;
+pub mod shape_graph;
+pub mod lattice;
+pub mod puzzle;
+pub mod placement;
+pub mod dashboard;
+pub mod reference;
+pub mod subtractive;
+pub mod level;
+pub mod output;
+pub mod run;
+pub mod block_count;
+pub mod strategy;
+pub mod sampler;
+pub mod estimate;
+pub mod tensor;
+pub mod permutation;
+pub mod shape_ref;
+pub mod rooted;
+pub mod congruence;
+pub mod polycubes;
+pub mod metrics;
+pub mod self_test;
+pub mod expansion;
+pub mod bloom;
+pub mod shape_filter;
+pub mod augmentation;
+#[cfg(test)]
+mod golden;
+#[cfg(test)]
+mod transforms;
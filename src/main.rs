@@ -1,125 +1,1539 @@
-mod block_arrangement;
-mod mapper;
-mod point;
-mod block_hash;
-mod orientation;
-
 use std::collections::BTreeMap;
-use std::{env, io};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
-use crate::block_arrangement::block_variation::VariationGenerator;
-use crate::block_arrangement::BlockArrangement;
-use crate::block_hash::BlockHash;
+use std::{env, io, process};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use cube_combinations::analysis::{count_by_bounding_box, fixed_count_direct, fixed_count_from_free, BoundingBoxClass};
+use cube_combinations::block_arrangement::block_variation::{generate_variants_from, NeighborOrder};
+use cube_combinations::block_arrangement::BlockArrangement;
+use cube_combinations::block_count::BlockCount;
+use cube_combinations::block_hash::BlockHash;
+use cube_combinations::config::{Algorithm, OutputFilter, RunConfig};
+use cube_combinations::estimate::{CountEstimator, LevelEstimate, DEFAULT_SAMPLES};
+use cube_combinations::cache::{convert_cache, load_cache, load_record, load_shapes, cache_location, open_cache, CacheFormat, CacheLayout, CacheReader, Compression};
+use cube_combinations::cache::combined::{combine_caches, split_caches};
+use cube_combinations::cache::sharded::{shard_caches, unshard_caches, DEFAULT_HASH_SHARDS};
+use cube_combinations::cache::manifest::CacheManifest;
+use cube_combinations::cache::ids::{ids_path, load_ids, ShapeId, ShapeIds};
+use cube_combinations::cache::verify::verify_cache;
+use cube_combinations::cache::index::{read_index, ShapeMetadata};
+use cube_combinations::export::{export, page, sample, shuffled_page, ExportFormat, SortKey};
+use cube_combinations::permutation::Permutation;
+use cube_combinations::import::{import, import_file, ImportFormat};
+use cube_combinations::orientation::OrientationOrder;
+use cube_combinations::output::{CacheStatsOutput, ConvertOutput, ErrorOutput, EstimateOutput, GraphOutput, IdentifyOutput, LayoutOutput, LevelOutput, ListOutput, ManifestLevel, MetricsOutput, MorphOutput, MorphStep, ProblemOutput, PuzzleOutput, ReferenceLevel, ReferenceOutput, ReportRow, SampleOutput, SelfTestOutput, ShapeOutput, VerifyOutput};
+use cube_combinations::metrics::{Metric, MetricsTable};
+use cube_combinations::morph::{find_morph, DEFAULT_MAX_SHAPES};
+use cube_combinations::puzzle::DEFAULT_ATTEMPTS;
+use cube_combinations::shape_graph::{GraphFormat, ShapeGraph};
+use cube_combinations::symmetry::Symmetry;
+use cube_combinations::poly_tree::PolyTree;
+use cube_combinations::poly_tree::trace::Trace;
+use cube_combinations::polycube::Polycube;
+use cube_combinations::puzzle::PuzzleGenerator;
+use cube_combinations::reference;
+use cube_combinations::rooted::rooted_count;
+use cube_combinations::self_test::{known_count, SelfTest, KNOWN_COUNTS};
+use cube_combinations::run::Runner;
+use cube_combinations::shape_ref::ShapeRef;
+use cube_combinations::subtractive::carve;
+use cube_combinations::tensor::{TensorExport, TensorFormat};
+use cube_combinations::store::{open_file_store, open_store, CacheStore};
+use serde::Serialize;
+
+/// Set by `--json` in front of the subcommand, see [output](cube_combinations::output).
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Prints a progress message, which goes to stderr while stdout is reserved for the JSON document.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if json_output() { eprintln!($($arg)*) } else { println!($($arg)*) }
+    };
+}
+
+/// Prints the document of a command run with `--json`.
+fn print_json(value: &impl Serialize) -> Result<(), String> {
+    let json = serde_json::to_string(value).map_err(|e| format!("Failed to serialize the output: {e}"))?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Prints the message of a failed command, as an [ErrorOutput] with `--json`.
+fn print_error(error: &str) {
+    match json_output() {
+        true => eprintln!("{}", serde_json::to_string(&ErrorOutput { error: error.to_string() }).unwrap_or_default()),
+        false => eprintln!("{error}"),
+    }
+}
+
+/// Calculates how many unique arrangements can be made for n cubes attached to one another at the faces.
+///
+/// Without a subcommand the arguments describe a generation run like those of `generate`.
+#[derive(Parser, Debug)]
+#[command(name = "cube_combinations", version)]
+struct Cli {
+    /// Print a single JSON document instead of text. Only accepted in front of the subcommand.
+    #[arg(long)]
+    json: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Count the shapes with n blocks in little memory, without caching or exporting them.
+    Count(CountArgs),
+    /// Generate the shapes with up to n blocks and cache every level.
+    Generate(Box<RunArgs>),
+    /// Summarize the shapes of a cache file.
+    Stats(CacheFileArgs),
+    /// Print the cached shapes with n blocks.
+    Export(ExportArgs),
+    /// Check every record of a cache file against its recomputed canonical form and key.
+    Verify(CacheFileArgs),
+    /// Print a single shape of a cache file.
+    Get(GetArgs),
+    /// Print a page of the shapes of a cache file.
+    List(ListArgs),
+    /// Print shapes chosen uniformly at random from a cache file.
+    Sample(SampleArgs),
+    /// Write the metrics of the shapes of a cache file as CSV.
+    Metrics(MetricsArgs),
+    /// Convert, summarize, check and move caches.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Describe shapes and find them in the caches.
+    Identify(IdentifyArgs),
+    /// Print the shapes of a .vox, .binvox, .xyz or .json voxel file.
+    Import(ImportArgs),
+    /// Tabulate the caches by size.
+    Report(ReportArgs),
+    /// Print the moves transforming one shape into another.
+    Morph(MorphArgs),
+    /// Print the graph of moves between the shapes with n blocks.
+    Graph(GraphArgs),
+    /// Print pieces that fill a box in exactly one way.
+    Puzzle(PuzzleArgs),
+    /// Re-execute the insertions recorded by a poly tree run with --trace on a single thread.
+    Replay(ReplayArgs),
+    /// Compare the shapes of both algorithms with those of the reference enumerator.
+    Reference(ReferenceArgs),
+    /// Count the shapes fitting into a box by removing its blocks.
+    Carve(CarveArgs),
+    /// Estimate the numbers of shapes too large to enumerate.
+    Estimate(EstimateArgs),
+    /// Write the shapes of a cache file as voxel arrays for machine learning.
+    Tensor(TensorArgs),
+    /// Check the build and the platform in a few seconds and print PASS or FAIL.
+    SelfTest(SelfTestArgs),
+}
+
+/// The arguments of a generation run, which override those of the `--config` file.
+#[derive(Args, Debug, Default, PartialEq)]
+struct RunArgs {
+    /// The number of blocks of the largest shapes.
+    n: Option<BlockCount>,
+    /// How many times more shapes than its parents every level is expected to have.
+    growth_factor: Option<f64>,
+    /// Read the configuration of the run from this TOML file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// The expansion strategy, see `Algorithm`.
+    #[arg(long)]
+    algorithm: Option<Algorithm>,
+    /// Short form of --algorithm poly-tree.
+    #[arg(long, conflicts_with = "algorithm")]
+    poly_tree: bool,
+    /// Write an index section with the metadata of every shape into the caches.
+    #[arg(long)]
+    cache_index: bool,
+    /// How the caches of every level are laid out: per-size, combined or sharded.
+    #[arg(long)]
+    cache_layout: Option<CacheLayout>,
+    /// The number of hash shards every level is split into with the sharded layout.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    cache_shards: Option<u32>,
+    /// Ignore the cached levels and generate them again.
+    #[arg(long)]
+    force: bool,
+    /// Only count the shapes by canonical augmentation, without caching or exporting them.
+    #[arg(long)]
+    count_only: bool,
+    /// Worker threads, 0 for all cores.
+    #[arg(long)]
+    threads: Option<usize>,
+    /// End a hash map run after this many shapes, saving its progress and exiting with status 2.
+    #[arg(long)]
+    stop_after_shapes: Option<u64>,
+    /// End a hash map run after this many seconds, saving its progress and exiting with status 2.
+    #[arg(long)]
+    stop_after_seconds: Option<u64>,
+    /// The number of parents expanded per shard.
+    #[arg(long)]
+    shard_size: Option<usize>,
+    /// Save the children generated so far as a checkpoint at least this often.
+    #[arg(long)]
+    checkpoint_seconds: Option<u64>,
+    /// Read the parents of every level from its cache one at a time instead of keeping them in memory.
+    #[arg(long)]
+    stream_parents: bool,
+    /// Only keep the shapes that fit into this many layers.
+    #[arg(long)]
+    max_layers: Option<u32>,
+    /// The number of bytes the levels may occupy in memory.
+    #[arg(long)]
+    memory_budget: Option<u64>,
+    /// Size of the bloom filter in front of the dedup map.
+    #[arg(long)]
+    bloom_filter_bytes: Option<u64>,
+    /// A directory or an s3:// url.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+    /// Hand out the expansion of every level to workers connecting to this address.
+    #[arg(long)]
+    listen: Option<String>,
+    /// Serve a dashboard of the run on this port or address.
+    #[arg(long)]
+    serve: Option<String>,
+    /// Record the insertions of a poly tree run into this file.
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Export the shapes with n blocks in these formats.
+    #[arg(long = "output")]
+    output_formats: Vec<ExportFormat>,
+    /// chiral, achiral, asymmetric or symmetric.
+    #[arg(long = "filter")]
+    filters: Vec<OutputFilter>,
+    /// Only export the children of the shape with this code, which has one block less.
+    #[arg(long)]
+    descendants_of: Vec<String>,
+    /// sequential or hit-rate.
+    #[arg(long)]
+    orientation_order: Option<OrientationOrder>,
+    /// offsets or index.
+    #[arg(long)]
+    neighbor_order: Option<NeighborOrder>,
+}
+
+impl RunArgs {
+    /// Builds the [RunConfig] of the run, starting from the `--config` file if there is one.
+    fn into_config(self) -> Result<RunConfig, String> {
+        let mut config = match &self.config {
+            Some(path) => RunConfig::load(path).map_err(|e| format!("Failed to load config {}: {e}", path.display()))?,
+            None => RunConfig::default(),
+        };
+        if let Some(n) = self.n {
+            config.n = n;
+        }
+        if let Some(growth_factor) = self.growth_factor {
+            config.growth_factor = growth_factor;
+        }
+        if let Some(algorithm) = self.algorithm {
+            config.algorithm = algorithm;
+        }
+        if self.poly_tree {
+            config.algorithm = Algorithm::PolyTree;
+        }
+        config.cache_index |= self.cache_index;
+        config.force |= self.force;
+        config.count_only |= self.count_only;
+        config.stream_parents |= self.stream_parents;
+        if let Some(cache_layout) = self.cache_layout {
+            config.cache_layout = cache_layout;
+        }
+        if let Some(cache_shards) = self.cache_shards {
+            config.cache_shards = cache_shards;
+        }
+        if let Some(threads) = self.threads {
+            config.threads = worker_threads(threads);
+        }
+        if let Some(shard_size) = self.shard_size {
+            config.shard_size = shard_size;
+        }
+        if let Some(cache_dir) = self.cache_dir {
+            config.cache_dir = cache_dir;
+        }
+        if let Some(orientation_order) = self.orientation_order {
+            config.orientation_order = orientation_order;
+        }
+        if let Some(neighbor_order) = self.neighbor_order {
+            config.neighbor_order = neighbor_order;
+        }
+        config.checkpoint_seconds = self.checkpoint_seconds.or(config.checkpoint_seconds);
+        config.stop_after_shapes = self.stop_after_shapes.or(config.stop_after_shapes);
+        config.stop_after_seconds = self.stop_after_seconds.or(config.stop_after_seconds);
+        config.max_layers = self.max_layers.or(config.max_layers);
+        config.memory_budget = self.memory_budget.or(config.memory_budget);
+        config.bloom_filter_bytes = self.bloom_filter_bytes.or(config.bloom_filter_bytes);
+        config.listen = self.listen.or(config.listen);
+        config.serve = self.serve.or(config.serve);
+        config.trace = self.trace.or(config.trace);
+        if !self.output_formats.is_empty() {
+            config.output_formats = self.output_formats;
+        }
+        if !self.filters.is_empty() {
+            config.filters = self.filters;
+        }
+        if !self.descendants_of.is_empty() {
+            config.descendants_of = self.descendants_of;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// The number of worker threads requested with --threads, where 0 stands for all cores.
+fn worker_threads(threads: usize) -> usize {
+    match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        threads => threads,
+    }
+}
+
+#[derive(Args, Debug)]
+struct CountArgs {
+    /// The number of blocks of the counted shapes.
+    n: BlockCount,
+    /// Worker threads, 0 for all cores.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+impl CountArgs {
+    fn into_config(self) -> Result<RunConfig, String> {
+        let mut config = RunConfig { n: self.n, count_only: true, ..RunConfig::default() };
+        if let Some(threads) = self.threads {
+            config.threads = worker_threads(threads);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[derive(Args, Debug)]
+struct CacheFileArgs {
+    /// The path of a cache file.
+    cache: String,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// The number of blocks of the exported shapes.
+    n: BlockCount,
+    /// A directory or an s3:// url.
+    #[arg(long, default_value = ".")]
+    cache_dir: String,
+    #[arg(long, default_value_t = ExportFormat::Code)]
+    format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("shape").required(true).args(["id", "rank", "code"])))]
+struct GetArgs {
+    /// The path of a cache file.
+    cache: String,
+    /// The position of the shape in the cache.
+    #[arg(long)]
+    id: Option<ShapeId>,
+    /// The stable id of the shape, looked up in the .ids.idx file next to the cache.
+    #[arg(long)]
+    rank: Option<ShapeId>,
+    /// The code of the shape.
+    #[arg(long)]
+    code: Option<String>,
+    #[arg(long, default_value_t = ExportFormat::Code)]
+    format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// The path of a cache file.
+    cache: String,
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+    /// The number of shapes to print, by default all of them.
+    #[arg(long)]
+    limit: Option<usize>,
+    /// rank, surface-area, bbox or symmetry.
+    #[arg(long)]
+    sort_by: Option<SortKey>,
+    /// Print the shapes in a random order instead.
+    #[arg(long, conflicts_with = "sort_by")]
+    shuffle: bool,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[arg(long, default_value_t = ExportFormat::Code)]
+    format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+struct SampleArgs {
+    /// The path of a cache file.
+    cache: String,
+    /// The number of shapes to print.
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[arg(long, default_value_t = ExportFormat::Ascii)]
+    render: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+struct MetricsArgs {
+    /// The path of a cache file.
+    cache: String,
+    /// Write the table to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// The columns of the table out of surface_area, bbox, symmetry, diameter and holes.
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<Metric>,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Rewrite a cache in another format, by default the current one in place.
+    Convert(ConvertArgs),
+    /// Summarize the shapes of a cache file, like `stats`.
+    Stats(CacheFileArgs),
+    /// Check every record of a cache file, like `verify`.
+    Verify(CacheFileArgs),
+    /// Move the caches of all sizes into a single combined file.
+    Combine(CacheDirArgs),
+    /// Move the caches of a combined file or of hash shards back into a file per size.
+    Split(CacheDirArgs),
+    /// Move the caches of all sizes into hash shards, several files per size of about the same number of shapes.
+    Shard(ShardArgs),
+    /// List the cached levels of a run.
+    Manifest(ManifestArgs),
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// The path of a cache file.
+    input: String,
+    #[arg(long, default_value_t = CacheFormat::V2)]
+    to: CacheFormat,
+    #[arg(long, default_value_t = Compression::None)]
+    compress: Compression,
+    /// Write an index section with the metadata of every shape.
+    #[arg(long)]
+    index: bool,
+    /// Write the converted cache to this path instead of replacing the input.
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct CacheDirArgs {
+    /// A directory or an s3:// url.
+    cache_dir: String,
+}
+
+#[derive(Args, Debug)]
+struct ShardArgs {
+    #[command(flatten)]
+    dir: CacheDirArgs,
+    /// The number of hash shards of every size. Sizes sharded among another number are split again.
+    #[arg(long, default_value_t = DEFAULT_HASH_SHARDS, value_parser = clap::value_parser!(u32).range(1..))]
+    shards: u32,
+}
+
+#[derive(Args, Debug)]
+struct ManifestArgs {
+    /// A directory or an s3:// url.
+    cache_dir: String,
+    /// Recompute the checksum of every listed cache, failing if any of them changed.
+    #[arg(long)]
+    check: bool,
+}
 
-/// This program calculates out how many unique arangements can be made for n cubes attached to one another
-/// at the faces.
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("shapes").required(true).multiple(true).args(["files", "code", "json"])))]
+struct IdentifyArgs {
+    /// Voxel files containing the shapes.
+    files: Vec<String>,
+    /// The code of a shape.
+    #[arg(long)]
+    code: Vec<String>,
+    /// The coordinates of a shape as JSON.
+    #[arg(long)]
+    json: Vec<String>,
+    /// A directory or an s3:// url.
+    #[arg(long, default_value = ".")]
+    cache_dir: String,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// The path of a voxel file.
+    path: String,
+    #[arg(long, default_value_t = ExportFormat::Code)]
+    format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
+    report: Report,
+    /// The largest number of blocks.
+    max_n: BlockCount,
+    /// A directory or an s3:// url.
+    #[arg(long, default_value = ".")]
+    cache_dir: String,
+}
+
+#[derive(Args, Debug)]
+struct MorphArgs {
+    /// The code of the first shape.
+    from: String,
+    /// The code of the last shape.
+    to: String,
+    /// Give up after visiting this many shapes.
+    #[arg(long, default_value_t = DEFAULT_MAX_SHAPES)]
+    max_shapes: usize,
+}
+
+#[derive(Args, Debug)]
+struct GraphArgs {
+    /// The number of blocks of the shapes.
+    n: BlockCount,
+    #[arg(long, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+    /// A directory or an s3:// url.
+    #[arg(long, default_value = ".")]
+    cache_dir: String,
+}
+
+#[derive(Args, Debug)]
+struct PuzzleArgs {
+    /// The size of the box as <width>x<depth>x<height>.
+    size: String,
+    /// The number of pieces.
+    pieces: usize,
+    /// The largest number of blocks of a piece.
+    #[arg(long)]
+    max_size: Option<usize>,
+    /// By default derived from the current time and printed.
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value_t = DEFAULT_ATTEMPTS)]
+    attempts: usize,
+    #[arg(long, default_value_t = ExportFormat::Code)]
+    format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// A trace written by --trace.
+    trace: String,
+}
+
+#[derive(Args, Debug)]
+struct ReferenceArgs {
+    /// The largest number of blocks.
+    max_n: BlockCount,
+}
+
+#[derive(Args, Debug)]
+struct CarveArgs {
+    /// The size of the box as <width>x<depth>x<height>.
+    size: String,
+    /// The smallest number of blocks of the counted shapes.
+    #[arg(long, default_value_t = BlockCount::ONE)]
+    min_size: BlockCount,
+}
+
+#[derive(Args, Debug)]
+struct EstimateArgs {
+    /// The largest number of blocks.
+    max_n: BlockCount,
+    /// The number of random paths.
+    #[arg(long, default_value_t = DEFAULT_SAMPLES)]
+    samples: usize,
+    /// By default derived from the current time and printed.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// How the shapes are oriented in the voxel arrays.
+#[derive(ValueEnum, Debug, Copy, Clone)]
+enum TensorOrientation {
+    Canonical,
+    Stored,
+}
+
+#[derive(Args, Debug)]
+struct TensorArgs {
+    /// The path of a cache file.
+    cache: String,
+    #[arg(long)]
+    format: Option<TensorFormat>,
+    /// The size of the voxel arrays as <width>x<depth>x<height>.
+    #[arg(long)]
+    grid: Option<String>,
+    #[arg(long)]
+    orientation: Option<TensorOrientation>,
+    /// The fraction of the shapes written to the validation split.
+    #[arg(long)]
+    val: Option<f64>,
+    #[arg(long)]
+    shuffle: bool,
+    #[arg(long)]
+    seed: Option<u64>,
+    /// The start of the written file names, by default the cache path without its extension.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SelfTestArgs {
+    /// The largest number of blocks whose shapes are counted.
+    #[arg(long)]
+    max_blocks: Option<BlockCount>,
+    /// The algorithms to check, by default all of them.
+    #[arg(long)]
+    algorithm: Vec<Algorithm>,
+    #[arg(long)]
+    samples: Option<usize>,
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Runs the command line, see `--help`.
+///
+/// A generation run is carried out by a [Runner] and described by a [RunConfig] which is read from `--config <path>`
+/// and overridden by the other arguments. A run ended early by `--stop-after-shapes` or `--stop-after-seconds`
+/// exits with status 2, so job scripts can resubmit it until it completes. Every other failure, including
+/// invalid arguments, exits with status 1.
+///
+/// With `--json` in front of the subcommand, the run and every subcommand print a single JSON document
+/// described in [output](cube_combinations::output) instead of text.
 fn main() {
-    let mut args = env::args();
-    let _program_path = args.next();
-    let n: usize = args.next()
-        .map(|s| {
-            println!("{s}");
-            s.parse()
-        })
-        .expect("Expected at least one numeric arguments")
-        .expect("The argument has to be a valid number");
-    let num_unique_shapes: usize = generate(n).last().unwrap().len();
-    println!("The number of unique arrangements of {n} blocks is {num_unique_shapes}");
-}
-
-fn generate(n: usize) -> Vec<BTreeMap<BlockHash, BlockArrangement>> {
-    let mut initial_map = BTreeMap::new();
-    let ba = BlockArrangement::new();
-    initial_map.insert(BlockHash::from(&ba), ba);
-    let mut block_sets: Vec<BTreeMap<BlockHash, BlockArrangement>> = vec![
-        initial_map,
-    ];
-    let mut starting_block_size = 1;
-    if let Some((cache, block_num)) = load_next_lowest_cache(n) {
-        block_sets.push(cache);
-        starting_block_size = block_num;
-    }
-
-    for source_block_size in starting_block_size..n {
-        let generated_block_size = source_block_size + 1;
-        print!("Generating shapes with {generated_block_size} blocks...");
-        io::stdout().flush().expect("Unable to flush stout");
-        let new_blocks = generate_variants_from(block_sets.last().unwrap().values());
-        println!("Done");
-        print!("Saving cache data arrangements with {generated_block_size} blocks...");
-        io::stdout().flush().expect("Unable to flush stout");
-        // if source_block_size == 2 {
-        //     dbg!(&new_blocks.iter().map(|b|
-        //         b.center_mass_iter().collect::<Vec<_>>()
-        //     ).collect::<Vec<_>>());
-        // }
-        match save_cache(&new_blocks, generated_block_size) {
-            Ok(_) => {
-                println!("Saved cache with {} items.", new_blocks.len())
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            e.exit();
+        }
+        JSON_OUTPUT.store(env::args().nth(1).is_some_and(|arg| arg == "--json"), Ordering::Relaxed);
+        print_error(e.render().to_string().trim_end());
+        process::exit(1);
+    });
+    JSON_OUTPUT.store(cli.json, Ordering::Relaxed);
+    let result = match cli.command {
+        Some(_) if cli.run != RunArgs::default() => Err("The arguments of a run are only accepted without a subcommand".to_string()),
+        None => cli.run.into_config().and_then(run_generation),
+        Some(Command::Generate(args)) => (*args).into_config().and_then(run_generation),
+        Some(Command::Count(args)) => args.into_config().and_then(run_generation),
+        Some(command) => run_command(command).map(|_| false),
+    };
+    match result {
+        Err(e) => {
+            print_error(&e);
+            process::exit(1);
+        }
+        Ok(true) => process::exit(2),
+        Ok(false) => {}
+    }
+}
+
+/// Carries out a generation run and returns whether it was stopped before it completed.
+/// The first Ctrl-C stops a run that [can be stopped](RunConfig::can_stop_early) after saving the
+/// shapes generated so far, a second one quits right away.
+fn run_generation(config: RunConfig) -> Result<bool, String> {
+    status!("Effective configuration:\n{config}");
+    let interrupt = Arc::new(AtomicBool::new(false));
+    if config.can_stop_early() {
+        let handler_interrupt = interrupt.clone();
+        let installed = ctrlc::set_handler(move || {
+            if handler_interrupt.swap(true, Ordering::Relaxed) {
+                process::exit(130);
             }
+            eprintln!("Interrupted, saving the shapes generated so far. Press Ctrl-C again to quit right away.");
+        });
+        if let Err(e) = installed {
+            eprintln!("Failed to handle Ctrl-C, interrupting the run loses the current shard: {e}");
+        }
+    }
+    let report = Runner::new(&config)
+        .with_status(|message| status!("{message}"))
+        .with_interrupt(interrupt)
+        .run()
+        .map_err(|e| format!("The run failed, {e}"))?;
+    if json_output() {
+        print_json(&report)?;
+    }
+    Ok(report.stopped.is_some())
+}
+
+/// Runs a subcommand other than a generation run.
+fn run_command(command: Command) -> Result<(), String> {
+    match command {
+        Command::Count(_) | Command::Generate(_) => unreachable!("Generation runs are started by main."),
+        Command::Stats(args) => run_cache_stats(args),
+        Command::Export(args) => run_export(args),
+        Command::Verify(args) => run_cache_verify(args),
+        Command::Get(args) => run_get(args),
+        Command::List(args) => run_list(args),
+        Command::Sample(args) => run_sample(args),
+        Command::Metrics(args) => run_metrics(args),
+        Command::Cache(command) => run_cache(command),
+        Command::Identify(args) => run_identify(args),
+        Command::Import(args) => run_import(args),
+        Command::Report(args) => run_report(args),
+        Command::Morph(args) => run_morph(args),
+        Command::Graph(args) => run_graph(args),
+        Command::Puzzle(args) => run_puzzle(args),
+        Command::Replay(args) => run_replay(args),
+        Command::Reference(args) => run_reference(args),
+        Command::Carve(args) => run_carve(args),
+        Command::Estimate(args) => run_estimate(args),
+        Command::Tensor(args) => run_tensor(args),
+        Command::SelfTest(args) => run_self_test(args),
+    }
+}
+
+/// Runs the cache maintenance commands.
+fn run_cache(command: CacheCommand) -> Result<(), String> {
+    match command {
+        CacheCommand::Convert(args) => run_cache_convert(args),
+        CacheCommand::Stats(args) => run_cache_stats(args),
+        CacheCommand::Verify(args) => run_cache_verify(args),
+        CacheCommand::Combine(args) => run_cache_layout(args, CacheLayout::Combined, DEFAULT_HASH_SHARDS),
+        CacheCommand::Split(args) => run_cache_layout(args, CacheLayout::PerSize, DEFAULT_HASH_SHARDS),
+        CacheCommand::Shard(args) => run_cache_layout(args.dir, CacheLayout::Sharded, args.shards),
+        CacheCommand::Manifest(args) => run_cache_manifest(args),
+    }
+}
+
+fn run_cache_convert(args: ConvertArgs) -> Result<(), String> {
+    let ConvertArgs { input, to: format, compress: compression, index: indexed, output } = args;
+    let output = output.unwrap_or_else(|| input.clone());
+    let converted = convert_cache(&input, &output, format, compression, indexed)
+        .map_err(|e| format!("Failed to convert cache: {e}"))?;
+    if json_output() {
+        return print_json(&ConvertOutput { shapes: converted, format: format.to_string(), compression: compression.to_string() });
+    }
+    println!("Converted {converted} shapes to {format} with compression {compression}.");
+    Ok(())
+}
+
+/// Moves the caches of a cache directory into the given layout, the sharded one with the given number of shards.
+fn run_cache_layout(args: CacheDirArgs, layout: CacheLayout, shards: u32) -> Result<(), String> {
+    let cache_dir = args.cache_dir;
+    let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+    let moved = match layout {
+        CacheLayout::PerSize => split_caches(store.as_ref()).and_then(|mut sizes| {
+            sizes.extend(unshard_caches(store.as_ref())?);
+            sizes.sort();
+            sizes.dedup();
+            Ok(sizes)
+        }),
+        CacheLayout::Combined => combine_caches(store.as_ref()),
+        CacheLayout::Sharded => shard_caches(store.as_ref(), shards),
+    }.map_err(|e| format!("Failed to move the caches: {e}"))?;
+    if json_output() {
+        return print_json(&LayoutOutput { layout: layout.to_string(), sizes: moved });
+    }
+    let sizes: Vec<_> = moved.iter().map(BlockCount::to_string).collect();
+    println!("Moved the caches of {} sizes to the {layout} layout: {}", moved.len(), sizes.join(", "));
+    Ok(())
+}
+
+/// Prints the levels listed in the manifest of a cache directory. With `--check` the checksum of every
+/// listed cache is recomputed, which fails if any of them changed.
+fn run_cache_manifest(args: ManifestArgs) -> Result<(), String> {
+    let ManifestArgs { cache_dir, check } = args;
+    let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+    let manifest = CacheManifest::load(store.as_ref()).map_err(|e| format!("Failed to read the manifest: {e}"))?;
+    let mut levels = vec![];
+    for entry in manifest.levels() {
+        let valid = match check {
+            true => Some(entry.check(store.as_ref()).map_err(|e| format!("Failed to read the cache of {} blocks: {e}", entry.blocks))?),
+            false => None,
+        };
+        levels.push(ManifestLevel { blocks: entry.blocks, shapes: entry.shapes, checksum: entry.checksum.clone(), valid });
+    }
+    if json_output() {
+        print_json(&levels)?;
+    } else {
+        for level in &levels {
+            let status = match level.valid {
+                Some(true) => " ok",
+                Some(false) => " changed",
+                None => "",
+            };
+            println!("{} blocks: {} shapes, checksum {}{status}", level.blocks, level.shapes, level.checksum);
+        }
+    }
+    match levels.iter().any(|level| level.valid == Some(false)) {
+        true => Err("Some caches no longer match the manifest".to_string()),
+        false => Ok(()),
+    }
+}
+
+/// Prints the number of shapes per chirality and symmetry group and the range of their surface areas.
+/// Only the index section is read if the cache has one.
+fn run_cache_stats(args: CacheFileArgs) -> Result<(), String> {
+    let input = args.cache;
+    let (store, name) = open_file_store(&input).map_err(|e| format!("Failed to open {input}: {e}"))?;
+    let (metadata, source) = read_metadata(|| store.open(&name))?;
+    let chiral = metadata.iter().filter(|m| m.is_chiral()).count();
+    let mut groups: BTreeMap<&str, usize> = BTreeMap::new();
+    for m in &metadata {
+        *groups.entry(m.symmetry.name()).or_default() += 1;
+    }
+    let surface_areas = metadata.iter().map(|m| m.surface_area);
+    let (min_surface_area, max_surface_area) = (surface_areas.clone().min(), surface_areas.max());
+    if json_output() {
+        return print_json(&CacheStatsOutput {
+            shapes: metadata.len(),
+            source: source.to_string(),
+            chiral,
+            achiral: metadata.len() - chiral,
+            symmetries: groups.into_iter().map(|(group, count)| (group.to_string(), count)).collect(),
+            min_surface_area,
+            max_surface_area,
+        });
+    }
+    println!("Shapes: {} (read from the {source})", metadata.len());
+    println!("Chiral: {chiral}, achiral: {}", metadata.len() - chiral);
+    for (group, count) in groups {
+        println!("Symmetry {group}: {count}");
+    }
+    if let (Some(min), Some(max)) = (min_surface_area, max_surface_area) {
+        println!("Surface area: {min} to {max}");
+    }
+    Ok(())
+}
+
+/// Checks every record of a cache against its recomputed canonical form and key and prints those that disagree.
+/// Fails if any record has a problem.
+fn run_cache_verify(args: CacheFileArgs) -> Result<(), String> {
+    let input = args.cache;
+    let report = File::open(&input).and_then(verify_cache).map_err(|e| format!("Failed to read cache: {e}"))?;
+    if json_output() {
+        print_json(&VerifyOutput {
+            format: report.format.to_string(),
+            records: report.records,
+            valid: report.is_valid(),
+            problems: report.problems.iter()
+                .map(|(record, problem)| ProblemOutput { record: *record, problem: problem.to_string() })
+                .collect(),
+        })?;
+    } else {
+        for (position, problem) in &report.problems {
+            println!("Record {position}: {problem}");
+        }
+        println!("Checked {} records of a {} cache, found {} problems.", report.records, report.format, report.problems.len());
+    }
+    match report.is_valid() {
+        true => Ok(()),
+        false => Err(format!("The cache {input} is corrupted")),
+    }
+}
+
+/// Reads the metadata of every shape of a cache from its index section, or computes it from the records
+/// if the cache has no index. Also returns which of the two was read.
+fn read_metadata(open: impl Fn() -> io::Result<Box<dyn io::Read + Send>>) -> Result<(Vec<ShapeMetadata>, &'static str), String> {
+    let index = open().and_then(read_index).map_err(|e| format!("Failed to read cache: {e}"))?;
+    if let Some(index) = index {
+        return Ok((index, "index"));
+    }
+    let metadata = open()
+        .and_then(CacheReader::new)
+        .and_then(|reader| reader.collect::<io::Result<Vec<_>>>())
+        .map_err(|e| format!("Failed to read cache: {e}"))?
+        .iter()
+        .map(|(_, ba)| Polycube::new(ba).map(|polycube| ShapeMetadata::of(&polycube)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid shape in cache: {e}"))?;
+    Ok((metadata, "records"))
+}
+
+/// The tables `report` can print.
+#[derive(ValueEnum, Debug, Copy, Clone)]
+enum Report {
+    /// The number of shapes per sorted bounding box.
+    BoundingBoxes,
+    /// How many shapes fit into at most k layers for every k.
+    Layers,
+    /// The number of shapes that are only distinct up to translation, derived from their symmetries.
+    Fixed,
+    /// The number of shapes with a distinguished root block, which decodes every shape of the caches.
+    Rooted,
+}
+
+/// Fixed shapes are only enumerated directly to validate the derived counts up to this size.
+const DIRECT_FIXED_COUNT_LIMIT: BlockCount = BlockCount::new(7);
+
+/// Runs a [Report] over the caches of every size up to n whose cache exists. The fixed counts are
+/// validated against a direct enumeration for small sizes.
+fn run_report(args: ReportArgs) -> Result<(), String> {
+    let ReportArgs { report, max_n, cache_dir } = args;
+    let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+    let mut rows = vec![match report {
+        Report::BoundingBoxes => ReportRow::BoundingBox {
+            n: BlockCount::ONE,
+            bounding_box: BoundingBoxClass::from_axis_lengths([1, 1, 1]).to_string(),
+            shapes: 1,
+        },
+        Report::Layers => ReportRow::Layers { n: BlockCount::ONE, layers: 1, shapes: 1 },
+        Report::Fixed => ReportRow::Fixed { n: BlockCount::ONE, free: 1, fixed: 1, direct: Some(1) },
+        Report::Rooted => ReportRow::Rooted { n: BlockCount::ONE, free: 1, rooted: 1 },
+    }];
+    for n in BlockCount::new(2).up_to(max_n) {
+        if let Report::Rooted = report {
+            let shapes = load_cache(store.as_ref(), n)
+                .map_err(|e| format!("Failed to read cache: {e}"))
+                .and_then(|level| level.values()
+                    .map(Polycube::new)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid shape in cache: {e}")));
+            match shapes {
+                Ok(shapes) => rows.push(ReportRow::Rooted { n, free: shapes.len(), rooted: rooted_count(&shapes) }),
+                Err(e) => eprintln!("Skipping {n} blocks: {e}"),
+            }
+            continue;
+        }
+        let (metadata, _) = match read_metadata(|| open_cache(store.as_ref(), n)) {
+            Ok(metadata) => metadata,
             Err(e) => {
-                eprintln!("Failed to save cache data: {e}")
+                eprintln!("Skipping {n} blocks: {e}");
+                continue;
+            }
+        };
+        let counts = count_by_bounding_box(metadata.iter().map(BoundingBoxClass::from));
+        match report {
+            Report::BoundingBoxes => for (class, count) in counts {
+                rows.push(ReportRow::BoundingBox { n, bounding_box: class.to_string(), shapes: count });
+            },
+            Report::Layers => {
+                let max_thickness = counts.keys().map(BoundingBoxClass::thickness).max().unwrap_or_default();
+                for layers in 1..=max_thickness {
+                    let count: usize = counts.iter()
+                        .filter(|(class, _)| class.thickness() <= layers)
+                        .map(|(_, count)| count)
+                        .sum();
+                    rows.push(ReportRow::Layers { n, layers, shapes: count });
+                }
             }
+            Report::Rooted => {}
+            Report::Fixed => {
+                let fixed = fixed_count_from_free(metadata.iter().map(|m| &m.symmetry));
+                let direct = (n <= DIRECT_FIXED_COUNT_LIMIT).then(|| fixed_count_direct(n.as_usize()));
+                rows.push(ReportRow::Fixed { n, free: metadata.len(), fixed, direct });
+            }
+        }
+    }
+    if json_output() {
+        return print_json(&rows);
+    }
+    println!("{}", match report {
+        Report::BoundingBoxes => "n\tbounding box\tshapes",
+        Report::Layers => "n\tmax layers\tshapes",
+        Report::Fixed => "n\tfree\tfixed\tdirect",
+        Report::Rooted => "n\tfree\trooted",
+    });
+    for row in rows {
+        match row {
+            ReportRow::BoundingBox { n, bounding_box, shapes } => println!("{n}\t{bounding_box}\t{shapes}"),
+            ReportRow::Layers { n, layers, shapes } => println!("{n}\t{layers}\t{shapes}"),
+            ReportRow::Fixed { n, free, fixed, direct } => match direct {
+                Some(direct) if direct == fixed => println!("{n}\t{free}\t{fixed}\t{direct}"),
+                Some(direct) => println!("{n}\t{free}\t{fixed}\t{direct} (mismatch)"),
+                None => println!("{n}\t{free}\t{fixed}\t-"),
+            },
+            ReportRow::Rooted { n, free, rooted } => println!("{n}\t{free}\t{rooted}"),
         }
-        block_sets.push(new_blocks);
     }
-    block_sets
+    Ok(())
 }
 
-/// Attempts to load the cache with the largest block size lower that block_num
-/// that can be found.
-fn load_next_lowest_cache(block_num: usize) -> Option<(BTreeMap<BlockHash, BlockArrangement>, usize)> {
-    for i in (2..block_num).rev() {
-        println!("Attempting to load cache data for {i} blocks...");
-        let res = load_cache(i);
-        match res {
-            Err(e) => {
-                eprintln!("Failed load cache: {e}");
+/// Finds a shortest sequence of single block moves between two shapes given by their codes
+/// and prints every move with the code of the resulting shape.
+fn run_morph(args: MorphArgs) -> Result<(), String> {
+    let parse = |code: &str| Polycube::from_code(code).map_err(|e| format!("Invalid code {code}: {e}"));
+    let (from, to) = (parse(&args.from)?, parse(&args.to)?);
+    let morph = find_morph(&from, &to, args.max_shapes).map_err(|e| format!("Failed to find a morph: {e}"))?;
+    if json_output() {
+        let steps = std::iter::once(None).chain(morph.moves.iter().map(|step| Some(step.to_string())))
+            .zip(&morph.shapes)
+            .map(|(step, shape)| MorphStep { step, code: shape.code() })
+            .collect();
+        return print_json(&MorphOutput { steps });
+    }
+    println!("0: {}", from.code());
+    for (i, (step, shape)) in morph.moves.iter().zip(&morph.shapes[1..]).enumerate() {
+        println!("{}: {step} -> {}", i + 1, shape.code());
+    }
+    Ok(())
+}
+
+/// Writes the [ShapeGraph] of the shapes in the cache with n blocks to stdout.
+/// The nodes are numbered by the position of their shape in the cache.
+fn run_graph(args: GraphArgs) -> Result<(), String> {
+    let GraphArgs { n, format, cache_dir } = args;
+    let shapes = match n {
+        BlockCount::ONE => vec![Polycube::new(&BlockArrangement::new()).expect("A single block is connected")],
+        _ => {
+            let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+            open_cache(store.as_ref(), n)
+                .and_then(CacheReader::new)
+                .and_then(|reader| reader.map(|record| record.and_then(|(_, ba)| {
+                    Polycube::new(&ba).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })).collect::<io::Result<Vec<_>>>())
+                .map_err(|e| format!("Failed to read cache: {e}"))?
+        }
+    };
+    let graph = ShapeGraph::new(shapes);
+    if json_output() {
+        return print_json(&GraphOutput {
+            nodes: graph.shapes().iter().map(Polycube::code).collect(),
+            edges: graph.edges().collect(),
+        });
+    }
+    graph.write(&mut io::stdout().lock(), format).map_err(|e| format!("Failed to write graph: {e}"))
+}
+
+/// Prints the cached shapes with n blocks of a cache directory in the given format. The records are
+/// streamed, so the level may be larger than memory unless it is printed as a JSON document.
+fn run_export(args: ExportArgs) -> Result<(), String> {
+    let ExportArgs { n, cache_dir, format } = args;
+    let arrangements: Box<dyn Iterator<Item = io::Result<BlockArrangement>>> = match n {
+        BlockCount::ONE => Box::new(std::iter::once(Ok(BlockArrangement::new()))),
+        _ => {
+            let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+            let reader = open_cache(store.as_ref(), n)
+                .and_then(CacheReader::new)
+                .map_err(|e| format!("Failed to read cache: {e}"))?;
+            Box::new(reader.map(|record| record.map(|(_, ba)| ba)))
+        }
+    };
+    let mut shapes = vec![];
+    let mut stdout = io::stdout().lock();
+    for (i, ba) in arrangements.enumerate() {
+        let ba = ba.map_err(|e| format!("Failed to read cache: {e}"))?;
+        let polycube = Polycube::new(&ba).map_err(|e| format!("Invalid shape in cache: {e}"))?;
+        if json_output() {
+            shapes.push(ShapeOutput::from(&polycube));
+            continue;
+        }
+        // Drawings span several lines, so they are separated by an empty one.
+        let separator = if format == ExportFormat::Ascii && i > 0 { "\n" } else { "" };
+        match writeln!(stdout, "{separator}{}", export(&polycube, format)) {
+            // The reader, like `head`, has seen enough.
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+            result => result.map_err(|e| format!("Failed to write the shapes: {e}"))?,
+        }
+    }
+    match json_output() {
+        true => print_json(&shapes),
+        false => Ok(()),
+    }
+}
+
+/// Splits a box into pieces with a unique solution and prints the pieces followed by their placement.
+/// Without a seed one is derived from the current time and printed, so the puzzle can be recreated.
+fn run_puzzle(args: PuzzleArgs) -> Result<(), String> {
+    let PuzzleArgs { size, pieces, max_size, seed, attempts, format } = args;
+    let mut generator = PuzzleGenerator::new(parse_box_size(&size)?, pieces).with_attempts(attempts);
+    if let Some(max_size) = max_size {
+        generator = generator.with_max_piece_size(max_size);
+    }
+    let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+    let puzzle = generator.with_seed(seed).generate().map_err(|e| format!("Failed to generate a puzzle: {e}"))?;
+    eprintln!("Found a unique decomposition of the {size} box with seed {seed} after {} attempts", puzzle.attempts);
+    if json_output() {
+        return print_json(&PuzzleOutput {
+            seed,
+            attempts: puzzle.attempts,
+            pieces: puzzle.pieces.iter().map(ShapeOutput::from).collect(),
+            solution: puzzle.solution,
+        });
+    }
+    for piece in &puzzle.pieces {
+        println!("{}", export(piece, format));
+    }
+    for (i, cells) in puzzle.solution.iter().enumerate() {
+        let cells: Vec<_> = cells.iter().map(ToString::to_string).collect();
+        eprintln!("Piece {}: {}", i + 1, cells.join(" "));
+    }
+    Ok(())
+}
+
+/// Parses the size of a box given as `<width>x<depth>x<height>`.
+fn parse_box_size(size: &str) -> Result<[u32; 3], String> {
+    size.split('x')
+        .map(|len| len.parse::<u32>().ok().filter(|len| *len > 0))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|dims| <[u32; 3]>::try_from(dims).ok())
+        .ok_or_else(|| format!("Invalid box size {size}, expected <width>x<depth>x<height>"))
+}
+
+/// Prints the number of shapes of every size that are left after removing blocks from a full box.
+fn run_carve(args: CarveArgs) -> Result<(), String> {
+    let CarveArgs { size, min_size } = args;
+    let levels = carve(parse_box_size(&size)?, min_size).map_err(|e| format!("Unable to carve the {size} box: {e:?}"))?;
+    if json_output() {
+        return print_json(&levels.iter().rev()
+            .map(|(block_count, shapes)| LevelOutput { blocks: *block_count, shapes: shapes.len(), insertions: None })
+            .collect::<Vec<_>>());
+    }
+    for (block_count, shapes) in levels.iter().rev() {
+        println!("{block_count} blocks: {} shapes", shapes.len());
+    }
+    Ok(())
+}
+
+/// Estimates the numbers of shapes up to the given number of blocks, see [CountEstimator].
+/// Without a seed one is derived from the current time and printed, so the estimate can be recreated.
+fn run_estimate(args: EstimateArgs) -> Result<(), String> {
+    let EstimateArgs { max_n: n, samples, seed } = args;
+    let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+    status!("Estimating the numbers of shapes from {samples} random paths with seed {seed}, the counts are not exact");
+    let levels = CountEstimator::new(n).with_samples(samples).with_seed(seed).estimate();
+    if json_output() {
+        return print_json(&EstimateOutput { estimate: true, samples, seed, levels });
+    }
+    println!("n\testimated fixed\t95% interval\testimated free\t95% interval");
+    for LevelEstimate { blocks, fixed, free } in levels {
+        println!(
+            "{blocks}\t~{:.4e}\t{:.4e}..{:.4e}\t~{:.4e}\t{:.4e}..{:.4e}",
+            fixed.mean, fixed.low, fixed.high, free.mean, free.low, free.high,
+        );
+    }
+    Ok(())
+}
+
+/// Runs the checks of a [SelfTest] and prints the outcome of every check as it is known. Fails if any check failed.
+fn run_self_test(args: SelfTestArgs) -> Result<(), String> {
+    let mut self_test = SelfTest::new();
+    if let Some(max_blocks) = args.max_blocks {
+        if known_count(max_blocks).is_none() {
+            return Err(format!("The number of shapes with {max_blocks} blocks is unknown, at most {} blocks can be checked", KNOWN_COUNTS.len()));
+        }
+        self_test = self_test.with_max_blocks(max_blocks);
+    }
+    if !args.algorithm.is_empty() {
+        self_test = self_test.with_algorithms(args.algorithm);
+    }
+    if let Some(samples) = args.samples {
+        self_test = self_test.with_samples(samples);
+    }
+    if let Some(seed) = args.seed {
+        self_test = self_test.with_seed(seed);
+    }
+    let checks = self_test.run(|outcome| {
+        let verdict = if outcome.passed { "PASS" } else { "FAIL" };
+        status!("{verdict} {}: {}", outcome.name, outcome.detail);
+    });
+    let passed = checks.iter().all(|outcome| outcome.passed);
+    if json_output() {
+        print_json(&SelfTestOutput { passed, checks })?;
+    } else {
+        println!("{}", if passed { "PASS" } else { "FAIL" });
+    }
+    match passed {
+        true => Ok(()),
+        false => Err("The self test failed".to_string()),
+    }
+}
+
+/// Rebuilds a poly tree from a trace and prints the number of shapes of every replayed level.
+fn run_replay(args: ReplayArgs) -> Result<(), String> {
+    let path = args.trace;
+    let trace = File::open(&path)
+        .and_then(|mut file| Trace::read_from(&mut file))
+        .map_err(|e| format!("Failed to read the trace {path}: {e}"))?;
+    let tree = PolyTree::replay(&trace).map_err(|e| format!("Failed to replay {path}: {e}"))?;
+    let levels: Vec<_> = trace.levels().iter().enumerate()
+        .map(|(i, events)| {
+            let blocks = BlockCount::try_from(trace.first_level().as_usize() + i).map_err(|_| format!("Too many levels in {path}"))?;
+            Ok(LevelOutput { blocks, shapes: tree.level(blocks).len(), insertions: Some(events.len()) })
+        })
+        .collect::<Result<_, String>>()?;
+    if json_output() {
+        return print_json(&levels);
+    }
+    for LevelOutput { blocks, shapes, insertions } in levels {
+        println!("{blocks} blocks: {} insertions, {shapes} shapes", insertions.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Compares the poly tree and the hash map levels with the reference shapes for every size up to the given one.
+fn run_reference(args: ReferenceArgs) -> Result<(), String> {
+    let max_n = args.max_n;
+    let mut tree = PolyTree::new();
+    let mut level = vec![BlockArrangement::new()];
+    let mut levels = vec![];
+    for n in BlockCount::ONE.up_to(max_n) {
+        while tree.depth() < n {
+            tree.generate_next_level();
+        }
+        if n > BlockCount::ONE {
+            let (children, _) = generate_variants_from(level.iter(), level.len() * 8, None);
+            level = children.into_values().collect();
+        }
+        let reference_count = reference::free_shapes(n.as_usize()).len();
+        for (algorithm, shapes) in [("poly tree", tree.iter_level(n).collect::<Vec<_>>()), ("hash map", level.clone())] {
+            let found = shapes.len();
+            let comparison = reference::compare(n.as_usize(), shapes);
+            levels.push(ReferenceLevel {
+                blocks: n,
+                algorithm: algorithm.to_string(),
+                found,
+                reference: reference_count,
+                missing: comparison.missing.len(),
+                unexpected: comparison.unexpected.len(),
+                duplicates: comparison.duplicates,
+            });
+            if !json_output() {
+                println!(
+                    "{n} blocks: {found} {algorithm} shapes for {reference_count} reference shapes, {} missing, {} unexpected, {} duplicates",
+                    comparison.missing.len(), comparison.unexpected.len(), comparison.duplicates,
+                );
             }
-            Ok(cache) => {
-                println!("Loaded cache with {} items.", cache.len());
-                return Some((cache, i));
+        }
+    }
+    let mismatches = levels.iter().filter(|level| level.missing + level.unexpected + level.duplicates > 0).count();
+    if json_output() {
+        print_json(&ReferenceOutput { levels, mismatches })?;
+    }
+    match mismatches {
+        0 => Ok(()),
+        _ => Err(format!("{mismatches} levels differ from the reference")),
+    }
+}
+
+/// Imports a voxel file and prints every connected shape in it.
+fn run_import(args: ImportArgs) -> Result<(), String> {
+    let ImportArgs { path, format } = args;
+    let shapes = import_file(&path).map_err(|e| format!("Failed to import {path}: {e}"))?
+        .iter()
+        .map(Polycube::new)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid imported shape: {e}"))?;
+    if json_output() {
+        return print_json(&shapes.iter().map(ShapeOutput::from).collect::<Vec<_>>());
+    }
+    for polycube in shapes {
+        println!("{}", export(&polycube, format));
+    }
+    Ok(())
+}
+
+/// Canonicalizes the given shapes and prints their size, code, symmetry and id in the cache of their size.
+fn run_identify(args: IdentifyArgs) -> Result<(), String> {
+    let IdentifyArgs { files, code, json, cache_dir } = args;
+    let mut shapes = vec![];
+    for file in files {
+        shapes.extend(import_file(&file).map_err(|e| format!("Failed to import {file}: {e}"))?);
+    }
+    for code in code {
+        shapes.push(Polycube::from_code(&code).map_err(|e| format!("Invalid code {code}: {e}"))?.into_inner());
+    }
+    for json in json {
+        shapes.extend(import(json.as_bytes(), ImportFormat::Json).map_err(|e| format!("Invalid json: {e}"))?);
+    }
+    let store = open_store(&cache_dir).map_err(|e| format!("Failed to open {cache_dir}: {e}"))?;
+    if json_output() {
+        let mut identified = vec![];
+        for ba in shapes {
+            identified.push(identify_json(store.as_ref(), &ba)?);
+        }
+        return print_json(&identified);
+    }
+    for ba in shapes {
+        let polycube = Polycube::new(&ba).map_err(|e| format!("Invalid shape: {e}"))?;
+        let num_blocks = polycube.num_blocks();
+        println!("Blocks: {num_blocks}");
+        println!("Code: {}", polycube.code());
+        println!("Symmetry: {}", Symmetry::of(&polycube));
+        println!("Chirality: {}", if polycube.is_chiral() { "chiral" } else { "achiral" });
+        let location = cache_location(store.as_ref(), num_blocks);
+        match load_ids(store.as_ref(), num_blocks) {
+            Ok(ids) => match ids.id(&polycube) {
+                Some(id) => println!("Stable id: {id} of {}", ids.len()),
+                None => println!("Stable id: not found"),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => println!("Stable id: unknown, failed to read the ids: {e}"),
+        }
+        match find_in_cache(store.as_ref(), num_blocks, &polycube) {
+            Ok(Some((id, total, cached))) => {
+                println!("Id: {id} of {total} in {location}");
+                if let Some((orientation, translation)) = ba.congruence(&cached) {
+                    println!("Maps onto the cached shape by {orientation:?} and a translation by {translation}");
+                }
             }
+            Ok(None) => println!("Id: not found in {location}"),
+            Err(e) => println!("Id: unknown, failed to read {location}: {e}"),
         }
+    }
+    Ok(())
+}
+
+/// Describes a shape like [run_identify] does, failing on caches and ids files that can not be read.
+fn identify_json(store: &dyn CacheStore, ba: &BlockArrangement) -> Result<IdentifyOutput, String> {
+    let polycube = Polycube::new(ba).map_err(|e| format!("Invalid shape: {e}"))?;
+    let num_blocks = polycube.num_blocks();
+    let stable_id = match load_ids(store, num_blocks) {
+        Ok(ids) => ids.id(&polycube),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read the ids: {e}")),
+    };
+    let found = match find_in_cache(store, num_blocks, &polycube) {
+        Ok(found) => found,
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read {}: {e}", cache_location(store, num_blocks))),
     };
-    None
+    let congruence = found.as_ref().and_then(|(_, _, cached)| ba.congruence(cached));
+    Ok(IdentifyOutput {
+        blocks: num_blocks,
+        code: polycube.code(),
+        symmetry: Symmetry::of(&polycube).name().to_string(),
+        chiral: polycube.is_chiral(),
+        stable_id,
+        cache_id: found.as_ref().map(|(id, _, _)| *id),
+        cache_total: found.as_ref().map(|(_, total, _)| *total),
+        orientation: congruence.as_ref().map(|(orientation, _)| *orientation),
+        translation: congruence.map(|(_, translation)| translation),
+    })
+}
+
+/// Returns the position of the shape in the cache, the number of shapes in it and the cached arrangement.
+/// Only records whose indexed metadata matches the shape are compared, and a cache without
+/// such records is not read beyond its index. The records are compared as [ShapeRef] views, and only
+/// the found one is decoded into an arrangement.
+fn find_in_cache(store: &dyn CacheStore, block_count: BlockCount, polycube: &Polycube) -> io::Result<Option<(ShapeId, u64, BlockArrangement)>> {
+    let candidates: Option<Vec<bool>> = read_index(open_cache(store, block_count)?)?.map(|index| {
+        let metadata = ShapeMetadata::of(polycube);
+        index.iter().map(|m| *m == metadata).collect()
+    });
+    if candidates.as_ref().is_some_and(|candidates| !candidates.contains(&true)) {
+        return Ok(None);
+    }
+    let is_candidate = |id: usize| candidates.as_ref().is_none_or(|candidates| candidates[id]);
+    match load_shapes(store, block_count) {
+        Ok(shapes) => {
+            // Cached shapes are canonical, so the views are compared with the occupancy of the shape as they are.
+            let occupancy = polycube.occupancy();
+            let shape = ShapeRef::try_from(&occupancy).expect("The occupancy of a polycube is valid.");
+            for (id, view) in shapes.iter().enumerate() {
+                let view = view?;
+                if is_candidate(id) && view == shape {
+                    let ba = view.to_polycube().map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?.into_inner();
+                    return Ok(Some((ShapeId::from(id), shapes.len(), ba)));
+                }
+            }
+            return Ok(None);
+        }
+        Err(e) if e.kind() != ErrorKind::Unsupported => return Err(e),
+        Err(_) => {}
+    }
+    let reader = CacheReader::new(open_cache(store, block_count)?)?;
+    let total = reader.remaining();
+    for (id, record) in reader.enumerate() {
+        let (_, ba) = record?;
+        if is_candidate(id) && Polycube::new(&ba).is_ok_and(|p| p == *polycube) {
+            return Ok(Some((ShapeId::from(id), total, ba)));
+        }
+    }
+    Ok(None)
 }
 
-fn load_cache(block_count: usize) -> Result<BTreeMap<BlockHash, BlockArrangement>, Error> {
-    let file_name = gen_cache_file_name(block_count);
-    let cache_file = File::open(file_name)?;
-    let mut buff_reader = BufReader::new(cache_file);
+/// Prints the shapes of a cache file at the given positions of the requested order, by default all of them by rank.
+/// The records are streamed, so only the shapes up to the end of the page are held in memory.
+fn run_list(args: ListArgs) -> Result<(), String> {
+    let ListArgs { cache: cache_path, offset, limit, sort_by, shuffle, seed, format } = args;
+    let limit = limit.unwrap_or(usize::MAX);
+    let reader = CacheReader::open(&cache_path).map_err(|e| format!("Failed to read cache: {e}"))?;
+    let permutation = Permutation::new(reader.remaining(), seed);
+    let mut error = None;
+    let shapes = reader.map_while(|record| match record {
+        Ok((_, ba)) => Polycube::new(&ba).map_err(|e| error = Some(format!("Invalid shape in cache: {e}"))).ok(),
+        Err(e) => {
+            error = Some(format!("Failed to read cache: {e}"));
+            None
+        }
+    });
+    let shapes = match shuffle {
+        true => shuffled_page(shapes, &permutation, offset, limit),
+        false => page(shapes, sort_by.unwrap_or_default(), offset, limit),
+    };
+    if let Some(error) = error {
+        return Err(error);
+    }
+    if json_output() {
+        return print_json(&ListOutput { offset, shapes: shapes.iter().map(ShapeOutput::from).collect() });
+    }
+    for polycube in shapes {
+        println!("{}", export(&polycube, format));
+    }
+    Ok(())
+}
 
-    let config = bincode::config::standard();
-    bincode::serde::decode_from_std_read(&mut buff_reader, config)
-        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+/// Prints K shapes chosen uniformly at random from a cache file, by default 10 drawn as ASCII art.
+/// The records are streamed and only the chosen shapes are kept, so the cache may be larger than memory.
+fn run_sample(args: SampleArgs) -> Result<(), String> {
+    let SampleArgs { cache: cache_path, k, seed, render: format } = args;
+    let reader = CacheReader::open(&cache_path).map_err(|e| format!("Failed to read cache: {e}"))?;
+    let total = reader.remaining();
+    let mut error = None;
+    let records = reader.map_while(|record| record.map_err(|e| error = Some(format!("Failed to read cache: {e}"))).ok());
+    let sampled = sample(records, k, seed);
+    if let Some(error) = error {
+        return Err(error);
+    }
+    let shapes = sampled.iter()
+        .map(|(_, ba)| Polycube::new(ba))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid shape in cache: {e}"))?;
+    if json_output() {
+        return print_json(&SampleOutput { total, shapes: shapes.iter().map(ShapeOutput::from).collect() });
+    }
+    let rendered: Vec<_> = shapes.iter().map(|polycube| export(polycube, format)).collect();
+    println!("{}", rendered.join("\n\n"));
+    Ok(())
 }
 
-fn save_cache(set: &BTreeMap<BlockHash, BlockArrangement>, block_count: usize) -> Result<(), Error> {
-    let file_name = gen_cache_file_name(block_count);
-    if let Err(err) = std::fs::remove_file(&file_name) {
-        match err.kind() {
-            ErrorKind::NotFound => {}
-            _ => {return Err(err)}
+/// Writes a CSV row with the metrics of every shape of a cache file to `-o` or stdout, see [MetricsTable].
+fn run_metrics(args: MetricsArgs) -> Result<(), String> {
+    let MetricsArgs { cache: cache_path, output, columns } = args;
+    let table = match columns.is_empty() {
+        true => MetricsTable::default(),
+        false => MetricsTable::new(columns),
+    };
+    let reader = CacheReader::open(&cache_path).map_err(|e| format!("Failed to read cache: {e}"))?;
+    let shapes = reader.map(|record| record.map(|(_, ba)| ba));
+    let Some(path) = output else {
+        if json_output() {
+            return Err("Expected -o <path> with --json".to_string());
         }
+        let mut stdout = io::stdout().lock();
+        table.write(shapes, &mut stdout).map_err(|e| e.to_string())?;
+        return Ok(());
+    };
+    let mut file = io::BufWriter::new(File::create(&path).map_err(|e| format!("Failed to create {}: {e}", path.display()))?);
+    let shapes = table.write(shapes, &mut file).map_err(|e| e.to_string())?;
+    io::Write::flush(&mut file).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    if json_output() {
+        let columns = table.metrics().iter().map(Metric::to_string).collect();
+        return print_json(&MetricsOutput { shapes, output: path.display().to_string(), columns });
     }
-    let cache_file = File::create(&file_name)?;
-    let mut writer = BufWriter::new(cache_file);
+    status!("Wrote the metrics of {shapes} shapes to {}", path.display());
+    Ok(())
+}
 
-    let config = bincode::config::standard();
-    bincode::serde::encode_into_std_write(set, &mut writer, config)
-        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+/// Writes the shapes of a cache file as voxel arrays next to it, or to the files starting with `--output`,
+/// see [TensorExport].
+fn run_tensor(args: TensorArgs) -> Result<(), String> {
+    let cache_path = args.cache;
+    let output = args.output.unwrap_or_else(|| Path::new(&cache_path).with_extension(""));
+    let mut tensors = TensorExport::default().with_shuffle(args.shuffle);
+    if let Some(format) = args.format {
+        tensors = tensors.with_format(format);
+    }
+    if let Some(grid) = args.grid {
+        tensors = tensors.with_grid(parse_box_size(&grid)?);
+    }
+    if let Some(orientation) = args.orientation {
+        tensors = tensors.with_canonical(matches!(orientation, TensorOrientation::Canonical));
+    }
+    if let Some(fraction) = args.val {
+        tensors = tensors.with_validation_fraction(fraction);
+    }
+    if let Some(seed) = args.seed {
+        tensors = tensors.with_seed(seed);
+    }
+    let shapes: Vec<_> = CacheReader::open(&cache_path)
+        .and_then(|reader| reader.map(|record| record.map(|(_, ba)| ba)).collect::<io::Result<_>>())
+        .map_err(|e| format!("Failed to read cache: {e}"))?;
+    let (store, name) = open_file_store(&output).map_err(|e| format!("Failed to open {}: {e}", output.display()))?;
+    let manifest = tensors.write(&shapes, store.as_ref(), &name).map_err(|e| format!("Failed to write the tensors: {e}"))?;
+    if json_output() {
+        return print_json(&manifest);
+    }
+    for split in &manifest.splits {
+        println!("{}: {} shapes in {}", split.name, split.codes.len(), store.location(&split.file));
+    }
     Ok(())
 }
 
-fn gen_cache_file_name(block_count: usize) -> String {
-    format!("./shape_cache_{block_count}.cac")
+/// Looks up one shape of a cache file by its position, its stable id or its code and prints it.
+fn run_get(args: GetArgs) -> Result<(), String> {
+    let GetArgs { cache: cache_path, id, rank, code, format } = args;
+    let code = code.map(|code| Polycube::from_code(&code).map_err(|e| format!("Invalid code {code}: {e}"))).transpose()?;
+    let arrangement = match (id, rank, code) {
+        (Some(id), None, None) => load_record(&cache_path, id)
+            .map_err(|e| format!("Failed to read cache: {e}"))?
+            .map(|(_, ba)| ba)
+            .ok_or_else(|| format!("The cache has no shape with id {id}"))?,
+        (None, Some(rank), None) => {
+            let path = ids_path(&cache_path);
+            let ids = File::open(&path).and_then(ShapeIds::read_from)
+                .map_err(|e| format!("Failed to read the ids {}: {e}", path.display()))?;
+            let code = ids.code(rank).ok_or_else(|| format!("There is no shape with the stable id {rank}"))?;
+            Polycube::from_code(code).map_err(|e| format!("Invalid code {code} in the ids: {e}"))?.into_inner()
+        }
+        (None, None, Some(code)) => {
+            // The code is canonicalized once and the records are streamed one at a time, comparing only
+            // those whose hashes collide with it.
+            let shape = code.into_inner();
+            let hash = BlockHash::from(&shape);
+            CacheReader::open(&cache_path)
+                .map_err(|e| format!("Failed to read cache: {e}"))?
+                .find_map(|record| match record {
+                    Ok((record_hash, ba)) if record_hash == hash && ba == shape => Some(Ok(ba)),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(format!("Failed to read cache: {e}"))),
+                })
+                .ok_or("The cache does not contain the shape")??
+        }
+        _ => return Err("Expected exactly one of --id, --rank and --code".to_string()),
+    };
+    let polycube = Polycube::new(&arrangement).map_err(|e| format!("Invalid shape in cache: {e}"))?;
+    if json_output() {
+        return print_json(&ShapeOutput::from(&polycube));
+    }
+    println!("{}", export(&polycube, format));
+    Ok(())
 }
 
-/// Generates variants of blocks from the given iterator and returns a set of those blocks.
-fn generate_variants_from<'a>(iter: impl Iterator<Item = &'a BlockArrangement>) -> BTreeMap<BlockHash, BlockArrangement> {
-    iter.flat_map(VariationGenerator::new)
-        .map(|ba| (BlockHash::from(&ba), ba))
-        .collect()
-}
\ No newline at end of file
+#[cfg(test)]
+mod main_tests {
+    use clap::CommandFactory;
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        // A run without a subcommand parses like the arguments of generate.
+        let cli = Cli::try_parse_from(["cube_combinations", "--json", "7", "--poly-tree", "--output", "code", "--output", "obj"]).unwrap();
+        let config = cli.run.into_config().unwrap();
+        assert!(cli.json && cli.command.is_none());
+        assert_eq!((BlockCount::new(7), Algorithm::PolyTree), (config.n, config.algorithm));
+        assert_eq!(vec![ExportFormat::Code, ExportFormat::Obj], config.output_formats);
+        let Some(Command::Count(args)) = Cli::try_parse_from(["cube_combinations", "count", "9", "--threads", "2"]).unwrap().command else {
+            panic!("Expected the count subcommand");
+        };
+        let config = args.into_config().unwrap();
+        assert!(config.count_only && config.threads == 2);
+        let cli = Cli::try_parse_from(["cube_combinations", "7", "--threads", "0"]).unwrap();
+        assert!(cli.run.into_config().unwrap().threads >= 1);
+        assert!(Cli::try_parse_from(["cube_combinations", "get", "shape_cache_5.cac", "--id", "1", "--rank", "1"]).is_err());
+        assert!(Cli::try_parse_from(["cube_combinations", "list", "shape_cache_5.cac", "--shuffle", "--sort-by", "bbox"]).is_err());
+    }
+}
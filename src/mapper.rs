@@ -1,104 +1,143 @@
-use std::usize;
-use getset::{CopyGetters, MutGetters, Setters};
+use getset::{CopyGetters, Setters};
 use serde::{Deserialize, Serialize};
-use crate::orientation::Orientation;
-use crate::point::{Finite3DDimension, Point3D};
+use crate::orientation::Orientation48;
+use crate::point::{Axis3D, Boundary, DimensionError, Finite3DDimension, Point3D};
 
+/// Maps the points of a box to the indices of a bitset and back.
+/// The box is stored as its smallest [offset](Mapper::offset) and its [Finite3DDimension], so the
+/// coordinates relative to the offset are never negative and the index of a point is computed from them
+/// directly. The [Orientation48] is applied to the points after moving them by the offset. It is stored
+/// as an [Orientation](crate::orientation::Orientation), so the format of stored arrangements is unchanged.
 #[derive(Debug, Eq, PartialEq, Clone)]
-#[derive(CopyGetters, Setters, MutGetters)]
+#[derive(CopyGetters, Setters)]
 #[derive(Serialize, Deserialize)]
 pub struct Mapper {
-    #[getset(get_copy = "pub", set = "pub")]
+    #[getset(get_copy = "pub")]
     dimension: Finite3DDimension,
-    #[getset(get_copy = "pub", set = "pub", get_mut = "pub")]
-    orientation: Orientation,
+    /// The smallest corner of the box before orienting it, which is the point at index zero.
+    #[getset(get_copy = "pub")]
+    offset: Point3D<i32>,
+    #[getset(get_copy = "pub", set = "pub")]
+    orientation: Orientation48,
+    /// Not serialized so stored arrangements keep their format, which always uses bounded dimensions.
+    #[getset(get_copy = "pub")]
+    #[serde(skip)]
+    boundary: Boundary,
 }
 
 impl Mapper {
 
-    pub fn new(dim: Finite3DDimension) -> Self {
-        Self {
+    /// Fails if the indices of the dimension exceed the addressable range.
+    pub fn new(dim: Finite3DDimension) -> Result<Self, DimensionError> {
+        Self::with_boundary(dim, Boundary::Bounded)
+    }
+
+    /// Fails if the indices of the dimension exceed the addressable range.
+    pub fn with_boundary(dim: Finite3DDimension, boundary: Boundary) -> Result<Self, DimensionError> {
+        dim.size()?;
+        Ok(Self {
             dimension: dim,
+            offset: Point3D::default(),
             orientation: Default::default(),
-        }
+            boundary,
+        })
     }
 
-    pub fn unresolve(&self, mut point: Point3D<i32>) -> Option<usize> {
-        point.apply_inverse_orientation(&self.orientation);
-        if !self.dimension.in_bounds(&point) {
-            return None;
-        }
-
-        let u_point = point.map_each(|x_val| {
-            (x_val + self.dimension.x_neg() as i32) as usize
-        }, |y_val| {
-            (y_val + self.dimension.y_neg() as i32) as usize
-        }, |z_val| {
-            (z_val + self.dimension.z_neg() as i32) as usize
-        });
-
-        let (width, depth, _height) = self.dimension().all_axis_len();
-
-        let index = u_point.x() + width as usize * (u_point.y() + (depth) as usize * u_point.z());
+    /// Moves the box so its smallest corner is at the offset instead of the origin.
+    pub fn with_offset(mut self, offset: Point3D<i32>) -> Self {
+        self.offset = offset;
+        self
+    }
 
-        Some(index)
+    /// Returns the index of the point. Points outside of a periodic dimension are wrapped into it first,
+    /// so only a bounded dimension may return `None`.
+    pub fn unresolve(&self, point: Point3D<i32>) -> Option<usize> {
+        let point = self.orientation.inverse().apply(point);
+        let local = |axis: Axis3D| {
+            let local = i64::from(point[axis]) - i64::from(self.offset[axis]);
+            let len = self.dimension.axis_len(axis) as i64;
+            match self.boundary {
+                Boundary::Periodic => Some(local.rem_euclid(len) as usize),
+                Boundary::Bounded => (0..len).contains(&local).then_some(local as usize),
+            }
+        };
+        let (width, depth, _height) = self.dimension.all_axis_len();
+        Some(local(Axis3D::X)? + width as usize * (local(Axis3D::Y)? + depth as usize * local(Axis3D::Z)?))
     }
 
     pub fn resolve(&self, index: usize) -> Option<Point3D<i32>> {
-        let (width, depth, _height) = self.dimension().all_axis_len();
-
-        let z = (index / (width * depth) as usize) as i32 - self.dimension().z_neg() as i32;
-        let y = ((index / width as usize) % depth as usize) as i32  - self.dimension().y_neg() as i32;
-        let x = (index % width as usize) as i32 - self.dimension().x_neg() as i32;
-
-        let mut p = Point3D::from((x, y, z));
-        if self.dimension.in_bounds(&p) {
-            p.apply_orientation(&self.orientation);
-            Some(p)
-        } else {
-            None
+        if index >= self.dimension.size().ok()? {
+            return None;
         }
+        let (width, depth, _height) = self.dimension.all_axis_len();
+        let (width, depth) = (width as usize, depth as usize);
+        let local = Point3D::new(index % width, (index / width) % depth, index / (width * depth));
+        Some(self.orientation.apply(local.map_all(|v| v as i32) + self.offset))
     }
 }
 
 #[cfg(test)]
 mod mapper_tests {
+    use crate::orientation::OrientationIterator;
     use super::*;
 
-    #[test]
-    fn test_mapping_small() {
-        let dim = Finite3DDimension::new(1, 1, 1, 1, 1, 1);
-        let mapper = Mapper::new(dim);
-        for i in 0..dim.size() as usize {
+    /// Resolves every index of the mapper and unresolves the point again.
+    fn assert_round_trip(mapper: &Mapper) {
+        for i in 0..mapper.dimension().size().unwrap() {
             let point = mapper.resolve(i).unwrap_or_else(|| panic!("Expected save resolving of index {i}"));
-            assert!(dim.in_bounds(&point));
             let resolved_index = mapper.unresolve(point).unwrap_or_else(|| panic!("Expected save unresolve of point {point}"));
             assert_eq!(i, resolved_index, "The expected index of {i} was not converted back, but got {resolved_index} and point {point}")
         }
     }
 
+    #[test]
+    fn test_mapping_small() {
+        let dim = Finite3DDimension::new(3, 3, 3);
+        let mapper = Mapper::new(dim).expect("Addressable dimension").with_offset(Point3D::new(-1, -1, -1));
+        assert_round_trip(&mapper);
+        assert_eq!(Some(Point3D::new(-1, -1, -1)), mapper.resolve(0));
+        assert_eq!(Some(13), mapper.unresolve(Point3D::default()));
+        assert_eq!(None, mapper.resolve(27));
+        assert_eq!(None, mapper.unresolve(Point3D::new(2, 0, 0)));
+    }
+
     #[test]
     fn test_mapping_medium() {
-        let dim = Finite3DDimension::new(5, 3, 7, 9, 11, 13);
-        let mapper = Mapper::new(dim);
-        for i in 0..dim.size() as usize {
-            let point = mapper.resolve(i).unwrap_or_else(|| panic!("Expected save resolving of index {i}"));
-            assert!(dim.in_bounds(&point));
-            let resolved_index = mapper.unresolve(point).unwrap_or_else(|| panic!("Expected save unresolve of point {point}"));
-            assert_eq!(i, resolved_index, "The expected index of {i} was not converted back, but got {resolved_index} and point {point}")
+        let dim = Finite3DDimension::new(9, 17, 25);
+        assert_round_trip(&Mapper::new(dim).expect("Addressable dimension").with_offset(Point3D::new(-3, -9, -13)));
+    }
+
+    #[test]
+    fn test_mapping_oriented() {
+        let dim = Finite3DDimension::new(4, 5, 4);
+        for orientation in OrientationIterator::default() {
+            let mut mapper = Mapper::new(dim).expect("Addressable dimension").with_offset(Point3D::new(-1, -2, 0));
+            mapper.set_orientation(orientation.into());
+            assert_round_trip(&mapper);
         }
     }
 
+    #[test]
+    fn test_reject_unaddressable() {
+        let dim = Finite3DDimension::new(u32::MAX, u32::MAX, 2);
+        assert_eq!(Err(DimensionError::TooLarge), Mapper::new(dim));
+    }
+
+    #[test]
+    fn test_periodic_unresolve() {
+        let dim = Finite3DDimension::new(3, 3, 1);
+        let bounded = Mapper::new(dim).expect("Addressable dimension").with_offset(Point3D::new(-1, -1, 0));
+        let periodic = Mapper::with_boundary(dim, Boundary::Periodic).expect("Addressable dimension").with_offset(Point3D::new(-1, -1, 0));
+        assert_eq!(None, bounded.unresolve(Point3D::new(2, 0, 0)));
+        assert_eq!(periodic.unresolve(Point3D::new(-1, 0, 0)), periodic.unresolve(Point3D::new(2, 0, 0)));
+        assert_eq!(periodic.unresolve(Point3D::new(1, 1, 0)), periodic.unresolve(Point3D::new(-2, 4, 5)));
+        assert_round_trip(&periodic);
+    }
+
     #[test]
     #[ignore]
     fn test_mapping_large() {
-        let dim = Finite3DDimension::new(10, 15, 18, 19, 13, 11);
-        let mapper = Mapper::new(dim);
-        for i in 0..dim.size() as usize {
-            let point = mapper.resolve(i).unwrap_or_else(|| panic!("Expected save resolving of index {i}"));
-            assert!(dim.in_bounds(&point));
-            let resolved_index = mapper.unresolve(point).unwrap_or_else(|| panic!("Expected save unresolve of point {point}"));
-            assert_eq!(i, resolved_index, "The expected index of {i} was not converted back, but got {resolved_index} and point {point}")
-        }
+        let dim = Finite3DDimension::new(26, 38, 25);
+        assert_round_trip(&Mapper::new(dim).expect("Addressable dimension").with_offset(Point3D::new(-16, -19, -11)));
     }
-}
\ No newline at end of file
+}
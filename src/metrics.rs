@@ -0,0 +1,227 @@
+//! Measures shapes for statistical analyses outside of Rust. [MetricsTable] writes the metrics of every
+//! shape of a cache as one row of a CSV file, computing them in parallel batches while the cache is read.
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{Error, Write};
+use rayon::prelude::*;
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use crate::analysis::BoundingBoxClass;
+use crate::block_arrangement::BlockArrangement;
+use crate::point::Point3D;
+use crate::polycube::{Polycube, PolycubeError};
+use crate::symmetry::Symmetry;
+
+/// The number of shapes read before their rows are computed in parallel and written.
+const BATCH_SIZE: usize = 4096;
+
+/// The metrics a [MetricsTable] can write, named like their CSV columns.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum Metric {
+    /// The number of block faces that are not shared with another block.
+    SurfaceArea,
+    /// The sorted axis lengths of the bounding box, see [BoundingBoxClass].
+    Bbox,
+    /// The Schoenflies symbol of the point group, see [Symmetry::name].
+    Symmetry,
+    /// See [diameter].
+    Diameter,
+    /// The number of cells enclosed by the shape, see [enclosed_cells].
+    Holes,
+}
+
+impl Metric {
+    /// The value of the metric as written into a CSV cell.
+    pub fn measure(&self, polycube: &Polycube) -> String {
+        match self {
+            Metric::SurfaceArea => polycube.surface_area().to_string(),
+            Metric::Bbox => BoundingBoxClass::of(polycube).to_string(),
+            Metric::Symmetry => Symmetry::of(polycube).name().to_string(),
+            Metric::Diameter => diameter(polycube.arrangement()).to_string(),
+            Metric::Holes => enclosed_cells(polycube.arrangement()).len().to_string(),
+        }
+    }
+}
+
+/// The largest number of steps between two blocks when every step moves to a joined block,
+/// i.e. the longest shortest path within the shape. Zero for a single block.
+pub fn diameter(ba: &BlockArrangement) -> usize {
+    let offsets = ba.connectivity().offsets();
+    ba.block_iter()
+        .map(|start| {
+            let mut distances = HashSet::from([start]);
+            let mut queue = VecDeque::from([(start, 0)]);
+            let mut farthest = 0;
+            while let Some((p, distance)) = queue.pop_front() {
+                farthest = distance;
+                for neighbor in offsets.iter().map(|o| *o + p) {
+                    if ba.is_set(&neighbor) && distances.insert(neighbor) {
+                        queue.push_back((neighbor, distance + 1));
+                    }
+                }
+            }
+            farthest
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+/// The empty cells inside the bounding box that can not be reached from outside of it by moving through
+/// the faces of empty cells, ordered by z, y and x.
+pub fn enclosed_cells(ba: &BlockArrangement) -> Vec<Point3D<i32>> {
+    let blocks: HashSet<_> = ba.block_iter().collect();
+    let (min, max) = blocks.iter().fold(
+        (Point3D::new(i32::MAX, i32::MAX, i32::MAX), Point3D::new(i32::MIN, i32::MIN, i32::MIN)),
+        |(min, max), p| (
+            Point3D::new(*min.x().min(p.x()), *min.y().min(p.y()), *min.z().min(p.z())),
+            Point3D::new(*max.x().max(p.x()), *max.y().max(p.y()), *max.z().max(p.z())),
+        ),
+    );
+    let inside = |p: &Point3D<i32>, margin: i32| (min.x() - margin..=max.x() + margin).contains(p.x())
+        && (min.y() - margin..=max.y() + margin).contains(p.y())
+        && (min.z() - margin..=max.z() + margin).contains(p.z());
+    // Flood the empty cells from a corner of the bounding box grown by one cell in every direction.
+    let start = min - Point3D::new(1, 1, 1);
+    let mut outside = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(p) = queue.pop_front() {
+        for neighbor in BlockArrangement::NEIGHBOR_OFFSETS.iter().map(|o| *o + p) {
+            if inside(&neighbor, 1) && !blocks.contains(&neighbor) && outside.insert(neighbor) {
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    let mut enclosed = vec![];
+    for z in *min.z()..=*max.z() {
+        for y in *min.y()..=*max.y() {
+            for x in *min.x()..=*max.x() {
+                let p = Point3D::new(x, y, z);
+                if !blocks.contains(&p) && !outside.contains(&p) {
+                    enclosed.push(p);
+                }
+            }
+        }
+    }
+    enclosed
+}
+
+/// Writes the chosen metrics of shapes as CSV, starting with a header. Every row starts with the code of
+/// the shape followed by the metrics in the order they were chosen.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MetricsTable {
+    metrics: Vec<Metric>,
+}
+
+impl Default for MetricsTable {
+    /// Every metric in the order they are declared.
+    fn default() -> Self {
+        Self { metrics: Metric::iter().collect() }
+    }
+}
+
+impl MetricsTable {
+    pub fn new(metrics: Vec<Metric>) -> Self {
+        Self { metrics }
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    pub fn header(&self) -> String {
+        std::iter::once("code".to_string())
+            .chain(self.metrics.iter().map(Metric::to_string))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn row(&self, polycube: &Polycube) -> String {
+        std::iter::once(polycube.code())
+            .chain(self.metrics.iter().map(|metric| metric.measure(polycube)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Writes the header and one row per shape in the order of the shapes, returning the number of rows.
+    /// The rows of [BATCH_SIZE] shapes at a time are computed on the threads of the current rayon pool.
+    /// Stops at the first shape that could not be read.
+    pub fn write<E>(&self, shapes: impl IntoIterator<Item = Result<BlockArrangement, E>>, writer: &mut impl Write) -> Result<u64, MetricsError<E>> {
+        writeln!(writer, "{}", self.header()).map_err(MetricsError::Io)?;
+        let mut shapes = shapes.into_iter();
+        let mut rows = 0;
+        loop {
+            let batch = shapes.by_ref().take(BATCH_SIZE).collect::<Result<Vec<_>, _>>().map_err(MetricsError::Read)?;
+            if batch.is_empty() {
+                return Ok(rows);
+            }
+            let lines = batch.par_iter()
+                .map(|ba| Polycube::new(ba).map(|polycube| self.row(&polycube)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(MetricsError::InvalidShape)?;
+            for line in &lines {
+                writeln!(writer, "{line}").map_err(MetricsError::Io)?;
+            }
+            rows += lines.len() as u64;
+        }
+    }
+}
+
+/// Why [MetricsTable::write] failed, `E` being the error of reading the shapes.
+#[derive(Debug)]
+pub enum MetricsError<E> {
+    Read(E),
+    InvalidShape(PolycubeError),
+    Io(Error),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for MetricsError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetricsError::Read(e) => write!(f, "Failed to read the shapes: {e}"),
+            MetricsError::InvalidShape(e) => write!(f, "Invalid shape: {e}"),
+            MetricsError::Io(e) => write!(f, "Failed to write the metrics: {e}"),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for MetricsError<E> {}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::convert::Infallible;
+    use std::str::FromStr;
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        // A 3x3x3 cube without its center encloses one cell.
+        let points: Vec<_> = (0..27)
+            .map(|i| Point3D::new(i % 3, (i / 3) % 3, i / 9))
+            .filter(|p| *p != Point3D::new(1, 1, 1))
+            .collect();
+        let hollow = BlockArrangement::from_points(&points).unwrap();
+        assert_eq!(vec![Point3D::new(1, 1, 1)], enclosed_cells(&hollow).into_iter()
+            .map(|p| p - hollow.block_iter().min().unwrap())
+            .collect::<Vec<_>>());
+        assert_eq!(6, diameter(&hollow));
+
+        let line = BlockArrangement::from_points(&[Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(2, 0, 0)]).unwrap();
+        assert!(enclosed_cells(&line).is_empty());
+        assert_eq!(2, diameter(&line));
+        assert_eq!(0, diameter(&BlockArrangement::new()));
+
+        let table = MetricsTable::new(vec![Metric::from_str("bbox").unwrap(), Metric::Diameter, Metric::Holes, Metric::SurfaceArea]);
+        assert_eq!("code,bbox,diameter,holes,surface_area", table.header());
+        let mut csv = vec![];
+        let shapes = [line.clone(), hollow.clone()].map(Ok::<_, Infallible>);
+        assert_eq!(2, table.write(shapes, &mut csv).unwrap());
+        let line_code = Polycube::new(&line).unwrap().code();
+        let hollow_code = Polycube::new(&hollow).unwrap().code();
+        assert_eq!(
+            format!("code,bbox,diameter,holes,surface_area\n{line_code},1x1x3,2,0,14\n{hollow_code},3x3x3,6,1,60\n"),
+            String::from_utf8(csv).unwrap(),
+        );
+        assert_eq!("code,surface_area,bbox,symmetry,diameter,holes", MetricsTable::default().header());
+    }
+}
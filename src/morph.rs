@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use crate::block_arrangement::BlockArrangement;
+use crate::point::Point3D;
+use crate::polycube::Polycube;
+
+/// The default number of shapes [find_morph] visits before giving up.
+pub const DEFAULT_MAX_SHAPES: usize = 1_000_000;
+
+/// Moves one block of a shape to another position such that the shape stays connected.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Move {
+    pub removed: Point3D<i32>,
+    pub added: Point3D<i32>,
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "move {} to {}", self.removed, self.added)
+    }
+}
+
+/// A shortest sequence of [Move]s transforming one shape into another.
+#[derive(Debug, Clone)]
+pub struct Morph {
+    /// The shape after every move, starting with the source and ending with the target.
+    pub shapes: Vec<Polycube>,
+    /// The moves in the coordinates of the canonical source shape.
+    /// The blocks after the last move are a rotated, mirrored or translated version of the target.
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum MorphError {
+    /// Moves never change the number of blocks.
+    SizeMismatch,
+    /// The target was not reached after visiting the given number of shapes.
+    TooManyShapes(usize),
+}
+
+impl Display for MorphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MorphError::SizeMismatch => f.write_str("The shapes have different numbers of blocks"),
+            MorphError::TooManyShapes(count) => write!(f, "The target was not reached within {count} shapes"),
+        }
+    }
+}
+
+impl std::error::Error for MorphError {}
+
+/// Every move of the blocks that keeps them connected together with the blocks after the move.
+fn moves(points: &[Point3D<i32>]) -> Vec<(Move, Vec<Point3D<i32>>)> {
+    let mut result = vec![];
+    for (i, removed) in points.iter().enumerate() {
+        let mut rest = points.to_vec();
+        rest.swap_remove(i);
+        if BlockArrangement::from_points(&rest).is_err() {
+            continue;
+        }
+        let occupied: HashSet<_> = points.iter().collect();
+        let targets: HashSet<_> = rest.iter()
+            .flat_map(|p| BlockArrangement::NEIGHBOR_OFFSETS.map(|o| o + *p))
+            .filter(|p| !occupied.contains(p))
+            .collect();
+        for added in targets {
+            let mut moved = rest.clone();
+            moved.push(added);
+            result.push((Move { removed: *removed, added }, moved));
+        }
+    }
+    result
+}
+
+fn to_polycube(points: &[Point3D<i32>]) -> Polycube {
+    let ba = BlockArrangement::from_points(points).expect("Moves keep the blocks connected.");
+    Polycube::new(&ba).expect("Moves keep the blocks connected.")
+}
+
+/// The distinct shapes that differ from the shape by a single [Move], excluding the shape itself.
+pub fn neighbors(polycube: &Polycube) -> HashSet<Polycube> {
+    let points: Vec<_> = polycube.block_iter().collect();
+    let mut neighbors: HashSet<_> = moves(&points).iter()
+        .map(|(_, moved)| to_polycube(moved))
+        .collect();
+    neighbors.remove(polycube);
+    neighbors
+}
+
+/// Searches the shapes reachable by [Move]s breadth first until the target is found.
+/// Fails once more than `max_shapes` shapes were visited.
+pub fn find_morph(from: &Polycube, to: &Polycube, max_shapes: usize) -> Result<Morph, MorphError> {
+    if from.num_blocks() != to.num_blocks() {
+        return Err(MorphError::SizeMismatch);
+    }
+    let mut predecessors: HashMap<Polycube, Option<Polycube>> = HashMap::from([(from.clone(), None)]);
+    let mut queue = VecDeque::from([from.clone()]);
+    while let Some(shape) = queue.pop_front() {
+        if shape == *to {
+            break;
+        }
+        for neighbor in neighbors(&shape) {
+            if predecessors.len() >= max_shapes {
+                return Err(MorphError::TooManyShapes(max_shapes));
+            }
+            if !predecessors.contains_key(&neighbor) {
+                predecessors.insert(neighbor.clone(), Some(shape.clone()));
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    let mut shapes = vec![to.clone()];
+    while let Some(Some(previous)) = predecessors.get(shapes.last().expect("Never empty")) {
+        shapes.push(previous.clone());
+    }
+    shapes.reverse();
+
+    // Replays the path in the coordinates of the source shape.
+    let mut points: Vec<_> = from.block_iter().collect();
+    let mut path_moves = vec![];
+    for next in &shapes[1..] {
+        let (step, moved) = moves(&points).into_iter()
+            .find(|(_, moved)| to_polycube(moved) == *next)
+            .expect("Consecutive shapes of the path differ by a move.");
+        path_moves.push(step);
+        points = moved;
+    }
+    Ok(Morph { shapes, moves: path_moves })
+}
+
+#[cfg(test)]
+mod morph_tests {
+    use super::*;
+
+    fn polycube(points: &[(i32, i32, i32)]) -> Polycube {
+        let points: Vec<_> = points.iter().map(|(x, y, z)| Point3D::new(*x, *y, *z)).collect();
+        to_polycube(&points)
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let line = polycube(&[(0, 0, 0), (1, 0, 0), (2, 0, 0)]);
+        let l_shape = polycube(&[(0, 0, 0), (1, 0, 0), (1, 1, 0)]);
+        assert_eq!(HashSet::from([l_shape.clone()]), neighbors(&line));
+        assert_eq!(HashSet::from([line]), neighbors(&l_shape));
+    }
+
+    #[test]
+    fn test_find_morph() {
+        let line = polycube(&[(0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0)]);
+        let square = polycube(&[(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0)]);
+        let morph = find_morph(&line, &square, DEFAULT_MAX_SHAPES).expect("Connected shape space");
+        assert_eq!(2, morph.moves.len());
+        assert_eq!(3, morph.shapes.len());
+        assert_eq!((&line, &square), (&morph.shapes[0], &morph.shapes[2]));
+
+        let mut points: Vec<_> = line.block_iter().collect();
+        for step in &morph.moves {
+            let i = points.iter().position(|p| *p == step.removed).expect("Moved block exists");
+            points[i] = step.added;
+        }
+        assert_eq!(square, to_polycube(&points));
+
+        assert_eq!(0, find_morph(&line, &line, DEFAULT_MAX_SHAPES).unwrap().moves.len());
+        assert_eq!(MorphError::SizeMismatch, find_morph(&line, &polycube(&[(0, 0, 0)]), 10).unwrap_err());
+        assert_eq!(MorphError::TooManyShapes(1), find_morph(&line, &square, 1).unwrap_err());
+    }
+}
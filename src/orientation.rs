@@ -1,9 +1,13 @@
 use std::array::IntoIter;
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
 use getset::{CopyGetters, MutGetters, Setters};
 use serde::{Deserialize, Serialize};
-use strum::EnumIter;
-use crate::point::Axis3D;
+use strum::{Display, EnumIter, EnumString};
+use crate::point::{Axis3D, Point3D};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Hash)]
 #[derive(CopyGetters, MutGetters, Setters)]
@@ -50,6 +54,11 @@ impl Orientation {
         };
     }
 
+    /// Checks if the orientation mirrors shapes, i.e. mirrors along an odd number of axis.
+    pub fn is_reflection(&self) -> bool {
+        self.x_mir ^ self.y_mir ^ self.z_mir
+    }
+
     pub fn mirror(&mut self, axis: Axis3D) {
         match axis {
             Axis3D::X => {self.set_x_mir(!self.x_mir())}
@@ -57,6 +66,48 @@ impl Orientation {
             Axis3D::Z => {self.set_z_mir(!self.z_mir())}
         };
     }
+
+    /// Returns the orientation undoing this one, so applying both to a point in either order leaves it
+    /// where it was. An orientation mirrors before it rotates, so the inverse generally has other
+    /// rotations and mirrors than negating them would give. The inverses of all 512 orientations are
+    /// looked up once and then read from a table.
+    pub fn inverse(&self) -> Self {
+        static INVERSES: OnceLock<Vec<Orientation>> = OnceLock::new();
+        INVERSES.get_or_init(|| {
+            let mut inverses = vec![Orientation::default(); 512];
+            // A signed permutation of the axes is determined by where it moves a point with three
+            // distinct coordinates.
+            let probe = Point3D::new(1, 2, 3);
+            for orientation in OrientationIterator::default() {
+                let mut moved = probe;
+                moved.apply_orientation(&orientation);
+                inverses[orientation.index()] = OrientationIterator::default()
+                    .find(|candidate| {
+                        let mut p = moved;
+                        p.apply_orientation(candidate);
+                        p == probe
+                    })
+                    .expect("Every orientation has an inverse among the orientations.");
+            }
+            inverses
+        })[self.index()]
+    }
+
+    /// The position of the orientation among all 512, two bits per rotation and one bit per mirror.
+    fn index(&self) -> usize {
+        let rotation = |amount: RotationAmount| match amount {
+            RotationAmount::Zero => 0,
+            RotationAmount::Ninety => 1,
+            RotationAmount::OneEighty => 2,
+            RotationAmount::TwoSeventy => 3,
+        };
+        rotation(self.x_rot)
+            | rotation(self.y_rot) << 2
+            | rotation(self.z_rot) << 4
+            | usize::from(self.x_mir) << 6
+            | usize::from(self.y_mir) << 7
+            | usize::from(self.z_mir) << 8
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, EnumIter, Default, Hash)]
@@ -251,6 +302,335 @@ impl Iterator for OrientationIterator {
     }
 }
 
+/// The orientations that transform shapes differently, each the first of its kind in the order of
+/// [OrientationIterator].
+struct DistinctOrientations {
+    all: Vec<Orientation>,
+    rotations: Vec<Orientation>,
+}
+
+impl DistinctOrientations {
+    fn get() -> &'static Self {
+        static DISTINCT: OnceLock<DistinctOrientations> = OnceLock::new();
+        DISTINCT.get_or_init(|| {
+            // A signed permutation of the axes is determined by where it moves a point with three distinct coordinates.
+            let mut images = HashSet::new();
+            let all: Vec<_> = OrientationIterator::default()
+                .filter(|orientation| {
+                    let mut probe = Point3D::new(1, 2, 3);
+                    probe.apply_orientation(orientation);
+                    images.insert(probe)
+                })
+                .collect();
+            let rotations = all.iter().copied().filter(|orientation| !orientation.is_reflection()).collect();
+            Self { all, rotations }
+        })
+    }
+}
+
+/// The 24 rotations of the cube, starting with the identity. [OrientationIterator] yields 512 combinations
+/// of turns and mirrors, but many of them move shapes alike, so this yields the first of each in its order.
+pub fn rotations() -> impl ExactSizeIterator<Item = Orientation> + Clone {
+    DistinctOrientations::get().rotations.iter().copied()
+}
+
+/// The 48 symmetries of the cube, the [rotations] and the 24 reflections, starting with the identity and
+/// in the order of [OrientationIterator]. Every orientation moves shapes like exactly one of them.
+pub fn rotations_and_reflections() -> impl ExactSizeIterator<Item = Orientation> + Clone {
+    DistinctOrientations::get().all.iter().copied()
+}
+
+/// A rotation of the cube, named after where it moves the x and the y axis: `PyNx` moves the x axis onto
+/// the positive y axis and the y axis onto the negative x axis, which leaves the z axis where it is.
+/// Unlike [Orientation], which combines turns around the three axes and mirrors, every rotation has
+/// exactly one value and [Orientation24::compose] follows a composition table of the rotation group.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default, PartialOrd, Ord)]
+#[derive(Display, EnumString)]
+#[repr(u8)]
+pub enum Orientation24 {
+    #[default]
+    PxPy, PxNy, PxPz, PxNz,
+    NxPy, NxNy, NxPz, NxNz,
+    PyPx, PyNx, PyPz, PyNz,
+    NyPx, NyNx, NyPz, NyNz,
+    PzPx, PzNx, PzPy, PzNy,
+    NzPx, NzNx, NzPy, NzNy,
+}
+
+impl Orientation24 {
+    pub const ALL: [Self; 24] = {
+        use Orientation24::*;
+        [
+            PxPy, PxNy, PxPz, PxNz, NxPy, NxNy, NxPz, NxNz, PyPx, PyNx, PyPz, PyNz,
+            NyPx, NyNx, NyPz, NyNz, PzPx, PzNx, PzPy, PzNy, NzPx, NzNx, NzPy, NzNy,
+        ]
+    };
+
+    /// The images of the x and the y axis.
+    const fn axes(self) -> ([i32; 3], [i32; 3]) {
+        const PX: [i32; 3] = [1, 0, 0];
+        const NX: [i32; 3] = [-1, 0, 0];
+        const PY: [i32; 3] = [0, 1, 0];
+        const NY: [i32; 3] = [0, -1, 0];
+        const PZ: [i32; 3] = [0, 0, 1];
+        const NZ: [i32; 3] = [0, 0, -1];
+        use Orientation24::*;
+        match self {
+            PxPy => (PX, PY), PxNy => (PX, NY), PxPz => (PX, PZ), PxNz => (PX, NZ),
+            NxPy => (NX, PY), NxNy => (NX, NY), NxPz => (NX, PZ), NxNz => (NX, NZ),
+            PyPx => (PY, PX), PyNx => (PY, NX), PyPz => (PY, PZ), PyNz => (PY, NZ),
+            NyPx => (NY, PX), NyNx => (NY, NX), NyPz => (NY, PZ), NyNz => (NY, NZ),
+            PzPx => (PZ, PX), PzNx => (PZ, NX), PzPy => (PZ, PY), PzNy => (PZ, NY),
+            NzPx => (NZ, PX), NzNx => (NZ, NX), NzPy => (NZ, PY), NzNy => (NZ, NY),
+        }
+    }
+
+    /// The columns of the rotation matrix, i.e. the images of the x, y and z axis. The image of the z
+    /// axis is the cross product of the others, which makes the determinant one.
+    pub fn columns(self) -> [[i32; 3]; 3] {
+        let (x, y) = self.axes();
+        [x, y, [x[1] * y[2] - x[2] * y[1], x[2] * y[0] - x[0] * y[2], x[0] * y[1] - x[1] * y[0]]]
+    }
+
+    pub fn apply(self, p: Point3D<i32>) -> Point3D<i32> {
+        let [x, y, z] = self.columns();
+        let image = |i: usize| x[i] * p.x() + y[i] * p.y() + z[i] * p.z();
+        Point3D::new(image(0), image(1), image(2))
+    }
+
+    /// The rotation moving the x and the y axis onto the given images, `None` if they are not
+    /// two different axes.
+    fn from_axes(x: [i32; 3], y: [i32; 3]) -> Option<Self> {
+        Self::ALL.into_iter().find(|rotation| rotation.axes() == (x, y))
+    }
+
+    /// The rotation moving points like applying this rotation and then the other one.
+    pub fn compose(self, then: Self) -> Self {
+        static TABLE: OnceLock<[[Orientation24; 24]; 24]> = OnceLock::new();
+        TABLE.get_or_init(|| Self::ALL.map(|first| Self::ALL.map(|second| {
+            let image = |axis: [i32; 3]| {
+                let p = second.apply(first.apply(Point3D::new(axis[0], axis[1], axis[2])));
+                [*p.x(), *p.y(), *p.z()]
+            };
+            Self::from_axes(image([1, 0, 0]), image([0, 1, 0])).expect("Rotations compose to a rotation.")
+        })))[self as usize][then as usize]
+    }
+
+    /// The rotation undoing this one.
+    pub fn inverse(self) -> Self {
+        Self::ALL.into_iter()
+            .find(|other| self.compose(*other) == Self::PxPy)
+            .expect("Every rotation has an inverse.")
+    }
+}
+
+/// One of the 48 symmetries of the cube: a [rotation](Orientation24), or a rotation followed by the
+/// inversion at the origin, which negates every coordinate. The inversion commutes with every rotation,
+/// so every reflection is exactly one of these.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize)]
+#[serde(from = "Orientation", into = "Orientation")]
+pub enum Orientation48 {
+    Rotation(Orientation24),
+    Reflection(Orientation24),
+}
+
+impl Default for Orientation48 {
+    fn default() -> Self {
+        Orientation48::Rotation(Orientation24::default())
+    }
+}
+
+impl Orientation48 {
+    /// Every symmetry, the rotations first in the order of [Orientation24::ALL].
+    pub fn all() -> impl Iterator<Item = Self> {
+        let rotations = Orientation24::ALL.into_iter().map(Orientation48::Rotation);
+        rotations.chain(Orientation24::ALL.into_iter().map(Orientation48::Reflection))
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Orientation48::Rotation(rotation) => rotation as usize,
+            Orientation48::Reflection(rotation) => 24 + rotation as usize,
+        }
+    }
+
+    pub fn is_reflection(self) -> bool {
+        matches!(self, Orientation48::Reflection(_))
+    }
+
+    /// The rotation, which is followed by the inversion for a reflection.
+    pub fn rotation(self) -> Orientation24 {
+        match self {
+            Orientation48::Rotation(rotation) | Orientation48::Reflection(rotation) => rotation,
+        }
+    }
+
+    pub fn apply(self, p: Point3D<i32>) -> Point3D<i32> {
+        match self {
+            Orientation48::Rotation(rotation) => rotation.apply(p),
+            Orientation48::Reflection(rotation) => Point3D::default() - rotation.apply(p),
+        }
+    }
+
+    /// The symmetry moving points like applying this symmetry and then the other one.
+    pub fn compose(self, then: Self) -> Self {
+        let rotation = self.rotation().compose(then.rotation());
+        match self.is_reflection() != then.is_reflection() {
+            true => Orientation48::Reflection(rotation),
+            false => Orientation48::Rotation(rotation),
+        }
+    }
+
+    /// The symmetry undoing this one.
+    pub fn inverse(self) -> Self {
+        match self {
+            Orientation48::Rotation(rotation) => Orientation48::Rotation(rotation.inverse()),
+            Orientation48::Reflection(rotation) => Orientation48::Reflection(rotation.inverse()),
+        }
+    }
+}
+
+impl From<Orientation24> for Orientation48 {
+    fn from(rotation: Orientation24) -> Self {
+        Orientation48::Rotation(rotation)
+    }
+}
+
+impl From<Orientation> for Orientation48 {
+    /// The symmetry moving points like the orientation. Looked up in a table of all 512 orientations.
+    fn from(orientation: Orientation) -> Self {
+        static SYMMETRIES: OnceLock<Vec<Orientation48>> = OnceLock::new();
+        SYMMETRIES.get_or_init(|| {
+            let mut symmetries = vec![Orientation48::default(); 512];
+            for orientation in OrientationIterator::default() {
+                let image = |mut p: Point3D<i32>| {
+                    p.apply_orientation(&orientation);
+                    // The rotation of a reflection moves the axes the other way.
+                    if orientation.is_reflection() {
+                        p = Point3D::default() - p;
+                    }
+                    [*p.x(), *p.y(), *p.z()]
+                };
+                let rotation = Orientation24::from_axes(image(Point3D::new(1, 0, 0)), image(Point3D::new(0, 1, 0)))
+                    .expect("Orientations move the axes onto axes.");
+                symmetries[orientation.index()] = match orientation.is_reflection() {
+                    true => Orientation48::Reflection(rotation),
+                    false => Orientation48::Rotation(rotation),
+                };
+            }
+            symmetries
+        })[orientation.index()]
+    }
+}
+
+impl From<Orientation48> for Orientation {
+    /// The first orientation of [rotations_and_reflections] moving points like the symmetry, so the
+    /// identity is the default orientation.
+    fn from(symmetry: Orientation48) -> Self {
+        static ORIENTATIONS: OnceLock<Vec<Orientation>> = OnceLock::new();
+        ORIENTATIONS.get_or_init(|| {
+            let mut orientations = vec![Orientation::default(); 48];
+            for orientation in rotations_and_reflections() {
+                orientations[Orientation48::from(orientation).index()] = orientation;
+            }
+            orientations
+        })[symmetry.index()]
+    }
+}
+
+/// The order [BlockArrangement](crate::block_arrangement::BlockArrangement) equality and congruence try
+/// orientations in, see [OrientationRanking].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(EnumString, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum OrientationOrder {
+    /// The orientations of [rotations_and_reflections] in their order.
+    #[default]
+    Sequential,
+    /// The 48 distinct orientations, the identity first and the others by how often they matched so far.
+    /// Which of several matching orientations congruence returns depends on the earlier comparisons.
+    HitRate,
+}
+
+/// Counts how often every distinct orientation matched during equality and congruence checks, so
+/// [OrientationOrder::HitRate] tries those that succeeded most often first.
+pub struct OrientationRanking {
+    hit_rate: AtomicBool,
+    /// The orientations of [rotations_and_reflections].
+    orientations: Vec<Orientation>,
+    hits: Vec<AtomicU64>,
+    /// The indices of the orientations in the order they are tried.
+    order: RwLock<Vec<usize>>,
+    hits_since_sort: AtomicU64,
+}
+
+impl OrientationRanking {
+    /// The number of hits after which the order is updated.
+    const SORT_INTERVAL: u64 = 1024;
+
+    fn new() -> Self {
+        let orientations: Vec<_> = rotations_and_reflections().collect();
+        Self {
+            hit_rate: AtomicBool::new(false),
+            hits: orientations.iter().map(|_| AtomicU64::new(0)).collect(),
+            order: RwLock::new((0..orientations.len()).collect()),
+            orientations,
+            hits_since_sort: AtomicU64::new(0),
+        }
+    }
+
+    /// The ranking shared by all comparisons of the process.
+    pub fn global() -> &'static Self {
+        static RANKING: OnceLock<OrientationRanking> = OnceLock::new();
+        RANKING.get_or_init(Self::new)
+    }
+
+    pub fn order(&self) -> OrientationOrder {
+        match self.hit_rate.load(Ordering::Relaxed) {
+            true => OrientationOrder::HitRate,
+            false => OrientationOrder::Sequential,
+        }
+    }
+
+    pub fn set_order(&self, order: OrientationOrder) {
+        self.hit_rate.store(order == OrientationOrder::HitRate, Ordering::Relaxed);
+    }
+
+    /// Applies the function to the orientations in the current order and returns the first result,
+    /// counting it as a hit of its orientation.
+    pub fn find_map<T>(&self, mut f: impl FnMut(Orientation) -> Option<T>) -> Option<T> {
+        if !self.hit_rate.load(Ordering::Relaxed) {
+            return rotations_and_reflections().find_map(f);
+        }
+        let (index, result) = self.order.read().expect("No thread panicked while holding the lock.")
+            .iter()
+            .find_map(|index| f(self.orientations[*index]).map(|result| (*index, result)))?;
+        self.record_hit(index);
+        Some(result)
+    }
+
+    fn record_hit(&self, index: usize) {
+        self.hits[index].fetch_add(1, Ordering::Relaxed);
+        if self.hits_since_sort.fetch_add(1, Ordering::Relaxed) + 1 < Self::SORT_INTERVAL {
+            return;
+        }
+        self.hits_since_sort.store(0, Ordering::Relaxed);
+        let mut order = self.order.write().expect("No thread panicked while holding the lock.");
+        // The identity stays first as every arrangement equals itself without any rotation.
+        order[1..].sort_by_key(|index| Reverse(self.hits[*index].load(Ordering::Relaxed)));
+    }
+
+    /// The distinct orientations with the number of times they matched, in the order they are tried.
+    pub fn hits(&self) -> Vec<(Orientation, u64)> {
+        self.order.read().expect("No thread panicked while holding the lock.")
+            .iter()
+            .map(|index| (self.orientations[*index], self.hits[*index].load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod orientation_iter_tests {
     use std::collections::HashSet;
@@ -261,4 +641,122 @@ mod orientation_iter_tests {
         let set: HashSet<_> = OrientationIterator::default().collect();
         assert_eq!(512, set.len());
     }
+
+    #[test]
+    fn test_distinct() {
+        let image = |orientation: &Orientation| {
+            let mut p = Point3D::new(1, 2, 3);
+            p.apply_orientation(orientation);
+            p
+        };
+        let rotated: HashSet<_> = rotations().map(|orientation| image(&orientation)).collect();
+        let all: HashSet<_> = rotations_and_reflections().map(|orientation| image(&orientation)).collect();
+        assert_eq!((24, 48), (rotated.len(), all.len()));
+        assert!(rotated.is_subset(&all));
+        assert_eq!(all, OrientationIterator::default().map(|orientation| image(&orientation)).collect());
+        assert_eq!(Some(Orientation::default()), rotations().next());
+        assert!(rotations().all(|orientation| !orientation.is_reflection()));
+        assert_eq!(24, rotations_and_reflections().filter(Orientation::is_reflection).count());
+    }
+
+    #[test]
+    fn test_symmetries() {
+        let probe = Point3D::new(1, 2, 3);
+        let images: HashSet<_> = Orientation48::all().map(|symmetry| symmetry.apply(probe)).collect();
+        assert_eq!(48, images.len());
+        for orientation in OrientationIterator::default() {
+            let symmetry = Orientation48::from(orientation);
+            let mut p = probe;
+            p.apply_orientation(&orientation);
+            assert_eq!(p, symmetry.apply(probe));
+            assert_eq!(orientation.is_reflection(), symmetry.is_reflection());
+            assert_eq!(symmetry, Orientation48::from(Orientation::from(symmetry)));
+        }
+        assert_eq!(Orientation::default(), Orientation::from(Orientation48::default()));
+        let identity = Orientation48::default();
+        for a in Orientation48::all() {
+            assert_eq!(identity, a.compose(a.inverse()));
+            assert_eq!(identity, a.inverse().compose(a));
+            for b in Orientation48::all() {
+                let composed = a.compose(b);
+                assert_eq!(b.apply(a.apply(probe)), composed.apply(probe));
+                assert_eq!(a.is_reflection() != b.is_reflection(), composed.is_reflection());
+                for c in Orientation48::all().step_by(5) {
+                    assert_eq!(composed.compose(c), a.compose(b.compose(c)));
+                }
+            }
+        }
+        // A quarter turn around the z axis moves the x axis onto the y axis.
+        let quarter = Orientation24::PyNx;
+        assert_eq!(Point3D::new(0, 1, 0), quarter.apply(Point3D::new(1, 0, 0)));
+        assert_eq!(Orientation24::NxNy, quarter.compose(quarter));
+        assert_eq!(Orientation24::NyPx, quarter.inverse());
+        assert_eq!(Ok(quarter), "PyNx".parse());
+    }
+
+    #[test]
+    fn test_inverse() {
+        let points = [Point3D::new(1, 2, 3), Point3D::new(-4, 0, 7), Point3D::new(0, 0, 0)];
+        for orientation in OrientationIterator::default() {
+            let inverse = orientation.inverse();
+            assert_eq!(orientation.is_reflection(), inverse.is_reflection());
+            for point in points {
+                let mut p = point;
+                p.apply_orientation(&orientation);
+                p.apply_orientation(&inverse);
+                assert_eq!(point, p, "{orientation:?} followed by its inverse {inverse:?}");
+                p.apply_orientation(&inverse);
+                p.apply_orientation(&orientation);
+                assert_eq!(point, p, "The inverse {inverse:?} followed by {orientation:?}");
+
+                let mut undone = point;
+                undone.apply_orientation(&orientation);
+                undone.apply_inverse_orientation(&orientation);
+                assert_eq!(point, undone);
+            }
+            let mut twice = points[0];
+            twice.apply_orientation(&inverse.inverse());
+            let mut once = points[0];
+            once.apply_orientation(&orientation);
+            assert_eq!(once, twice);
+        }
+    }
+
+    #[test]
+    fn test_ranking() {
+        let ranking = OrientationRanking::new();
+        assert_eq!(48, ranking.hits().len());
+        assert_eq!(OrientationOrder::Sequential, ranking.order());
+        let mut target = Orientation::default();
+        target.rotate(Axis3D::Y, RotationAmount::TwoSeventy);
+        target.mirror(Axis3D::Z);
+        // Orientations are compared by their effect as several of them rotate the same way.
+        let image = |orientation: &Orientation| {
+            let mut p = Point3D::new(1, 2, 3);
+            p.apply_orientation(orientation);
+            p
+        };
+        let mut tried = 0;
+        ranking.find_map(|orientation| {
+            tried += 1;
+            (image(&orientation) == image(&target)).then_some(())
+        });
+        let sequential_tries = tried;
+        assert!(sequential_tries > 2);
+
+        ranking.set_order(OrientationOrder::HitRate);
+        for _ in 0..OrientationRanking::SORT_INTERVAL {
+            assert!(ranking.find_map(|orientation| (image(&orientation) == image(&target)).then_some(())).is_some());
+        }
+        let hits = ranking.hits();
+        assert_eq!((Orientation::default(), 0), hits[0]);
+        assert_eq!((image(&target), OrientationRanking::SORT_INTERVAL), (image(&hits[1].0), hits[1].1));
+        tried = 0;
+        ranking.find_map(|orientation| {
+            tried += 1;
+            (image(&orientation) == image(&target)).then_some(())
+        });
+        assert_eq!(2, tried);
+        assert_eq!(Ok(OrientationOrder::HitRate), "hit-rate".parse());
+    }
 }
\ No newline at end of file
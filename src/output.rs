@@ -0,0 +1,244 @@
+//! The documents the command line interface prints when it is run with `--json` in front of the
+//! subcommand, e.g. `cube_combinations --json cache stats shape_cache_6.cac`.
+//! Every invocation then prints exactly one document to stdout, human readable progress messages go to
+//! stderr and a failure is reported as an [ErrorOutput] on stderr.
+//! The types below are the schema of the documents: fields may be added but are never renamed or removed.
+//! Fields of type `Option` are `null` when the value is unknown.
+//! A generation run prints its [RunReport](crate::run::RunReport).
+
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::block_count::BlockCount;
+use crate::cache::ids::ShapeId;
+use crate::estimate::LevelEstimate;
+use crate::orientation::Orientation;
+use crate::point::Point3D;
+use crate::polycube::Polycube;
+use crate::self_test::CheckOutcome;
+
+/// Printed instead of the message of a failed command.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ErrorOutput {
+    pub error: String,
+}
+
+/// A shape printed by `get`, `list`, `sample`, `import` and `puzzle`. The `--format` of these commands is ignored.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ShapeOutput {
+    /// The code of the canonical orientation, see [Polycube::code].
+    pub code: String,
+    pub blocks: BlockCount,
+}
+
+impl From<&Polycube> for ShapeOutput {
+    fn from(polycube: &Polycube) -> Self {
+        Self { code: polycube.code(), blocks: polycube.num_blocks() }
+    }
+}
+
+/// The page of shapes printed by `list`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ListOutput {
+    /// The position of the first shape in the requested order.
+    pub offset: usize,
+    pub shapes: Vec<ShapeOutput>,
+}
+
+/// The shapes chosen by `sample`. The `--render` format is ignored.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct SampleOutput {
+    /// The number of shapes in the cache the sample was chosen from.
+    pub total: u64,
+    pub shapes: Vec<ShapeOutput>,
+}
+
+/// The result of `metrics`, which requires `-o` with `--json`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct MetricsOutput {
+    /// The number of rows written, one per shape.
+    pub shapes: u64,
+    /// The path of the CSV file.
+    pub output: String,
+    pub columns: Vec<String>,
+}
+
+/// The result of `cache convert`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ConvertOutput {
+    pub shapes: usize,
+    pub format: String,
+    pub compression: String,
+}
+
+/// The result of `cache combine` and `cache split`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct LayoutOutput {
+    pub layout: String,
+    /// The sizes whose caches were moved.
+    pub sizes: Vec<BlockCount>,
+}
+
+/// A level listed by `cache manifest`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ManifestLevel {
+    pub blocks: BlockCount,
+    pub shapes: u64,
+    pub checksum: String,
+    /// Whether the cache still has the checksum, only set with `--check`.
+    pub valid: Option<bool>,
+}
+
+/// The summary printed by `cache stats`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CacheStatsOutput {
+    pub shapes: usize,
+    /// Whether the statistics were read from the `index` or computed from the `records`.
+    pub source: String,
+    pub chiral: usize,
+    pub achiral: usize,
+    /// The number of shapes per symmetry group, by the name of the group.
+    pub symmetries: BTreeMap<String, usize>,
+    pub min_surface_area: Option<u32>,
+    pub max_surface_area: Option<u32>,
+}
+
+/// The result of `cache verify`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct VerifyOutput {
+    pub format: String,
+    pub records: u64,
+    pub valid: bool,
+    pub problems: Vec<ProblemOutput>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ProblemOutput {
+    /// The position of the record in the cache.
+    pub record: usize,
+    pub problem: String,
+}
+
+/// A shape described by `identify`, which prints a list of them.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct IdentifyOutput {
+    pub blocks: BlockCount,
+    pub code: String,
+    /// The name of the symmetry group.
+    pub symmetry: String,
+    pub chiral: bool,
+    /// The stable id from the ids file of the cache.
+    pub stable_id: Option<ShapeId>,
+    /// The position of the shape in the cache of its size.
+    pub cache_id: Option<ShapeId>,
+    pub cache_total: Option<u64>,
+    /// The orientation and translation mapping the given blocks onto the cached ones.
+    pub orientation: Option<Orientation>,
+    pub translation: Option<Point3D<i32>>,
+}
+
+/// A row of one of the tables of `report`, which prints a list of them.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ReportRow {
+    BoundingBox { n: BlockCount, bounding_box: String, shapes: usize },
+    Layers { n: BlockCount, layers: u32, shapes: usize },
+    /// `direct` is only counted for small n.
+    Fixed { n: BlockCount, free: usize, fixed: u64, direct: Option<u64> },
+    /// The shapes with a root block, see [rooted](crate::rooted).
+    Rooted { n: BlockCount, free: usize, rooted: u64 },
+}
+
+/// The moves found by `morph`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct MorphOutput {
+    pub steps: Vec<MorphStep>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct MorphStep {
+    /// The move leading to the shape, `null` for the source shape.
+    pub step: Option<String>,
+    pub code: String,
+}
+
+/// The graph printed by `graph`. Edges are given by the positions of their shapes in `nodes`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct GraphOutput {
+    pub nodes: Vec<String>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+/// The puzzle printed by `puzzle`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct PuzzleOutput {
+    pub seed: u64,
+    pub attempts: usize,
+    pub pieces: Vec<ShapeOutput>,
+    /// The cells covered by every piece, in the order of the pieces.
+    pub solution: Vec<Vec<Point3D<i32>>>,
+}
+
+/// The number of shapes of a level, printed as a list by `carve` and `replay`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct LevelOutput {
+    pub blocks: BlockCount,
+    pub shapes: usize,
+    /// The number of replayed insertions, `null` for `carve`.
+    pub insertions: Option<usize>,
+}
+
+/// The comparisons of `reference`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ReferenceOutput {
+    pub levels: Vec<ReferenceLevel>,
+    /// The number of levels that differ from the reference.
+    pub mismatches: usize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ReferenceLevel {
+    pub blocks: BlockCount,
+    pub algorithm: String,
+    pub found: usize,
+    pub reference: usize,
+    pub missing: usize,
+    pub unexpected: usize,
+    pub duplicates: usize,
+}
+
+/// The result of `estimate`. Every count is a statistical estimate, never an exact count.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EstimateOutput {
+    /// Always `true`, marks the counts as estimates.
+    pub estimate: bool,
+    pub samples: usize,
+    pub seed: u64,
+    pub levels: Vec<LevelEstimate>,
+}
+
+/// The outcome of `self-test`, which fails unless every check passed.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct SelfTestOutput {
+    pub passed: bool,
+    pub checks: Vec<CheckOutcome>,
+}
+
+#[cfg(test)]
+mod output_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::config::Algorithm;
+    use crate::run::RunReport;
+    use super::*;
+
+    #[test]
+    fn test_schema() {
+        let shape = ShapeOutput::from(&Polycube::new(&BlockArrangement::new()).unwrap());
+        assert_eq!(r#"{"code":"1x1x1:01","blocks":1}"#, serde_json::to_string(&shape).unwrap());
+        let row = ReportRow::Fixed { n: BlockCount::new(2), free: 1, fixed: 3, direct: None };
+        assert_eq!(r#"{"n":2,"free":1,"fixed":3,"direct":null}"#, serde_json::to_string(&row).unwrap());
+        let run = RunReport { n: BlockCount::new(3), algorithm: Algorithm::PolyTree, shapes: 2, accepted: None, levels: vec![], exports: vec![], seconds: 0.0, stopped: None };
+        let json: serde_json::Value = serde_json::to_value(&run).unwrap();
+        assert_eq!("poly-tree", json["algorithm"]);
+        assert!(json["stopped"].is_null());
+    }
+}
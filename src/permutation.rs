@@ -0,0 +1,82 @@
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+/// The number of Feistel rounds, enough for the permutation to look random.
+const ROUNDS: u32 = 6;
+
+/// A pseudo random permutation of the ids `0..len`, computed one id at a time so the permuted list is
+/// never held in memory. The same seed always results in the same permutation.
+///
+/// A balanced Feistel network permutes the smallest range of an even number of bits covering the ids,
+/// and ids permuted beyond `len` are permuted again until they fall inside, which keeps the result a
+/// permutation of `0..len` (cycle walking). Every step visits less than four ids on average.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Permutation {
+    len: u64,
+    half_bits: u32,
+    seed: u64,
+}
+
+impl Permutation {
+    pub fn new(len: u64, seed: u64) -> Self {
+        let bits = u64::BITS - len.saturating_sub(1).leading_zeros();
+        Self { len, half_bits: bits.div_ceil(2).max(1), seed }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The id at the position of the permuted order, `None` beyond the last position.
+    pub fn get(&self, position: u64) -> Option<u64> {
+        if position >= self.len {
+            return None;
+        }
+        let mut id = self.feistel(position);
+        while id >= self.len {
+            id = self.feistel(id);
+        }
+        Some(id)
+    }
+
+    /// The ids in their permuted order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len).map(|position| self.get(position).expect("The position is in range."))
+    }
+
+    fn feistel(&self, value: u64) -> u64 {
+        let mask = (1u64 << self.half_bits) - 1;
+        let (mut left, mut right) = (value >> self.half_bits, value & mask);
+        for round in 0..ROUNDS {
+            let mixed = xxh3_64_with_seed(&right.to_le_bytes(), self.seed ^ u64::from(round)) & mask;
+            (left, right) = (right, left ^ mixed);
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+#[cfg(test)]
+mod permutation_tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation() {
+        for len in [0, 1, 2, 3, 10, 64, 1000, 1025] {
+            let permutation = Permutation::new(len, 7);
+            let mut ids: Vec<_> = permutation.iter().collect();
+            assert_eq!(len as usize, ids.len());
+            ids.sort();
+            assert_eq!((0..len).collect::<Vec<_>>(), ids, "Permutation of {len} ids");
+        }
+        let permutation = Permutation::new(1000, 7);
+        assert_eq!(permutation.iter().collect::<Vec<_>>(), Permutation::new(1000, 7).iter().collect::<Vec<_>>());
+        assert_ne!(permutation.iter().collect::<Vec<_>>(), Permutation::new(1000, 8).iter().collect::<Vec<_>>());
+        assert_ne!((0..1000).collect::<Vec<_>>(), permutation.iter().collect::<Vec<_>>());
+        assert_eq!(None, permutation.get(1000));
+        let huge = Permutation::new(u64::MAX, 1);
+        assert!(huge.get(u64::MAX - 1).is_some());
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeSet;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_arrangement::row_masks::RowMasks;
+use crate::orientation::Orientation;
+use crate::point::Point3D;
+use crate::polycube::Chirality;
+use crate::symmetry::distinct_orientations;
+
+/// A set of cells pieces can be placed in.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Region {
+    cells: BTreeSet<Point3D<i32>>,
+}
+
+impl Region {
+    pub fn new(cells: impl IntoIterator<Item = Point3D<i32>>) -> Self {
+        Self { cells: cells.into_iter().collect() }
+    }
+
+    /// The box with the given width, depth and height whose minimal corner lies at the origin.
+    pub fn cuboid([width, depth, height]: [u32; 3]) -> Self {
+        let mut cells = BTreeSet::new();
+        for x in 0..width as i32 {
+            for y in 0..depth as i32 {
+                for z in 0..height as i32 {
+                    cells.insert(Point3D::new(x, y, z));
+                }
+            }
+        }
+        Self { cells }
+    }
+
+    pub fn contains(&self, p: &Point3D<i32>) -> bool {
+        self.cells.contains(p)
+    }
+
+    /// The cells in ascending order.
+    pub fn cells(&self) -> impl Iterator<Item = Point3D<i32>> + '_ {
+        self.cells.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/// A piece oriented and moved into a region.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Placement {
+    /// The orientation applied to the blocks of the piece before moving them.
+    pub orientation: Orientation,
+    pub offset: Point3D<i32>,
+    /// The covered cells in ascending order.
+    pub cells: Vec<Point3D<i32>>,
+}
+
+/// Every distinct placement of the piece inside the region using rotations only, as physical pieces can
+/// not be mirrored. See [placements_with].
+pub fn placements<'a>(piece: &BlockArrangement, region: &'a Region) -> impl Iterator<Item = Placement> + 'a {
+    placements_with(piece, region, Chirality::OneSided)
+}
+
+/// Every distinct placement of the piece inside the region, where mirror images are only used for
+/// [Chirality::Free]. Only one orientation of those that differ by a symmetry of the piece is used,
+/// see [distinct_orientations], so no two placements cover the same cells.
+/// The placements are ordered by orientation and then by their smallest cell.
+///
+/// The offsets are found by sliding the [RowMasks] of the piece over those of the region, unless the
+/// region is wider than [RowMasks::MAX_WIDTH] in which case every cell is tried as the position of the
+/// smallest block.
+pub fn placements_with<'a>(piece: &BlockArrangement, region: &'a Region, chirality: Chirality) -> impl Iterator<Item = Placement> + 'a {
+    let region_masks = RowMasks::new(region.cells());
+    let orientations: Vec<_> = distinct_orientations(piece, chirality).into_iter()
+        .map(|orientation| {
+            let mut blocks: Vec<_> = piece.block_iter()
+                .map(|mut p| {
+                    p.apply_orientation(&orientation);
+                    p
+                })
+                .collect();
+            blocks.sort();
+            (orientation, blocks)
+        })
+        .collect();
+    orientations.into_iter().flat_map(move |(orientation, blocks)| {
+        let piece_masks = RowMasks::new(blocks.iter().copied());
+        match (&region_masks, piece_masks) {
+            (Some(region_masks), Some(piece_masks)) => {
+                let mut offsets = piece_masks.translations_into(region_masks);
+                // Ordering the offsets orders the smallest cells, as the smallest block is the same for all.
+                offsets.sort();
+                offsets.into_iter()
+                    .map(|offset| Placement { orientation, offset, cells: blocks.iter().map(|p| *p + offset).collect() })
+                    .collect::<Vec<_>>()
+            }
+            _ => {
+                // The smallest block has to cover a cell, which determines the offset.
+                let anchor = blocks[0];
+                region.cells()
+                    .map(|cell| cell - anchor)
+                    .filter_map(|offset| {
+                        let cells: Vec<_> = blocks.iter().map(|p| *p + offset).collect();
+                        cells.iter().all(|p| region.contains(p))
+                            .then_some(Placement { orientation, offset, cells })
+                    })
+                    .collect::<Vec<_>>()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod placement_tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    fn piece(points: &[(i32, i32, i32)]) -> BlockArrangement {
+        let points: Vec<_> = points.iter().map(|(x, y, z)| Point3D::new(*x, *y, *z)).collect();
+        BlockArrangement::from_points(&points).unwrap()
+    }
+
+    #[test]
+    fn test_placements() {
+        let domino = piece(&[(0, 0, 0), (1, 0, 0)]);
+        // A domino lies along one of three axes and fits 2 * 3 * 3 ways along each of them.
+        assert_eq!(54, placements(&domino, &Region::cuboid([3, 3, 3])).count());
+        assert_eq!(1, placements(&domino, &Region::cuboid([2, 1, 1])).count());
+        assert_eq!(0, placements(&domino, &Region::cuboid([1, 1, 1])).count());
+
+        let l_tromino = piece(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)]);
+        let square = Region::cuboid([2, 2, 1]);
+        let l_placements: Vec<_> = placements(&l_tromino, &square).collect();
+        assert_eq!(4, l_placements.len());
+        let distinct: HashSet<_> = l_placements.iter().map(|placement| placement.cells.clone()).collect();
+        assert_eq!(4, distinct.len());
+        for placement in &l_placements {
+            let mut moved: Vec<_> = l_tromino.block_iter()
+                .map(|mut p| {
+                    p.apply_orientation(&placement.orientation);
+                    p + placement.offset
+                })
+                .collect();
+            moved.sort();
+            assert_eq!(placement.cells, moved);
+        }
+
+        let ring = Region::new([(0, 0, 0), (1, 0, 0), (2, 0, 0), (0, 1, 0), (2, 1, 0)].map(|(x, y, z)| Point3D::new(x, y, z)));
+        assert_eq!(2, placements(&l_tromino, &ring).count());
+        // Too wide for row masks, so every cell is tried as anchor.
+        let wide_ring = Region::new(ring.cells().chain([Point3D::new(70, 0, 0)]));
+        assert_eq!(2, placements(&l_tromino, &wide_ring).count());
+    }
+
+    #[test]
+    fn test_chirality() {
+        let twisted = piece(&[(0, 0, 0), (1, 0, 0), (1, 1, 0), (1, 1, 1)]);
+        let cube = Region::cuboid([2, 2, 2]);
+        let one_sided = placements(&twisted, &cube).count();
+        assert_eq!(2 * one_sided, placements_with(&twisted, &cube, Chirality::Free).count());
+    }
+
+    #[test]
+    fn test_symmetric_pieces() {
+        let cube = Region::cuboid([3, 3, 3]);
+        // Every cell once instead of once per orientation.
+        assert_eq!(27, placements(&BlockArrangement::new(), &cube).count());
+        let square = piece(&[(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0)]);
+        // Three planes, each with 2 * 2 positions in 3 layers.
+        assert_eq!(36, placements_with(&square, &cube, Chirality::Free).count());
+    }
+}
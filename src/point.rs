@@ -1,11 +1,13 @@
 use std::fmt::{Display, Formatter};
-use std::ops::{Add, Sub};
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Neg, Sub, SubAssign};
 use getset::{CopyGetters, Getters, MutGetters, Setters};
+#[cfg(feature = "decimal-metrics")]
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, IntoEnumIterator};
+use crate::orientation::*;
 
-#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Hash)]
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Hash, Ord, PartialOrd)]
 #[derive(Setters, MutGetters, Getters)]
 #[derive(Serialize, Deserialize)]
 pub struct Point3D<T> {
@@ -19,7 +21,6 @@ pub struct Point3D<T> {
 
 macro_rules! num_funcs_for_point {
     ($num_type:ty) => {
-        use crate::orientation::*;
         impl Point3D<$num_type> {
 
             /// Performs a clockwise 90 degree 2 dimensional rotation.
@@ -86,22 +87,18 @@ macro_rules! num_funcs_for_point {
             }
 
             pub fn mirror(&mut self, axis: Axis3D) {
-                match axis {
-                    Axis3D::X => {
-                        self.x = -self.x;
-                    }
-                    Axis3D::Y => {
-                        self.y = -self.y;
-                    }
-                    Axis3D::Z => {
-                        self.z = -self.z;
-                    }
-                }
+                self[axis] = -self[axis];
+            }
+
+            /// The sum of the absolute coordinates, i.e. the number of unit steps to the origin.
+            pub fn manhattan_norm(&self) -> $num_type {
+                self.x.abs() + self.y.abs() + self.z.abs()
             }
 
             /// Calculates the distance to the origin.
+            #[cfg(feature = "decimal-metrics")]
             pub fn distance_to_origin(&self) -> Decimal {
-                let square_sum = (self.x * self.x) + (self.y * self.y) + (self.z * self.z);
+                let square_sum = self.dot(*self);
                 let sqroot = f64::sqrt(square_sum as f64);
                 use rust_decimal::prelude::FromPrimitive;
                 Decimal::from_f64(sqroot).expect("This is a save conversion since the result of sqrt is expected to be save")
@@ -112,6 +109,14 @@ macro_rules! num_funcs_for_point {
 }
 
 num_funcs_for_point!(i32);
+// Allows transforming precise positions like centers of mass and mesh vertices the same way as blocks.
+num_funcs_for_point!(f64);
+
+impl From<Point3D<i32>> for Point3D<f64> {
+    fn from(value: Point3D<i32>) -> Self {
+        value.map_all(f64::from)
+    }
+}
 
 impl<T: Add<Output = T>> Add for Point3D<T> {
     type Output = Self;
@@ -137,6 +142,76 @@ impl<T: Sub<Output = T>> Sub for Point3D<T> {
     }
 }
 
+impl<T: AddAssign> AddAssign for Point3D<T> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.x += rhs.x;
+        self.y += rhs.y;
+        self.z += rhs.z;
+    }
+}
+
+impl<T: SubAssign> SubAssign for Point3D<T> {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+        self.z -= rhs.z;
+    }
+}
+
+impl<T> Index<Axis3D> for Point3D<T> {
+    type Output = T;
+
+    fn index(&self, axis: Axis3D) -> &Self::Output {
+        match axis {
+            Axis3D::X => &self.x,
+            Axis3D::Y => &self.y,
+            Axis3D::Z => &self.z,
+        }
+    }
+}
+
+impl<T> IndexMut<Axis3D> for Point3D<T> {
+    fn index_mut(&mut self, axis: Axis3D) -> &mut Self::Output {
+        match axis {
+            Axis3D::X => &mut self.x,
+            Axis3D::Y => &mut self.y,
+            Axis3D::Z => &mut self.z,
+        }
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for Point3D<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        self.map_all(|v| v * rhs)
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Point3D<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        self.map_all(|v| -v)
+    }
+}
+
+impl<T: Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Copy> Point3D<T> {
+
+    pub fn dot(&self, rhs: Self) -> T {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    /// The vector perpendicular to both vectors following the right hand rule.
+    pub fn cross(&self, rhs: Self) -> Self {
+        Self {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
+}
+
 impl<T: Display> Display for Point3D<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("({}, {}, {})", self.x, self.y, self.z))
@@ -184,7 +259,6 @@ pub enum Axis3D {
 
 #[cfg(test)]
 mod point_tests {
-    use crate::orientation::RotationAmount::TwoSeventy;
     use super::*;
 
     #[test]
@@ -239,67 +313,153 @@ mod point_tests {
         p_clone.apply_inverse_orientation(&orientation);
         assert_eq!(p, p_clone)
     }
+
+    #[test]
+    fn test_vector_arithmetic() {
+        let a = Point3D::new(1, -2, 3);
+        let b = Point3D::new(4, 0, -1);
+        assert_eq!(Point3D::new(2, -4, 6), a * 2);
+        assert_eq!(Point3D::new(-1, 2, -3), -a);
+        assert_eq!(1, a.dot(b));
+        assert_eq!(6, a.manhattan_norm());
+        let x = Point3D::new(1, 0, 0);
+        let y = Point3D::new(0, 1, 0);
+        assert_eq!(Point3D::new(0, 0, 1), x.cross(y));
+        assert_eq!(0, a.cross(b).dot(a));
+        assert_eq!(0, a.cross(b).dot(b));
+        assert_eq!(1.5, Point3D::new(0.5, -0.5, 0.5).manhattan_norm());
+    }
+
+    #[test]
+    fn test_assign_and_index() {
+        let mut p = Point3D::new(1, 2, 3);
+        p += Point3D::new(1, 1, 1);
+        assert_eq!(Point3D::new(2, 3, 4), p);
+        p -= Point3D::new(2, 0, 1);
+        assert_eq!(Point3D::new(0, 3, 3), p);
+        assert_eq!([0, 3, 3], [p[Axis3D::X], p[Axis3D::Y], p[Axis3D::Z]]);
+        p[Axis3D::Z] = -1;
+        assert_eq!(Point3D::new(0, 3, -1), p);
+    }
+
+    #[test]
+    fn test_f64_orientation() {
+        for orientation in OrientationIterator::default() {
+            let mut p = Point3D::new(1, -2, 3);
+            let mut precise = Point3D::<f64>::from(p);
+            p.apply_orientation(&orientation);
+            precise.apply_orientation(&orientation);
+            assert_eq!(Point3D::<f64>::from(p), precise);
+            precise.apply_inverse_orientation(&orientation);
+            assert_eq!(Point3D::new(1.0, -2.0, 3.0), precise);
+        }
+        let mut half = Point3D::new(0.5, 0.0, 0.0);
+        half.rotate(Axis3D::Z, RotationAmount::Ninety);
+        assert_eq!(Point3D::new(0.0, 0.5, 0.0), half);
+    }
 }
 
+/// How points beyond the extent of a [Finite3DDimension] are treated.
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
-#[derive(CopyGetters, Setters)]
+#[derive(Serialize, Deserialize)]
+pub enum Boundary {
+    /// Points outside of the dimension do not exist.
+    #[default]
+    Bounded,
+    /// The dimension is a unit cell of a periodic lattice, so leaving it on one side enters it on the
+    /// opposite side.
+    Periodic,
+}
+
+/// The lengths of a box of points along the x, y and z axis. The box starts at the origin, so its points
+/// have no negative coordinate and none as large as the length of its axis. A box anywhere else is
+/// described by its smallest corner together with its dimension, see [Finite3DDimension::fitting].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(CopyGetters)]
 #[derive(Serialize, Deserialize)]
 pub struct Finite3DDimension {
-    #[getset(get_copy = "pub", set = "pub")]
-    x_pos: u32,
-    #[getset(get_copy = "pub", set = "pub")]
-    x_neg: u32,
-    #[getset(get_copy = "pub", set = "pub")]
-    y_pos: u32,
-    #[getset(get_copy = "pub", set = "pub")]
-    y_neg: u32,
-    #[getset(get_copy = "pub", set = "pub")]
-    z_pos: u32,
-    #[getset(get_copy = "pub", set = "pub")]
-    z_neg: u32,
+    #[getset(get_copy = "pub")]
+    width: u32,
+    #[getset(get_copy = "pub")]
+    depth: u32,
+    #[getset(get_copy = "pub")]
+    height: u32,
+}
+
+impl Default for Finite3DDimension {
+    /// A single point.
+    fn default() -> Self {
+        Self::new(1, 1, 1)
+    }
 }
 
 impl Finite3DDimension {
-    /// Returns a new dimension.
-    /// Size specifies the length along the 3 axis away from the origin.
-    pub fn new(x_pos: u32, x_neg: u32, y_pos: u32, y_neg: u32, z_pos: u32, z_neg: u32) -> Self {
+    /// Returns a new dimension with the given lengths along the 3 axis, each of which is at least one.
+    pub fn new(width: u32, depth: u32, height: u32) -> Self {
         Self {
-            x_pos,
-            x_neg,
-            y_pos,
-            y_neg,
-            z_pos,
-            z_neg,
+            width: width.max(1),
+            depth: depth.max(1),
+            height: height.max(1),
         }
     }
 
+    /// The smallest corner and the dimension of the smallest box containing every point, `None` without points.
+    /// Lengths that would exceed a `u32` are cut off at its maximum.
+    pub fn fitting<'a>(points: impl IntoIterator<Item = &'a Point3D<i32>>) -> Option<(Point3D<i32>, Self)> {
+        let (min, max) = points.into_iter().fold(None, |bounds: Option<(Point3D<i32>, Point3D<i32>)>, p| {
+            let (min, max) = bounds.unwrap_or((*p, *p));
+            Some((
+                Point3D::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Point3D::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            ))
+        })?;
+        let len = |axis: Axis3D| u32::try_from(i64::from(max[axis]) - i64::from(min[axis]) + 1).unwrap_or(u32::MAX);
+        Some((min, Self::new(len(Axis3D::X), len(Axis3D::Y), len(Axis3D::Z))))
+    }
+
     /// The number of points contained in this dimension.
-    pub fn size(&self) -> u32 {
+    /// Fails if the points can not be indexed with a usize.
+    pub fn size(&self) -> Result<usize, DimensionError> {
         Axis3D::iter()
-            .map(|a| self.axis_len(a))
-            .product()
+            .try_fold(1u64, |size, a| size.checked_mul(self.axis_len(a)))
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or(DimensionError::TooLarge)
     }
 
-    pub fn axis_len(&self, axis: Axis3D) -> u32 {
-        let (pos, neg) = match axis {
-            Axis3D::X => {
-                (self.x_pos, self.x_neg)
-            }
-            Axis3D::Y => {
-                (self.y_pos, self.y_neg)
-            }
-            Axis3D::Z => {
-                (self.z_pos, self.z_neg)
-            }
-        };
-        pos + neg + 1
+    pub fn axis_len(&self, axis: Axis3D) -> u64 {
+        match axis {
+            Axis3D::X => self.width.into(),
+            Axis3D::Y => self.depth.into(),
+            Axis3D::Z => self.height.into(),
+        }
     }
 
     /// Returns the axis lengts for each of the three axis in order of x, y and z.
-    pub fn all_axis_len(&self) -> (u32, u32, u32) {
+    pub fn all_axis_len(&self) -> (u64, u64, u64) {
         (self.axis_len(Axis3D::X), self.axis_len(Axis3D::Y), self.axis_len(Axis3D::Z))
     }
 
+    /// The smallest corner and the dimension of the smallest box containing both this box, which starts at
+    /// `corner`, and the other box starting at `other_corner`.
+    /// Fails if a length of the box would exceed a `u32`.
+    pub fn union(&self, corner: Point3D<i32>, other: &Self, other_corner: Point3D<i32>) -> Result<(Point3D<i32>, Self), DimensionError> {
+        let mut min = corner;
+        let mut lens = [0; 3];
+        for (i, axis) in Axis3D::iter().enumerate() {
+            let end = |corner: Point3D<i32>, dim: &Self| i64::from(corner[axis]) + dim.axis_len(axis) as i64;
+            min[axis] = corner[axis].min(other_corner[axis]);
+            let len = end(corner, self).max(end(other_corner, other)) - i64::from(min[axis]);
+            lens[i] = u32::try_from(len).map_err(|_| DimensionError::TooLarge)?;
+        }
+        Ok((min, Self::new(lens[0], lens[1], lens[2])))
+    }
+
+    /// The smallest corner and the dimension of the smallest box containing both this box, which starts at
+    /// `corner`, and the point. Fails like [Finite3DDimension::union].
+    pub fn expand_to_include(&self, corner: Point3D<i32>, p: &Point3D<i32>) -> Result<(Point3D<i32>, Self), DimensionError> {
+        self.union(corner, &Self::default(), *p)
+    }
+
     /// Checks if the given point is in bounds inside this dimension.
     /// The default Point will always be inside this dimension.
     pub fn in_bounds(&self, p: &Point3D<i32>) -> bool {
@@ -309,34 +469,100 @@ impl Finite3DDimension {
     /// Checks if the given point is in bounds inside the specified [Axis3D].
     /// The default Point will always be inside this dimension.
     pub fn dim_in_bounds(&self, p: &Point3D<i32>, axis: Axis3D) -> bool {
-        match axis {
-            Axis3D::X => {
-                -(self.x_neg as i32) <= *p.x() && *p.x() <= self.x_pos as i32
-            }
-            Axis3D::Y => {
-                -(self.y_neg as i32) <= *p.y() && *p.y() <= self.y_pos as i32
-            }
-            Axis3D::Z => {
-                -(self.z_neg as i32) <= *p.z() && *p.z() <= self.z_pos as i32
-            }
+        (0..self.axis_len(axis) as i64).contains(&i64::from(p[axis]))
+    }
+
+    /// Moves the point into this dimension by wrapping it around every axis,
+    /// treating the dimension as a periodic unit cell.
+    pub fn wrap(&self, p: &Point3D<i32>) -> Point3D<i32> {
+        let mut wrapped = *p;
+        for axis in Axis3D::iter() {
+            wrapped[axis] = (p[axis] as i64).rem_euclid(self.axis_len(axis) as i64) as i32;
         }
+        wrapped
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DimensionError {
+    /// The dimension contains more points than can be addressed.
+    TooLarge,
+}
+
+impl Display for DimensionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DimensionError::TooLarge => f.write_str("The dimension contains more points than can be addressed"),
+        }
+    }
+}
+
+impl std::error::Error for DimensionError {}
+
 #[cfg(test)]
 mod dimension_tests {
     use super::*;
 
     #[test]
     fn test_in_bounds() {
-        let dim = Finite3DDimension::new(3,3,3,3,3,3);
-        for x in -3..4 {
-            for y in -3..4 {
-                for z in -3..4 {
-                    let p = Point3D::new(x,y,z);
-                    assert!(dim.in_bounds(&p), "In bounds check failed at point {p}")
+        let dim = Finite3DDimension::new(7, 7, 7);
+        for x in -1..8 {
+            for y in 0..7 {
+                for z in 0..7 {
+                    let p = Point3D::new(x, y, z);
+                    assert_eq!((0..7).contains(&x), dim.in_bounds(&p), "In bounds check failed at point {p}")
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fitting() {
+        assert_eq!(None, Finite3DDimension::fitting(&[]));
+        let points = [Point3D::new(2, -3, 0), Point3D::new(1, -1, 0)];
+        assert_eq!(Some((Point3D::new(1, -3, 0), Finite3DDimension::new(2, 3, 1))), Finite3DDimension::fitting(&points));
+        let (_, huge) = Finite3DDimension::fitting(&[Point3D::new(i32::MIN, 0, 0), Point3D::new(i32::MAX, 0, 0)]).unwrap();
+        assert_eq!(u32::MAX, huge.width());
+    }
+
+    #[test]
+    fn test_expand_to_include() {
+        let (corner, dim) = Finite3DDimension::default().expand_to_include(Point3D::new(0, 0, 0), &Point3D::new(2, -3, 0)).unwrap();
+        assert_eq!((Point3D::new(0, -3, 0), Finite3DDimension::new(3, 4, 1)), (corner, dim));
+        assert_eq!(Ok((corner, dim)), dim.expand_to_include(corner, &Point3D::new(1, -1, 0)));
+        let (corner, dim) = dim.expand_to_include(corner, &Point3D::new(-1, 0, 2)).unwrap();
+        assert_eq!((Point3D::new(-1, -3, 0), Finite3DDimension::new(4, 4, 3)), (corner, dim));
+        assert!(dim.in_bounds(&(Point3D::new(2, -3, 0) - corner)));
+        assert_eq!(Finite3DDimension::new(1, 1, 1), Finite3DDimension::new(0, 0, 0));
+    }
+
+    #[test]
+    fn test_union() {
+        let a = (Point3D::new(0, -2, 0), Finite3DDimension::new(2, 3, 4));
+        let b = (Point3D::new(-4, 0, 0), Finite3DDimension::new(1, 2, 1));
+        let union = (Point3D::new(-4, -2, 0), Finite3DDimension::new(6, 4, 4));
+        assert_eq!(Ok(union), a.1.union(a.0, &b.1, b.0));
+        assert_eq!(Ok(union), b.1.union(b.0, &a.1, a.0));
+        let fitted = Finite3DDimension::fitting(&[a.0, Point3D::new(1, 1, 3), b.0]).unwrap();
+        assert_eq!(fitted, union);
+        let far = Point3D::new(i32::MAX, 0, 0);
+        assert_eq!(Err(DimensionError::TooLarge), a.1.union(Point3D::new(i32::MIN, 0, 0), &b.1, far));
+    }
+
+    #[test]
+    fn test_size_overflow() {
+        assert_eq!(Ok(1), Finite3DDimension::default().size());
+        let huge = Finite3DDimension::new(u32::MAX, u32::MAX, u32::MAX);
+        assert_eq!(u32::MAX as u64, huge.axis_len(Axis3D::X));
+        assert_eq!(Err(DimensionError::TooLarge), huge.size());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let dim = Finite3DDimension::new(4, 1, 3);
+        assert_eq!(Point3D::new(1, 0, 2), dim.wrap(&Point3D::new(1, 0, 2)));
+        assert_eq!(Point3D::new(3, 0, 0), dim.wrap(&Point3D::new(-1, 1, 3)));
+        assert_eq!(Point3D::new(2, 0, 1), dim.wrap(&Point3D::new(-2, -5, -2)));
+        assert!(dim.in_bounds(&dim.wrap(&Point3D::new(-100, 100, 7))));
+    }
+}
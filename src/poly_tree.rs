@@ -0,0 +1,727 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::hash::{Hash, Hasher};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+use crate::block_arrangement::{BlockArrangement, PlacementError};
+use crate::block_count::BlockCount;
+use crate::polycube::{CanonicalForms, Polycube};
+use crate::point::Point3D;
+use crate::poly_tree::trace::ExpansionEvent;
+
+pub mod trace;
+
+/// Identifies a node inside a [PolyTree].
+pub type NodeId = usize;
+
+/// A canonical shape inside a [PolyTree].
+/// Only the difference to the parent shape is stored, the shape itself is reconstructed on demand.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(CopyGetters, Getters)]
+pub struct PolyTreeNode {
+    /// The shape with one block less this shape was first created from.
+    /// Only the root has no parent.
+    #[get_copy = "pub"]
+    parent: Option<NodeId>,
+    /// The block added to the canonical shape of the parent, in the coordinates of the parent.
+    #[get_copy = "pub"]
+    added_block: Point3D<i32>,
+    /// Every other shape this shape can be created from by adding one block, sorted by id.
+    #[get = "pub"]
+    other_parents: Vec<NodeId>,
+    /// The shapes that can be created by adding a block to this shape, sorted by id.
+    #[get = "pub"]
+    children: Vec<NodeId>,
+}
+
+impl PolyTreeNode {
+    /// Iterates over every shape this shape can be created from, starting with [PolyTreeNode::parent].
+    pub fn parents(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.parent.iter().chain(&self.other_parents).copied()
+    }
+}
+
+/// Stores canonical shapes of every block count together with their lineage.
+/// Every shape records one canonical parent with one block less and the block that was added to it,
+/// which makes the tree both a compact storage and a record of how the shapes were grown.
+/// A shape that can be grown from multiple parents is stored once, with an edge from every parent.
+#[derive(Debug, Clone)]
+pub struct PolyTree {
+    nodes: Vec<PolyTreeNode>,
+    /// The node ids grouped by the number of blocks of their shape, starting at one block.
+    levels: Vec<Vec<NodeId>>,
+    /// Maps the code of every canonical shape to its node.
+    /// Built on first use, since it requires reconstructing every shape.
+    index: OnceLock<HashMap<String, NodeId>>,
+    /// The number of one sided shapes of every level, counted while the added shapes are canonicalized.
+    /// `None` for the levels of a tree that was read, whose shapes are only reconstructed on demand.
+    one_sided: Vec<Option<usize>>,
+}
+
+/// The on disk representation of a non root [PolyTreeNode].
+/// Children and levels are implied by the parents, so only the delta to the parent is stored.
+#[derive(Serialize, Deserialize)]
+struct NodeDelta {
+    parent: NodeId,
+    added_block: Point3D<i32>,
+    other_parents: Vec<NodeId>,
+}
+
+/// The on disk representation of a [PolyTree].
+#[derive(Serialize, Deserialize)]
+struct PolyTreeFile {
+    version: u8,
+    /// Every node except the root, ordered by id.
+    nodes: Vec<NodeDelta>,
+}
+
+/// A child found by a [LevelInserter] that is not yet part of the tree.
+#[derive(Debug)]
+struct ChildCandidate {
+    parent: NodeId,
+    added_block: Point3D<i32>,
+    num_blocks: BlockCount,
+    /// The number of one sided shapes the shape stands for.
+    one_sided: usize,
+    /// Every parent creating the shape, including the kept one.
+    parents: BTreeSet<NodeId>,
+}
+
+/// Collects new shapes from multiple threads at once before they are added to a [PolyTree]
+/// with [PolyTree::commit_level].
+/// The shapes are distributed over independently locked shards by their code, so threads
+/// inserting different shapes rarely wait on each other.
+#[derive(Debug)]
+pub struct LevelInserter {
+    shards: Vec<Mutex<HashMap<String, ChildCandidate>>>,
+    /// Every insertion in the order the shards were modified, if recording.
+    events: Option<Mutex<Vec<ExpansionEvent>>>,
+}
+
+impl LevelInserter {
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            shards: (0..shard_count.max(1)).map(|_| Mutex::default()).collect(),
+            events: None,
+        }
+    }
+
+    /// Records every insertion, see [LevelInserter::take_events].
+    pub fn with_recording(mut self) -> Self {
+        self.events = Some(Mutex::default());
+        self
+    }
+
+    /// The insertions recorded so far, in the order they modified the shards.
+    pub fn take_events(&mut self) -> Vec<ExpansionEvent> {
+        self.events.as_mut()
+            .map(|events| std::mem::take(events.get_mut().expect("No thread panicked while holding the lock.")))
+            .unwrap_or_default()
+    }
+
+    /// Records the shape created by adding the block to the parent.
+    /// If multiple parents create the same shape, the smallest parent id and added block is kept
+    /// so that the result does not depend on the order of insertion.
+    pub fn insert(&self, parent: NodeId, parent_shape: &Polycube, added_block: Point3D<i32>) -> Result<(), PlacementError> {
+        let (child, one_sided) = PolyTree::child_of(parent_shape, added_block)?;
+        let code = child.code();
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        let shard = &self.shards[(hasher.finish() % self.shards.len() as u64) as usize];
+        let mut shard = shard.lock().expect("No thread panicked while holding the lock.");
+        // Recorded while holding the shard so the order matches the order of the modifications.
+        if let Some(events) = &self.events {
+            events.lock().expect("No thread panicked while holding the lock.").push(ExpansionEvent { parent, added_block });
+        }
+        match shard.entry(code) {
+            Entry::Occupied(mut entry) => {
+                let candidate = entry.get_mut();
+                candidate.parents.insert(parent);
+                if (parent, added_block) < (candidate.parent, candidate.added_block) {
+                    candidate.parent = parent;
+                    candidate.added_block = added_block;
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(ChildCandidate {
+                    parent,
+                    added_block,
+                    num_blocks: child.num_blocks(),
+                    one_sided,
+                    parents: BTreeSet::from([parent]),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PolyTree {
+    /// Creates a tree containing only the shape with a single block.
+    fn default() -> Self {
+        let root = Polycube::new(&BlockArrangement::new())
+            .expect("A single block is always connected.");
+        Self {
+            nodes: vec![PolyTreeNode {
+                parent: None,
+                added_block: Point3D::default(),
+                other_parents: vec![],
+                children: vec![],
+            }],
+            levels: vec![vec![Self::ROOT]],
+            index: OnceLock::from(HashMap::from([(root.code(), Self::ROOT)])),
+            one_sided: vec![Some(1)],
+        }
+    }
+}
+
+impl PolyTree {
+
+    /// The id of the single block shape every other shape descends from.
+    pub const ROOT: NodeId = 0;
+
+    /// The version of the format written by [PolyTree::write_to].
+    const FORMAT_VERSION: u8 = 2;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of shapes in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The largest block count of any shape in the tree.
+    pub fn depth(&self) -> BlockCount {
+        BlockCount::try_from(self.levels.len()).expect("Every level holds shapes with a valid block count.")
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&PolyTreeNode> {
+        self.nodes.get(id)
+    }
+
+    /// The ids of all shapes with the given number of blocks.
+    pub fn level(&self, num_blocks: BlockCount) -> &[NodeId] {
+        num_blocks.as_usize().checked_sub(1)
+            .and_then(|i| self.levels.get(i))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Adds the shape created by placing a block at the given point of the parents canonical shape.
+    /// If the resulting shape is already part of the tree its existing id is returned and
+    /// the parent is only recorded as an additional parent, so every shape keeps the first parent
+    /// it was found from for its reconstruction.
+    pub fn insert(&mut self, parent: NodeId, added_block: Point3D<i32>) -> Result<NodeId, PlacementError> {
+        let parent_shape = self.shape(parent);
+        self.insert_child(parent, &parent_shape, added_block)
+    }
+
+    /// Expands every shape with the most blocks by one block in every possible way and adds
+    /// the new shapes as a new level. Returns the number of added shapes.
+    /// The work is spread over all available cores.
+    pub fn generate_next_level(&mut self) -> usize {
+        let threads = thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+        self.generate_next_level_with_threads(threads)
+    }
+
+    /// Like [PolyTree::generate_next_level] but with the given number of threads.
+    /// The resulting tree is the same for any number of threads.
+    pub fn generate_next_level_with_threads(&mut self, threads: usize) -> usize {
+        let inserter = self.expand_level(threads, LevelInserter::new(threads * 4));
+        self.commit_level(inserter)
+    }
+
+    /// Inserts every child of the shapes with the most blocks into the inserter using the given number of threads.
+    fn expand_level(&self, threads: usize, inserter: LevelInserter) -> LevelInserter {
+        let parents = self.level(self.depth());
+        let chunk_size = parents.len().div_ceil(threads.max(1)).max(1);
+        thread::scope(|scope| {
+            for chunk in parents.chunks(chunk_size) {
+                let inserter = &inserter;
+                scope.spawn(move || {
+                    for parent in chunk {
+                        let parent_shape = self.shape(*parent);
+                        for added_block in parent_shape.free_neighbors() {
+                            inserter.insert(*parent, &parent_shape, added_block)
+                                .expect("Free neighbors can always be added.");
+                        }
+                    }
+                });
+            }
+        });
+        inserter
+    }
+
+    /// Adds the shapes collected by the inserter that are not yet part of the tree.
+    /// Returns the number of added shapes.
+    pub fn commit_level(&mut self, inserter: LevelInserter) -> usize {
+        let mut candidates: Vec<_> = inserter.shards.into_iter()
+            .flat_map(|shard| shard.into_inner().expect("No thread panicked while holding the lock."))
+            .collect();
+        // Sorting by lineage assigns the same ids as inserting the children one by one.
+        candidates.sort_by_key(|(_, candidate)| (candidate.parent, candidate.added_block));
+        let previous_len = self.len();
+        for (code, candidate) in candidates {
+            let id = match self.index().get(&code) {
+                Some(id) => *id,
+                None => self.push_node(candidate.parent, candidate.added_block, candidate.num_blocks, candidate.one_sided, code),
+            };
+            for parent in candidate.parents {
+                self.add_edge(parent, id);
+            }
+        }
+        self.len() - previous_len
+    }
+
+    /// Inserts the child of an already reconstructed parent shape.
+    fn insert_child(&mut self, parent: NodeId, parent_shape: &Polycube, added_block: Point3D<i32>) -> Result<NodeId, PlacementError> {
+        let (child, one_sided) = Self::child_of(parent_shape, added_block)?;
+        let code = child.code();
+        if let Some(id) = self.index().get(&code).copied() {
+            self.add_edge(parent, id);
+            return Ok(id);
+        }
+        Ok(self.push_node(parent, added_block, child.num_blocks(), one_sided, code))
+    }
+
+    /// Records that the child can be created from the parent, unless that is already known.
+    fn add_edge(&mut self, parent: NodeId, child: NodeId) {
+        if self.nodes[child].parent == Some(parent) {
+            return;
+        }
+        if let Err(pos) = self.nodes[child].other_parents.binary_search(&parent) {
+            self.nodes[child].other_parents.insert(pos, parent);
+        }
+        if let Err(pos) = self.nodes[parent].children.binary_search(&child) {
+            self.nodes[parent].children.insert(pos, child);
+        }
+    }
+
+    /// Creates the canonical shape resulting from adding the block to the parent together with the
+    /// number of one sided shapes it stands for.
+    fn child_of(parent_shape: &Polycube, added_block: Point3D<i32>) -> Result<(Polycube, usize), PlacementError> {
+        let mut arrangement = parent_shape.clone().into_inner();
+        if arrangement.is_set(&added_block) {
+            return Err(PlacementError::Occupied);
+        }
+        arrangement.add_block_at(&added_block)?;
+        Ok(Polycube::with_one_sided_count(&arrangement).expect("Adding an adjacent block keeps the shape connected."))
+    }
+
+    fn push_node(&mut self, parent: NodeId, added_block: Point3D<i32>, num_blocks: BlockCount, one_sided: usize, code: String) -> NodeId {
+        self.index();
+        let id = self.nodes.len();
+        self.nodes.push(PolyTreeNode {
+            parent: Some(parent),
+            added_block,
+            other_parents: vec![],
+            children: vec![],
+        });
+        self.nodes[parent].children.push(id);
+        let level = num_blocks.as_usize() - 1;
+        if self.levels.len() <= level {
+            self.levels.resize_with(level + 1, Vec::new);
+            self.one_sided.resize(level + 1, Some(0));
+        }
+        self.levels[level].push(id);
+        if let Some(count) = &mut self.one_sided[level] {
+            *count += one_sided;
+        }
+        self.index.get_mut()
+            .expect("The index was initialized above.")
+            .insert(code, id);
+        id
+    }
+
+    /// Looks up the node of the given shape.
+    pub fn find(&self, polycube: &Polycube) -> Option<NodeId> {
+        self.index().get(&polycube.code()).copied()
+    }
+
+    fn index(&self) -> &HashMap<String, NodeId> {
+        self.index.get_or_init(|| {
+            let mut index = HashMap::with_capacity(self.nodes.len());
+            let mut parent_shapes = HashMap::from([(Self::ROOT, Self::root_shape())]);
+            index.insert(Self::root_shape().code(), Self::ROOT);
+            for level in self.levels.iter().skip(1) {
+                let shapes: HashMap<_, _> = level.iter()
+                    .map(|id| (*id, self.child_shape(&parent_shapes, *id)))
+                    .collect();
+                index.extend(shapes.iter().map(|(id, shape)| (shape.code(), *id)));
+                parent_shapes = shapes;
+            }
+            index
+        })
+    }
+
+    /// Reconstructs the shape of a node from the already reconstructed shape of its parent.
+    fn child_shape(&self, parent_shapes: &HashMap<NodeId, Polycube>, id: NodeId) -> Polycube {
+        let node = &self.nodes[id];
+        let parent = node.parent.expect("Only the root has no parent.");
+        let mut arrangement = parent_shapes[&parent].clone().into_inner();
+        arrangement.add_block_at(&node.added_block)
+            .expect("Stored blocks are checked to be free and adjacent to their parent.");
+        Polycube::new(&arrangement).expect("Stored shapes are connected.")
+    }
+
+    /// Writes the tree in a compact format storing only the parents and the added block of every shape.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        let file = PolyTreeFile {
+            version: Self::FORMAT_VERSION,
+            nodes: self.nodes.iter()
+                .skip(1)
+                .map(|node| NodeDelta {
+                    parent: node.parent.expect("Only the root has no parent."),
+                    added_block: node.added_block,
+                    other_parents: node.other_parents.clone(),
+                })
+                .collect(),
+        };
+        bincode::serde::encode_into_std_write(file, writer, bincode::config::standard())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(())
+    }
+
+    /// Reads a tree written by [PolyTree::write_to].
+    /// Shapes are not reconstructed while loading, only once they or the index are accessed. Every stored
+    /// block is checked against the shape of its parent, which is replayed along its chain of parents only.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Error> {
+        let file: PolyTreeFile = bincode::serde::decode_from_std_read(reader, bincode::config::standard())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if file.version != Self::FORMAT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported poly tree version {}", file.version)));
+        }
+        let mut tree = Self::new();
+        let mut node_levels = vec![0];
+        for (index, delta) in file.nodes.into_iter().enumerate() {
+            let id = index + 1;
+            if let Some(parent) = delta.other_parents.iter().chain([&delta.parent]).find(|parent| **parent >= id) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Node {id} references the later node {parent} as parent")));
+            }
+            let level = node_levels[delta.parent] + 1;
+            if let Some(parent) = delta.other_parents.iter().find(|parent| node_levels[**parent] + 1 != level) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Node {id} references node {parent} of another level as parent")));
+            }
+            let parent_shape = tree.shape(delta.parent).into_inner();
+            if parent_shape.is_set(&delta.added_block) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Node {id} adds the occupied block {} to its parent", delta.added_block)));
+            }
+            if !parent_shape.free_neighbors().contains(&delta.added_block) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Node {id} adds the block {} not adjacent to its parent", delta.added_block)));
+            }
+            node_levels.push(level);
+            tree.nodes.push(PolyTreeNode {
+                parent: Some(delta.parent),
+                added_block: delta.added_block,
+                other_parents: vec![],
+                children: vec![],
+            });
+            tree.nodes[delta.parent].children.push(id);
+            for parent in delta.other_parents {
+                tree.add_edge(parent, id);
+            }
+            if tree.levels.len() <= level {
+                tree.levels.resize_with(level + 1, Vec::new);
+            }
+            tree.levels[level].push(id);
+        }
+        tree.index = OnceLock::new();
+        tree.one_sided = vec![None; tree.levels.len()];
+        Ok(tree)
+    }
+
+    /// Reconstructs the canonical shape of the node by replaying the added blocks from the root.
+    /// Panics if the id is not part of the tree.
+    pub fn shape(&self, id: NodeId) -> Polycube {
+        let mut path = self.ancestors(id);
+        path.reverse();
+        path.push(id);
+        path.iter()
+            .skip(1)
+            .fold(Self::root_shape(), |shape, id| {
+                let mut arrangement = shape.into_inner();
+                arrangement.add_block_at(&self.nodes[*id].added_block)
+                    .expect("Stored blocks are checked to be free and adjacent to their parent.");
+                Polycube::new(&arrangement).expect("Stored shapes are connected.")
+            })
+    }
+
+    /// Iterates over the shapes with the given number of blocks.
+    /// Every shape is only reconstructed once the iterator reaches it.
+    pub fn iter_level(&self, num_blocks: BlockCount) -> impl Iterator<Item = BlockArrangement> + '_ {
+        self.level(num_blocks).iter()
+            .map(|id| self.shape(*id).into_inner())
+    }
+
+    /// The number of shapes with the given number of blocks when mirror images are considered distinct.
+    /// It is counted while the level is generated, only the shapes of levels that were read are
+    /// reconstructed for it.
+    pub fn one_sided_count(&self, num_blocks: BlockCount) -> usize {
+        let counted = num_blocks.as_usize().checked_sub(1).and_then(|i| self.one_sided.get(i).copied().flatten());
+        counted.unwrap_or_else(|| self.iter_level(num_blocks)
+            .map(|ba| CanonicalForms::of(&ba).one_sided_count())
+            .sum())
+    }
+
+    /// Iterates over every shape of the tree ordered by block count.
+    pub fn iter_all(&self) -> impl Iterator<Item = BlockArrangement> + '_ {
+        BlockCount::ONE.up_to(self.depth()).flat_map(|num_blocks| self.iter_level(num_blocks))
+    }
+
+    /// Iterates over the shapes without a parent, which every other shape descends from.
+    pub fn roots(&self) -> impl Iterator<Item = BlockArrangement> + '_ {
+        self.nodes.iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(id, _)| self.shape(id).into_inner())
+    }
+
+    /// Returns the ids of all parents of the node, starting with the direct parent and ending
+    /// with the root.
+    pub fn ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        std::iter::successors(self.nodes[id].parent, |parent| self.nodes[*parent].parent)
+            .collect()
+    }
+
+    /// Returns the ids of every shape that can be grown from the node, ordered by block count.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = vec![];
+        let mut visited = HashSet::from([id]);
+        let mut queue = VecDeque::from([id]);
+        while let Some(next) = queue.pop_front() {
+            for child in &self.nodes[next].children {
+                if visited.insert(*child) {
+                    descendants.push(*child);
+                    queue.push_back(*child);
+                }
+            }
+        }
+        descendants
+    }
+
+    fn root_shape() -> Polycube {
+        Polycube::new(&BlockArrangement::new()).expect("A single block is always connected.")
+    }
+}
+
+#[cfg(test)]
+mod poly_tree_tests {
+    use super::*;
+
+    /// Builds a tree with every shape of up to the given number of blocks.
+    fn full_tree(num_blocks: u32) -> PolyTree {
+        let mut tree = PolyTree::new();
+        for n in BlockCount::ONE.up_to(BlockCount::new(num_blocks - 1)) {
+            for parent in tree.level(n).to_vec() {
+                let shape = tree.shape(parent);
+                let candidates: Vec<_> = shape.block_iter()
+                    .flat_map(|p| BlockArrangement::NEIGHBOR_OFFSETS.map(|o| o + p))
+                    .filter(|p| !shape.is_set(p))
+                    .collect();
+                for p in candidates {
+                    tree.insert(parent, p).expect("Free neighboring position");
+                }
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn test_level_sizes() {
+        let tree = full_tree(5);
+        let sizes: Vec<_> = BlockCount::ONE.up_to(BlockCount::new(5)).map(|n| tree.level(n).len()).collect();
+        assert_eq!(vec![1, 1, 2, 7, 23], sizes);
+        assert_eq!(34, tree.len());
+    }
+
+    #[test]
+    fn test_lineage() {
+        let tree = full_tree(4);
+        for id in tree.level(BlockCount::new(4)) {
+            let shape = tree.shape(*id);
+            assert_eq!(BlockCount::new(4), shape.num_blocks());
+            assert_eq!(Some(*id), tree.find(&shape));
+            let ancestors = tree.ancestors(*id);
+            assert_eq!(3, ancestors.len());
+            assert_eq!(Some(&PolyTree::ROOT), ancestors.last());
+            assert!(tree.descendants(ancestors[0]).contains(id));
+        }
+        assert_eq!(tree.len() - 1, tree.descendants(PolyTree::ROOT).len());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let tree = full_tree(5);
+        let level: Vec<_> = tree.iter_level(BlockCount::new(4)).collect();
+        assert_eq!(7, level.len());
+        assert!(level.iter().all(|ba| ba.num_blocks() == BlockCount::new(4)));
+        assert_eq!(0, tree.iter_level(BlockCount::new(6)).count());
+        assert_eq!(0, tree.iter_level(BlockCount::new(0)).count());
+        assert_eq!(tree.len(), tree.iter_all().count());
+        let roots: Vec<_> = tree.roots().collect();
+        assert_eq!(vec![BlockArrangement::new()], roots);
+    }
+
+    #[test]
+    fn test_generate_next_level() {
+        let mut tree = PolyTree::new();
+        let added: Vec<_> = (0..5).map(|_| tree.generate_next_level()).collect();
+        assert_eq!(vec![1, 2, 7, 23, 112], added);
+        assert_eq!(BlockCount::new(6), tree.depth());
+        let built = full_tree(5);
+        for n in BlockCount::ONE.up_to(BlockCount::new(5)) {
+            assert_eq!(built.level(n), tree.level(n));
+        }
+    }
+
+    #[test]
+    fn test_one_sided_count() {
+        let tree = full_tree(5);
+        let counts: Vec<_> = BlockCount::ONE.up_to(BlockCount::new(5)).map(|n| tree.one_sided_count(n)).collect();
+        assert_eq!(vec![1, 1, 2, 8, 29], counts);
+        // The levels of a read tree are counted from their shapes instead.
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).expect("Writing to memory");
+        let loaded = PolyTree::read_from(&mut &bytes[..]).expect("Reading valid data");
+        assert_eq!(vec![None; 5], loaded.one_sided);
+        assert_eq!(counts, BlockCount::ONE.up_to(BlockCount::new(5)).map(|n| loaded.one_sided_count(n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_thread_count_independence() {
+        let mut single = PolyTree::new();
+        let mut multi = PolyTree::new();
+        for _ in 0..5 {
+            single.generate_next_level_with_threads(1);
+            multi.generate_next_level_with_threads(7);
+        }
+        assert_eq!(single.len(), multi.len());
+        for id in 0..single.len() {
+            assert_eq!(single.node(id), multi.node(id));
+        }
+    }
+
+    #[test]
+    fn test_concurrent_inserter() {
+        let tree = PolyTree::new();
+        let inserter = LevelInserter::new(2);
+        let root_shape = tree.shape(PolyTree::ROOT);
+        thread::scope(|scope| {
+            for p in root_shape.free_neighbors() {
+                let (inserter, root_shape) = (&inserter, &root_shape);
+                scope.spawn(move || inserter.insert(PolyTree::ROOT, root_shape, p).expect("Free neighbor"));
+            }
+        });
+        let mut tree = tree;
+        assert_eq!(1, tree.commit_level(inserter));
+        let child = tree.level(BlockCount::new(2))[0];
+        assert_eq!(Some(root_shape.free_neighbors()[0]), tree.node(child).map(PolyTreeNode::added_block));
+    }
+
+    #[test]
+    fn test_shared_children() {
+        let mut tree = PolyTree::new();
+        tree.generate_next_level();
+        let domino = tree.level(BlockCount::new(2))[0];
+        tree.insert(domino, Point3D::new(0, 0, 2)).expect("Free position");
+        tree.insert(domino, Point3D::new(0, 1, 0)).expect("Free position");
+        let (line, corner) = (tree.level(BlockCount::new(3))[0], tree.level(BlockCount::new(3))[1]);
+        let l_shape = Polycube::new(&BlockArrangement::from_points(&[
+            Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(2, 0, 0), Point3D::new(2, 1, 0),
+        ]).expect("Connected points")).expect("Connected shape");
+        let growing_block = |parent: NodeId| {
+            let shape = tree.shape(parent);
+            shape.free_neighbors().into_iter()
+                .find(|p| PolyTree::child_of(&shape, *p).is_ok_and(|(child, _)| child == l_shape))
+                .expect("Both trominoes can grow into the L shape")
+        };
+        let (line_block, corner_block) = (growing_block(line), growing_block(corner));
+        // Growing the line at its end sideways and the corner at its end straight both create
+        // the same L shaped tetracube.
+        let from_line = tree.insert(line, line_block).expect("Free position");
+        let from_corner = tree.insert(corner, corner_block).expect("Free position");
+        assert_eq!(from_line, from_corner);
+        assert_eq!(1, tree.level(BlockCount::new(4)).len());
+        let node = tree.node(from_line).expect("Inserted node");
+        assert_eq!(vec![line, corner], node.parents().collect::<Vec<_>>());
+        assert_eq!(&vec![from_line], tree.node(line).expect("Inserted node").children());
+        assert_eq!(&vec![from_line], tree.node(corner).expect("Inserted node").children());
+        assert_eq!(vec![line, corner, from_line], tree.descendants(domino));
+    }
+
+    #[test]
+    fn test_shared_children_in_generated_levels() {
+        let mut tree = PolyTree::new();
+        (0..4).for_each(|_| { tree.generate_next_level(); });
+        let edges: usize = tree.level(BlockCount::new(4)).iter()
+            .map(|id| tree.node(*id).expect("Generated node").parents().count())
+            .sum();
+        assert!(edges > tree.level(BlockCount::new(4)).len());
+        for id in tree.level(BlockCount::new(4)) {
+            for parent in tree.node(*id).expect("Generated node").parents() {
+                assert!(tree.node(parent).expect("Generated node").children().contains(id));
+            }
+        }
+        assert_eq!(tree.len() - 1, tree.descendants(PolyTree::ROOT).len());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let tree = full_tree(5);
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).expect("Writing to memory");
+        let loaded = PolyTree::read_from(&mut &bytes[..]).expect("Reading valid data");
+        assert_eq!(tree.len(), loaded.len());
+        for n in BlockCount::ONE.up_to(BlockCount::new(5)) {
+            assert_eq!(tree.level(n), loaded.level(n));
+        }
+        for id in 0..tree.len() {
+            assert_eq!(tree.node(id), loaded.node(id));
+            assert_eq!(Some(id), loaded.find(&tree.shape(id)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_insert() {
+        let mut tree = PolyTree::new();
+        assert_eq!(Err(PlacementError::Occupied), tree.insert(PolyTree::ROOT, Point3D::new(0, 0, 0)));
+        assert_eq!(Err(PlacementError::NotAdjacentToBlock), tree.insert(PolyTree::ROOT, Point3D::new(2, 0, 0)));
+        assert_eq!(1, tree.len());
+    }
+
+    #[test]
+    fn test_read_invalid_delta() {
+        let tree = full_tree(3);
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).expect("Writing to memory");
+        let decode = || -> PolyTreeFile {
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Decoding valid data").0
+        };
+        let assert_rejected = |file: &PolyTreeFile| {
+            let mut bytes = vec![];
+            bincode::serde::encode_into_std_write(file, &mut bytes, bincode::config::standard()).expect("Writing to memory");
+            let error = PolyTree::read_from(&mut &bytes[..]).expect_err("Rejecting invalid deltas");
+            assert_eq!(ErrorKind::InvalidData, error.kind());
+        };
+        for added_block in [Point3D::new(0, 0, 0), Point3D::new(3, 0, 0)] {
+            let mut file = decode();
+            file.nodes[0].added_block = added_block;
+            assert_rejected(&file);
+        }
+        // The root is two levels above the shapes of three blocks.
+        let mut file = decode();
+        let last = file.nodes.len() - 1;
+        file.nodes[last].other_parents.push(PolyTree::ROOT);
+        assert_rejected(&file);
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::block_count::BlockCount;
+use crate::point::Point3D;
+use crate::poly_tree::{LevelInserter, NodeId, PolyTree};
+use crate::polycube::Polycube;
+
+/// A block added to a parent while a level of a [PolyTree] was expanded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ExpansionEvent {
+    pub parent: NodeId,
+    /// The block added to the canonical shape of the parent.
+    pub added_block: Point3D<i32>,
+}
+
+/// The expansion events of consecutive levels of a [PolyTree] in the order they were inserted,
+/// which lets a parallel run be re-executed on a single thread with [PolyTree::replay].
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct Trace {
+    version: u8,
+    /// The block count of the first recorded level. Earlier levels are generated normally on replay.
+    first_level: BlockCount,
+    levels: Vec<Vec<ExpansionEvent>>,
+}
+
+impl Trace {
+    /// The version of the format written by [Trace::write_to].
+    const FORMAT_VERSION: u8 = 1;
+
+    pub fn new() -> Self {
+        Self { version: Self::FORMAT_VERSION, ..Self::default() }
+    }
+
+    pub fn first_level(&self) -> BlockCount {
+        self.first_level
+    }
+
+    /// The events of every recorded level, starting with [Trace::first_level].
+    pub fn levels(&self) -> &[Vec<ExpansionEvent>] {
+        &self.levels
+    }
+
+    /// Appends the events of the level with the given block count, which has to follow the last recorded level.
+    pub fn push_level(&mut self, block_count: BlockCount, events: Vec<ExpansionEvent>) {
+        if self.levels.is_empty() {
+            self.first_level = block_count;
+        }
+        assert_eq!(self.first_level.as_usize() + self.levels.len(), block_count.as_usize(), "Levels are recorded in order.");
+        self.levels.push(events);
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        bincode::serde::encode_into_std_write(self, writer, bincode::config::standard())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Error> {
+        let trace: Self = bincode::serde::decode_from_std_read(reader, bincode::config::standard())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if trace.version != Self::FORMAT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported trace version {}", trace.version)));
+        }
+        Ok(trace)
+    }
+}
+
+impl PolyTree {
+    /// Like [PolyTree::generate_next_level_with_threads] but appends every insertion to the trace
+    /// in the order the threads performed them.
+    pub fn generate_next_level_traced(&mut self, threads: usize, trace: &mut Trace) -> usize {
+        let mut inserter = self.expand_level(threads, LevelInserter::new(threads * 4).with_recording());
+        let block_count = self.depth().next().expect("The tree is smaller than the largest block count.");
+        trace.push_level(block_count, inserter.take_events());
+        self.commit_level(inserter)
+    }
+
+    /// Expands the shapes with the most blocks by inserting the recorded events one after another on
+    /// a single thread. Returns the number of added shapes.
+    pub fn replay_level(&mut self, events: &[ExpansionEvent]) -> Result<usize, Error> {
+        let depth = self.depth();
+        let inserter = LevelInserter::new(1);
+        let mut parent_shape: Option<(NodeId, Polycube)> = None;
+        for event in events {
+            if self.level(depth).binary_search(&event.parent).is_err() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Node {} is not a shape with {depth} blocks", event.parent)));
+            }
+            if parent_shape.as_ref().map(|(id, _)| *id) != Some(event.parent) {
+                parent_shape = Some((event.parent, self.shape(event.parent)));
+            }
+            let (_, shape) = parent_shape.as_ref().expect("The shape of the parent was reconstructed above.");
+            inserter.insert(event.parent, shape, event.added_block)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Unable to add {:?} to node {}: {e:?}", event.added_block, event.parent)))?;
+        }
+        Ok(self.commit_level(inserter))
+    }
+
+    /// Builds a new tree from the trace. Levels before the first recorded one are generated on a single thread.
+    pub fn replay(trace: &Trace) -> Result<Self, Error> {
+        let mut tree = Self::new();
+        while tree.depth() < trace.first_level().previous().unwrap_or_default() {
+            tree.generate_next_level_with_threads(1);
+        }
+        for events in trace.levels() {
+            tree.replay_level(events)?;
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut tree = PolyTree::new();
+        tree.generate_next_level();
+        let mut trace = Trace::new();
+        for _ in 0..3 {
+            tree.generate_next_level_traced(4, &mut trace);
+        }
+        assert_eq!(BlockCount::new(3), trace.first_level());
+        let expected_events: usize = tree.level(BlockCount::new(2)).iter().map(|id| tree.shape(*id).free_neighbors().len()).sum();
+        assert_eq!(expected_events, trace.levels()[0].len());
+
+        let mut buffer = vec![];
+        trace.write_to(&mut buffer).expect("Writable buffer");
+        let read = Trace::read_from(&mut buffer.as_slice()).expect("Valid trace");
+        assert_eq!(trace, read);
+
+        let replayed = PolyTree::replay(&read).expect("Valid trace");
+        assert_eq!(tree.depth(), replayed.depth());
+        for id in 0..tree.len() {
+            assert_eq!(tree.node(id), replayed.node(id));
+        }
+    }
+
+    #[test]
+    fn test_invalid_replay() {
+        let mut tree = PolyTree::new();
+        tree.generate_next_level();
+        let missing = ExpansionEvent { parent: PolyTree::ROOT, added_block: Point3D::new(1, 0, 0) };
+        assert_eq!(ErrorKind::InvalidData, tree.replay_level(&[missing]).unwrap_err().kind());
+        let occupied = ExpansionEvent { parent: 1, added_block: Point3D::new(0, 0, 0) };
+        assert!(tree.replay_level(&[occupied]).is_err());
+        assert!(Trace::read_from(&mut [9u8, 0, 0].as_slice()).is_err());
+    }
+}
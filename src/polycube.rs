@@ -0,0 +1,441 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use serde::{Deserialize, Serialize};
+use crate::block_arrangement::{BlockArrangement, PlacementError};
+use crate::congruence::EqualityStrategy;
+use crate::orientation::rotations_and_reflections;
+use crate::point::{Finite3DDimension, Point3D};
+use crate::shape_ref::ShapeRef;
+
+/// A [BlockArrangement] that is guaranteed to be connected, canonically oriented and stored
+/// in a dimension that tightly fits its blocks.
+/// It can only be created through [Polycube::new] or [TryFrom], both of which validate and
+/// canonicalize the arrangement, so users can rely on the invariants without checking them again.
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "BlockArrangement", into = "BlockArrangement")]
+pub struct Polycube(BlockArrangement);
+
+/// The blocks of a shape as one bit per cell of its bounding box.
+/// The cells are ordered with the x axis varying fastest, eight cells per byte starting at the
+/// least significant bit.
+/// Occupancies are ordered by their size and then by their bits byte by byte.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize)]
+pub struct Occupancy {
+    /// The width, depth and height of the bounding box.
+    pub size: [u32; 3],
+    pub bits: Vec<u8>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PolycubeError {
+    /// Not every block is reachable from every other block over shared faces.
+    Disconnected,
+    /// The given string is not a valid polycube code.
+    InvalidCode,
+}
+
+impl Display for PolycubeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolycubeError::Disconnected => f.write_str("The blocks of the arrangement are not connected"),
+            PolycubeError::InvalidCode => f.write_str("The code does not describe a polycube"),
+        }
+    }
+}
+
+impl std::error::Error for PolycubeError {}
+
+impl Polycube {
+
+    /// Validates the arrangement and stores it in its canonical form.
+    pub fn new(ba: &BlockArrangement) -> Result<Self, PolycubeError> {
+        Self::with_one_sided_count(ba).map(|(polycube, _)| polycube)
+    }
+
+    /// Like [Polycube::new], but also returns the number of one sided shapes the free shape stands for,
+    /// which canonicalizing finds anyway, see [BlockArrangement::canonical_one_sided].
+    pub fn with_one_sided_count(ba: &BlockArrangement) -> Result<(Self, usize), PolycubeError> {
+        if !ba.is_connected() {
+            return Err(PolycubeError::Disconnected);
+        }
+        let (canonical, one_sided) = ba.canonical_one_sided();
+        Ok((Self(canonical), one_sided))
+    }
+
+    /// Returns the validated arrangement.
+    pub fn arrangement(&self) -> &BlockArrangement {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> BlockArrangement {
+        self.0
+    }
+
+    /// Returns the blocks as one bit per cell of the bounding box.
+    pub fn occupancy(&self) -> Occupancy {
+        let blocks: Vec<_> = self.block_iter().collect();
+        let (min_corner, bounding_box) = Finite3DDimension::fitting(&blocks).expect("There is always at least one block.");
+        let (width, depth, height) = bounding_box.all_axis_len();
+        let mut bits = vec![0u8; bounding_box.size().expect("The bounding box fits into the arrangement.").div_ceil(8)];
+        for p in blocks {
+            let offset = p - min_corner;
+            let index = *offset.x() as usize
+                + width as usize * (*offset.y() as usize + depth as usize * *offset.z() as usize);
+            bits[index / 8] |= 1 << (index % 8);
+        }
+        Occupancy {
+            // Every axis of the bounding box is at most as long as the number of blocks.
+            size: [width, depth, height].map(|len| len as u32),
+            bits,
+        }
+    }
+
+    /// Creates the polycube described by the occupancy.
+    /// The described shape does not have to be canonically oriented.
+    pub fn from_occupancy(occupancy: &Occupancy) -> Result<Self, PolycubeError> {
+        let [width, depth, height] = occupancy.size.map(|len| len as usize);
+        let size = width.checked_mul(depth)
+            .and_then(|area| area.checked_mul(height))
+            .ok_or(PolycubeError::InvalidCode)?;
+        if occupancy.bits.len() != size.div_ceil(8) {
+            return Err(PolycubeError::InvalidCode);
+        }
+        let points: Vec<_> = (0..size)
+            .filter(|index| occupancy.bits[index / 8] & (1 << (index % 8)) != 0)
+            .map(|index| Point3D::new(
+                (index % width) as i32,
+                ((index / width) % depth) as i32,
+                (index / (width * depth)) as i32,
+            ))
+            .collect();
+        let ba = BlockArrangement::from_points(&points)
+            .map_err(|e| match e {
+                PlacementError::NotAdjacentToBlock | PlacementError::Disconnects => PolycubeError::Disconnected,
+                PlacementError::NoBlocks | PlacementError::Occupied | PlacementError::Unoccupied | PlacementError::TooLarge => PolycubeError::InvalidCode,
+            })?;
+        Self::new(&ba)
+    }
+
+    /// Creates the polycube of a view that is canonically oriented already, like the records of the
+    /// caches written by this crate, without searching for the canonical orientation again.
+    /// Use [Polycube::from_occupancy] for shapes from elsewhere.
+    pub(crate) fn from_canonical(view: &ShapeRef<'_>) -> Result<Self, PolycubeError> {
+        // The canonical form is anchored at its smallest block rather than at the corner of its box.
+        let mut points: Vec<_> = view.block_iter().collect();
+        points.sort();
+        let anchor = *points.first().ok_or(PolycubeError::InvalidCode)?;
+        points.iter_mut().for_each(|p| *p -= anchor);
+        BlockArrangement::from_points(&points).map(Self).map_err(|_| PolycubeError::Disconnected)
+    }
+
+    /// Returns a compact textual code uniquely identifying the shape.
+    /// The code has the form `{width}x{depth}x{height}:{hex}` where the hex digits encode the
+    /// [Occupancy] of the bounding box.
+    pub fn code(&self) -> String {
+        let Occupancy { size: [width, depth, height], bits } = self.occupancy();
+        let hex: String = bits.iter().map(|b| format!("{b:02x}")).collect();
+        format!("{width}x{depth}x{height}:{hex}")
+    }
+
+    /// Parses a code created by [Polycube::code].
+    /// The encoded shape does not have to be canonically oriented.
+    pub fn from_code(code: &str) -> Result<Self, PolycubeError> {
+        let (dims, hex) = code.split_once(':').ok_or(PolycubeError::InvalidCode)?;
+        let dims = dims.split('x')
+            .map(|d| d.parse::<u32>().map_err(|_| PolycubeError::InvalidCode))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size: [u32; 3] = dims.try_into().map_err(|_| PolycubeError::InvalidCode)?;
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(PolycubeError::InvalidCode);
+        }
+        let bits = (0..hex.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| PolycubeError::InvalidCode))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_occupancy(&Occupancy { size, bits })
+    }
+
+    /// Checks if the mirror image of the shape can not be rotated onto the shape itself.
+    pub fn is_chiral(&self) -> bool {
+        CanonicalForms::of(self).is_chiral()
+    }
+
+    /// Every distinct way to color the blocks of the shape with colors below `color_count`,
+    /// where colorings that only differ by a rotation or reflection of the shape are the same.
+    pub fn colorings(&self, color_count: u8) -> HashSet<Polycube> {
+        let points: Vec<_> = self.block_iter().collect();
+        let total = (color_count as u64).checked_pow(points.len() as u32)
+            .expect("The number of colorings fits into an u64.");
+        (0..total)
+            .map(|mut coloring| {
+                let mut colored = self.0.clone();
+                for p in &points {
+                    colored.set_color(p, (coloring % color_count as u64) as u8).expect("Every point is a block.");
+                    coloring /= color_count as u64;
+                }
+                Self::new(&colored).expect("Coloring keeps the blocks connected.")
+            })
+            .collect()
+    }
+}
+
+/// Whether a shape and its mirror image are considered the same shape.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Chirality {
+    /// Shapes are equal up to rotations, so mirror images of chiral shapes are distinct.
+    OneSided,
+    /// Shapes are equal up to rotations and reflections.
+    Free,
+}
+
+/// The canonical block coordinates of a shape under every [Chirality], calculated in one pass
+/// over all orientations.
+/// A canonical form is the sorted list of block coordinates that is lexicographically smallest
+/// among the allowed orientations, translated so that its smallest coordinate lies at the origin.
+/// Colored shapes break ties between equal coordinates by the colors of the blocks in coordinate order.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CanonicalForms {
+    /// The smallest coordinates among the rotations.
+    rotated: (Vec<Point3D<i32>>, Vec<u8>),
+    /// The smallest coordinates among the rotations of the mirror image.
+    reflected: (Vec<Point3D<i32>>, Vec<u8>),
+}
+
+impl CanonicalForms {
+    pub fn of(ba: &BlockArrangement) -> Self {
+        let mut rotated: Option<(Vec<Point3D<i32>>, Vec<u8>)> = None;
+        let mut reflected: Option<(Vec<Point3D<i32>>, Vec<u8>)> = None;
+        for orientation in rotations_and_reflections() {
+            let oriented = |mut p: Point3D<i32>| {
+                p.apply_orientation(&orientation);
+                p
+            };
+            // The colors are only collected for colored shapes, which keeps them empty otherwise.
+            let (mut points, colors): (Vec<_>, Vec<_>) = if ba.is_colored() {
+                let mut blocks: Vec<_> = ba.block_iter().map(oriented).zip(ba.color_iter()).collect();
+                blocks.sort();
+                blocks.into_iter().unzip()
+            } else {
+                let mut points: Vec<_> = ba.block_iter().map(oriented).collect();
+                points.sort();
+                (points, vec![])
+            };
+            let anchor = points[0];
+            points.iter_mut().for_each(|p| *p -= anchor);
+            let form = (points, colors);
+            let min = if orientation.is_reflection() { &mut reflected } else { &mut rotated };
+            if min.as_ref().is_none_or(|min| form < *min) {
+                *min = Some(form);
+            }
+        }
+        Self {
+            rotated: rotated.expect("There is always at least one rotation."),
+            reflected: reflected.expect("There is always at least one reflection."),
+        }
+    }
+
+    fn form(&self, chirality: Chirality) -> &(Vec<Point3D<i32>>, Vec<u8>) {
+        match chirality {
+            Chirality::OneSided => &self.rotated,
+            Chirality::Free => (&self.rotated).min(&self.reflected),
+        }
+    }
+
+    /// The canonical coordinates under the given [Chirality].
+    pub fn points(&self, chirality: Chirality) -> &[Point3D<i32>] {
+        &self.form(chirality).0
+    }
+
+    /// The colors of the blocks at the canonical coordinates, which is empty for uncolored shapes.
+    pub fn colors(&self, chirality: Chirality) -> &[u8] {
+        &self.form(chirality).1
+    }
+
+    /// Checks if the mirror image can not be rotated onto the shape.
+    pub fn is_chiral(&self) -> bool {
+        self.rotated != self.reflected
+    }
+
+    /// The number of distinct one sided shapes the free shape stands for.
+    pub fn one_sided_count(&self) -> usize {
+        if self.is_chiral() { 2 } else { 1 }
+    }
+}
+
+impl TryFrom<BlockArrangement> for Polycube {
+    type Error = PolycubeError;
+
+    fn try_from(ba: BlockArrangement) -> Result<Self, Self::Error> {
+        Self::new(&ba)
+    }
+}
+
+impl From<Polycube> for BlockArrangement {
+    fn from(polycube: Polycube) -> Self {
+        polycube.0
+    }
+}
+
+impl Deref for Polycube {
+    type Target = BlockArrangement;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<BlockArrangement> for Polycube {
+    fn as_ref(&self) -> &BlockArrangement {
+        &self.0
+    }
+}
+
+/// Since both sides are canonical, equal shapes have equal blocks in equal order.
+impl PartialEq for Polycube {
+    fn eq(&self, other: &Self) -> bool {
+        EqualityStrategy::CanonicalForm.congruent(&self.0, &other.0)
+    }
+}
+
+impl Eq for Polycube {}
+
+/// The rank order of shapes: by number of blocks, then by the [Occupancy] of the canonical orientation
+/// and finally by the colors of the blocks. It only depends on the canonical form, so the rank of a shape
+/// among others is the same on every machine and in every run.
+impl Ord for Polycube {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.num_blocks().cmp(&other.num_blocks())
+            .then_with(|| self.occupancy().cmp(&other.occupancy()))
+            .then_with(|| self.color_iter().cmp(other.color_iter()))
+    }
+}
+
+impl PartialOrd for Polycube {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Polycube {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.block_iter().for_each(|p| p.hash(state));
+        // Canonical colored shapes always have a block with a color other than zero.
+        if self.is_colored() {
+            self.color_iter().for_each(|color| color.hash(state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod polycube_tests {
+    use std::collections::HashSet;
+    use crate::orientation::Orientation;
+    use crate::orientation::RotationAmount;
+    use crate::point::Axis3D;
+    use super::*;
+
+    fn l_shape() -> BlockArrangement {
+        let mut blocks = BlockArrangement::new();
+        blocks.add_block_at(&Point3D::new(1,0,0)).expect("Checked coordinates.");
+        blocks.add_block_at(&Point3D::new(2,0,0)).expect("Checked coordinates.");
+        blocks.add_block_at(&Point3D::new(2,1,0)).expect("Checked coordinates.");
+        blocks
+    }
+
+    #[test]
+    fn test_canonical_orientation() {
+        let blocks = l_shape();
+        let mut rotated = blocks.clone();
+        let mut o = Orientation::default();
+        o.rotate(Axis3D::Z, RotationAmount::Ninety);
+        o.mirror(Axis3D::Y);
+        rotated.set_orientation(o);
+        let a = Polycube::new(&blocks).expect("Connected shape");
+        let b = Polycube::new(&rotated).expect("Connected shape");
+        assert_eq!(a, b);
+        assert_eq!(1, HashSet::from([a.clone(), b]).len());
+        assert_eq!(blocks, *a.arrangement());
+    }
+
+    #[test]
+    fn test_transformed_canonical_form() {
+        crate::transforms::assert_invariant("canonical form", |ba| {
+            let polycube = Polycube::new(ba).unwrap();
+            (polycube.code(), polycube.occupancy(), polycube)
+        });
+    }
+
+    #[test]
+    fn test_tight_dimension() {
+        let polycube = Polycube::new(&l_shape()).expect("Connected shape");
+        assert_eq!(polycube.bounding_box(), polycube.dimension());
+        assert!(polycube.is_connected());
+    }
+
+    #[test]
+    fn test_code_round_trip() {
+        let polycube = Polycube::new(&l_shape()).expect("Connected shape");
+        let code = polycube.code();
+        assert_eq!(polycube, Polycube::from_code(&code).expect("Valid code"));
+        assert_eq!(Polycube::new(&BlockArrangement::new()).unwrap(), Polycube::from_code("1x1x1:01").unwrap());
+        assert_eq!(Err(PolycubeError::InvalidCode), Polycube::from_code("1x1x1:00"));
+        assert_eq!(Err(PolycubeError::Disconnected), Polycube::from_code("3x1x1:05"));
+        assert_eq!(Err(PolycubeError::InvalidCode), Polycube::from_code("2x1:01"));
+    }
+
+    #[test]
+    fn test_chirality() {
+        assert!(!Polycube::new(&l_shape()).unwrap().is_chiral());
+        let twisted = BlockArrangement::from_points(&[
+            Point3D::new(0, 0, 0), Point3D::new(1, 0, 0), Point3D::new(1, 1, 0), Point3D::new(1, 1, 1),
+        ]).expect("Connected points");
+        let mut mirrored = twisted.clone();
+        mirrored.orientation_mut(|o| o.mirror(Axis3D::X));
+        let (twisted_forms, mirrored_forms) = (CanonicalForms::of(&twisted), CanonicalForms::of(&mirrored));
+        assert!(twisted_forms.is_chiral());
+        assert_eq!(2, twisted_forms.one_sided_count());
+        assert_eq!(twisted_forms.points(Chirality::Free), mirrored_forms.points(Chirality::Free));
+        assert_ne!(twisted_forms.points(Chirality::OneSided), mirrored_forms.points(Chirality::OneSided));
+        let polycube = Polycube::new(&twisted).unwrap();
+        assert!(polycube.is_chiral());
+        assert_eq!(polycube, Polycube::new(&mirrored).unwrap());
+    }
+
+    #[test]
+    fn test_colorings() {
+        let line = |len: i32| BlockArrangement::from_points(&(0..len).map(|x| Point3D::new(x, 0, 0)).collect::<Vec<_>>())
+            .expect("Connected points");
+        assert_eq!(3, Polycube::new(&line(2)).unwrap().colorings(2).len());
+        // Eight colorings of which the four that are not palindromes pair up.
+        assert_eq!(6, Polycube::new(&line(3)).unwrap().colorings(2).len());
+        // Only the reflection through its own plane maps the L shape onto itself.
+        let l_colorings = Polycube::new(&l_shape()).unwrap().colorings(2);
+        assert_eq!(16, l_colorings.len());
+        assert!(l_colorings.contains(&Polycube::new(&l_shape()).unwrap()));
+
+        let mut marked = l_shape();
+        marked.set_color(&Point3D::new(2, 1, 0), 1).expect("Block of the L shape");
+        let mut rotated = marked.clone();
+        rotated.orientation_mut(|o| o.rotate(Axis3D::Z, RotationAmount::Ninety));
+        let polycube = Polycube::new(&marked).unwrap();
+        assert_eq!(polycube, Polycube::new(&rotated).unwrap());
+        assert!(polycube.is_colored());
+        assert_eq!(1, polycube.color_iter().filter(|color| *color == 1).count());
+        assert_ne!(polycube, Polycube::new(&l_shape()).unwrap());
+    }
+
+    #[test]
+    fn test_serde() {
+        let polycube = Polycube::new(&l_shape()).expect("Connected shape");
+        let config = bincode::config::standard();
+        let ser = bincode::serde::encode_to_vec(&polycube, config)
+            .expect("Expecting successful serialization");
+        let (deser, _): (Polycube, _) = bincode::serde::decode_from_slice(&ser[..], config)
+            .expect("Expecting successful deserialization.");
+        assert_eq!(polycube, deser);
+    }
+}
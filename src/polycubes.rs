@@ -0,0 +1,176 @@
+//! The canonical shapes with a given number of blocks as a rayon [ParallelIterator], for code using this
+//! crate as a library that wants to analyze the shapes in parallel without caring how they are stored.
+//! The shapes with up to [MAX_BUILTIN_BLOCKS] blocks are embedded into the binary, see [Polycubes::builtin].
+
+use std::sync::OnceLock;
+use rayon::prelude::*;
+use crate::block_count::BlockCount;
+use crate::cache::{load_poly_tree, read_shapes, save_poly_tree};
+use crate::poly_tree::PolyTree;
+use crate::polycube::Polycube;
+use crate::store::CacheStore;
+
+/// The number of shapes a task of the thread pool reconstructs from the [PolyTree] one after another.
+const CHUNK_SIZE: usize = 256;
+
+/// The largest number of blocks whose shapes are embedded into the binary.
+pub const MAX_BUILTIN_BLOCKS: BlockCount = BlockCount::new(8);
+
+/// The zstd compressed [CacheFormat::V2](crate::cache::CacheFormat::V2) caches of the shapes with one to
+/// [MAX_BUILTIN_BLOCKS] blocks, about 42 KB together. Rewritten by the tests with `UPDATE_GOLDEN=1`
+/// like the [golden](crate::golden) files.
+const BUILTIN_CACHES: [&[u8]; MAX_BUILTIN_BLOCKS.as_usize()] = [
+    include_bytes!("../builtin/shape_cache_1.cac"),
+    include_bytes!("../builtin/shape_cache_2.cac"),
+    include_bytes!("../builtin/shape_cache_3.cac"),
+    include_bytes!("../builtin/shape_cache_4.cac"),
+    include_bytes!("../builtin/shape_cache_5.cac"),
+    include_bytes!("../builtin/shape_cache_6.cac"),
+    include_bytes!("../builtin/shape_cache_7.cac"),
+    include_bytes!("../builtin/shape_cache_8.cac"),
+];
+
+/// Enumerates the free polycubes in a [PolyTree] that is grown on demand. With a store the tree is loaded
+/// from and saved to it like by [PolyTreeStrategy](crate::strategy::PolyTreeStrategy), so shapes
+/// enumerated once are read back later.
+pub struct Polycubes {
+    tree: PolyTree,
+    store: Option<Box<dyn CacheStore>>,
+}
+
+impl Polycubes {
+    /// Keeps the shapes in memory only.
+    pub fn new() -> Self {
+        Self { tree: PolyTree::new(), store: None }
+    }
+
+    /// Starts from the poly tree of the store, or from an empty one if it has none yet.
+    pub fn with_store(store: Box<dyn CacheStore>) -> Self {
+        let tree = load_poly_tree(store.as_ref()).unwrap_or_else(|_| PolyTree::new());
+        Self { tree, store: Some(store) }
+    }
+
+    /// The sorted canonical shapes with n blocks embedded into the binary, `None` for more than
+    /// [MAX_BUILTIN_BLOCKS] blocks. Neither reads files nor generates shapes, the embedded caches of a
+    /// size are decoded on its first access only.
+    pub fn builtin(n: BlockCount) -> Option<&'static [Polycube]> {
+        static DECODED: [OnceLock<Vec<Polycube>>; MAX_BUILTIN_BLOCKS.as_usize()] = [const { OnceLock::new() }; MAX_BUILTIN_BLOCKS.as_usize()];
+        let i = n.as_usize().checked_sub(1)?;
+        let cache = *BUILTIN_CACHES.get(i)?;
+        let shapes = DECODED[i].get_or_init(|| {
+            let cached = read_shapes(cache).expect("The builtin caches are valid.");
+            let views: Vec<_> = cached.iter().collect::<Result<_, _>>().expect("The builtin caches are valid.");
+            let mut shapes: Vec<_> = views.par_iter()
+                .map(|view| Polycube::from_canonical(view).expect("Cached shapes are connected."))
+                .collect();
+            shapes.sort_unstable();
+            shapes
+        });
+        Some(shapes)
+    }
+
+    pub fn tree(&self) -> &PolyTree {
+        &self.tree
+    }
+
+    /// Generates the levels up to n blocks that are missing, saving the tree to the store afterwards.
+    pub fn grow(&mut self, n: BlockCount) {
+        if self.tree.depth() >= n {
+            return;
+        }
+        while self.tree.depth() < n {
+            self.tree.generate_next_level_with_threads(rayon::current_num_threads());
+        }
+        if let Some(store) = &self.store {
+            if let Err(e) = save_poly_tree(&self.tree, store.as_ref()) {
+                eprintln!("Failed to save poly tree: {e}");
+            }
+        }
+    }
+
+    /// Every canonical shape with n blocks exactly once, in no particular order. The missing levels are
+    /// generated first, see [Polycubes::grow], and the shapes are reconstructed in chunks on the threads
+    /// of the current rayon pool.
+    pub fn par_iter(&mut self, n: BlockCount) -> impl ParallelIterator<Item = Polycube> + '_ {
+        self.grow(n);
+        let tree = &self.tree;
+        tree.level(n)
+            .par_chunks(CHUNK_SIZE)
+            .flat_map_iter(move |ids| ids.iter().map(move |id| tree.shape(*id)))
+    }
+}
+
+impl Default for Polycubes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod polycubes_tests {
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::block_hash::BlockHash;
+    use crate::cache::{write_cache, CacheFormat, Compression};
+    use crate::golden::golden_path;
+    use crate::store::FsStore;
+    use super::*;
+
+    /// Rewrites the builtin caches from a freshly generated [PolyTree] if `UPDATE_GOLDEN` is set,
+    /// otherwise compares them with the golden files.
+    #[test]
+    fn test_builtin() {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let mut tree = PolyTree::new();
+            for n in BlockCount::ONE.up_to(MAX_BUILTIN_BLOCKS) {
+                while tree.depth() < n {
+                    tree.generate_next_level();
+                }
+                let mut shapes: Vec<_> = tree.iter_level(n).map(|ba| Polycube::new(&ba).unwrap()).collect();
+                shapes.sort_unstable();
+                let records: Vec<_> = shapes.into_iter().map(|polycube| (BlockHash::from(polycube.arrangement()), polycube.into_inner())).collect();
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("builtin").join(format!("shape_cache_{n}.cac"));
+                write_cache(path, records.iter().map(|(hash, ba)| (hash, ba)), records.len(), CacheFormat::V2, Compression::Zstd, false)
+                    .expect("Writable builtin cache");
+            }
+            return;
+        }
+        for n in BlockCount::ONE.up_to(MAX_BUILTIN_BLOCKS) {
+            let golden = fs::read_to_string(golden_path(n.as_usize())).expect("Readable golden file");
+            let expected: BTreeSet<_> = golden.lines().map(str::to_string).collect();
+            let builtin = Polycubes::builtin(n).expect("A builtin size");
+            assert_eq!(expected.len(), builtin.len(), "Builtin shapes with {n} blocks");
+            assert_eq!(expected, builtin.iter().map(Polycube::code).collect::<BTreeSet<_>>(), "Builtin shapes with {n} blocks");
+            assert!(builtin.is_sorted());
+            // Decoding trusts the orientation of the cached shapes instead of canonicalizing them again.
+            for polycube in builtin.iter().take(50) {
+                assert_eq!(polycube.arrangement(), Polycube::new(polycube.arrangement()).unwrap().arrangement());
+            }
+        }
+        assert_eq!(None, Polycubes::builtin(MAX_BUILTIN_BLOCKS.next().unwrap()));
+        assert_eq!(None, Polycubes::builtin(BlockCount::default()));
+    }
+
+    #[test]
+    fn test_par_iter() {
+        let dir = std::env::temp_dir().join("polycubes_tests_par_iter");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut polycubes = Polycubes::with_store(Box::new(FsStore::new(&dir)));
+        for (n, expected) in [(1, 1), (2, 1), (3, 2), (4, 7), (5, 23)] {
+            let shapes: BTreeSet<_> = polycubes.par_iter(BlockCount::new(n)).collect();
+            assert_eq!(expected, shapes.len(), "Shapes with {n} blocks");
+            assert!(shapes.iter().all(|polycube| polycube.num_blocks() == BlockCount::new(n)));
+        }
+        // The grown tree was saved, so the shapes are not generated again.
+        let stored = Polycubes::with_store(Box::new(FsStore::new(&dir)));
+        assert_eq!(BlockCount::new(5), stored.tree().depth());
+        let mut fresh = Polycubes::new();
+        let expected: BTreeSet<_> = fresh.par_iter(BlockCount::new(4)).collect();
+        let actual: BTreeSet<_> = polycubes.par_iter(BlockCount::new(4)).collect();
+        assert_eq!(expected, actual);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use crate::block_arrangement::BlockArrangement;
+use crate::orientation::{rotations, Orientation};
+use crate::placement::{placements, Region};
+use crate::point::Point3D;
+use crate::polycube::Polycube;
+
+/// The default number of random decompositions [PuzzleGenerator] tries before giving up.
+pub const DEFAULT_ATTEMPTS: usize = 1000;
+
+/// A box together with pieces that fill it in exactly one way, up to rotations of the box.
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    /// The width, depth and height of the box.
+    pub size: [u32; 3],
+    pub pieces: Vec<Polycube>,
+    /// The cells of the box covered by every piece, in the order of the pieces.
+    pub solution: Vec<Vec<Point3D<i32>>>,
+    /// The number of decompositions tried until one had a unique solution.
+    pub attempts: usize,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PuzzleError {
+    /// The box can not be split into the given number of pieces of the allowed size.
+    InvalidPieceCount,
+    /// None of the tried decompositions had a unique solution.
+    NotFound(usize),
+}
+
+impl Display for PuzzleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PuzzleError::InvalidPieceCount => f.write_str("The box can not be split into that many pieces of the allowed size"),
+            PuzzleError::NotFound(attempts) => write!(f, "None of {attempts} decompositions had a unique solution"),
+        }
+    }
+}
+
+impl std::error::Error for PuzzleError {}
+
+/// Splits a box into connected pieces at random until the pieces can only be assembled into the box
+/// in one way. The same seed always results in the same puzzle.
+pub struct PuzzleGenerator {
+    size: [u32; 3],
+    piece_count: usize,
+    max_piece_size: usize,
+    seed: u64,
+    attempts: usize,
+}
+
+impl PuzzleGenerator {
+    pub fn new(size: [u32; 3], piece_count: usize) -> Self {
+        Self {
+            size,
+            piece_count,
+            max_piece_size: usize::MAX,
+            seed: 0,
+            attempts: DEFAULT_ATTEMPTS,
+        }
+    }
+
+    /// Limits the number of blocks of every piece.
+    pub fn with_max_piece_size(mut self, max_piece_size: usize) -> Self {
+        self.max_piece_size = max_piece_size;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the number of decompositions tried before giving up.
+    pub fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn generate(&self) -> Result<Puzzle, PuzzleError> {
+        let cells = Region::cuboid(self.size).cells().collect::<Vec<_>>();
+        if self.piece_count == 0
+            || self.piece_count > cells.len()
+            || self.piece_count.saturating_mul(self.max_piece_size) < cells.len() {
+            return Err(PuzzleError::InvalidPieceCount);
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        for attempt in 1..=self.attempts {
+            let Some(solution) = self.decompose(&cells, &mut rng) else {
+                continue;
+            };
+            let pieces: Vec<_> = solution.iter()
+                .map(|piece| {
+                    let ba = BlockArrangement::from_points(piece).expect("Pieces are grown from connected cells.");
+                    Polycube::new(&ba).expect("Pieces are grown from connected cells.")
+                })
+                .collect();
+            if count_solutions(self.size, &pieces, 2) == 1 {
+                return Ok(Puzzle { size: self.size, pieces, solution, attempts: attempt });
+            }
+        }
+        Err(PuzzleError::NotFound(self.attempts))
+    }
+
+    /// Grows the pieces from random cells one random neighbor at a time.
+    /// Returns `None` if cells are left over that no piece can grow into anymore.
+    fn decompose(&self, cells: &[Point3D<i32>], rng: &mut StdRng) -> Option<Vec<Vec<Point3D<i32>>>> {
+        let mut free: HashSet<_> = cells.iter().copied().collect();
+        let mut pieces: Vec<Vec<Point3D<i32>>> = cells.choose_multiple(rng, self.piece_count)
+            .map(|cell| vec![*cell])
+            .collect();
+        pieces.iter().for_each(|piece| { free.remove(&piece[0]); });
+        while !free.is_empty() {
+            let growable: Vec<_> = pieces.iter().enumerate()
+                .filter(|(_, piece)| piece.len() < self.max_piece_size)
+                .filter_map(|(i, piece)| {
+                    let mut targets: Vec<_> = piece.iter()
+                        .flat_map(|p| BlockArrangement::NEIGHBOR_OFFSETS.map(|o| o + *p))
+                        .filter(|p| free.contains(p))
+                        .collect();
+                    targets.sort();
+                    targets.dedup();
+                    (!targets.is_empty()).then_some((i, targets))
+                })
+                .collect();
+            let (i, targets) = growable.choose(rng)?;
+            let target = *targets.choose(rng).expect("Only pieces with free neighbors can grow.");
+            free.remove(&target);
+            pieces[*i].push(target);
+        }
+        Some(pieces)
+    }
+}
+
+fn oriented(mut p: Point3D<i32>, orientation: &Orientation) -> Point3D<i32> {
+    p.apply_orientation(orientation);
+    p
+}
+
+/// Sorts the points and moves them so the smallest lies at the origin.
+fn normalized(mut points: Vec<Point3D<i32>>) -> Vec<Point3D<i32>> {
+    points.sort();
+    let anchor = points[0];
+    points.iter_mut().for_each(|p| *p -= anchor);
+    points
+}
+
+/// Counts the ways the pieces fill the box exactly, each piece used once and only rotated, stopping once
+/// `limit` solutions were found. Solutions that are rotations of each other and solutions that only
+/// swap identical pieces count once.
+pub fn count_solutions(size: [u32; 3], pieces: &[Polycube], limit: usize) -> usize {
+    let cells: Vec<_> = Region::cuboid(size).cells().collect();
+    if pieces.iter().map(|piece| piece.num_blocks().as_usize()).sum::<usize>() != cells.len() {
+        return 0;
+    }
+    let mut box_rotations: Vec<Orientation> = vec![];
+    let box_normalized = normalized(cells.clone());
+    for rotation in rotations() {
+        if normalized(cells.iter().map(|p| oriented(*p, &rotation)).collect()) == box_normalized {
+            box_rotations.push(rotation);
+        }
+    }
+    let region = Region::cuboid(size);
+    let mut solver = Solver {
+        occupied: vec![false; cells.len()],
+        placements: pieces.iter()
+            .map(|piece| {
+                let mut by_first_cell = vec![vec![]; cells.len()];
+                for placement in placements(piece, &region) {
+                    let indices: Vec<_> = placement.cells.iter()
+                        .map(|p| index(size, p).expect("Placements lie inside the box."))
+                        .collect();
+                    by_first_cell[indices[0]].push(indices);
+                }
+                by_first_cell
+            })
+            .collect(),
+        cells,
+        used: vec![false; pieces.len()],
+        // Only the first unused one of identical pieces is tried, so swapping them does not create new solutions.
+        first_identical: pieces.iter().enumerate()
+            .map(|(i, piece)| pieces.iter().position(|other| other == piece).unwrap_or(i))
+            .collect(),
+        placed: vec![],
+        box_rotations,
+        solutions: HashSet::new(),
+        limit,
+    };
+    solver.solve(0);
+    solver.solutions.len()
+}
+
+/// The position of the cell among the ascending cells of the box.
+fn index([width, depth, height]: [u32; 3], p: &Point3D<i32>) -> Option<usize> {
+    let [width, depth, height] = [width, depth, height].map(|len| len as i32);
+    let (x, y, z) = (*p.x(), *p.y(), *p.z());
+    if !(0..width).contains(&x) || !(0..depth).contains(&y) || !(0..height).contains(&z) {
+        return None;
+    }
+    Some(((x * depth + y) * height + z) as usize)
+}
+
+struct Solver {
+    /// The cells of the box in ascending order.
+    cells: Vec<Point3D<i32>>,
+    /// Whether every cell is covered.
+    occupied: Vec<bool>,
+    /// The [placements] of every piece as cell indices, grouped by their smallest cell.
+    placements: Vec<Vec<Vec<Vec<usize>>>>,
+    used: Vec<bool>,
+    first_identical: Vec<usize>,
+    /// The cell indices of the placed pieces.
+    placed: Vec<Vec<usize>>,
+    box_rotations: Vec<Orientation>,
+    /// The found solutions in the form that is smallest among the rotations of the box.
+    solutions: HashSet<Vec<Vec<Point3D<i32>>>>,
+    limit: usize,
+}
+
+impl Solver {
+    /// Covers the smallest uncovered cell with every fitting piece in turn. Since every smaller cell is
+    /// already covered, only placements whose smallest cell is the uncovered one can fit.
+    fn solve(&mut self, start: usize) {
+        if self.solutions.len() >= self.limit {
+            return;
+        }
+        let Some(first_free) = (start..self.occupied.len()).find(|i| !self.occupied[*i]) else {
+            self.record();
+            return;
+        };
+        for piece in 0..self.placements.len() {
+            if self.used[piece] || self.same_as_unused_earlier(piece) {
+                continue;
+            }
+            for i in 0..self.placements[piece][first_free].len() {
+                let indices = &self.placements[piece][first_free][i];
+                if indices.iter().any(|index| self.occupied[*index]) {
+                    continue;
+                }
+                let indices = indices.clone();
+                indices.iter().for_each(|index| self.occupied[*index] = true);
+                self.used[piece] = true;
+                self.placed.push(indices);
+                self.solve(first_free + 1);
+                let indices = self.placed.pop().expect("Placed before solving");
+                self.used[piece] = false;
+                indices.iter().for_each(|index| self.occupied[*index] = false);
+            }
+        }
+    }
+
+    fn same_as_unused_earlier(&self, piece: usize) -> bool {
+        let first = self.first_identical[piece];
+        (first..piece).any(|other| self.first_identical[other] == first && !self.used[other])
+    }
+
+    fn record(&mut self) {
+        let canonical = self.box_rotations.iter()
+            .map(|rotation| {
+                let rotated_box: Vec<_> = self.cells.iter().map(|p| oriented(*p, rotation)).collect();
+                let min = rotated_box.iter().fold(rotated_box[0], |min, p| Point3D::new(
+                    *min.x().min(p.x()),
+                    *min.y().min(p.y()),
+                    *min.z().min(p.z()),
+                ));
+                let mut pieces: Vec<Vec<_>> = self.placed.iter()
+                    .map(|piece| {
+                        let mut cells: Vec<_> = piece.iter().map(|index| oriented(self.cells[*index], rotation) - min).collect();
+                        cells.sort();
+                        cells
+                    })
+                    .collect();
+                pieces.sort();
+                pieces
+            })
+            .min()
+            .expect("The identity maps the box onto itself.");
+        self.solutions.insert(canonical);
+    }
+}
+
+#[cfg(test)]
+mod puzzle_tests {
+    use crate::block_count::BlockCount;
+    use super::*;
+
+    fn polycube(points: &[(i32, i32, i32)]) -> Polycube {
+        let points: Vec<_> = points.iter().map(|(x, y, z)| Point3D::new(*x, *y, *z)).collect();
+        Polycube::new(&BlockArrangement::from_points(&points).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let domino = polycube(&[(0, 0, 0), (1, 0, 0)]);
+        let monocube = polycube(&[(0, 0, 0)]);
+        // Three dominoes tile a 2x3 rectangle in three ways, two of which are rotations of each other.
+        assert_eq!(2, count_solutions([1, 2, 3], &[domino.clone(), domino.clone(), domino.clone()], 10));
+        assert_eq!(1, count_solutions([1, 2, 3], &[domino.clone(), domino.clone(), domino.clone()], 1));
+        assert_eq!(1, count_solutions([1, 1, 3], &[domino.clone(), monocube.clone()], 10));
+        assert_eq!(1, count_solutions([2, 2, 1], &[domino.clone(), domino.clone()], 10));
+        assert_eq!(0, count_solutions([2, 2, 1], &[domino, monocube], 10));
+    }
+
+    #[test]
+    fn test_generate() {
+        let generator = PuzzleGenerator::new([3, 3, 2], 4).with_max_piece_size(6).with_seed(7);
+        let puzzle = generator.generate().expect("Some decomposition is unique");
+        assert_eq!(4, puzzle.pieces.len());
+        assert!(puzzle.pieces.iter().all(|piece| piece.num_blocks() <= BlockCount::new(6)));
+        let mut covered: Vec<_> = puzzle.solution.concat();
+        covered.sort();
+        assert_eq!(Region::cuboid([3, 3, 2]).cells().collect::<Vec<_>>(), covered);
+        assert_eq!(1, count_solutions(puzzle.size, &puzzle.pieces, 2));
+
+        let again = generator.generate().expect("Same seed");
+        assert_eq!(puzzle.pieces, again.pieces);
+        assert_eq!(Err(PuzzleError::InvalidPieceCount), PuzzleGenerator::new([2, 2, 2], 2).with_max_piece_size(3).generate().map(|p| p.pieces.len()));
+    }
+}
@@ -0,0 +1,139 @@
+//! A deliberately simple enumerator to validate the optimized ones against.
+//! It shares no code with the rest of the crate: shapes are plain sorted coordinate lists, every
+//! fixed shape is grown from every fixed shape with one block less, and free shapes are deduplicated
+//! by comparing their smallest coordinate list over all 48 rotations and reflections.
+//! It is slow and only meant for small block counts.
+
+use std::collections::BTreeSet;
+use crate::block_arrangement::BlockArrangement;
+
+/// The sorted coordinates of the blocks of a shape, translated so the smallest coordinate on every axis is 0.
+pub type Cells = Vec<[i32; 3]>;
+
+const FACE_OFFSETS: [[i32; 3]; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+const AXIS_PERMUTATIONS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+pub fn normalize(mut cells: Cells) -> Cells {
+    for axis in 0..3 {
+        let min = cells.iter().map(|cell| cell[axis]).min().unwrap_or(0);
+        cells.iter_mut().for_each(|cell| cell[axis] -= min);
+    }
+    cells.sort();
+    cells.dedup();
+    cells
+}
+
+/// Every image of the cells under a permutation of the axes combined with flipping any of them.
+pub fn images(cells: &[[i32; 3]]) -> Vec<Cells> {
+    let mut images = vec![];
+    for permutation in AXIS_PERMUTATIONS {
+        for flips in 0..8 {
+            let sign = |axis: usize| if flips & (1 << axis) == 0 { 1 } else { -1 };
+            let image = cells.iter()
+                .map(|cell| [0, 1, 2].map(|axis| sign(axis) * cell[permutation[axis]]))
+                .collect();
+            images.push(normalize(image));
+        }
+    }
+    images
+}
+
+/// The smallest image of the cells, which is the same for all shapes that are congruent including reflections.
+pub fn canonical(cells: &[[i32; 3]]) -> Cells {
+    images(cells).into_iter().min().expect("There are 48 images.")
+}
+
+/// Every shape of n blocks that is distinct up to translation.
+pub fn fixed_shapes(n: usize) -> BTreeSet<Cells> {
+    let mut shapes = BTreeSet::new();
+    if n == 0 {
+        return shapes;
+    }
+    shapes.insert(vec![[0, 0, 0]]);
+    for _ in 1..n {
+        let mut grown = BTreeSet::new();
+        for shape in &shapes {
+            for cell in shape {
+                for offset in FACE_OFFSETS {
+                    let neighbor = [0, 1, 2].map(|axis| cell[axis] + offset[axis]);
+                    if !shape.contains(&neighbor) {
+                        let mut child = shape.clone();
+                        child.push(neighbor);
+                        grown.insert(normalize(child));
+                    }
+                }
+            }
+        }
+        shapes = grown;
+    }
+    shapes
+}
+
+/// Every shape of n blocks that is distinct up to translation, rotation and reflection, by its canonical cells.
+pub fn free_shapes(n: usize) -> BTreeSet<Cells> {
+    fixed_shapes(n).iter().map(|shape| canonical(shape)).collect()
+}
+
+/// The differences between the shapes of an enumerator and the [free_shapes] with the same number of blocks.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Comparison {
+    /// Reference shapes the enumerator did not produce.
+    pub missing: Vec<Cells>,
+    /// Shapes the reference does not know, e.g. because they have the wrong number of blocks.
+    pub unexpected: Vec<Cells>,
+    /// The number of shapes the enumerator produced more than once.
+    pub duplicates: usize,
+}
+
+impl Comparison {
+    pub fn is_match(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.duplicates == 0
+    }
+}
+
+/// Compares the shapes to the reference shapes with n blocks.
+pub fn compare(n: usize, shapes: impl IntoIterator<Item = BlockArrangement>) -> Comparison {
+    let expected = free_shapes(n);
+    let mut found = BTreeSet::new();
+    let mut comparison = Comparison::default();
+    for ba in shapes {
+        let cells: Cells = ba.block_iter().map(|p| [*p.x(), *p.y(), *p.z()]).collect();
+        if !found.insert(canonical(&cells)) {
+            comparison.duplicates += 1;
+        }
+    }
+    comparison.missing = expected.difference(&found).cloned().collect();
+    comparison.unexpected = found.difference(&expected).cloned().collect();
+    comparison
+}
+
+#[cfg(test)]
+mod reference_tests {
+    use crate::block_count::BlockCount;
+    use crate::poly_tree::PolyTree;
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        assert_eq!(vec![0, 1, 3, 15, 86, 534], (0..6).map(|n| fixed_shapes(n).len()).collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 1, 2, 7, 23], (0..6).map(|n| free_shapes(n).len()).collect::<Vec<_>>());
+        assert_eq!(48, images(&[[0, 0, 0], [1, 0, 0], [1, 1, 0], [1, 1, 1]]).len());
+    }
+
+    #[test]
+    fn test_compare_poly_tree() {
+        let mut tree = PolyTree::new();
+        for n in BlockCount::ONE.up_to(BlockCount::new(6)) {
+            while tree.depth() < n {
+                tree.generate_next_level();
+            }
+            let comparison = compare(n.as_usize(), tree.iter_level(n));
+            assert!(comparison.is_match(), "Shapes with {n} blocks differ: {comparison:?}");
+        }
+        let lines = [vec![BlockArrangement::new()], vec![BlockArrangement::new()]].concat();
+        let comparison = compare(2, lines);
+        assert_eq!(1, comparison.duplicates);
+        assert_eq!((1, 1), (comparison.missing.len(), comparison.unexpected.len()));
+    }
+}
@@ -0,0 +1,124 @@
+//! Polycubes with one distinguished block, the root. Two rooted polycubes are only equal if a rotation or
+//! reflection maps the blocks of one onto the other and its root onto the root of the other, so a shape
+//! has as many rooted versions as its symmetries leave orbits of blocks. The root is marked with a
+//! color, which the canonicalization of [Polycube::new] keeps apart from the other blocks.
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use crate::block_arrangement::BlockArrangement;
+use crate::point::Point3D;
+use crate::polycube::{Polycube, PolycubeError};
+
+/// The color of the root block of a [RootedPolycube], every other block has the default color zero.
+pub const ROOT_COLOR: u8 = 1;
+
+/// A polycube with a root block, stored in the canonical orientation of both together.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct RootedPolycube(Polycube);
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RootedError {
+    /// The root is not one of the blocks.
+    NotABlock,
+    Polycube(PolycubeError),
+}
+
+impl Display for RootedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RootedError::NotABlock => f.write_str("The root is not a block of the arrangement"),
+            RootedError::Polycube(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RootedError {}
+
+impl RootedPolycube {
+    /// Roots the arrangement at the block at the given point, in the coordinates of
+    /// [BlockArrangement::block_iter]. Colors of the arrangement are ignored.
+    pub fn new(ba: &BlockArrangement, root: &Point3D<i32>) -> Result<Self, RootedError> {
+        if !ba.is_set(root) {
+            return Err(RootedError::NotABlock);
+        }
+        // The first point ends up at the origin.
+        let points: Vec<_> = std::iter::once(*root).chain(ba.block_iter().filter(|p| p != root)).collect();
+        let mut rooted = BlockArrangement::from_points(&points)
+            .map_err(|_| RootedError::Polycube(PolycubeError::Disconnected))?;
+        rooted.set_color(&Point3D::default(), ROOT_COLOR).expect("The root is at the origin.");
+        Polycube::new(&rooted).map(Self).map_err(RootedError::Polycube)
+    }
+
+    /// The canonical polycube with the root colored [ROOT_COLOR].
+    pub fn polycube(&self) -> &Polycube {
+        &self.0
+    }
+
+    /// The root in the coordinates of the [polycube](RootedPolycube::polycube).
+    pub fn root(&self) -> Point3D<i32> {
+        self.0.block_iter().zip(self.0.color_iter())
+            .find(|(_, color)| *color == ROOT_COLOR)
+            .map(|(p, _)| p)
+            .expect("A rooted polycube has a root.")
+    }
+
+    /// The shape without its root.
+    pub fn shape(&self) -> Polycube {
+        let points: Vec<_> = self.0.block_iter().collect();
+        let ba = BlockArrangement::from_points(&points).expect("The blocks of a polycube are connected.");
+        Polycube::new(&ba).expect("The blocks of a polycube are connected.")
+    }
+}
+
+impl Display for RootedPolycube {
+    /// The code of the shape followed by the root in its canonical coordinates.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let root = self.root();
+        write!(f, "{}@{},{},{}", self.0.code(), root.x(), root.y(), root.z())
+    }
+}
+
+/// The distinct rooted versions of the shape, one per orbit of its blocks under its symmetries, in rank order.
+pub fn rooted_shapes(polycube: &Polycube) -> Vec<RootedPolycube> {
+    let rooted: BTreeSet<_> = polycube.block_iter()
+        .map(|root| RootedPolycube::new(polycube.arrangement(), &root).expect("Every block of a polycube can be the root."))
+        .collect();
+    rooted.into_iter().collect()
+}
+
+/// The number of distinct rooted shapes among the rooted versions of all given shapes.
+pub fn rooted_count<'a>(polycubes: impl IntoIterator<Item = &'a Polycube>) -> u64 {
+    polycubes.into_iter().map(|polycube| rooted_shapes(polycube).len() as u64).sum()
+}
+
+#[cfg(test)]
+mod rooted_tests {
+    use crate::block_count::BlockCount;
+    use crate::poly_tree::PolyTree;
+    use super::*;
+
+    #[test]
+    fn test_rooted() {
+        let mut tree = PolyTree::new();
+        (0..4).for_each(|_| { tree.generate_next_level(); });
+        // The numbers of rooted shapes with one to four blocks, e.g. the I, L, T and S tetrominoes have
+        // 2, 4, 3 and 2 orbits of blocks.
+        for (n, expected) in [(1, 1), (2, 1), (3, 4), (4, 16)] {
+            let shapes: Vec<_> = tree.iter_level(BlockCount::new(n)).map(|ba| Polycube::new(&ba).unwrap()).collect();
+            assert_eq!(expected, rooted_count(&shapes), "Rooted shapes with {n} blocks");
+        }
+
+        let mut line = BlockArrangement::new();
+        line.add_block_at(&Point3D::new(1, 0, 0)).unwrap();
+        line.add_block_at(&Point3D::new(2, 0, 0)).unwrap();
+        let end = RootedPolycube::new(&line, &Point3D::new(0, 0, 0)).unwrap();
+        assert_eq!(end, RootedPolycube::new(&line, &Point3D::new(2, 0, 0)).unwrap());
+        let middle = RootedPolycube::new(&line, &Point3D::new(1, 0, 0)).unwrap();
+        assert_ne!(end, middle);
+        assert_eq!(Point3D::new(0, 0, 1), middle.root());
+        assert_eq!(Polycube::new(&line).unwrap(), middle.shape());
+        assert!(!middle.shape().is_colored());
+        assert_eq!(format!("{}@0,0,1", middle.shape().code()), middle.to_string());
+        assert_eq!(Err(RootedError::NotABlock), RootedPolycube::new(&line, &Point3D::new(0, 1, 0)));
+    }
+}
@@ -0,0 +1,517 @@
+//! Runs an enumeration described by a [RunConfig] in process, exactly like the command line interface does
+//! when it is not given a subcommand, and reports the results instead of printing them.
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use serde::Serialize;
+use crate::augmentation::count_levels;
+use crate::block_arrangement::block_variation::NeighborOrder;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::cache::load_poly_tree;
+use crate::cache::manifest::LineageError;
+use crate::config::{Algorithm, RunConfig};
+use crate::dashboard::{serve, LevelReport, Progress};
+use crate::export::{export, ExportFormat};
+use crate::orientation::OrientationRanking;
+use crate::polycube::{Polycube, PolycubeError};
+use crate::shape_filter::ShapeFilter;
+use crate::store::CacheStore;
+
+/// Why a run could not be completed.
+#[derive(Debug)]
+pub enum RunError {
+    /// The configuration is inconsistent, see [RunConfig::validate].
+    InvalidConfig(&'static str),
+    /// The cache directory could not be opened.
+    Store(Error),
+    /// Listening for or accepting workers failed.
+    Workers(Error),
+    /// The cached parents with the number of blocks could not be read, see [RunConfig::stream_parents].
+    Parents(BlockCount, Error),
+    /// A shape of the last level could not be checked against the filters.
+    InvalidShape(PolycubeError),
+    /// The shape with the code is not a shape of the poly tree with one block less than the run.
+    UnknownParent(String),
+    /// Saving a level would replace a cache with a different result, see [check_lineage](crate::cache::manifest::check_lineage).
+    Lineage(BlockCount, LineageError),
+    /// A stop condition of the config was met. Strategies return it to end the run early, and
+    /// [Runner::run] reports it as [RunReport::stopped] instead of failing.
+    Stopped(StopPoint),
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::InvalidConfig(e) => write!(f, "invalid configuration: {e}"),
+            RunError::Store(e) => write!(f, "failed to open the cache directory: {e}"),
+            RunError::Workers(e) => write!(f, "failed to distribute the level to workers: {e}"),
+            RunError::Parents(n, e) => write!(f, "failed to read the cached shapes with {n} blocks: {e}"),
+            RunError::InvalidShape(e) => write!(f, "failed to filter the shapes: {e}"),
+            RunError::UnknownParent(code) => write!(f, "{code} is not a shape with one block less to export the descendants of"),
+            RunError::Lineage(n, e) => write!(f, "refusing to replace the cache of {n} blocks as {e}, use --force to replace it"),
+            RunError::Stopped(stop) => write!(f, "stopped early, {stop}"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// A file the shapes were exported to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct Export {
+    pub format: ExportFormat,
+    /// The location of the file in the store, see [CacheStore::location].
+    pub location: String,
+}
+
+/// The stop condition of the config or the interrupt that ended a run early.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    /// [RunConfig::stop_after_shapes]
+    Shapes,
+    /// [RunConfig::stop_after_seconds]
+    Seconds,
+    /// [Runner::with_interrupt]
+    Interrupted,
+}
+
+/// How far a run got that was stopped by a stop condition.
+/// The conditions are checked before the children of every shard of parents are generated, so a run
+/// stops at most one shard after a condition is met, and smaller [RunConfig::shard_size]s stop sooner.
+/// An interrupt also ends the shard being generated after the current parent.
+/// Every completed level is saved as a cache and every completed shard as a partial shard, so running
+/// the same configuration again continues where the run stopped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct StopPoint {
+    pub reason: StopReason,
+    /// The number of blocks of the level that was being generated.
+    pub blocks: BlockCount,
+    /// The number of parents of that level whose children are saved.
+    pub completed_parents: usize,
+    pub parents: usize,
+    /// The number of shapes generated by the run before it stopped, counting the shapes of every level.
+    pub shapes: u64,
+}
+
+impl Display for StopPoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cause = match self.reason {
+            StopReason::Shapes => "the shape limit was reached",
+            StopReason::Seconds => "the time limit was reached",
+            StopReason::Interrupted => "the run was interrupted",
+        };
+        write!(
+            f, "{cause} after generating {} shapes, the children of {} of {} parents with {} blocks are saved",
+            self.shapes, self.completed_parents, self.parents, self.blocks,
+        )
+    }
+}
+
+/// The outcome of a run.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct RunReport {
+    pub n: BlockCount,
+    pub algorithm: Algorithm,
+    /// The number of unique shapes with n blocks, zero if the run was stopped. Shapes discarded by
+    /// hereditary filters of [Runner::with_filter] while generating the levels are not counted.
+    pub shapes: usize,
+    /// The number of shapes passing the filters among the descendants of [RunConfig::descendants_of],
+    /// `None` without filters and parents.
+    pub accepted: Option<usize>,
+    /// The levels generated by this run, levels read from the caches of earlier runs are left out.
+    pub levels: Vec<LevelReport>,
+    pub exports: Vec<Export>,
+    pub seconds: f64,
+    /// Where the run stopped if a stop condition ended it early, `None` if it completed.
+    pub stopped: Option<StopPoint>,
+}
+
+/// Runs the enumeration of a [RunConfig]. Use [run] for a run without progress reporting.
+pub struct Runner<'a> {
+    config: &'a RunConfig,
+    progress: Arc<Progress>,
+    status: Box<dyn Fn(&str) + 'a>,
+    filters: Vec<Box<dyn ShapeFilter + 'a>>,
+    interrupt: Arc<AtomicBool>,
+    started: Instant,
+}
+
+impl<'a> Runner<'a> {
+    /// The run starts when the runner is created, which is when the time limit of the config starts.
+    pub fn new(config: &'a RunConfig) -> Self {
+        Self { config, progress: Arc::new(Progress::new(config.n)), status: Box::new(|_| {}), filters: vec![], interrupt: Arc::default(), started: Instant::now() }
+    }
+
+    /// Reports the progress of the run to the given one instead of a private one, e.g. to serve it elsewhere.
+    pub fn with_progress(mut self, progress: Arc<Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Passes every status message of the run to the callback, one line at a time.
+    /// Failures that do not stop the run, like a cache that could not be saved, are printed to stderr.
+    pub fn with_status(mut self, status: impl Fn(&str) + 'a) -> Self {
+        self.status = Box::new(status);
+        self
+    }
+
+    /// Only accepts the shapes with n blocks the filter keeps, in addition to the filters of the config.
+    /// A [hereditary](ShapeFilter::is_hereditary) filter also prunes the levels of [Algorithm::HashMap],
+    /// whose caches are then kept in a subdirectory of the cache directory named after these filters.
+    pub fn with_filter(mut self, filter: impl ShapeFilter + 'a) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Stops the run at the next safe point once the flag is set, e.g. by a Ctrl-C handler, like a stop
+    /// condition of the config. Only runs that [can be stopped](RunConfig::can_stop_early) ever check it.
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Whether the flag of [Runner::with_interrupt] is set.
+    pub fn interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed)
+    }
+
+    /// The filters of [Runner::with_filter] that may prune the levels of a run.
+    pub fn hereditary_filters(&self) -> impl Iterator<Item = &dyn ShapeFilter> {
+        self.filters.iter().map(|filter| filter.as_ref() as &dyn ShapeFilter).filter(|filter| filter.is_hereditary())
+    }
+
+    /// Whether every filter of the config and of [Runner::with_filter] keeps the shape.
+    pub fn accepts(&self, shape: &Polycube) -> bool {
+        self.config.accepts(shape) && self.filters.iter().all(|filter| filter.keep(shape))
+    }
+
+    pub fn config(&self) -> &'a RunConfig {
+        self.config
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Passes the message to the callback of [Runner::with_status].
+    pub fn status(&self, message: &str) {
+        (self.status)(message)
+    }
+
+    /// The stop condition of the config that is met after generating the given number of shapes, if any.
+    pub fn stop_reason(&self, shapes: u64) -> Option<StopReason> {
+        let config = self.config;
+        if self.interrupted() {
+            return Some(StopReason::Interrupted);
+        }
+        if config.stop_after_shapes.is_some_and(|limit| shapes >= limit) {
+            return Some(StopReason::Shapes);
+        }
+        if config.stop_after_seconds.is_some_and(|limit| self.started.elapsed().as_secs() >= limit) {
+            return Some(StopReason::Seconds);
+        }
+        None
+    }
+
+    /// Generates the shapes with n blocks, saving every level in the cache directory, and exports them.
+    pub fn run(&self) -> Result<RunReport, RunError> {
+        let config = self.config;
+        config.validate().map_err(RunError::InvalidConfig)?;
+        if config.count_only {
+            return self.count();
+        }
+        let pruned_by: Vec<_> = self.hereditary_filters().map(|filter| filter.name()).collect();
+        let store = match pruned_by.is_empty() {
+            true => config.store(),
+            false => config.filtered_store(&pruned_by),
+        }.map_err(RunError::Store)?;
+        let store = store.as_ref();
+        if let Err(e) = config.save(store) {
+            eprintln!("Failed to store the configuration: {e}");
+        }
+        OrientationRanking::global().set_order(config.orientation_order);
+        NeighborOrder::set_global(config.neighbor_order);
+        if let Some(address) = config.serve_address() {
+            match TcpListener::bind(&address) {
+                Ok(listener) => {
+                    self.status(&format!("Serving the progress on http://{address}"));
+                    serve(listener, self.progress.clone());
+                }
+                Err(e) => eprintln!("Failed to serve the progress on {address}: {e}"),
+            }
+        }
+        let n = config.n;
+        let mut report = RunReport {
+            n,
+            algorithm: config.algorithm,
+            shapes: 0,
+            accepted: None,
+            levels: vec![],
+            exports: vec![],
+            seconds: 0.0,
+            stopped: None,
+        };
+        let shapes = match config.algorithm.strategy().enumerate(self, store) {
+            Err(RunError::Stopped(stop)) => {
+                self.status(&format!("Stopped as {stop}, run again to continue."));
+                report.levels = self.progress.snapshot().levels;
+                report.seconds = self.started.elapsed().as_secs_f64();
+                report.stopped = Some(stop);
+                return Ok(report);
+            }
+            shapes => shapes?,
+        };
+        self.status(&format!("The number of unique arrangements of {n} blocks is {}", shapes.len()));
+        report.shapes = shapes.len();
+        let descendants = match config.descendants_of.is_empty() {
+            true => None,
+            false => Some(descendants(config, store)?),
+        };
+        if let Some(descendants) = &descendants {
+            self.status(&format!("{} of them descend from the given shapes", descendants.len()));
+        }
+        let filtered = !config.filters.is_empty() || !self.filters.is_empty();
+        if !config.output_formats.is_empty() || filtered || descendants.is_some() {
+            let accepted = self.accepted_shapes(descendants.as_deref().unwrap_or(&shapes)).map_err(RunError::InvalidShape)?;
+            if filtered {
+                self.status(&format!("{} of them pass the filters", accepted.len()));
+            }
+            if filtered || descendants.is_some() {
+                report.accepted = Some(accepted.len());
+            }
+            for format in &config.output_formats {
+                match write_output(config, store, &accepted, *format) {
+                    Ok(location) => {
+                        self.status(&format!("Exported the shapes as {format} to {location}"));
+                        report.exports.push(Export { format: *format, location });
+                    }
+                    Err(e) => eprintln!("Failed to export the shapes as {format}: {e}"),
+                }
+            }
+        }
+        report.levels = self.progress.snapshot().levels;
+        report.seconds = self.started.elapsed().as_secs_f64();
+        Ok(report)
+    }
+
+    /// Counts the shapes of every size up to n without storing them, see [count_levels].
+    fn count(&self) -> Result<RunReport, RunError> {
+        if !self.filters.is_empty() {
+            return Err(RunError::InvalidConfig("A run with --count-only keeps no shapes to filter"));
+        }
+        let n = self.config.n;
+        self.status(&format!("Counting the shapes with up to {n} blocks without storing them..."));
+        self.progress.start_level(n, 1);
+        let counts = count_levels(n, self.config.threads);
+        let shapes = counts.last().copied().unwrap_or(0) as usize;
+        self.progress.complete_parents(1);
+        self.progress.finish_level(shapes, None);
+        for (blocks, count) in counts.iter().enumerate() {
+            self.status(&format!("Found {count} shapes with {} blocks.", blocks + 1));
+        }
+        self.status(&format!("The number of unique arrangements of {n} blocks is {shapes}"));
+        Ok(RunReport {
+            n,
+            algorithm: self.config.algorithm,
+            shapes,
+            accepted: None,
+            levels: self.progress.snapshot().levels,
+            exports: vec![],
+            seconds: self.started.elapsed().as_secs_f64(),
+            stopped: None,
+        })
+    }
+
+    /// The shapes that pass the filters of the run.
+    /// Only the final level is filtered, except by hereditary filters, since the other filters do not
+    /// carry over from parents to their children.
+    fn accepted_shapes(&self, shapes: &[BlockArrangement]) -> Result<Vec<Polycube>, PolycubeError> {
+        let mut accepted = vec![];
+        for ba in shapes {
+            let polycube = Polycube::new(ba)?;
+            if self.accepts(&polycube) {
+                accepted.push(polycube);
+            }
+        }
+        Ok(accepted)
+    }
+}
+
+/// Runs the enumeration of the config without reporting its progress, see [Runner].
+pub fn run(config: &RunConfig) -> Result<RunReport, RunError> {
+    Runner::new(config).run()
+}
+
+/// The shapes with n blocks that are children of the [RunConfig::descendants_of] shapes in the stored
+/// [PolyTree](crate::poly_tree::PolyTree), ordered by their ids in the tree.
+fn descendants(config: &RunConfig, store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError> {
+    let tree = load_poly_tree(store).map_err(RunError::Store)?;
+    let mut children = BTreeSet::new();
+    for code in &config.descendants_of {
+        let parent = Polycube::from_code(code).map_err(RunError::InvalidShape)?;
+        if config.n.previous() != Some(parent.num_blocks()) {
+            return Err(RunError::UnknownParent(code.clone()));
+        }
+        let parent = tree.find(&parent)
+            .and_then(|id| tree.node(id))
+            .ok_or_else(|| RunError::UnknownParent(code.clone()))?;
+        children.extend(parent.children().iter().copied());
+    }
+    Ok(children.into_iter().map(|id| tree.shape(id).into_inner()).collect())
+}
+
+/// Exports the shapes into one file in the store.
+fn write_output(config: &RunConfig, store: &dyn CacheStore, shapes: &[Polycube], format: ExportFormat) -> Result<String, Error> {
+    let name = format!("shapes_{}.{format}", config.n);
+    let mut data = vec![];
+    for polycube in shapes {
+        writeln!(data, "{}", export(polycube, format))?;
+    }
+    store.put(&name, &data)?;
+    Ok(store.location(&name))
+}
+
+#[cfg(test)]
+mod run_tests {
+    use crate::cache::checkpoint::CheckpointManifest;
+    use crate::config::OutputFilter;
+    use crate::export::ExportFormat;
+    use crate::shape_filter::{MaxExtent, NoHoles};
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join("run_tests_run");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            n: BlockCount::new(3),
+            cache_dir: dir.clone(),
+            output_formats: vec![ExportFormat::Code],
+            filters: vec![OutputFilter::Achiral],
+            ..RunConfig::default()
+        };
+        let report = run(&config).expect("Valid config");
+        assert_eq!(2, report.shapes);
+        assert_eq!(Some(2), report.accepted);
+        assert_eq!(vec![2, 3], report.levels.iter().map(|level| level.block_count.get()).collect::<Vec<_>>());
+        assert_eq!(2, report.levels[1].shapes);
+        assert_eq!(Some(2), report.levels[1].one_sided);
+        assert_eq!(1, report.exports.len());
+        let exported = std::fs::read_to_string(&report.exports[0].location).expect("Exported shapes");
+        assert_eq!(2, exported.lines().count());
+
+        let messages = std::cell::RefCell::new(vec![]);
+        let report = Runner::new(&RunConfig { n: BlockCount::new(4), algorithm: Algorithm::PolyTree, ..config.clone() })
+            .with_status(|message| messages.borrow_mut().push(message.to_string()))
+            .run()
+            .unwrap();
+        assert_eq!(7, report.shapes);
+        assert!(messages.borrow().iter().any(|message| message == "The number of unique arrangements of 4 blocks is 7"));
+
+        // The I, L and T tetrominoes can be grown from the I tromino.
+        let family = RunConfig { n: BlockCount::new(4), algorithm: Algorithm::PolyTree, filters: vec![], descendants_of: vec!["1x1x3:07".to_string()], ..config.clone() };
+        let report = run(&family).unwrap();
+        assert_eq!((7, Some(3)), (report.shapes, report.accepted));
+        let exported = std::fs::read_to_string(&report.exports[0].location).expect("Exported shapes");
+        assert_eq!(3, exported.lines().count());
+        let unknown = RunConfig { descendants_of: vec!["1x1x2:03".to_string()], ..family.clone() };
+        assert!(matches!(run(&unknown), Err(RunError::UnknownParent(_))));
+        assert!(matches!(run(&RunConfig { algorithm: Algorithm::HashMap, ..family }), Err(RunError::InvalidConfig(_))));
+
+        // Counting neither caches nor exports anything, so it can not be combined with exports.
+        let count_only = RunConfig { n: BlockCount::new(5), cache_dir: dir.join("count_only"), output_formats: vec![], filters: vec![], count_only: true, ..config.clone() };
+        let report = run(&count_only).unwrap();
+        assert_eq!((23, None), (report.shapes, report.accepted));
+        assert!(!dir.join("count_only").exists());
+        assert!(matches!(run(&RunConfig { output_formats: vec![ExportFormat::Code], ..count_only }), Err(RunError::InvalidConfig(_))));
+
+        let invalid = RunConfig { n: BlockCount::default(), ..config };
+        assert!(matches!(run(&invalid), Err(RunError::InvalidConfig(_))));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_shape_filters() {
+        let dir = std::env::temp_dir().join("run_tests_shape_filters");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig { n: BlockCount::new(4), cache_dir: dir.clone(), ..RunConfig::default() };
+        // The square, the tripod and the screw fit into a 2x2x2 box, and all of them contain the L tromino.
+        let report = Runner::new(&config).with_filter(MaxExtent(2)).run().unwrap();
+        assert_eq!((3, Some(3)), (report.shapes, report.accepted));
+        assert_eq!(vec![1, 1, 3], report.levels.iter().map(|level| level.shapes).collect::<Vec<_>>());
+        // The screw and its mirror image are distinct one sided shapes.
+        assert_eq!(vec![Some(1), Some(1), Some(4)], report.levels.iter().map(|level| level.one_sided).collect::<Vec<_>>());
+        assert!(dir.join("filtered_max-extent-2").join("shape_cache_3.cac").exists());
+        assert!(!dir.join("shape_cache_3.cac").exists());
+
+        let report = Runner::new(&config).with_filter(NoHoles).with_filter(OutputFilter::Achiral).run().unwrap();
+        assert_eq!((7, Some(6)), (report.shapes, report.accepted));
+        assert!(dir.join("shape_cache_3.cac").exists());
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_stop() {
+        let dir = std::env::temp_dir().join("run_tests_stop");
+        let _ = std::fs::remove_dir_all(&dir);
+        let complete = RunConfig { n: BlockCount::new(4), cache_dir: dir.join("complete"), ..RunConfig::default() };
+        let expected = run(&complete).unwrap();
+        assert_eq!(None, expected.stopped);
+
+        // Every shard has a single parent. The limit is reached by the domino and the two trominoes, which
+        // stops the run before the first shard of the tetrominoes.
+        let config = RunConfig { shard_size: 1, stop_after_shapes: Some(2), cache_dir: dir.join("stopped"), ..complete.clone() };
+        let report = run(&config).unwrap();
+        let stop = StopPoint { reason: StopReason::Shapes, blocks: BlockCount::new(4), completed_parents: 0, parents: 2, shapes: 3 };
+        assert_eq!(Some(stop), report.stopped);
+        assert_eq!(0, report.shapes);
+        // Continues from the cached trominoes and stops in the middle of the tetrominoes.
+        let report = run(&RunConfig { stop_after_shapes: Some(1), ..config.clone() }).unwrap();
+        assert_eq!(Some((1, 2)), report.stopped.map(|stop| (stop.completed_parents, stop.parents)));
+
+        let messages = std::cell::RefCell::new(vec![]);
+        let resumed = RunConfig { stop_after_shapes: None, ..config.clone() };
+        let report = Runner::new(&resumed)
+            .with_status(|message| messages.borrow_mut().push(message.to_string()))
+            .run()
+            .unwrap();
+        assert_eq!((expected.shapes, None), (report.shapes, report.stopped));
+        assert!(messages.borrow().iter().any(|message| message == "Reused 1 partial shards covering 1 of 2 parents."), "{messages:?}");
+
+        // Without a shard size every parent still ends a shard of its own, which is saved with a checkpoint.
+        let checkpointed = RunConfig { checkpoint_seconds: Some(0), stop_after_shapes: Some(2), cache_dir: dir.join("checkpointed"), ..complete.clone() };
+        assert_eq!(Some((0, 2)), run(&checkpointed).unwrap().stopped.map(|stop| (stop.completed_parents, stop.parents)));
+        let report = run(&RunConfig { stop_after_shapes: Some(1), ..checkpointed.clone() }).unwrap();
+        assert_eq!(Some((1, 2)), report.stopped.map(|stop| (stop.completed_parents, stop.parents)));
+        let store = checkpointed.store().unwrap();
+        assert_eq!(1, CheckpointManifest::load(store.as_ref(), BlockCount::new(4)).unwrap().shards.len());
+        let report = run(&RunConfig { stop_after_shapes: None, ..checkpointed.clone() }).unwrap();
+        assert_eq!((expected.shapes, None), (report.shapes, report.stopped));
+        assert_eq!(CheckpointManifest::default(), CheckpointManifest::load(store.as_ref(), BlockCount::new(4)).unwrap());
+        assert!(matches!(run(&RunConfig { algorithm: Algorithm::PolyTree, ..checkpointed }), Err(RunError::InvalidConfig(_))));
+
+        // An interrupt before the run stops it before the dominoes, and the next run starts from scratch.
+        let interrupted = RunConfig { cache_dir: dir.join("interrupted"), ..complete.clone() };
+        let report = Runner::new(&interrupted).with_interrupt(Arc::new(AtomicBool::new(true))).run().unwrap();
+        let stop = StopPoint { reason: StopReason::Interrupted, blocks: BlockCount::new(2), completed_parents: 0, parents: 1, shapes: 0 };
+        assert_eq!(Some(stop), report.stopped);
+        assert_eq!((expected.shapes, None), run(&interrupted).map(|report| (report.shapes, report.stopped)).unwrap());
+
+        let timed = RunConfig { stop_after_seconds: Some(0), cache_dir: dir.join("timed"), ..complete.clone() };
+        assert_eq!(Some(StopReason::Seconds), run(&timed).unwrap().stopped.map(|stop| stop.reason));
+        let poly_tree = RunConfig { algorithm: Algorithm::PolyTree, ..timed };
+        assert!(matches!(run(&poly_tree), Err(RunError::InvalidConfig(_))));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
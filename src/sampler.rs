@@ -0,0 +1,192 @@
+use std::collections::BTreeSet;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use strum::IntoEnumIterator;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::point::{Axis3D, Point3D};
+use crate::polycube::Polycube;
+
+/// Where a [ShapeSampler] places the next block.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Growth {
+    /// Next to any block, every free neighbor of the shape being equally likely before the [GrowthBias]
+    /// is applied.
+    #[default]
+    Perimeter,
+    /// Next to the block placed last, like a self-avoiding walk. A walk that is trapped continues from a
+    /// random earlier block, so it branches instead of being discarded.
+    Walk,
+}
+
+/// Which shapes a [ShapeSampler] prefers. The strength is how many times more or less likely a
+/// preferred or penalized block is than any other, plus one.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum GrowthBias {
+    #[default]
+    None,
+    /// Penalizes blocks that make the bounding box thicker along its shortest axis.
+    Flat(f64),
+    /// Prefers blocks that make the bounding box longer along its longest axis.
+    Elongated(f64),
+}
+
+/// Generates random shapes with a fixed number of blocks by growing them from a single block, never
+/// placing a block onto an occupied cell. The shapes are not uniformly distributed over the free
+/// shapes, which makes them suited for exploring sizes that can not be enumerated rather than for
+/// counting. The same seed always results in the same shapes.
+pub struct ShapeSampler {
+    n: BlockCount,
+    growth: Growth,
+    bias: GrowthBias,
+    seed: u64,
+}
+
+impl ShapeSampler {
+    pub fn new(n: BlockCount) -> Self {
+        Self {
+            n,
+            growth: Growth::default(),
+            bias: GrowthBias::default(),
+            seed: 0,
+        }
+    }
+
+    pub fn with_growth(mut self, growth: Growth) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: GrowthBias) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Endlessly generates shapes, an empty shape is never generated.
+    pub fn iter(&self) -> impl Iterator<Item = Polycube> + '_ {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        std::iter::repeat_with(move || self.sample(&mut rng))
+    }
+
+    /// Grows one shape with the number of blocks of the sampler, at least one.
+    pub fn sample(&self, rng: &mut StdRng) -> Polycube {
+        let mut shape = Growing::new();
+        let mut last = Point3D::default();
+        while shape.len() < self.n.as_usize() {
+            let candidates: Vec<_> = match self.growth {
+                Growth::Walk => shape.free_neighbors(last).collect(),
+                Growth::Perimeter => vec![],
+            };
+            let candidates = match candidates.is_empty() {
+                true => shape.perimeter.iter().copied().collect(),
+                false => candidates,
+            };
+            let weights = candidates.iter().map(|candidate| self.weight(&shape, candidate));
+            let index = WeightedIndex::new(weights).expect("Every shape has free neighbors and every weight is positive.");
+            last = candidates[index.sample(rng)];
+            shape.add(last);
+        }
+        let points: Vec<_> = shape.blocks.into_iter().collect();
+        let ba = BlockArrangement::from_points(&points).expect("Every block is placed next to another one.");
+        Polycube::new(&ba).expect("Every block is placed next to another one.")
+    }
+
+    fn weight(&self, shape: &Growing, candidate: &Point3D<i32>) -> f64 {
+        let before = shape.extents();
+        let after = shape.extents_with(candidate);
+        match self.bias {
+            GrowthBias::None => 1.0,
+            GrowthBias::Flat(strength) if after.iter().min() > before.iter().min() => 1.0 / (1.0 + strength.max(0.0)),
+            GrowthBias::Elongated(strength) if after.iter().max() > before.iter().max() => 1.0 + strength.max(0.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// A shape being grown together with the free cells next to it.
+struct Growing {
+    blocks: BTreeSet<Point3D<i32>>,
+    /// Kept ordered so the same seed picks the same cells.
+    perimeter: BTreeSet<Point3D<i32>>,
+    min: Point3D<i32>,
+    max: Point3D<i32>,
+}
+
+impl Growing {
+    fn new() -> Self {
+        let origin = Point3D::default();
+        let mut shape = Self { blocks: BTreeSet::new(), perimeter: BTreeSet::new(), min: origin, max: origin };
+        shape.add(origin);
+        shape
+    }
+
+    fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn add(&mut self, block: Point3D<i32>) {
+        self.blocks.insert(block);
+        self.perimeter.remove(&block);
+        for offset in BlockArrangement::NEIGHBOR_OFFSETS {
+            let neighbor = block + offset;
+            if !self.blocks.contains(&neighbor) {
+                self.perimeter.insert(neighbor);
+            }
+        }
+        for axis in Axis3D::iter() {
+            self.min[axis] = self.min[axis].min(block[axis]);
+            self.max[axis] = self.max[axis].max(block[axis]);
+        }
+    }
+
+    fn free_neighbors(&self, block: Point3D<i32>) -> impl Iterator<Item = Point3D<i32>> + '_ {
+        BlockArrangement::NEIGHBOR_OFFSETS.into_iter()
+            .map(move |offset| block + offset)
+            .filter(|neighbor| !self.blocks.contains(neighbor))
+    }
+
+    /// The length of the bounding box along every axis minus one.
+    fn extents(&self) -> [i32; 3] {
+        [Axis3D::X, Axis3D::Y, Axis3D::Z].map(|axis| self.max[axis] - self.min[axis])
+    }
+
+    /// The extents after adding the block.
+    fn extents_with(&self, block: &Point3D<i32>) -> [i32; 3] {
+        [Axis3D::X, Axis3D::Y, Axis3D::Z].map(|axis| self.max[axis].max(block[axis]) - self.min[axis].min(block[axis]))
+    }
+}
+
+#[cfg(test)]
+mod sampler_tests {
+    use super::*;
+
+    /// The number of the shapes whose bounding box is one block thick.
+    fn planar(shapes: &[Polycube]) -> usize {
+        shapes.iter().filter(|shape| shape.occupancy().size.contains(&1)).count()
+    }
+
+    #[test]
+    fn test_sampler() {
+        for growth in [Growth::Perimeter, Growth::Walk] {
+            let sampler = ShapeSampler::new(BlockCount::new(12)).with_growth(growth).with_seed(3);
+            let shapes: Vec<_> = sampler.iter().take(20).collect();
+            assert!(shapes.iter().all(|shape| shape.num_blocks() == BlockCount::new(12)));
+            assert_eq!(shapes, sampler.iter().take(20).collect::<Vec<_>>());
+        }
+        assert_eq!(BlockCount::ONE, ShapeSampler::new(BlockCount::ONE).iter().next().unwrap().num_blocks());
+
+        let unbiased: Vec<_> = ShapeSampler::new(BlockCount::new(10)).iter().take(100).collect();
+        let flat: Vec<_> = ShapeSampler::new(BlockCount::new(10)).with_bias(GrowthBias::Flat(100.0)).iter().take(100).collect();
+        assert!(planar(&flat) > 90, "{} flat shapes", planar(&flat));
+        assert!(planar(&unbiased) < planar(&flat));
+        let longest = |shapes: &[Polycube]| shapes.iter().map(|shape| *shape.occupancy().size.iter().max().unwrap()).sum::<u32>();
+        let elongated: Vec<_> = ShapeSampler::new(BlockCount::new(10)).with_bias(GrowthBias::Elongated(10.0)).iter().take(100).collect();
+        assert!(longest(&elongated) > longest(&unbiased));
+    }
+}
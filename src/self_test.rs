@@ -0,0 +1,266 @@
+//! A quick check of the build and the platform before a long run is started. [SelfTest] enumerates the
+//! small sizes with several algorithms and compares their counts with [KNOWN_COUNTS], writes caches and
+//! reads them back, and round-trips random shapes through the serializations, including corrupted ones.
+//! Every check runs in a temporary directory and a check that panics fails instead of aborting the others.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::block_hash::BlockHash;
+use crate::cache::verify::verify_cache;
+use crate::cache::{write_cache, CacheFormat, CacheReader, Compression};
+use crate::config::{Algorithm, RunConfig};
+use crate::poly_tree::PolyTree;
+use crate::polycube::{Occupancy, Polycube};
+use crate::run::run;
+use crate::sampler::ShapeSampler;
+
+/// The number of free shapes with 1, 2, 3, ... blocks, counting a shape and its mirror image as one
+/// like every algorithm of this crate does (OEIS A038119).
+pub const KNOWN_COUNTS: [u64; 16] = [
+    1, 1, 2, 7, 23, 112, 607, 3811, 25413, 178083, 1279537, 9371094, 69513546, 520878101, 3934285874, 29915913663,
+];
+
+/// The number of free shapes with n blocks from [KNOWN_COUNTS], `None` beyond the table.
+pub fn known_count(n: BlockCount) -> Option<u64> {
+    KNOWN_COUNTS.get(n.as_usize().checked_sub(1)?).copied()
+}
+
+/// The largest number of blocks enumerated by default, which takes a few seconds.
+pub const DEFAULT_MAX_BLOCKS: BlockCount = BlockCount::new(6);
+
+/// The number of random shapes serialized by default.
+pub const DEFAULT_SAMPLES: usize = 500;
+
+/// Random shapes have up to this many blocks.
+const MAX_SAMPLE_BLOCKS: usize = 24;
+
+/// The outcome of one check of a [SelfTest].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct CheckOutcome {
+    pub name: String,
+    pub passed: bool,
+    /// What was checked, or why the check failed.
+    pub detail: String,
+}
+
+/// The checks of the `self-test` command, see the module documentation.
+/// [Algorithm::Subtractive] is not checked by default since it alone takes longer than all other checks.
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    max_blocks: BlockCount,
+    algorithms: Vec<Algorithm>,
+    samples: usize,
+    seed: u64,
+    dir: PathBuf,
+}
+
+impl Default for SelfTest {
+    fn default() -> Self {
+        Self {
+            max_blocks: DEFAULT_MAX_BLOCKS,
+            algorithms: vec![Algorithm::HashMap, Algorithm::PolyTree, Algorithm::DoubleExtension],
+            samples: DEFAULT_SAMPLES,
+            seed: 0,
+            dir: std::env::temp_dir().join(format!("polycubes_self_test_{}", std::process::id())),
+        }
+    }
+}
+
+impl SelfTest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enumerates the shapes with up to this many blocks, at most the last entry of [KNOWN_COUNTS].
+    pub fn with_max_blocks(mut self, max_blocks: BlockCount) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+
+    /// Checks the counts of these algorithms instead.
+    pub fn with_algorithms(mut self, algorithms: Vec<Algorithm>) -> Self {
+        self.algorithms = algorithms;
+        self
+    }
+
+    /// Serializes this many random shapes.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Chooses other random shapes and corruptions, the same seed always checks the same ones.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Writes the caches of the checks into this directory instead of a new one in the temporary
+    /// directory. It is removed afterwards either way.
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Runs every check, passing each outcome to the callback as soon as it is known.
+    pub fn run(&self, mut on_outcome: impl FnMut(&CheckOutcome)) -> Vec<CheckOutcome> {
+        let mut outcomes = vec![];
+        let mut record = |name: String, check: &dyn Fn() -> Result<String, String>| {
+            let outcome = run_check(name, check);
+            on_outcome(&outcome);
+            outcomes.push(outcome);
+        };
+        for algorithm in &self.algorithms {
+            record(format!("counts of {algorithm}"), &|| self.check_counts(*algorithm));
+        }
+        record("cache round trip".to_string(), &|| self.check_caches());
+        record("serialization".to_string(), &|| self.check_serialization());
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            eprintln!("Failed to remove {}: {e}", self.dir.display());
+        }
+        outcomes
+    }
+
+    fn check_counts(&self, algorithm: Algorithm) -> Result<String, String> {
+        let mut counts = vec![];
+        for n in BlockCount::ONE.up_to(self.max_blocks) {
+            let expected = known_count(n).ok_or(format!("The number of shapes with {n} blocks is unknown"))?;
+            let config = RunConfig { n, algorithm, cache_dir: self.dir.join(algorithm.to_string()), ..RunConfig::default() };
+            let report = run(&config).map_err(|e| format!("The run of {n} blocks failed, {e}"))?;
+            if report.shapes as u64 != expected {
+                return Err(format!("Found {} shapes with {n} blocks instead of {expected}", report.shapes));
+            }
+            counts.push(expected.to_string());
+        }
+        Ok(format!("Found {} shapes", counts.join(", ")))
+    }
+
+    /// Writes the shapes with the largest number of blocks in every format, reads them back and
+    /// verifies every record.
+    fn check_caches(&self) -> Result<String, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {e}", self.dir.display()))?;
+        let mut tree = PolyTree::new();
+        while tree.depth() < self.max_blocks {
+            tree.generate_next_level();
+        }
+        // Not collected into a Level, which keeps a single shape per hash.
+        let records: Vec<_> = tree.iter_level(self.max_blocks).map(|ba| (BlockHash::from(&ba), ba)).collect();
+        let expected = codes(records.iter().map(|(_, ba)| ba))?;
+        let formats = [
+            // Version 1 keys are decimals.
+            #[cfg(feature = "decimal-metrics")]
+            (CacheFormat::V1, Compression::None, false),
+            (CacheFormat::V2, Compression::None, false),
+            (CacheFormat::V2, Compression::Zstd, true),
+        ];
+        for (format, compression, indexed) in formats {
+            let name = format!("{format} {compression}{}", if indexed { " indexed" } else { "" });
+            let path = self.dir.join(format!("round_trip_{format}_{compression}.cac"));
+            write_cache(&path, records.iter().map(|(hash, ba)| (hash, ba)), records.len(), format, compression, indexed)
+                .map_err(|e| format!("Failed to write a {name} cache: {e}"))?;
+            let read = CacheReader::open(&path)
+                .and_then(|reader| reader.map(|record| record.map(|(_, ba)| ba)).collect::<Result<Vec<_>, _>>())
+                .map_err(|e| format!("Failed to read a {name} cache: {e}"))?;
+            if read.len() != records.len() || codes(read.iter())? != expected {
+                return Err(format!("A {name} cache read back other shapes than were written"));
+            }
+            let file = File::open(&path).map_err(|e| format!("Failed to open a {name} cache: {e}"))?;
+            let report = verify_cache(file).map_err(|e| format!("Failed to verify a {name} cache: {e}"))?;
+            if let Some((record, problem)) = report.problems.first() {
+                return Err(format!("Record {record} of a {name} cache is invalid, {problem}"));
+            }
+        }
+        Ok(format!("Wrote and read back {} shapes in {} formats", records.len(), formats.len()))
+    }
+
+    /// Round-trips random shapes through their codes, their encoded [Occupancy] and their JSON, and
+    /// decodes a copy of every encoding with a random byte changed, which may fail but must not panic.
+    fn check_serialization(&self) -> Result<String, String> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let config = bincode::config::standard();
+        for i in 0..self.samples {
+            let n = BlockCount::try_from(1 + i % MAX_SAMPLE_BLOCKS).expect("A valid block count");
+            let polycube = ShapeSampler::new(n).sample(&mut rng);
+            let code = polycube.code();
+            if Polycube::from_code(&code).as_ref() != Ok(&polycube) {
+                return Err(format!("The code {code} does not decode to its shape"));
+            }
+            let encoded = bincode::serde::encode_to_vec(polycube.occupancy(), config).map_err(|e| e.to_string())?;
+            let decoded: Result<(Occupancy, usize), _> = bincode::serde::decode_from_slice(&encoded, config);
+            if decoded.ok().and_then(|(occupancy, _)| Polycube::from_occupancy(&occupancy).ok()).as_ref() != Some(&polycube) {
+                return Err(format!("The occupancy of {code} does not decode to its shape"));
+            }
+            let json = serde_json::to_string(polycube.arrangement()).map_err(|e| e.to_string())?;
+            let decoded: Result<BlockArrangement, _> = serde_json::from_str(&json);
+            if decoded.ok().and_then(|ba| Polycube::new(&ba).ok()).as_ref() != Some(&polycube) {
+                return Err(format!("The JSON of {code} does not decode to its shape"));
+            }
+
+            let mut corrupted = encoded.clone();
+            let position = rng.gen_range(0..corrupted.len());
+            corrupted[position] ^= rng.gen_range(1..=u8::MAX);
+            if let Ok((occupancy, _)) = bincode::serde::decode_from_slice::<Occupancy, _>(&corrupted, config) {
+                let _ = Polycube::from_occupancy(&occupancy);
+            }
+            let mut corrupted = code.into_bytes();
+            let position = rng.gen_range(0..corrupted.len());
+            corrupted[position] = rng.gen_range(b' '..=b'~');
+            let _ = Polycube::from_code(&String::from_utf8_lossy(&corrupted));
+        }
+        Ok(format!("Round-tripped {} random shapes of up to {MAX_SAMPLE_BLOCKS} blocks", self.samples))
+    }
+}
+
+/// Runs the check, turning a panic into a failure with the message of the panic.
+fn run_check(name: String, check: &dyn Fn() -> Result<String, String>) -> CheckOutcome {
+    let result = panic::catch_unwind(AssertUnwindSafe(check)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown reason".to_string());
+        Err(format!("Panicked: {message}"))
+    });
+    match result {
+        Ok(detail) => CheckOutcome { name, passed: true, detail },
+        Err(detail) => CheckOutcome { name, passed: false, detail },
+    }
+}
+
+fn codes<'a>(shapes: impl Iterator<Item = &'a BlockArrangement>) -> Result<BTreeSet<String>, String> {
+    shapes
+        .map(|ba| Polycube::new(ba).map(|polycube| polycube.code()).map_err(|e| format!("Invalid shape: {e}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod self_test_tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(Some(112), known_count(BlockCount::new(6)));
+        assert_eq!(None, known_count(BlockCount::new(17)));
+        let dir = std::env::temp_dir().join("self_test_tests_self_test");
+        let mut reported = 0;
+        let outcomes = SelfTest::new()
+            .with_max_blocks(BlockCount::new(5))
+            .with_algorithms(vec![Algorithm::PolyTree, Algorithm::DoubleExtension])
+            .with_samples(50)
+            .with_dir(&dir)
+            .run(|_| reported += 1);
+        assert_eq!(4, reported);
+        assert!(outcomes.iter().all(|outcome| outcome.passed), "{outcomes:?}");
+        assert_eq!("Found 1, 1, 2, 7, 23 shapes", outcomes[0].detail);
+        assert!(!dir.exists());
+
+        let failed = run_check("failing".to_string(), &|| panic!("broken"));
+        assert_eq!(CheckOutcome { name: "failing".to_string(), passed: false, detail: "Panicked: broken".to_string() }, failed);
+    }
+}
@@ -0,0 +1,138 @@
+//! Constrained enumerations without a fork of the generation loop: a [ShapeFilter] decides for every
+//! canonical shape whether it is kept, and [Runner::with_filter](crate::run::Runner::with_filter) hands
+//! it to the enumeration of a run.
+//!
+//! Every filter decides which shapes with n blocks a run accepts. A [hereditary](ShapeFilter::is_hereditary)
+//! filter additionally prunes the levels below n while they are generated by [Algorithm::HashMap](crate::config::Algorithm::HashMap),
+//! since none of the descendants of a shape it discards would be kept either. The [OutputFilter]s of a
+//! [RunConfig](crate::config::RunConfig) are filters as well.
+
+use crate::config::OutputFilter;
+use crate::metrics::enclosed_cells;
+use crate::polycube::Polycube;
+
+/// Keeps or discards a canonical shape.
+pub trait ShapeFilter: Send + Sync {
+    /// Whether the shape is kept.
+    fn keep(&self, shape: &Polycube) -> bool;
+
+    /// Whether no shape grown from a discarded shape by adding blocks is ever kept, like a limit on the
+    /// bounding box which never shrinks. Only such filters may prune the levels of a run.
+    fn is_hereditary(&self) -> bool {
+        false
+    }
+
+    /// Identifies the filter with its parameters in the name of the directory pruned levels are cached
+    /// in, so it may only contain characters that are valid in file names.
+    fn name(&self) -> String;
+}
+
+impl ShapeFilter for OutputFilter {
+    fn keep(&self, shape: &Polycube) -> bool {
+        self.accepts(shape)
+    }
+
+    fn name(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Keeps the shapes whose bounding box is at most this long along its shortest axis, like
+/// [RunConfig::max_layers](crate::config::RunConfig::max_layers).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MaxLayers(pub u32);
+
+impl ShapeFilter for MaxLayers {
+    fn keep(&self, shape: &Polycube) -> bool {
+        let (width, depth, height) = shape.bounding_box().all_axis_len();
+        width.min(depth).min(height) <= u64::from(self.0)
+    }
+
+    fn is_hereditary(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> String {
+        format!("max-layers-{}", self.0)
+    }
+}
+
+/// Keeps the shapes whose bounding box is at most this long along its longest axis, so they stand
+/// at most this high however they are placed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MaxExtent(pub u32);
+
+impl ShapeFilter for MaxExtent {
+    fn keep(&self, shape: &Polycube) -> bool {
+        let (width, depth, height) = shape.bounding_box().all_axis_len();
+        width.max(depth).max(height) <= u64::from(self.0)
+    }
+
+    fn is_hereditary(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> String {
+        format!("max-extent-{}", self.0)
+    }
+}
+
+/// Keeps the shapes without [enclosed cells](enclosed_cells). Not hereditary since a later block may
+/// fill the cavity.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NoHoles;
+
+impl ShapeFilter for NoHoles {
+    fn keep(&self, shape: &Polycube) -> bool {
+        enclosed_cells(shape).is_empty()
+    }
+
+    fn name(&self) -> String {
+        "no-holes".to_string()
+    }
+}
+
+/// Keeps the shapes whose [surface area](crate::block_arrangement::BlockArrangement::surface_area)
+/// lies between both bounds, inclusively. Not hereditary since a block added into a corner covers more
+/// faces than it adds.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SurfaceArea {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl ShapeFilter for SurfaceArea {
+    fn keep(&self, shape: &Polycube) -> bool {
+        (self.min..=self.max).contains(&shape.surface_area())
+    }
+
+    fn name(&self) -> String {
+        format!("surface-area-{}-{}", self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod shape_filter_tests {
+    use crate::block_count::BlockCount;
+    use crate::polycubes::Polycubes;
+    use super::*;
+
+    #[test]
+    fn test_filters() {
+        let pentacubes = Polycubes::builtin(BlockCount::new(5)).unwrap();
+        let count = |filter: &dyn ShapeFilter| pentacubes.iter().filter(|shape| filter.keep(shape)).count();
+        assert_eq!(23, pentacubes.len());
+        // The 12 pentominoes lie flat.
+        assert_eq!(12, count(&MaxLayers(1)));
+        assert_eq!(23, count(&MaxLayers(2)));
+        // Only the I pentomino is five long, and the L, N and Y pentominoes are four long.
+        assert_eq!(19, count(&MaxExtent(3)));
+        assert_eq!(22, count(&MaxExtent(4)));
+        assert_eq!(23, count(&NoHoles));
+        // Only the shapes containing a square of four blocks hide more than eight faces.
+        assert_eq!(2, count(&SurfaceArea { min: 0, max: 20 }));
+        assert_eq!(6, count(&OutputFilter::Chiral));
+        assert!(MaxExtent(3).is_hereditary() && !NoHoles.is_hereditary() && !OutputFilter::Chiral.is_hereditary());
+        assert_eq!("max-extent-3", MaxExtent(3).name());
+    }
+}
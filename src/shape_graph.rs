@@ -0,0 +1,128 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, Write};
+use strum::{Display, EnumString};
+use crate::morph::neighbors;
+use crate::polycube::Polycube;
+
+/// The file formats a [ShapeGraph] can be written in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum GraphFormat {
+    /// The Graphviz language.
+    Dot,
+    /// The XML format read by most network analysis tools.
+    GraphMl,
+}
+
+/// The graph whose nodes are shapes of one size and whose edges connect shapes that differ by a single
+/// [Move](crate::morph::Move). The nodes are identified by their position in the given shapes, which
+/// matches their id in a cache when the shapes are read from one.
+pub struct ShapeGraph {
+    shapes: Vec<Polycube>,
+    /// Every edge once, with the smaller node first.
+    edges: BTreeSet<(usize, usize)>,
+}
+
+impl ShapeGraph {
+    /// Connects the shapes. Moves to shapes that are not part of the given ones are ignored.
+    pub fn new(shapes: Vec<Polycube>) -> Self {
+        let ids: HashMap<&Polycube, usize> = shapes.iter().enumerate().map(|(id, shape)| (shape, id)).collect();
+        let edges = shapes.iter().enumerate()
+            .flat_map(|(id, shape)| neighbors(shape).into_iter()
+                .filter_map(|neighbor| ids.get(&neighbor).copied())
+                .map(move |other| (id.min(other), id.max(other))))
+            .collect();
+        Self { shapes, edges }
+    }
+
+    pub fn shapes(&self) -> &[Polycube] {
+        &self.shapes
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.edges.iter().copied()
+    }
+
+    /// The number of edges of every node.
+    pub fn degrees(&self) -> Vec<usize> {
+        let mut degrees = vec![0; self.shapes.len()];
+        for (a, b) in self.edges() {
+            degrees[a] += 1;
+            degrees[b] += 1;
+        }
+        degrees
+    }
+
+    /// Writes the graph with the code of every shape as the label of its node.
+    pub fn write(&self, writer: &mut impl Write, format: GraphFormat) -> Result<(), Error> {
+        match format {
+            GraphFormat::Dot => {
+                writeln!(writer, "graph shapes {{")?;
+                for (id, shape) in self.shapes.iter().enumerate() {
+                    writeln!(writer, "  {id} [label=\"{}\"];", shape.code())?;
+                }
+                for (a, b) in self.edges() {
+                    writeln!(writer, "  {a} -- {b};")?;
+                }
+                writeln!(writer, "}}")?;
+            }
+            GraphFormat::GraphMl => {
+                writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+                writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+                writeln!(writer, r#"  <key id="code" for="node" attr.name="code" attr.type="string"/>"#)?;
+                writeln!(writer, r#"  <graph id="shapes" edgedefault="undirected">"#)?;
+                for (id, shape) in self.shapes.iter().enumerate() {
+                    writeln!(writer, r#"    <node id="n{id}"><data key="code">{}</data></node>"#, shape.code())?;
+                }
+                for (a, b) in self.edges() {
+                    writeln!(writer, r#"    <edge source="n{a}" target="n{b}"/>"#)?;
+                }
+                writeln!(writer, "  </graph>\n</graphml>")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod shape_graph_tests {
+    use std::str::FromStr;
+    use crate::block_count::BlockCount;
+    use crate::poly_tree::PolyTree;
+    use super::*;
+
+    fn graph(block_count: u32) -> ShapeGraph {
+        let mut tree = PolyTree::new();
+        (0..block_count).for_each(|_| { tree.generate_next_level(); });
+        ShapeGraph::new(tree.iter_level(BlockCount::new(block_count)).map(|ba| Polycube::new(&ba).unwrap()).collect())
+    }
+
+    #[test]
+    fn test_edges() {
+        assert_eq!(vec![(0, 1)], graph(3).edges().collect::<Vec<_>>());
+        let tetracubes = graph(4);
+        assert_eq!(7, tetracubes.shapes().len());
+        // Every shape can be reached from every other one, so no node is isolated.
+        assert!(tetracubes.degrees().iter().all(|degree| *degree > 0));
+        let degree_sum: usize = tetracubes.degrees().iter().sum();
+        assert_eq!(2 * tetracubes.edges().count(), degree_sum);
+    }
+
+    #[test]
+    fn test_write() {
+        let trominoes = graph(3);
+        let mut dot = vec![];
+        trominoes.write(&mut dot, GraphFormat::Dot).expect("Writable buffer");
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("graph shapes {"));
+        assert!(dot.contains("  0 -- 1;"));
+        assert!(dot.contains(&format!("[label=\"{}\"]", trominoes.shapes()[1].code())));
+
+        let mut graphml = vec![];
+        trominoes.write(&mut graphml, GraphFormat::from_str("graphml").unwrap()).expect("Writable buffer");
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert_eq!(2, graphml.matches("<node ").count());
+        assert!(graphml.contains(r#"<edge source="n0" target="n1"/>"#));
+    }
+}
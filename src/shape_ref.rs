@@ -0,0 +1,237 @@
+//! Borrowed views of the shapes of a cache. A [ShapeRef] reads the blocks of a shape straight from the
+//! encoded bits of its [Occupancy], so tools that only inspect shapes do not have to build an owned
+//! [BlockArrangement](crate::block_arrangement::BlockArrangement) for every record. The views borrow from
+//! the mapped cache file if it is uncompressed and on the local disk, and from a decompressed copy of the
+//! records otherwise, see [load_shapes](crate::cache::load_shapes).
+
+use std::io::{Error, ErrorKind};
+use crate::point::Point3D;
+use crate::polycube::{Occupancy, Polycube, PolycubeError};
+
+/// The blocks of a shape as one bit per cell of its bounding box, borrowed from an [Occupancy] or from
+/// the records of a cache, see [ShapeRefs].
+/// The shapes of a cache are canonically oriented, so two views of cached shapes are congruent exactly
+/// if they are equal.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ShapeRef<'a> {
+    size: [u32; 3],
+    bits: &'a [u8],
+}
+
+impl<'a> ShapeRef<'a> {
+    /// Views the bits of a bounding box of the given size, which have to be as many as in an [Occupancy].
+    pub fn new(size: [u32; 3], bits: &'a [u8]) -> Result<Self, PolycubeError> {
+        let cells = size.iter().try_fold(1usize, |cells, &len| cells.checked_mul(len as usize))
+            .ok_or(PolycubeError::InvalidCode)?;
+        match bits.len() == cells.div_ceil(8) {
+            true => Ok(Self { size, bits }),
+            false => Err(PolycubeError::InvalidCode),
+        }
+    }
+
+    /// The width, depth and height of the bounding box.
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn bits(&self) -> &'a [u8] {
+        self.bits
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.bits.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    /// Whether the cell of the bounding box is a block. Cells outside of the bounding box never are.
+    pub fn is_set(&self, p: &Point3D<i32>) -> bool {
+        self.index(*p.x(), *p.y(), *p.z()).is_some_and(|index| self.bit(index))
+    }
+
+    /// The blocks ordered by z, y and x, with the smallest corner of the bounding box at the origin.
+    pub fn block_iter(&self) -> impl Iterator<Item = Point3D<i32>> + 'a {
+        let [width, depth, _] = self.size.map(|len| len as usize);
+        let view = *self;
+        (0..self.cells())
+            .filter(move |&index| view.bit(index))
+            .map(move |index| Point3D::new(
+                (index % width) as i32,
+                ((index / width) % depth) as i32,
+                (index / (width * depth)) as i32,
+            ))
+    }
+
+    /// The number of block faces that are not shared with another block,
+    /// like [BlockArrangement::surface_area](crate::block_arrangement::BlockArrangement::surface_area).
+    pub fn surface_area(&self) -> usize {
+        let shared_faces: usize = self.block_iter()
+            .map(|p| [(1, 0, 0), (0, 1, 0), (0, 0, 1)].iter()
+                .filter(|(x, y, z)| self.is_set(&Point3D::new(p.x() + x, p.y() + y, p.z() + z)))
+                .count())
+            .sum();
+        6 * self.num_blocks() - 2 * shared_faces
+    }
+
+    /// Copies the view into an owned [Occupancy].
+    pub fn to_occupancy(&self) -> Occupancy {
+        Occupancy { size: self.size, bits: self.bits.to_vec() }
+    }
+
+    /// Builds the owned polycube of the shape, for the parts of the shape API a view does not offer.
+    pub fn to_polycube(&self) -> Result<Polycube, PolycubeError> {
+        Polycube::from_occupancy(&self.to_occupancy())
+    }
+
+    /// Whether the shapes are equal up to rotations and reflections. Views of canonically oriented shapes,
+    /// like the records of a cache, are compared without building either shape.
+    pub fn is_congruent(&self, other: &ShapeRef<'_>) -> bool {
+        let sorted = |mut size: [u32; 3]| {
+            size.sort();
+            size
+        };
+        if self == other {
+            return true;
+        }
+        if sorted(self.size) != sorted(other.size) || self.num_blocks() != other.num_blocks() {
+            return false;
+        }
+        match (self.to_polycube(), other.to_polycube()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.size.iter().map(|&len| len as usize).product()
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn index(&self, x: i32, y: i32, z: i32) -> Option<usize> {
+        let [width, depth, height] = self.size.map(|len| len as i32);
+        let inside = (0..width).contains(&x) && (0..depth).contains(&y) && (0..height).contains(&z);
+        inside.then(|| x as usize + width as usize * (y as usize + depth as usize * z as usize))
+    }
+}
+
+impl<'a> TryFrom<&'a Occupancy> for ShapeRef<'a> {
+    type Error = PolycubeError;
+
+    fn try_from(occupancy: &'a Occupancy) -> Result<Self, Self::Error> {
+        Self::new(occupancy.size, &occupancy.bits)
+    }
+}
+
+/// Views the consecutive encoded [Occupancy] records of an uncompressed cache without copying them.
+/// Every record is its three axis lengths followed by the number of bytes and the bytes of its bits,
+/// all lengths being variable length integers as written by bincode.
+pub struct ShapeRefs<'a> {
+    data: &'a [u8],
+    remaining: u64,
+}
+
+impl<'a> ShapeRefs<'a> {
+    /// Views the given number of records at the start of the data.
+    pub fn new(data: &'a [u8], records: u64) -> Self {
+        Self { data, remaining: records }
+    }
+
+    /// The number of records that have not been viewed yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    fn read_record(&mut self) -> Result<ShapeRef<'a>, Error> {
+        let mut size = [0; 3];
+        for len in &mut size {
+            *len = u32::try_from(self.read_varint()?)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Axis length out of range"))?;
+        }
+        let len = usize::try_from(self.read_varint()?)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Record length out of range"))?;
+        let bits = self.take(len)?;
+        ShapeRef::new(size, bits).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Reads an integer in the variable length encoding of bincode: a single byte below 251, otherwise a
+    /// marker byte followed by a little endian u16, u32 or u64.
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let marker = self.take(1)?[0];
+        let width = match marker {
+            0..=250 => return Ok(u64::from(marker)),
+            251 => 2,
+            252 => 4,
+            253 => 8,
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("Invalid integer marker {marker}"))),
+        };
+        let mut bytes = [0; 8];
+        bytes[..width].copy_from_slice(self.take(width)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "The cache is truncated"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+impl<'a> Iterator for ShapeRefs<'a> {
+    type Item = Result<ShapeRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let record = self.read_record();
+        if record.is_err() {
+            self.remaining = 0;
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod shape_ref_tests {
+    use crate::block_arrangement::BlockArrangement;
+    use crate::block_arrangement::block_variation::VariationGenerator;
+    use super::*;
+
+    #[test]
+    fn test_shape_refs() {
+        let mut data = vec![];
+        let mut polycubes = vec![];
+        let ba = BlockArrangement::new();
+        let trominoes = VariationGenerator::new(&ba)
+            .flat_map(|ba| VariationGenerator::new(&ba).collect::<Vec<_>>());
+        for ba in trominoes {
+            let polycube = Polycube::new(&ba).unwrap();
+            bincode::serde::encode_into_std_write(polycube.occupancy(), &mut data, bincode::config::standard()).unwrap();
+            polycubes.push(polycube);
+        }
+        let views: Vec<_> = ShapeRefs::new(&data, polycubes.len() as u64).collect::<Result<_, _>>().unwrap();
+        assert_eq!(polycubes.len(), views.len());
+        for (view, polycube) in views.iter().zip(&polycubes) {
+            let occupancy = polycube.occupancy();
+            assert_eq!(ShapeRef::try_from(&occupancy).unwrap(), *view);
+            assert_eq!(polycube.num_blocks().as_usize(), view.num_blocks());
+            assert_eq!(polycube.surface_area(), view.surface_area());
+            assert_eq!(view.num_blocks(), view.block_iter().filter(|p| view.is_set(p)).count());
+            assert_eq!(*polycube, view.to_polycube().unwrap());
+            assert!(views.iter().all(|other| view.is_congruent(other) == (polycube == &other.to_polycube().unwrap())));
+        }
+
+        // An unsorted bounding box is congruent to its canonical one.
+        let line = Occupancy { size: [1, 1, 3], bits: vec![0b111] };
+        let canonical = Polycube::from_occupancy(&line).unwrap().occupancy();
+        assert!(ShapeRef::try_from(&line).unwrap().is_congruent(&ShapeRef::try_from(&canonical).unwrap()));
+        assert!(ShapeRef::new([2, 2, 2], &[0xFF, 0]).is_err());
+        assert_eq!(Some(ErrorKind::UnexpectedEof), ShapeRefs::new(&data[..data.len() - 1], polycubes.len() as u64)
+            .find_map(|view| view.err()).map(|e| e.kind()));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "s3")]
+pub mod s3;
+
+/// A flat collection of named files the caches, partial shards and outputs of runs are stored in.
+/// Names never contain a path separator, see [check_name].
+pub trait CacheStore: Send + Sync {
+    /// Opens the file for reading. Fails with [ErrorKind::NotFound] if it does not exist.
+    fn open(&self, name: &str) -> Result<Box<dyn Read + Send>, Error>;
+
+    /// Stores the file, replacing a previous version. Readers never see a partially written file.
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// The names of all files in the store.
+    fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// Removes the file. Fails with [ErrorKind::NotFound] if it does not exist.
+    fn remove(&self, name: &str) -> Result<(), Error>;
+
+    /// Where the file is stored, for messages to the user.
+    fn location(&self, name: &str) -> String;
+
+    /// The path of the file on the local disk, `None` if the store keeps its files elsewhere.
+    fn local_path(&self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Opens the store at the location, which is either a directory or, with the `s3` feature,
+/// an `s3://<bucket>/<prefix>` url.
+pub fn open_store(location: impl AsRef<Path>) -> Result<Box<dyn CacheStore>, Error> {
+    let location = location.as_ref();
+    match location.to_str().and_then(|location| location.strip_prefix("s3://")) {
+        #[cfg(feature = "s3")]
+        Some(bucket_and_prefix) => Ok(Box::new(s3::S3Store::from_env(bucket_and_prefix)?)),
+        #[cfg(not(feature = "s3"))]
+        Some(_) => Err(Error::new(ErrorKind::Unsupported, "S3 storage requires the s3 feature")),
+        None => Ok(Box::new(FsStore::new(location))),
+    }
+}
+
+/// Splits the location of a single file into the store containing it and its name in there.
+pub fn open_file_store(location: impl AsRef<Path>) -> Result<(Box<dyn CacheStore>, String), Error> {
+    let location = location.as_ref();
+    let name = location.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} does not name a file", location.display())))?;
+    let dir = location.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    Ok((open_store(dir)?, name.to_string()))
+}
+
+/// Fails with [ErrorKind::InvalidInput] unless the name is a valid file name on every platform, so a store
+/// can be copied between them: it may not be empty, `.` or `..`, contain a path separator or a character
+/// Windows does not allow, end with a dot or space, or be a device name of Windows like `nul.cac`.
+pub fn check_name(name: &str) -> Result<(), Error> {
+    const DEVICE_NAMES: [&str; 4] = ["con", "prn", "aux", "nul"];
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_lowercase();
+    let is_device = DEVICE_NAMES.contains(&stem.as_str())
+        || (stem.len() == 4 && (stem.starts_with("com") || stem.starts_with("lpt")) && stem.ends_with(|c: char| c.is_ascii_digit()));
+    let is_valid = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.ends_with(['.', ' '])
+        && !name.chars().any(|c| c.is_control() || "/\\<>:\"|?*".contains(c))
+        && !is_device;
+    match is_valid {
+        true => Ok(()),
+        false => Err(Error::new(ErrorKind::InvalidInput, format!("{name:?} is not a valid name in a store"))),
+    }
+}
+
+/// Renames the file, replacing the target if it exists. Windows refuses to replace a file while another
+/// process has it open, which virus scanners and indexers do briefly, so the rename is retried there.
+pub fn replace_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), Error> {
+    const ATTEMPTS: u32 = if cfg!(windows) { 10 } else { 1 };
+    let (from, to) = (long_path(from.as_ref()), long_path(to.as_ref()));
+    let mut attempt = 1;
+    loop {
+        match std::fs::rename(&from, &to) {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied && attempt < ATTEMPTS => {
+                std::thread::sleep(std::time::Duration::from_millis(10 << attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The path with the `\\?\` prefix Windows needs to access paths longer than 260 characters.
+/// Relative and short paths and paths on other platforms are returned as they are.
+pub fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::path::{Component, Prefix};
+        const MAX_PATH: usize = 260;
+        if path.is_absolute() && path.as_os_str().len() >= MAX_PATH {
+            if let Some(Component::Prefix(prefix)) = path.components().next() {
+                // The prefix disables the normalization of the path, so it has to use backslashes only.
+                let normalized: PathBuf = path.components().collect();
+                let mut long = OsString::new();
+                match prefix.kind() {
+                    Prefix::Disk(_) => long.push(r"\\?\"),
+                    Prefix::UNC(..) => {
+                        long.push(r"\\?\UNC");
+                        let unc = normalized.as_os_str().to_string_lossy();
+                        long.push(&unc[1..]);
+                        return PathBuf::from(long);
+                    }
+                    _ => return path.to_path_buf(),
+                }
+                long.push(normalized.as_os_str());
+                return PathBuf::from(long);
+            }
+        }
+    }
+    path.to_path_buf()
+}
+
+/// The suffix of the files [FsStore::put] writes before renaming them, which [FsStore::list] leaves out.
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// Makes the temporary files of concurrent writes unique, even of names that only differ in case.
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Stores the files in a local directory, which is created on the first write.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    /// A relative directory is resolved against the current directory once, so the store keeps using
+    /// the same directory when the current directory changes later.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self { dir: std::path::absolute(&dir).unwrap_or(dir) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, Error> {
+        check_name(name)?;
+        Ok(long_path(&self.dir.join(name)))
+    }
+}
+
+impl CacheStore for FsStore {
+    fn open(&self, name: &str) -> Result<Box<dyn Read + Send>, Error> {
+        Ok(Box::new(BufReader::new(File::open(self.path(name)?)?)))
+    }
+
+    /// Writes to a temporary file first, flushes it to the disk and renames it afterwards.
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(long_path(&self.dir))?;
+        let temp_id = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+        let temp_path = long_path(&self.dir.join(format!("{name}.{}-{temp_id}{TEMP_SUFFIX}", std::process::id())));
+        let written = File::create(&temp_path)
+            .and_then(|mut file| file.write_all(data).and_then(|_| file.sync_all()))
+            .and_then(|_| replace_file(&temp_path, &path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        written
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let entries = match std::fs::read_dir(long_path(&self.dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                names.extend(entry.file_name().into_string().ok().filter(|name| !name.ends_with(TEMP_SUFFIX)));
+            }
+        }
+        Ok(names)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        std::fs::remove_file(self.path(name)?)
+    }
+
+    fn location(&self, name: &str) -> String {
+        self.dir.join(name).display().to_string()
+    }
+
+    fn local_path(&self, name: &str) -> Option<PathBuf> {
+        self.path(name).ok()
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+
+    #[test]
+    fn test_fs_store() {
+        let dir = std::env::temp_dir().join("store_tests_fs_store");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = open_store(&dir).expect("Local directory");
+        assert!(store.list().expect("Missing directory is empty").is_empty());
+        assert_eq!(ErrorKind::NotFound, store.open("a").err().unwrap().kind());
+
+        store.put("a", b"first").expect("Writable temp dir");
+        store.put("a", b"second").expect("Writable temp dir");
+        store.put("b", b"").expect("Writable temp dir");
+        let mut names = store.list().expect("Readable temp dir");
+        names.sort();
+        assert_eq!(vec!["a", "b"], names);
+        let mut content = String::new();
+        store.open("a").expect("Stored file").read_to_string(&mut content).expect("Readable file");
+        assert_eq!("second", content);
+
+        store.remove("a").expect("Stored file");
+        assert_eq!(ErrorKind::NotFound, store.remove("a").unwrap_err().kind());
+        // The temporary file of an interrupted write is left out.
+        std::fs::write(dir.join("c.1-0.tmp"), b"partial").expect("Writable temp dir");
+        assert_eq!(vec!["b"], store.list().expect("Readable temp dir"));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_portable_names() {
+        for name in ["shape_cache_5.cac", "shape_cache_5.part_0_10_of_20.cac", "console.toml", "com10.cac", ".hidden"] {
+            assert!(check_name(name).is_ok(), "{name}");
+        }
+        for name in ["", ".", "..", "a/b", "a\\b", "a:b", "a?", "trailing.", "trailing ", "NUL", "nul.cac", "Com1.txt", "lpt9"] {
+            assert_eq!(ErrorKind::InvalidInput, check_name(name).unwrap_err().kind(), "{name:?}");
+        }
+        let store = FsStore::new("store_tests_relative");
+        assert!(store.dir().is_absolute());
+        assert_eq!(std::env::current_dir().unwrap().join("store_tests_relative"), store.dir());
+        assert_eq!(ErrorKind::InvalidInput, store.put("../escaped", b"").unwrap_err().kind());
+        assert_eq!(ErrorKind::InvalidInput, store.open("aux").err().unwrap().kind());
+        assert!(!store.dir().exists());
+        assert_eq!(Path::new("relative"), long_path(Path::new("relative")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_paths() {
+        let dir = std::env::temp_dir().join("store_tests_long_paths");
+        let _ = std::fs::remove_dir_all(&dir);
+        let nested = (0..30).fold(dir.clone(), |path, i| path.join(format!("level_{i:02}")));
+        assert!(nested.as_os_str().len() > 260);
+        let store = FsStore::new(&nested);
+        store.put("shape_cache_5.cac", b"first").expect("Writable long path");
+        store.put("shape_cache_5.cac", b"second").expect("Writable long path");
+        assert_eq!(vec!["shape_cache_5.cac"], store.list().expect("Readable long path"));
+        let mut content = String::new();
+        store.open("shape_cache_5.cac").expect("Stored file").read_to_string(&mut content).expect("Readable file");
+        assert_eq!("second", content);
+        store.remove("shape_cache_5.cac").expect("Stored file");
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[cfg(any(windows, target_os = "macos"))]
+    #[test]
+    fn test_case_insensitive_names() {
+        let dir = std::env::temp_dir().join("store_tests_case_insensitive");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        store.put("Shape.cac", b"first").expect("Writable temp dir");
+        // macOS volumes can be case sensitive as well.
+        if dir.join("shape.cac").exists() {
+            store.put("shape.cac", b"second").expect("Writable temp dir");
+            assert_eq!(1, store.list().expect("Readable temp dir").len());
+            let mut content = String::new();
+            store.open("SHAPE.cac").expect("Stored file").read_to_string(&mut content).expect("Readable file");
+            assert_eq!("second", content);
+            store.remove("SHAPE.cac").expect("Stored file");
+            assert!(store.list().expect("Readable temp dir").is_empty());
+        }
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_open_file_store() {
+        let (_, name) = open_file_store("shape_cache_3.cac").expect("File name");
+        assert_eq!("shape_cache_3.cac", name);
+        assert!(open_file_store("/").is_err());
+        #[cfg(not(feature = "s3"))]
+        assert_eq!(ErrorKind::Unsupported, open_store("s3://bucket/caches").err().unwrap().kind());
+    }
+}
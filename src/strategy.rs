@@ -0,0 +1,599 @@
+//! The algorithms a [Runner] can enumerate the shapes of a run with, selected by [Algorithm].
+//! Every algorithm implements [ExpansionStrategy], so a new one only needs an implementation and a
+//! variant of [Algorithm] to be run, reported and compared with the others.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::net::TcpListener;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use crate::block_arrangement::block_variation::{generate_variants_filtered, generate_variants_from, VariationStats};
+use crate::block_arrangement::BlockArrangement;
+use crate::block_count::BlockCount;
+use crate::block_hash::BlockHash;
+use crate::cache::ids::save_ids;
+use crate::cache::manifest::{check_lineage, CacheManifest};
+use crate::cache::checkpoint::Checkpoints;
+use crate::cache::{load_cache, load_poly_tree, open_records, remove_partial_shards, save_poly_tree, uncovered_ranges, PartialShard, Records};
+use crate::config::Algorithm;
+use crate::distributed::Coordinator;
+use crate::level::Level;
+use crate::poly_tree::trace::Trace;
+use crate::poly_tree::PolyTree;
+use crate::polycube::Polycube;
+use crate::run::{RunError, Runner, StopPoint};
+use crate::store::CacheStore;
+use crate::subtractive::carve;
+
+/// An algorithm enumerating the free shapes with the number of blocks of a run.
+pub trait ExpansionStrategy: Sync {
+    /// Every shape with [RunConfig::n](crate::config::RunConfig::n) blocks exactly once.
+    /// The strategy reports the levels it generates to the [Progress](crate::dashboard::Progress) of
+    /// the runner and may keep intermediate results in the store to speed up later runs.
+    fn enumerate(&self, runner: &Runner, store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError>;
+}
+
+impl Algorithm {
+    pub fn strategy(&self) -> &'static dyn ExpansionStrategy {
+        match self {
+            Algorithm::HashMap => &HashMapStrategy,
+            Algorithm::PolyTree => &PolyTreeStrategy,
+            Algorithm::DoubleExtension => &DoubleExtensionStrategy,
+            Algorithm::Subtractive => &SubtractiveStrategy,
+        }
+    }
+}
+
+/// Adds every possible block to every shape of the previous level and deduplicates the children of a
+/// level in a set keyed by their [BlockHash]. Every level is saved as a cache, and a run starts from
+/// the largest cached level below n.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct HashMapStrategy;
+
+impl ExpansionStrategy for HashMapStrategy {
+    fn enumerate(&self, runner: &Runner, store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError> {
+        Ok(self.generate(runner, store)?.into_values().collect())
+    }
+}
+
+impl HashMapStrategy {
+    /// Generates the levels up to n blocks, starting from the largest cached one, and returns the last one.
+    /// With [RunConfig::stream_parents](crate::config::RunConfig::stream_parents) a saved level is dropped
+    /// and read back from its cache one parent at a time, a level whose cache could not be saved is kept.
+    fn generate(&self, runner: &Runner, store: &dyn CacheStore) -> Result<Level, RunError> {
+        let config = runner.config();
+        let n = config.n;
+        let ba = BlockArrangement::new();
+        // `None` while the level is only available from its cache.
+        let mut level = Some(Level::from_unsorted(vec![(BlockHash::from(&ba), ba)]));
+        let mut source_block_size = BlockCount::ONE;
+        let mut generated = 0;
+        if config.stream_parents {
+            if let Some(block_num) = self.find_next_lowest_cache(runner, store, n) {
+                level = None;
+                source_block_size = block_num;
+            }
+        } else if let Some((cache, block_num)) = self.load_next_lowest_cache(runner, store, n) {
+            level = Some(cache);
+            source_block_size = block_num;
+        }
+
+        while source_block_size < n {
+            let generated_block_size = source_block_size.next().expect("Smaller than the block count of the run.");
+            runner.status(&format!("Generating shapes with {generated_block_size} blocks..."));
+            let mut parents = match &level {
+                Some(level) => Parents::InMemory(level.values().collect()),
+                None => Parents::open(store, source_block_size).map_err(|e| RunError::Parents(source_block_size, e))?,
+            };
+            runner.progress().start_level(generated_block_size, parents.len());
+            let (mut new_blocks, stats) = self.generate_level(runner, store, &mut parents, generated_block_size, &mut generated)?;
+            if runner.hereditary_filters().next().is_some() {
+                let unfiltered = new_blocks.len();
+                new_blocks.retain(|_, ba| {
+                    let shape = Polycube::new(ba).expect("Children are connected.");
+                    runner.hereditary_filters().all(|filter| filter.keep(&shape))
+                });
+                runner.status(&format!("Pruned {} of {unfiltered} shapes whose descendants the filters discard.", unfiltered - new_blocks.len()));
+            }
+            let one_sided = new_blocks.one_sided_count();
+            runner.progress().finish_level(new_blocks.len(), Some(one_sided));
+            runner.status(&format!("Found {} free and {one_sided} one sided shapes with {generated_block_size} blocks.", new_blocks.len()));
+            runner.status(&format!(
+                "Considered {} candidates, skipped {} as already set and emitted {} children for {} unique shapes.",
+                stats.candidates(), stats.skipped(), stats.emitted(), new_blocks.len(),
+            ));
+            if new_blocks.collisions() > 0 {
+                runner.status(&format!("Kept {} shapes whose hashes collide with those of other shapes.", new_blocks.collisions()));
+            }
+            if let Some(rate) = stats.false_positive_rate() {
+                runner.status(&format!(
+                    "The bloom filter reported {} of {} children as probable duplicates, {} of them new ({:.3}% of the new children).",
+                    stats.probable_duplicates(), stats.filtered(), stats.false_positives(), rate * 100.0,
+                ));
+            }
+            if let Some(layers) = config.max_layers {
+                runner.status(&format!(
+                    "Pruned {} candidates, found {} shapes with {generated_block_size} blocks in at most {layers} layers.",
+                    stats.pruned(), new_blocks.len(),
+                ));
+            }
+            let parent_shapes = parents.len() as u64;
+            drop(parents);
+            if !config.force {
+                check_lineage(store, generated_block_size, new_blocks.len() as u64, parent_shapes)
+                    .map_err(|e| RunError::Lineage(generated_block_size, e))?;
+            }
+            runner.status(&format!("Saving cache data arrangements with {generated_block_size} blocks..."));
+            let mut saved = false;
+            match config.cache_layout.save(&new_blocks, store, generated_block_size, config.cache_index, config.cache_shards, Some(parent_shapes)) {
+                Ok(_) => {
+                    saved = true;
+                    runner.status(&format!("Saved cache with {} items.", new_blocks.len()));
+                    if let Err(e) = save_ids(&new_blocks, store, generated_block_size) {
+                        eprintln!("Failed to save the shape ids: {e}")
+                    }
+                    if let Err(e) = remove_partial_shards(store, generated_block_size) {
+                        eprintln!("Failed to remove partial shards: {e}")
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to save cache data: {e}")
+                }
+            }
+            let streamed = config.stream_parents && saved && generated_block_size < n;
+            level = (!streamed).then_some(new_blocks);
+            source_block_size = generated_block_size;
+        }
+        Ok(level.expect("The level with n blocks is never streamed."))
+    }
+
+    /// Generates the next level from the parents in the order of their cache one shard of parents at a time.
+    /// Every shard except the last is saved as a checkpoint, and partial shards left behind by an
+    /// interrupted run are reused instead of generating their parents again. With
+    /// [RunConfig::checkpoint_seconds](crate::config::RunConfig::checkpoint_seconds) a shard also ends once
+    /// the interval passed, and its remaining parents start the next one.
+    /// The shards are merged into one [Level] once all of them are complete.
+    /// The returned [VariationStats] only cover the newly generated shards, whose shapes are added to
+    /// `generated`. Fails with [RunError::Stopped] before a shard if a stop condition is met.
+    fn generate_level(
+        &self,
+        runner: &Runner,
+        store: &dyn CacheStore,
+        parents: &mut Parents,
+        block_count: BlockCount,
+        generated: &mut u64,
+    ) -> Result<(Level, VariationStats), RunError> {
+        let config = runner.config();
+        let mut levels = vec![];
+        let mut stats = VariationStats::default();
+        let mut covered = vec![];
+        let checkpoints = Checkpoints::open(store, block_count, parents.len());
+        match checkpoints.load() {
+            Ok(shards) => for shard in shards {
+                covered.push(shard.range);
+                levels.push(shard.records);
+            },
+            Err(e) => eprintln!("Failed to scan for partial shards: {e}"),
+        }
+        if !covered.is_empty() {
+            let reused: usize = covered.iter().map(|range| range.len()).sum();
+            runner.progress().complete_parents(reused);
+            runner.status(&format!("Reused {} partial shards covering {reused} of {} parents.", covered.len(), parents.len()));
+        }
+        let pending = uncovered_ranges(parents.len(), &covered, config.shard_size);
+        if let Some(address) = &config.listen {
+            let parents = parents.as_slice().expect("Parents are not streamed to workers.");
+            let shards = self.distribute(runner, address, parents, pending, &checkpoints).map_err(RunError::Workers)?;
+            levels.extend(shards.into_iter().map(|shard| shard.records));
+            return Ok((Level::merge(levels), stats));
+        }
+        let interval = config.checkpoint_seconds.map(Duration::from_secs);
+        let mut pending = VecDeque::from(pending);
+        let mut completed_parents: usize = covered.iter().map(|range| range.len()).sum();
+        while let Some(range) = pending.pop_front() {
+            // Every shard before this one is saved, since only the last one is not.
+            if let Some(reason) = runner.stop_reason(*generated) {
+                return Err(RunError::Stopped(StopPoint { reason, blocks: block_count, completed_parents, parents: parents.len(), shapes: *generated }));
+            }
+            let capacity = expected_level_size(range.len(), config.growth_factor, config.memory_budget);
+            let started = Instant::now();
+            let mut expanded = 0;
+            // The first parent is always expanded, so every shard makes progress. An interrupt ends the
+            // shard early, so the children generated so far are saved before the run stops. The end is
+            // checked before a parent is read, so a streamed parent is never read without being expanded.
+            let shard_parents = range.clone().map_while(|index| {
+                if expanded > 0 && (runner.interrupted() || interval.is_some_and(|interval| started.elapsed() >= interval)) {
+                    return None;
+                }
+                let parent = parents.get(index)?;
+                expanded += 1;
+                Some(parent)
+            });
+            let (shard, shard_stats) = match config.bloom_filter_bytes {
+                Some(bytes) => generate_variants_filtered(shard_parents, capacity, config.max_layers, bytes),
+                None => generate_variants_from(shard_parents, capacity, config.max_layers),
+            };
+            parents.check()?;
+            let (range, remaining) = (range.start..range.start + expanded, range.start + expanded..range.end);
+            if !remaining.is_empty() {
+                pending.push_front(remaining);
+            }
+            stats += shard_stats;
+            *generated += shard.len() as u64;
+            completed_parents += range.len();
+            runner.progress().complete_parents(range.len());
+            if !pending.is_empty() {
+                if let Err(e) = checkpoints.save(&shard, &range) {
+                    eprintln!("Failed to save partial shard: {e}");
+                }
+            }
+            levels.push(shard);
+        }
+        Ok((Level::merge(levels), stats))
+    }
+
+    /// Lets workers connecting to the address expand the ranges of parents.
+    /// Every completed shard is saved as a checkpoint right away.
+    fn distribute(
+        &self,
+        runner: &Runner,
+        address: &str,
+        parents: &[&BlockArrangement],
+        ranges: Vec<Range<usize>>,
+        checkpoints: &Checkpoints,
+    ) -> Result<Vec<PartialShard>, Error> {
+        let listener = TcpListener::bind(address)?;
+        runner.status(&format!("Waiting for workers on {address}..."));
+        let progress = runner.progress();
+        let on_shard = |shard: &PartialShard| {
+            progress.complete_parents(shard.range.len());
+            if let Err(e) = checkpoints.save(&shard.records, &shard.range) {
+                eprintln!("Failed to save partial shard: {e}");
+            }
+        };
+        Coordinator::new(parents)
+            .with_max_layers(runner.config().max_layers)
+            .with_progress(progress)
+            .run(&listener, ranges, &on_shard)
+    }
+
+    /// Like [HashMapStrategy::load_next_lowest_cache], but only opens the cache to check that it can be read,
+    /// so its shapes are [streamed](Parents::Streamed) later.
+    fn find_next_lowest_cache(&self, runner: &Runner, store: &dyn CacheStore, block_num: BlockCount) -> Option<BlockCount> {
+        let manifest = CacheManifest::load(store).unwrap_or_else(|e| {
+            eprintln!("Failed to read the cache manifest: {e}");
+            CacheManifest::default()
+        });
+        for entry in manifest.below(block_num).filter(|entry| entry.blocks > BlockCount::ONE) {
+            match entry.open(store) {
+                Err(e) => eprintln!("Failed to open the listed cache: {e}"),
+                Ok(_) => {
+                    runner.status(&format!("Streaming {} shapes with {} blocks listed in the manifest...", entry.shapes, entry.blocks));
+                    return Some(entry.blocks);
+                }
+            }
+        }
+        for i in BlockCount::new(2).up_to(block_num.previous()?).rev() {
+            if manifest.get(i).is_some() {
+                continue;
+            }
+            match Parents::open(store, i) {
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => eprintln!("Failed to open cache: {e}"),
+                Ok(parents) => {
+                    runner.status(&format!("Streaming cache with {} items.", parents.len()));
+                    return Some(i);
+                }
+            }
+        }
+        None
+    }
+
+    /// Attempts to load the cache with the largest block size lower that block_num
+    /// that can be found. The levels listed in the [CacheManifest] are tried first, and only if none of
+    /// them loads are the caches probed one size at a time.
+    fn load_next_lowest_cache(&self, runner: &Runner, store: &dyn CacheStore, block_num: BlockCount) -> Option<(Level, BlockCount)> {
+        let manifest = CacheManifest::load(store).unwrap_or_else(|e| {
+            eprintln!("Failed to read the cache manifest: {e}");
+            CacheManifest::default()
+        });
+        for entry in manifest.below(block_num).filter(|entry| entry.blocks > BlockCount::ONE) {
+            runner.status(&format!("Loading {} shapes with {} blocks listed in the manifest...", entry.shapes, entry.blocks));
+            match entry.load(store) {
+                Err(e) => eprintln!("Failed to load the listed cache: {e}"),
+                Ok(cache) => return Some((cache, entry.blocks)),
+            }
+        }
+        for i in BlockCount::new(2).up_to(block_num.previous()?).rev() {
+            if manifest.get(i).is_some() {
+                continue;
+            }
+            runner.status(&format!("Attempting to load cache data for {i} blocks..."));
+            match load_cache(store, i) {
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    eprintln!("Failed load cache: {e}");
+                }
+                Ok(cache) => {
+                    runner.status(&format!("Loaded cache with {} items.", cache.len()));
+                    return Some((cache, i));
+                }
+            }
+        };
+        None
+    }
+}
+
+/// The parents a level is generated from, see [HashMapStrategy::generate].
+enum Parents<'a> {
+    InMemory(Vec<&'a BlockArrangement>),
+    /// The shapes of the cache with the number of blocks, read one at a time in the order of the level, see
+    /// [open_records]. Of its `len` records the first `position` ones were read, and reading stopped at the
+    /// first error.
+    Streamed { blocks: BlockCount, reader: Records, len: usize, position: usize, error: Option<Error> },
+}
+
+impl<'a> Parents<'a> {
+    fn open(store: &dyn CacheStore, blocks: BlockCount) -> Result<Self, Error> {
+        let (len, reader) = open_records(store, blocks)?;
+        Ok(Parents::Streamed { blocks, reader, len: len as usize, position: 0, error: None })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Parents::InMemory(parents) => parents.len(),
+            Parents::Streamed { len, .. } => *len,
+        }
+    }
+
+    fn as_slice(&self) -> Option<&[&'a BlockArrangement]> {
+        match self {
+            Parents::InMemory(parents) => Some(parents),
+            Parents::Streamed { .. } => None,
+        }
+    }
+
+    /// The parent at the index. Streamed parents skip the ones before the index and can not go back,
+    /// so they are read in ascending order. `None` past the end and after an error, see [Parents::check].
+    fn get(&mut self, index: usize) -> Option<Cow<'a, BlockArrangement>> {
+        match self {
+            Parents::InMemory(parents) => parents.get(index).map(|parent| Cow::Borrowed(*parent)),
+            Parents::Streamed { error: Some(_), .. } => None,
+            Parents::Streamed { position, error, .. } if index < *position => {
+                *error = Some(Error::new(ErrorKind::InvalidInput, format!("Parent {index} was requested after parent {position}")));
+                None
+            }
+            Parents::Streamed { reader, position, error, .. } => {
+                while *position < index {
+                    if let Err(e) = reader.next()? {
+                        *error = Some(e);
+                        return None;
+                    }
+                    *position += 1;
+                }
+                *position += 1;
+                match reader.next()? {
+                    Ok((_, ba)) => Some(Cow::Owned(ba)),
+                    Err(e) => {
+                        *error = Some(e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fails with the error that ended reading the streamed parents, if any.
+    fn check(&mut self) -> Result<(), RunError> {
+        if let Parents::Streamed { blocks, error: error @ Some(_), .. } = self {
+            return Err(RunError::Parents(*blocks, error.take().expect("The error is set.")));
+        }
+        Ok(())
+    }
+}
+
+/// Estimates the number of unique arrangements of the next level from the size of the previous one.
+/// The estimate is capped to the number of entries that fit into the memory budget.
+fn expected_level_size(previous_level_size: usize, growth_factor: f64, memory_budget: Option<u64>) -> usize {
+    let expected = (previous_level_size as f64 * growth_factor).ceil() as usize;
+    match memory_budget {
+        Some(budget) => {
+            let entry_size = size_of::<(BlockHash, BlockArrangement)>() as u64;
+            expected.min((budget / entry_size) as usize)
+        }
+        None => expected,
+    }
+}
+
+/// Grows the stored [PolyTree], which keeps every shape once as the child of its canonical parent and
+/// only links it to its other parents, until it contains the shapes with n blocks.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PolyTreeStrategy;
+
+impl ExpansionStrategy for PolyTreeStrategy {
+    fn enumerate(&self, runner: &Runner, store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError> {
+        let config = runner.config();
+        let progress = runner.progress();
+        runner.status("Attempting to load the poly tree...");
+        let mut tree = match load_poly_tree(store) {
+            Ok(tree) => tree,
+            Err(e) if e.kind() == ErrorKind::NotFound => PolyTree::new(),
+            Err(e) => {
+                runner.status(&format!("Failed to load poly tree: {e}"));
+                PolyTree::new()
+            }
+        };
+        runner.status(&format!("Loaded poly tree with {} levels.", tree.depth()));
+        let mut trace = Trace::new();
+        while tree.depth() < config.n {
+            let block_count = tree.depth().next().expect("The tree is smaller than the largest block count.");
+            runner.status(&format!("Generating shapes with {block_count} blocks..."));
+            progress.start_level(block_count, tree.level(tree.depth()).len());
+            let added = match &config.trace {
+                Some(path) => {
+                    let added = tree.generate_next_level_traced(config.threads, &mut trace);
+                    // Written after every level so the trace of a crashed run is kept.
+                    if let Err(e) = File::create(path).and_then(|mut file| trace.write_to(&mut file)) {
+                        eprintln!("Failed to write the trace to {}: {e}", path.display());
+                    }
+                    added
+                }
+                None => tree.generate_next_level_with_threads(config.threads),
+            };
+            let one_sided = tree.one_sided_count(tree.depth());
+            progress.finish_level(added, Some(one_sided));
+            runner.status(&format!("Found {added} free and {one_sided} one sided shapes with {block_count} blocks."));
+            runner.status("Saving poly tree...");
+            match save_poly_tree(&tree, store) {
+                Ok(_) => runner.status(&format!("Saved poly tree with {} items.", tree.len())),
+                Err(e) => eprintln!("Failed to save poly tree: {e}"),
+            }
+        }
+        Ok(tree.iter_level(config.n).collect())
+    }
+}
+
+/// Adds two blocks at a time: the children of every parent are only deduplicated among themselves
+/// before their own children join the level two blocks larger, so every other level is never held as
+/// a whole. Starts with a single block when n is odd and with a domino otherwise. Nothing is cached.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DoubleExtensionStrategy;
+
+impl ExpansionStrategy for DoubleExtensionStrategy {
+    fn enumerate(&self, runner: &Runner, _store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError> {
+        let n = runner.config().n;
+        let monomer = Polycube::with_one_sided_count(&BlockArrangement::new()).map_err(RunError::InvalidShape)?;
+        let mut level = HashMap::from([monomer]);
+        let mut block_count = BlockCount::ONE;
+        if n.get().is_multiple_of(2) {
+            level = extend(&level);
+            block_count = BlockCount::new(2);
+        }
+        while block_count < n {
+            let generated = block_count.next().and_then(BlockCount::next).expect("At most the block count of the run.");
+            runner.status(&format!("Generating shapes with {generated} blocks..."));
+            runner.progress().start_level(generated, level.len());
+            let mut next = HashMap::new();
+            for (parent, one_sided) in &level {
+                next.extend(extend(&extend(&HashMap::from([(parent.clone(), *one_sided)]))));
+                runner.progress().complete_parents(1);
+            }
+            let one_sided = next.values().sum();
+            runner.progress().finish_level(next.len(), Some(one_sided));
+            runner.status(&format!("Found {} free and {one_sided} one sided shapes with {generated} blocks.", next.len()));
+            level = next;
+            block_count = generated;
+        }
+        Ok(level.into_keys().map(Polycube::into_inner).collect())
+    }
+}
+
+/// Every shape created by adding a single block to one of the shapes, with the number of one sided
+/// shapes it stands for.
+fn extend(shapes: &HashMap<Polycube, usize>) -> HashMap<Polycube, usize> {
+    shapes.keys()
+        .flat_map(|shape| {
+            let ba = shape.clone().into_inner();
+            ba.free_neighbors().into_iter().map(move |p| {
+                let mut child = ba.clone();
+                child.add_block_at(&p).expect("Free neighbors are adjacent to a block.");
+                Polycube::with_one_sided_count(&child).expect("Adding a neighbor keeps the shape connected.")
+            })
+        })
+        .collect()
+}
+
+/// Carves the shapes out of full boxes, see [carve]. A shape of n blocks spans at most n + 2 cells along
+/// the three axes of its bounding box together, so carving every box whose side lengths add up to
+/// n + 2 finds every shape. The boxes grow quickly with n, which limits this strategy to small shapes.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SubtractiveStrategy;
+
+impl SubtractiveStrategy {
+    /// The sizes of the boxes every shape with the number of blocks fits into, shortest side first.
+    fn boxes(n: BlockCount) -> Vec<[u32; 3]> {
+        let sides = n.get() + 2;
+        (1..=sides / 3)
+            .flat_map(|width| (width..=(sides - width) / 2).map(move |depth| [width, depth, sides - width - depth]))
+            .collect()
+    }
+}
+
+impl ExpansionStrategy for SubtractiveStrategy {
+    fn enumerate(&self, runner: &Runner, _store: &dyn CacheStore) -> Result<Vec<BlockArrangement>, RunError> {
+        let n = runner.config().n;
+        let boxes = Self::boxes(n);
+        runner.progress().start_level(n, boxes.len());
+        let mut shapes = BTreeMap::new();
+        for size in boxes {
+            let [width, depth, height] = size;
+            runner.status(&format!("Carving the shapes with {n} blocks out of a {width}x{depth}x{height} box..."));
+            let mut levels = carve(size, n).map_err(|_| RunError::InvalidConfig("The boxes of the shapes are too large to carve"))?;
+            shapes.extend(levels.remove(&n).unwrap_or_default());
+            runner.progress().complete_parents(1);
+        }
+        let one_sided = shapes.values().sum();
+        runner.progress().finish_level(shapes.len(), Some(one_sided));
+        runner.status(&format!("Found {} free and {one_sided} one sided shapes with {n} blocks.", shapes.len()));
+        Ok(shapes.into_keys().map(Polycube::into_inner).collect())
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use std::collections::BTreeSet;
+    use crate::config::RunConfig;
+    use crate::store::FsStore;
+    use super::*;
+
+    fn codes(shapes: Vec<BlockArrangement>) -> BTreeSet<String> {
+        shapes.iter().map(|ba| Polycube::new(ba).unwrap().code()).collect()
+    }
+
+    #[test]
+    fn test_strategies() {
+        let dir = std::env::temp_dir().join("strategy_tests_strategies");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        for (n, count) in [(1, 1), (2, 1), (3, 2), (4, 7), (5, 23)] {
+            let config = RunConfig { n: BlockCount::new(n), algorithm: Algorithm::PolyTree, cache_dir: dir.clone(), ..RunConfig::default() };
+            let expected = codes(Algorithm::PolyTree.strategy().enumerate(&Runner::new(&config), &store).unwrap());
+            assert_eq!(count, expected.len());
+            for algorithm in [Algorithm::DoubleExtension, Algorithm::Subtractive] {
+                let shapes = algorithm.strategy().enumerate(&Runner::new(&config), &store).unwrap();
+                assert_eq!(count, shapes.len(), "{algorithm} shapes with {n} blocks");
+                assert_eq!(expected, codes(shapes), "{algorithm} shapes with {n} blocks");
+            }
+        }
+        assert_eq!(vec![[1, 1, 5], [1, 2, 4], [1, 3, 3], [2, 2, 3]], SubtractiveStrategy::boxes(BlockCount::new(5)));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+
+    #[test]
+    fn test_streamed_parents() {
+        let dir = std::env::temp_dir().join("strategy_tests_streamed_parents");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+        let config = RunConfig { n: BlockCount::new(5), cache_dir: dir.clone(), ..RunConfig::default() };
+        let expected = codes(HashMapStrategy.enumerate(&Runner::new(&config), &store).unwrap());
+        // Starts from the cached tetrominoes and from scratch, reading every level back from its cache.
+        for (n, force, count) in [(6, false, 112), (5, true, 23)] {
+            let streamed = RunConfig { n: BlockCount::new(n), stream_parents: true, shard_size: 3, force, ..config.clone() };
+            assert_eq!(count, HashMapStrategy.enumerate(&Runner::new(&streamed), &store).unwrap().len());
+        }
+        let shapes = HashMapStrategy.enumerate(&Runner::new(&RunConfig { stream_parents: true, ..config.clone() }), &store).unwrap();
+        assert_eq!(expected, codes(shapes));
+
+        let mut parents = Parents::open(&store, BlockCount::new(4)).unwrap();
+        assert_eq!(7, parents.len());
+        let in_memory = load_cache(&store, BlockCount::new(4)).unwrap();
+        assert_eq!(Some(in_memory.values().nth(2).unwrap()), parents.get(2).as_deref());
+        assert!(parents.check().is_ok());
+        // Streamed parents can not go back.
+        assert_eq!(None, parents.get(1));
+        assert!(matches!(parents.check(), Err(RunError::Parents(_, e)) if e.kind() == ErrorKind::InvalidInput));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::block_arrangement::{BlockArrangement, PlacementError};
+use crate::block_count::BlockCount;
+use crate::placement::Region;
+use crate::polycube::Polycube;
+
+/// Enumerates shapes by starting from the full box with the given width, depth and height and removing
+/// one block at a time while the remaining blocks stay connected. Every shape that fits into the box is
+/// reached this way, so the result contains exactly those shapes, grouped by their number of blocks
+/// from the full box down to `min_blocks`, each with the number of one sided shapes it stands for.
+pub fn carve(size: [u32; 3], min_blocks: BlockCount) -> Result<BTreeMap<BlockCount, HashMap<Polycube, usize>>, PlacementError> {
+    let cell_count = size.into_iter()
+        .try_fold(1u32, u32::checked_mul)
+        .map(BlockCount::new)
+        .ok_or(PlacementError::TooLarge)?;
+    let cells: Vec<_> = Region::cuboid(size).cells().collect();
+    let full = Polycube::with_one_sided_count(&BlockArrangement::from_points(&cells)?)
+        .expect("A box is connected.");
+    let min_blocks = min_blocks.max(BlockCount::ONE);
+    let mut levels = BTreeMap::new();
+    let mut level = HashMap::from([full]);
+    for block_count in min_blocks.up_to(cell_count).rev() {
+        let next = match block_count > min_blocks {
+            true => remove_one(&level),
+            false => HashMap::new(),
+        };
+        levels.insert(block_count, level);
+        level = next;
+    }
+    Ok(levels)
+}
+
+/// Every shape created by removing a single block of one of the shapes.
+fn remove_one(shapes: &HashMap<Polycube, usize>) -> HashMap<Polycube, usize> {
+    shapes.keys()
+        .flat_map(|shape| {
+            let ba = shape.clone().into_inner();
+            ba.removable_blocks().into_iter().map(move |p| {
+                let mut child = ba.clone();
+                child.remove_block_at(&p).expect("Only removable blocks are removed.");
+                Polycube::with_one_sided_count(&child).expect("Removable blocks keep the shape connected.")
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod subtractive_tests {
+    use std::collections::BTreeSet;
+    use crate::reference::canonical;
+    use super::*;
+
+    /// The number of distinct connected subsets of the box for every size, found by trying every subset.
+    fn fitting_counts(size: [u32; 3]) -> Vec<usize> {
+        let cells: Vec<_> = Region::cuboid(size).cells().collect();
+        let mut shapes = vec![BTreeSet::new(); cells.len()];
+        for subset in 1..1u32 << cells.len() {
+            let points: Vec<_> = cells.iter().enumerate()
+                .filter(|(i, _)| subset & (1 << i) != 0)
+                .map(|(_, p)| *p)
+                .collect();
+            if BlockArrangement::from_points(&points).is_ok() {
+                let coordinates: Vec<_> = points.iter().map(|p| [*p.x(), *p.y(), *p.z()]).collect();
+                shapes[points.len() - 1].insert(canonical(&coordinates));
+            }
+        }
+        shapes.iter().map(BTreeSet::len).collect()
+    }
+
+    #[test]
+    fn test_carve() {
+        for size in [[2, 2, 1], [3, 2, 1], [2, 2, 2], [3, 3, 1]] {
+            let levels = carve(size, BlockCount::ONE).expect("Small box");
+            let counts: Vec<_> = levels.values().map(HashMap::len).collect();
+            assert_eq!(fitting_counts(size), counts, "Shapes fitting into {size:?}");
+        }
+        let levels = carve([2, 2, 2], BlockCount::new(6)).expect("Small box");
+        assert_eq!(vec![6, 7, 8], levels.keys().map(|block_count| block_count.get()).collect::<Vec<_>>());
+        // Two removed blocks are adjacent, on a face diagonal or on a space diagonal.
+        assert_eq!(3, levels[&BlockCount::new(6)].len());
+        // Of the tetrominoes fitting into the box only the screw is chiral.
+        let tetrominoes = &carve([2, 2, 2], BlockCount::new(4)).expect("Small box")[&BlockCount::new(4)];
+        assert_eq!((3, 4), (tetrominoes.len(), tetrominoes.values().sum()));
+        assert_eq!(Err(PlacementError::TooLarge), carve([2048, 2048, 1024], BlockCount::MAX));
+    }
+}
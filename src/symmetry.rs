@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use crate::orientation::{rotations, rotations_and_reflections, Orientation};
+use crate::point::Point3D;
+use crate::block_arrangement::BlockArrangement;
+use crate::polycube::{Chirality, Polycube};
+
+/// The symmetries of a shape, i.e. the orientations that map it onto itself.
+/// Each symmetry is counted once even if several [Orientation]s describe the same transformation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct Symmetry {
+    /// The number of symmetries including the identity.
+    order: usize,
+    /// The number of symmetries that do not mirror the shape.
+    rotations: usize,
+    /// Whether the point reflection through the center is a symmetry.
+    inversion: bool,
+    /// Whether a quarter turn is a symmetry.
+    fourfold_rotation: bool,
+    /// Whether a quarter turn followed by a reflection at the perpendicular plane is a symmetry.
+    fourfold_rotoreflection: bool,
+}
+
+impl Symmetry {
+    pub fn of(polycube: &Polycube) -> Self {
+        let points = normalized(polycube.block_iter().collect());
+        let mut symmetry = Self::default();
+        for orientation in rotations_and_reflections() {
+            let transformed = normalized(points.iter().map(|p| oriented(*p, &orientation)).collect());
+            if transformed != points {
+                continue;
+            }
+            let [[a, _, _], [_, b, _], [_, _, c]] = matrix(&orientation);
+            let trace = a + b + c;
+            symmetry.order += 1;
+            match (orientation.is_reflection(), trace) {
+                (false, 1) => symmetry.fourfold_rotation = true,
+                (true, -3) => symmetry.inversion = true,
+                (true, -1) => symmetry.fourfold_rotoreflection = true,
+                _ => {}
+            }
+            if !orientation.is_reflection() {
+                symmetry.rotations += 1;
+            }
+        }
+        symmetry
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// Checks if the shape is its own mirror image.
+    pub fn is_achiral(&self) -> bool {
+        self.order > self.rotations
+    }
+
+    /// The Schoenflies symbol of the point group.
+    /// Every subgroup of the cube symmetries is determined by its order and the kinds of its elements.
+    pub fn name(&self) -> &'static str {
+        let achiral = self.is_achiral();
+        match (self.order, achiral) {
+            (1, _) => "C1",
+            (2, false) => "C2",
+            (2, true) if self.inversion => "Ci",
+            (2, true) => "Cs",
+            (3, _) => "C3",
+            (4, false) if self.fourfold_rotation => "C4",
+            (4, false) => "D2",
+            (4, true) if self.inversion => "C2h",
+            (4, true) if self.fourfold_rotoreflection => "S4",
+            (4, true) => "C2v",
+            (6, false) => "D3",
+            (6, true) if self.inversion => "S6",
+            (6, true) => "C3v",
+            (8, false) => "D4",
+            (8, true) if self.fourfold_rotation && self.inversion => "C4h",
+            (8, true) if self.fourfold_rotation => "C4v",
+            (8, true) if self.inversion => "D2h",
+            (8, true) => "D2d",
+            (12, false) => "T",
+            (12, true) => "D3d",
+            (16, _) => "D4h",
+            (24, false) => "O",
+            (24, true) if self.inversion => "Th",
+            (24, true) => "Td",
+            _ => "Oh",
+        }
+    }
+}
+
+impl Display for Symmetry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (order {}, {} rotations)", self.name(), self.order, self.rotations)
+    }
+}
+
+/// Checks if the identity is the only symmetry of the shape. Stops at the first other symmetry instead
+/// of calculating the whole [Symmetry], and rejects flat shapes without trying any orientation since
+/// the reflection at their plane maps them onto themselves.
+pub fn is_asymmetric(polycube: &Polycube) -> bool {
+    let (width, depth, height) = polycube.bounding_box().all_axis_len();
+    if width.min(depth).min(height) == 1 {
+        return false;
+    }
+    let points = normalized(polycube.block_iter().collect());
+    // The identity comes first.
+    !rotations_and_reflections().skip(1)
+        .any(|orientation| normalized(points.iter().map(|p| oriented(*p, &orientation)).collect()) == points)
+}
+
+/// One orientation for every distinct image of the blocks under the orientations allowed by the
+/// chirality, up to translation. Orientations that only differ by a symmetry of the shape form a coset of
+/// its symmetry group and result in the same image, so the number of orientations is the number of
+/// allowed orientations divided by the number of allowed symmetries.
+pub fn distinct_orientations(ba: &BlockArrangement, chirality: Chirality) -> Vec<Orientation> {
+    let allowed: Vec<_> = match chirality {
+        Chirality::Free => rotations_and_reflections().collect(),
+        Chirality::OneSided => rotations().collect(),
+    };
+    let points = normalized(ba.block_iter().collect());
+    let symmetries: Vec<_> = allowed.iter()
+        .filter(|orientation| normalized(points.iter().map(|p| oriented(*p, orientation)).collect()) == points)
+        .map(matrix)
+        .collect();
+    let mut covered = HashSet::new();
+    let mut representatives = vec![];
+    for orientation in allowed {
+        let m = matrix(&orientation);
+        if covered.contains(&m) {
+            continue;
+        }
+        covered.extend(symmetries.iter().map(|symmetry| multiply(&m, symmetry)));
+        representatives.push(orientation);
+    }
+    representatives
+}
+
+/// A group of transformations of the cube, like the symmetries a shape is made to have with
+/// [BlockArrangement::symmetrize]. Each transformation is kept once even if several [Orientation]s describe it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SymmetryGroup {
+    /// The matrices of the transformations, starting with the identity.
+    elements: Vec<[[i32; 3]; 3]>,
+}
+
+impl SymmetryGroup {
+    /// The smallest group containing the orientations, which is just the identity for none.
+    pub fn generated_by(generators: &[Orientation]) -> Self {
+        let generators: Vec<_> = generators.iter().map(matrix).collect();
+        let identity = matrix(&Orientation::default());
+        let mut elements = vec![identity];
+        let mut seen = HashSet::from([identity]);
+        // Every element is a product of generators, and there are at most 48 of them.
+        let mut next = 0;
+        while let Some(element) = elements.get(next).copied() {
+            for generator in &generators {
+                let product = multiply(generator, &element);
+                if seen.insert(product) {
+                    elements.push(product);
+                }
+            }
+            next += 1;
+        }
+        Self { elements }
+    }
+
+    /// All 48 transformations, which only cubes and other fully symmetric shapes have.
+    pub fn full() -> Self {
+        Self::generated_by(&rotations_and_reflections().collect::<Vec<_>>())
+    }
+
+    /// The 24 transformations that do not mirror.
+    pub fn rotations() -> Self {
+        Self::generated_by(&rotations().collect::<Vec<_>>())
+    }
+
+    /// The number of transformations including the identity.
+    pub fn order(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// One orientation for every transformation of the group.
+    pub fn orientations(&self) -> Vec<Orientation> {
+        self.elements.iter()
+            .map(|element| rotations_and_reflections()
+                .find(|orientation| matrix(orientation) == *element)
+                .expect("Every transformation of the cube is an orientation."))
+            .collect()
+    }
+
+    /// The points followed by their images under every transformation, each point once.
+    /// The transformations move the points around the center of their bounding box. A group exchanging
+    /// two axes needs a center that is a whole or a half block away from the blocks on both of them, so
+    /// the center is moved by half a block along the exchanged axes where this is not the case.
+    pub fn orbit(&self, points: &[Point3D<i32>]) -> Vec<Point3D<i32>> {
+        if points.is_empty() {
+            return vec![];
+        }
+        let min = BlockArrangement::min_corner(points.iter().copied());
+        let max = BlockArrangement::max_corner(points.iter().copied());
+        // Coordinates are doubled so the center always lies on the lattice.
+        let mut center = [*min.x() + *max.x(), *min.y() + *max.y(), *min.z() + *max.z()];
+        let exchanged = |row: usize, column: usize| self.elements.iter().any(|element| element[row][column] != 0);
+        // Every step moves one more axis to whole blocks, so this ends after at most three.
+        while let Some((row, column)) = (0..3).flat_map(|row| (0..3).map(move |column| (row, column)))
+            .find(|&(row, column)| exchanged(row, column) && (center[row] - center[column]) % 2 != 0)
+        {
+            center[row] += center[row].rem_euclid(2);
+            center[column] += center[column].rem_euclid(2);
+        }
+        let mut seen = HashSet::new();
+        self.elements.iter()
+            .flat_map(|element| points.iter().map(move |p| {
+                let doubled = [2 * *p.x() - center[0], 2 * *p.y() - center[1], 2 * *p.z() - center[2]];
+                let [x, y, z] = [0, 1, 2].map(|row| ((0..3).map(|k| element[row][k] * doubled[k]).sum::<i32>() + center[row]) / 2);
+                Point3D::new(x, y, z)
+            }))
+            .filter(|p| seen.insert(*p))
+            .collect()
+    }
+}
+
+fn multiply(a: &[[i32; 3]; 3], b: &[[i32; 3]; 3]) -> [[i32; 3]; 3] {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|k| a[row][k] * b[k][column]).sum()))
+}
+
+fn oriented(mut p: Point3D<i32>, orientation: &Orientation) -> Point3D<i32> {
+    p.apply_orientation(orientation);
+    p
+}
+
+/// The rows of the matrix that performs the orientation.
+fn matrix(orientation: &Orientation) -> [[i32; 3]; 3] {
+    let columns = [Point3D::new(1, 0, 0), Point3D::new(0, 1, 0), Point3D::new(0, 0, 1)]
+        .map(|p| oriented(p, orientation));
+    [0, 1, 2].map(|row| columns.map(|c| [*c.x(), *c.y(), *c.z()][row]))
+}
+
+fn normalized(mut points: Vec<Point3D<i32>>) -> Vec<Point3D<i32>> {
+    points.sort();
+    let anchor = points[0];
+    points.iter_mut().for_each(|p| *p -= anchor);
+    points
+}
+
+#[cfg(test)]
+mod symmetry_tests {
+    use crate::block_count::BlockCount;
+    use crate::import::{import, ImportFormat};
+    use crate::orientation::RotationAmount;
+    use crate::point::Axis3D;
+    use crate::poly_tree::PolyTree;
+    use crate::polycubes::Polycubes;
+    use super::*;
+
+    fn symmetry(xyz: &str) -> Symmetry {
+        let shapes = import(xyz.as_bytes(), ImportFormat::Xyz).expect("Valid xyz");
+        Symmetry::of(&Polycube::new(&shapes[0]).expect("Connected shape"))
+    }
+
+    #[test]
+    fn test_is_asymmetric() {
+        let mut tree = PolyTree::new();
+        let mut asymmetric_counts = vec![];
+        for n in BlockCount::ONE.up_to(BlockCount::new(6)) {
+            tree.generate_next_level();
+            let polycubes: Vec<_> = tree.iter_level(n).map(|ba| Polycube::new(&ba).unwrap()).collect();
+            for polycube in &polycubes {
+                assert_eq!(Symmetry::of(polycube).order() == 1, is_asymmetric(polycube), "Shape {}", polycube.code());
+            }
+            asymmetric_counts.push(polycubes.iter().filter(|polycube| is_asymmetric(polycube)).count());
+        }
+        assert_eq!(vec![0, 0, 0, 0, 4], asymmetric_counts[..5]);
+        assert!(asymmetric_counts[5] > asymmetric_counts[4]);
+    }
+
+    #[test]
+    fn test_distinct_orientations() {
+        let shapes = ["0 0 0", "0 0 0\n1 0 0", "0 0 0\n1 0 0\n2 0 0\n0 1 0", "0 0 0\n1 0 0\n1 1 0\n1 1 1"];
+        for xyz in shapes {
+            let ba = import(xyz.as_bytes(), ImportFormat::Xyz).expect("Valid xyz").remove(0);
+            let symmetry = Symmetry::of(&Polycube::new(&ba).unwrap());
+            let free = distinct_orientations(&ba, Chirality::Free);
+            assert_eq!(48 / symmetry.order(), free.len(), "Free orientations of {xyz:?}");
+            assert_eq!(24 / symmetry.rotations(), distinct_orientations(&ba, Chirality::OneSided).len(), "Rotations of {xyz:?}");
+            let images: HashSet<_> = free.iter()
+                .map(|orientation| normalized(ba.block_iter().map(|p| oriented(p, orientation)).collect()))
+                .collect();
+            assert_eq!(free.len(), images.len());
+        }
+    }
+
+    #[test]
+    fn test_symmetrize() {
+        let orientation = |f: &dyn Fn(&mut Orientation)| {
+            let mut orientation = Orientation::default();
+            f(&mut orientation);
+            orientation
+        };
+        let mirror_x = SymmetryGroup::generated_by(&[orientation(&|o| o.mirror(Axis3D::X))]);
+        let quarter_turn = SymmetryGroup::generated_by(&[orientation(&|o| o.rotate(Axis3D::Z, RotationAmount::Ninety))]);
+        let inversion = SymmetryGroup::generated_by(&[orientation(&|o| {
+            o.mirror(Axis3D::X);
+            o.mirror(Axis3D::Y);
+            o.mirror(Axis3D::Z);
+        })]);
+        let groups = [SymmetryGroup::generated_by(&[]), mirror_x.clone(), quarter_turn.clone(), inversion, SymmetryGroup::rotations(), SymmetryGroup::full()];
+        assert_eq!(vec![1, 2, 4, 2, 24, 48], groups.iter().map(SymmetryGroup::order).collect::<Vec<_>>());
+
+        let l_tromino = import("0 0 0\n1 0 0\n0 1 0".as_bytes(), ImportFormat::Xyz).expect("Valid xyz").remove(0);
+        let square = l_tromino.symmetrize(&mirror_x).expect("Connected union");
+        assert_eq!("D4h", Symmetry::of(&Polycube::new(&square).unwrap()).name());
+        let l_tetromino = import("0 0 0\n1 0 0\n2 0 0\n0 1 0".as_bytes(), ImportFormat::Xyz).expect("Valid xyz").remove(0);
+        // The ring of the eight blocks around the center of the three by three square.
+        let ring = l_tetromino.symmetrize(&quarter_turn).expect("Connected union");
+        assert_eq!(8, ring.num_blocks().get());
+        assert_eq!("D4h", Symmetry::of(&Polycube::new(&ring).unwrap()).name());
+
+        let mut connected = [0; 6];
+        for polycube in Polycubes::builtin(BlockCount::new(5)).unwrap() {
+            for (group, connected) in groups.iter().zip(&mut connected) {
+                let Some(symmetric) = polycube.symmetrize(group) else {
+                    continue;
+                };
+                *connected += 1;
+                let points = normalized(symmetric.block_iter().collect());
+                for orientation in group.orientations() {
+                    assert_eq!(points, normalized(points.iter().map(|p| oriented(*p, &orientation)).collect()), "Shape {}", polycube.code());
+                }
+                assert_eq!(0, Symmetry::of(&Polycube::new(&symmetric).unwrap()).order() % group.order());
+                assert!(symmetric.num_blocks() >= polycube.num_blocks());
+            }
+            assert_eq!(polycube.block_iter().count(), polycube.symmetrize(&groups[0]).unwrap().block_iter().count());
+        }
+        assert_eq!(23, connected[0]);
+        assert!(connected.iter().all(|count| *count > 0), "{connected:?}");
+    }
+
+    #[test]
+    fn test_point_groups() {
+        assert_eq!("Oh", symmetry("0 0 0").name());
+        assert_eq!(48, symmetry("0 0 0").order());
+        assert_eq!("D4h", symmetry("0 0 0\n1 0 0").name());
+        assert_eq!("C2v", symmetry("0 0 0\n1 0 0\n0 1 0").name());
+        assert_eq!("D4h", symmetry("0 0 0\n1 0 0\n0 1 0\n1 1 0").name());
+        assert_eq!("Cs", symmetry("0 0 0\n1 0 0\n2 0 0\n0 1 0").name());
+        assert_eq!("C2v", symmetry("0 0 0\n1 0 0\n2 0 0\n1 1 0").name());
+        assert_eq!("C2h", symmetry("0 0 0\n1 0 0\n1 1 0\n2 1 0").name());
+        assert_eq!("C3v", symmetry("0 0 0\n1 0 0\n0 1 0\n0 0 1").name());
+        assert_eq!("C2", symmetry("0 0 0\n1 0 0\n1 1 0\n1 1 1").name());
+        assert!(!symmetry("0 0 0\n1 0 0\n1 1 0\n1 1 1").is_achiral());
+        assert_eq!("Oh", symmetry("0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1").name());
+    }
+}
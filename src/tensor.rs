@@ -0,0 +1,294 @@
+//! Writes the shapes of a level as a dataset of dense voxel grids for machine learning.
+//!
+//! Every split of the dataset is one array of `u8` with the shape `(shapes, width, depth, height)` in
+//! row major order, so the height varies fastest, and a voxel is 1 where the shape has a block.
+//! Every shape is placed at the minimal corner of the grid. A JSON [TensorManifest] describes the files.
+
+use std::fmt::{Display, Formatter};
+use std::io::Error;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::Serialize;
+use strum::{EnumString, IntoEnumIterator};
+use crate::block_arrangement::BlockArrangement;
+use crate::permutation::Permutation;
+use crate::point::{Axis3D, Point3D};
+use crate::polycube::{Polycube, PolycubeError};
+use crate::store::CacheStore;
+
+/// The file formats of the arrays.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[derive(EnumString, strum::Display, Serialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TensorFormat {
+    /// A NumPy `.npy` file, readable with `numpy.load`.
+    #[default]
+    Npy,
+    /// The bare voxels in a `.bin` file, whose shape is only given by the manifest.
+    Raw,
+}
+
+impl TensorFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TensorFormat::Npy => "npy",
+            TensorFormat::Raw => "bin",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TensorError {
+    /// The shape with the code does not fit into the grid.
+    DoesNotFit { code: String, grid: [u32; 3] },
+    /// The fraction of validation shapes is not between 0 and 1.
+    InvalidFraction,
+    InvalidShape(PolycubeError),
+    Io(Error),
+}
+
+impl Display for TensorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TensorError::DoesNotFit { code, grid: [width, depth, height] } => write!(f, "{code} does not fit into a {width}x{depth}x{height} grid"),
+            TensorError::InvalidFraction => f.write_str("The fraction of validation shapes has to be between 0 and 1"),
+            TensorError::InvalidShape(e) => write!(f, "Invalid shape: {e}"),
+            TensorError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TensorError {}
+
+/// Describes the arrays written by [TensorExport::write].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TensorManifest {
+    pub format: TensorFormat,
+    /// The NumPy type of a voxel.
+    pub dtype: String,
+    /// The width, depth and height of the grid of every shape.
+    pub grid: [u32; 3],
+    /// Whether the shapes are in their canonical orientation or in the one they were stored in.
+    pub canonical: bool,
+    pub seed: u64,
+    pub splits: Vec<TensorSplit>,
+}
+
+/// One array of the dataset.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TensorSplit {
+    /// `train` or `val`.
+    pub name: String,
+    /// The name of the file in the store.
+    pub file: String,
+    /// The shape of the array.
+    pub shape: [usize; 4],
+    /// The code of every shape in the array, see [Polycube::code].
+    pub codes: Vec<String>,
+}
+
+/// The options of a voxel dataset. Splitting the shapes with the same seed always results in the same splits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorExport {
+    format: TensorFormat,
+    grid: Option<[u32; 3]>,
+    canonical: bool,
+    validation_fraction: f64,
+    shuffle: bool,
+    seed: u64,
+}
+
+impl Default for TensorExport {
+    fn default() -> Self {
+        Self { format: TensorFormat::default(), grid: None, canonical: true, validation_fraction: 0.0, shuffle: false, seed: 0 }
+    }
+}
+
+impl TensorExport {
+    pub fn with_format(mut self, format: TensorFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Pads every shape to the grid instead of the smallest grid that fits every shape.
+    pub fn with_grid(mut self, grid: [u32; 3]) -> Self {
+        self.grid = Some(grid);
+        self
+    }
+
+    /// Whether the shapes are rotated into their canonical orientation, which is the default, or kept in
+    /// the orientation they are stored in.
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Moves this fraction of the shapes, chosen at random, into a separate validation split.
+    pub fn with_validation_fraction(mut self, fraction: f64) -> Self {
+        self.validation_fraction = fraction;
+        self
+    }
+
+    /// Orders the shapes of every split by a [Permutation] of their positions instead of keeping their order.
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Writes the splits of the shapes as `<name>_train` and `<name>_val` and the manifest as
+    /// `<name>.json` into the store. The validation split is left out when its fraction is zero.
+    pub fn write(&self, shapes: &[BlockArrangement], store: &dyn CacheStore, name: &str) -> Result<TensorManifest, TensorError> {
+        if !(0.0..=1.0).contains(&self.validation_fraction) {
+            return Err(TensorError::InvalidFraction);
+        }
+        let shapes = shapes.iter()
+            .map(|ba| {
+                let polycube = Polycube::new(ba).map_err(TensorError::InvalidShape)?;
+                let blocks = match self.canonical {
+                    true => polycube.block_iter().collect(),
+                    false => ba.block_iter().collect(),
+                };
+                Ok((polycube.code(), normalized(blocks)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let grid = self.grid.unwrap_or_else(|| fitting_grid(shapes.iter().map(|(_, blocks)| blocks)));
+        if let Some((code, _)) = shapes.iter().find(|(_, blocks)| !fits(blocks, grid)) {
+            return Err(TensorError::DoesNotFit { code: code.clone(), grid });
+        }
+
+        let mut positions: Vec<_> = (0..shapes.len()).collect();
+        positions.shuffle(&mut StdRng::seed_from_u64(self.seed));
+        let validation_count = (shapes.len() as f64 * self.validation_fraction).round() as usize;
+        let mut in_validation = vec![false; shapes.len()];
+        positions[shapes.len() - validation_count..].iter().for_each(|position| in_validation[*position] = true);
+        let order: Vec<_> = match self.shuffle {
+            true => Permutation::new(shapes.len() as u64, self.seed).iter().map(|id| id as usize).collect(),
+            false => (0..shapes.len()).collect(),
+        };
+        let (validation, train): (Vec<_>, Vec<_>) = order.into_iter().partition(|position| in_validation[*position]);
+
+        let mut splits = vec![("train", train)];
+        if self.validation_fraction > 0.0 {
+            splits.push(("val", validation));
+        }
+        let splits = splits.into_iter().map(|(split, positions)| {
+            let file = format!("{name}_{split}.{}", self.format.extension());
+            let shape = [positions.len(), grid[0] as usize, grid[1] as usize, grid[2] as usize];
+            let mut data = match self.format {
+                TensorFormat::Npy => npy_header(shape),
+                TensorFormat::Raw => vec![],
+            };
+            for position in &positions {
+                data.extend(voxels(&shapes[*position].1, grid));
+            }
+            store.put(&file, &data).map_err(TensorError::Io)?;
+            let codes = positions.iter().map(|position| shapes[*position].0.clone()).collect();
+            Ok(TensorSplit { name: split.to_string(), file, shape, codes })
+        }).collect::<Result<Vec<_>, _>>()?;
+        let manifest = TensorManifest {
+            format: self.format,
+            dtype: "uint8".to_string(),
+            grid,
+            canonical: self.canonical,
+            seed: self.seed,
+            splits,
+        };
+        let json = serde_json::to_vec_pretty(&manifest).expect("The manifest is serializable.");
+        store.put(&format!("{name}.json"), &json).map_err(TensorError::Io)?;
+        Ok(manifest)
+    }
+}
+
+/// The blocks moved so their minimal coordinate on every axis is zero.
+fn normalized(mut blocks: Vec<Point3D<i32>>) -> Vec<Point3D<i32>> {
+    for axis in Axis3D::iter() {
+        let min = blocks.iter().map(|p| p[axis]).min().unwrap_or_default();
+        blocks.iter_mut().for_each(|p| p[axis] -= min);
+    }
+    blocks
+}
+
+/// The smallest grid every one of the normalized shapes fits into.
+fn fitting_grid<'a>(shapes: impl Iterator<Item = &'a Vec<Point3D<i32>>>) -> [u32; 3] {
+    shapes.flatten().fold([1; 3], |grid, p| [
+        grid[0].max(*p.x() as u32 + 1),
+        grid[1].max(*p.y() as u32 + 1),
+        grid[2].max(*p.z() as u32 + 1),
+    ])
+}
+
+fn fits(blocks: &[Point3D<i32>], [width, depth, height]: [u32; 3]) -> bool {
+    blocks.iter().all(|p| (*p.x() as u32) < width && (*p.y() as u32) < depth && (*p.z() as u32) < height)
+}
+
+/// One byte per cell of the grid in row major order.
+fn voxels(blocks: &[Point3D<i32>], [width, depth, height]: [u32; 3]) -> Vec<u8> {
+    let [width, depth, height] = [width, depth, height].map(|len| len as usize);
+    let mut voxels = vec![0u8; width * depth * height];
+    for p in blocks {
+        voxels[(*p.x() as usize * depth + *p.y() as usize) * height + *p.z() as usize] = 1;
+    }
+    voxels
+}
+
+/// The header of a version 1.0 `.npy` file of an array of `u8`, padded so the data starts at a multiple of 64 bytes.
+fn npy_header([count, width, depth, height]: [usize; 4]) -> Vec<u8> {
+    let dict = format!("{{'descr': '|u1', 'fortran_order': False, 'shape': ({count}, {width}, {depth}, {height}), }}");
+    // The magic string, the version and the length of the header take 10 bytes, the header ends with a newline.
+    let padding = (64 - (10 + dict.len() + 1) % 64) % 64;
+    let header = format!("{dict}{}\n", " ".repeat(padding));
+    let mut data = b"\x93NUMPY\x01\x00".to_vec();
+    data.extend((header.len() as u16).to_le_bytes());
+    data.extend(header.into_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tensor_tests {
+    use crate::block_count::BlockCount;
+    use crate::poly_tree::PolyTree;
+    use crate::store::FsStore;
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let mut tree = PolyTree::new();
+        (0..3).for_each(|_| { tree.generate_next_level(); });
+        let shapes: Vec<_> = tree.iter_level(BlockCount::new(4)).collect();
+        let dir = std::env::temp_dir().join("tensor_tests_write");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FsStore::new(&dir);
+
+        let manifest = TensorExport::default().write(&shapes, &store, "tetracubes").expect("Writable temp dir");
+        let [split] = &manifest.splits[..] else { panic!("Expected a single split") };
+        let cells = manifest.grid.iter().product::<u32>() as usize;
+        assert_eq!([7, manifest.grid[0] as usize, manifest.grid[1] as usize, manifest.grid[2] as usize], split.shape);
+        let data = std::fs::read(dir.join(&split.file)).expect("Written array");
+        assert_eq!(b"\x93NUMPY", &data[..6]);
+        let header_len = u16::from_le_bytes([data[8], data[9]]) as usize;
+        assert_eq!(0, (10 + header_len) % 64);
+        let voxels = &data[10 + header_len..];
+        assert_eq!(7 * cells, voxels.len());
+        assert!(voxels.chunks(cells).all(|shape| shape.iter().map(|v| *v as usize).sum::<usize>() == 4));
+        assert!(dir.join("tetracubes.json").exists());
+
+        let split = TensorExport::default().with_format(TensorFormat::Raw).with_grid([4, 4, 4]).with_validation_fraction(0.3).with_seed(5);
+        let manifest = split.write(&shapes, &store, "split").unwrap();
+        assert_eq!(vec![5, 2], manifest.splits.iter().map(|split| split.codes.len()).collect::<Vec<_>>());
+        assert_eq!(2 * 64, std::fs::read(dir.join("split_val.bin")).unwrap().len());
+        assert_eq!(manifest, split.write(&shapes, &store, "split").unwrap());
+        let shuffled = split.with_shuffle(true).write(&shapes, &store, "split").unwrap();
+        assert_ne!(manifest.splits[0].codes, shuffled.splits[0].codes);
+        let sorted = |codes: &[String]| codes.iter().cloned().collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(sorted(&manifest.splits[0].codes), sorted(&shuffled.splits[0].codes));
+        assert!(matches!(TensorExport::default().with_grid([2, 2, 2]).write(&shapes, &store, "small"), Err(TensorError::DoesNotFit { .. })));
+        std::fs::remove_dir_all(dir).expect("Removable temp dir");
+    }
+}
@@ -0,0 +1,62 @@
+//! Test support producing every rotated and reflected copy of the shapes of a small golden set, so the
+//! properties that must not depend on the orientation of a shape are checked under all 48 symmetries of
+//! the cube instead of a few hand picked orientations.
+//! Every copy is built twice: once from its transformed coordinates and once by orienting the
+//! arrangement itself with [BlockArrangement::set_orientation].
+
+use std::fmt::Debug;
+use std::fs;
+use crate::block_arrangement::BlockArrangement;
+use crate::golden::golden_path;
+use crate::orientation::{rotations_and_reflections, Orientation};
+use crate::polycube::Polycube;
+
+/// The largest number of blocks of the shapes in [golden_set], which has 41 shapes.
+const MAX_SET_BLOCKS: usize = 5;
+
+/// The shapes of the golden files with up to [MAX_SET_BLOCKS] blocks.
+pub(crate) fn golden_set() -> Vec<Polycube> {
+    (1..=MAX_SET_BLOCKS)
+        .flat_map(|block_count| {
+            let content = fs::read_to_string(golden_path(block_count)).expect("Readable golden file");
+            content.lines().map(|code| Polycube::from_code(code).expect("Valid golden code")).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The 48 distinct orientations, one per symmetry of the cube.
+pub(crate) fn cube_symmetries() -> Vec<Orientation> {
+    let symmetries: Vec<_> = rotations_and_reflections().collect();
+    assert_eq!(48, symmetries.len());
+    symmetries
+}
+
+/// Every transformed copy of the arrangement, two per symmetry of the cube, see the module documentation.
+pub(crate) fn transformed_copies(ba: &BlockArrangement) -> Vec<BlockArrangement> {
+    cube_symmetries().into_iter()
+        .flat_map(|orientation| {
+            let points: Vec<_> = ba.block_iter()
+                .map(|mut p| {
+                    p.apply_orientation(&orientation);
+                    p
+                })
+                .collect();
+            let moved = BlockArrangement::from_points(&points).expect("Transformed blocks stay connected");
+            let mut oriented = ba.clone();
+            oriented.set_orientation(orientation);
+            [moved, oriented]
+        })
+        .collect()
+}
+
+/// Asserts that the property of every transformed copy of every shape of the golden set equals the
+/// property of the shape.
+pub(crate) fn assert_invariant<T: PartialEq + Debug>(property: &str, f: impl Fn(&BlockArrangement) -> T) {
+    for polycube in golden_set() {
+        let expected = f(polycube.arrangement());
+        for (i, copy) in transformed_copies(polycube.arrangement()).iter().enumerate() {
+            let actual = f(copy);
+            assert_eq!(expected, actual, "The {property} of {} changed by transformation {i}", polycube.code());
+        }
+    }
+}